@@ -0,0 +1,65 @@
+//! Minimal Android entry point. Build it as a shared library with `cargo apk` or
+//! `cargo ndk`, e.g. `cargo apk run --example android`.
+
+#[cfg(target_os = "android")]
+#[no_mangle]
+fn android_main(app: winit::platform::android::activity::AndroidApp) {
+    use krakatoa::camera::Camera;
+    use krakatoa::krakatoa::Krakatoa;
+    use winit::event::{Event, WindowEvent};
+    use winit::event_loop::{ControlFlow, EventLoopBuilder};
+    use winit::platform::android::EventLoopBuilderExtAndroid;
+    use winit::window::WindowBuilder;
+
+    let event_loop = EventLoopBuilder::new().with_android_app(app).build();
+    // The native window only exists once the activity resumed, so the engine is created there.
+    let mut krakatoa: Option<Krakatoa> = None;
+    let mut camera = Camera::builder().build();
+
+    event_loop.run(move |event, event_loop, controlflow| match event {
+        Event::Resumed => match &mut krakatoa {
+            Some(krakatoa) => krakatoa.resume().expect("Resuming."),
+            None => {
+                let window = WindowBuilder::new()
+                    .build(event_loop)
+                    .expect("Creating the window.");
+                let engine = Krakatoa::init(window).expect("Initialising the engine.");
+                camera.set_aspect(engine.aspect_ratio());
+                krakatoa = Some(engine);
+            }
+        },
+        Event::Suspended => {
+            if let Some(krakatoa) = &mut krakatoa {
+                krakatoa.suspend().expect("Suspending.");
+            }
+        }
+        Event::WindowEvent {
+            event: WindowEvent::Resized(_),
+            ..
+        } => {
+            if let Some(krakatoa) = &mut krakatoa {
+                krakatoa
+                    .recreate_swapchain()
+                    .expect("Recreating the swapchain.");
+                camera.set_aspect(krakatoa.aspect_ratio());
+            }
+        }
+        Event::MainEventsCleared => match &krakatoa {
+            Some(krakatoa) if !krakatoa.is_paused() => {
+                *controlflow = ControlFlow::Poll;
+                krakatoa.window.request_redraw();
+            }
+            _ => *controlflow = ControlFlow::Wait,
+        },
+        Event::RedrawRequested(_) => match &mut krakatoa {
+            Some(krakatoa) if !krakatoa.is_paused() => {
+                let recreated = krakatoa.render_frame(&camera).expect("Rendering a frame.");
+                if recreated {
+                    camera.set_aspect(krakatoa.aspect_ratio());
+                }
+            }
+            _ => {}
+        },
+        _ => {}
+    });
+}
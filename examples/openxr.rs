@@ -0,0 +1,51 @@
+//! Renders the default scene to an OpenXR headset, mirrored in the window. Needs a
+//! running OpenXR runtime: `cargo run --example openxr --features openxr`.
+
+use krakatoa::camera::Camera;
+use krakatoa::krakatoa::Krakatoa;
+use krakatoa::xr::XrSession;
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::WindowBuilder;
+
+fn main() {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("Krakatoa XR")
+        .build(&event_loop)
+        .expect("Creating the window.");
+    let mut xr = XrSession::init(window, Krakatoa::builder()).expect("Starting OpenXR.");
+    // Only the near and far planes matter to the headset; the window uses the rest.
+    let mut camera = Camera::builder().build();
+    camera.set_aspect(xr.krakatoa.aspect_ratio());
+
+    event_loop.run(move |event, _, controlflow| match event {
+        Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        } => *controlflow = ControlFlow::Exit,
+        Event::WindowEvent {
+            event: WindowEvent::Resized(_),
+            ..
+        } => {
+            xr.krakatoa
+                .recreate_swapchain()
+                .expect("Recreating the swapchain.");
+            camera.set_aspect(xr.krakatoa.aspect_ratio());
+        }
+        Event::MainEventsCleared => {
+            if !xr.poll_events().expect("Polling OpenXR events.") {
+                *controlflow = ControlFlow::Exit;
+                return;
+            }
+            xr.krakatoa.window.request_redraw();
+        }
+        Event::RedrawRequested(_) => {
+            let recreated = xr.render_frame(&camera).expect("Rendering a frame.");
+            if recreated {
+                camera.set_aspect(xr.krakatoa.aspect_ratio());
+            }
+        }
+        _ => {}
+    });
+}
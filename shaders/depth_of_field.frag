@@ -0,0 +1,56 @@
+#version 450
+
+layout (location = 0) out vec4 colour;
+
+layout (set = 0, binding = 0) uniform sampler2D source;
+layout (set = 0, binding = 1) uniform sampler2D depth;
+
+layout (push_constant) uniform DepthOfField {
+    // Projection's depth scale and offset, focal distance, circle of confusion scale.
+    vec4 projection_and_focus;
+    // Largest radius in pixels.
+    float max_radius;
+} parameters;
+
+const int SAMPLES = 48;
+const float GOLDEN_ANGLE = 2.39996323;
+
+float view_depth(ivec2 pixel) {
+    float ndc_depth = texelFetch(depth, pixel, 0).r;
+    return parameters.projection_and_focus.y
+        / min(ndc_depth - parameters.projection_and_focus.x, -1e-7);
+}
+
+// Signed radius of the circle of confusion in pixels, negative in front of the focus.
+float circle_of_confusion(ivec2 pixel) {
+    float view_distance = view_depth(pixel);
+    float focus = parameters.projection_and_focus.z;
+    float radius = parameters.projection_and_focus.w * (view_distance - focus) / view_distance;
+    return clamp(radius, -parameters.max_radius, parameters.max_radius);
+}
+
+void main() {
+    ivec2 size = textureSize(source, 0);
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float centre = circle_of_confusion(pixel);
+
+    vec3 sum = texelFetch(source, pixel, 0).rgb;
+    float total_weight = 1.0;
+    for (int i = 0; i < SAMPLES; i++) {
+        float offset = sqrt((float(i) + 0.5) / float(SAMPLES)) * parameters.max_radius;
+        float angle = float(i) * GOLDEN_ANGLE;
+        ivec2 neighbour = clamp(
+            pixel + ivec2(round(offset * vec2(cos(angle), sin(angle)))),
+            ivec2(0),
+            size - 1
+        );
+        float radius = circle_of_confusion(neighbour);
+        // The near field spreads over whatever is behind it; the far field does not
+        // bleed over sharper things in front.
+        float spread = radius < centre ? abs(radius) : min(abs(radius), abs(centre));
+        float weight = clamp(spread - offset + 1.0, 0.0, 1.0);
+        sum += texelFetch(source, neighbour, 0).rgb * weight;
+        total_weight += weight;
+    }
+    colour = vec4(sum / total_weight, 1.0);
+}
@@ -0,0 +1,62 @@
+#version 450
+
+layout (location = 0) in vec3 view_ray;
+layout (location = 1) in vec3 camera_position;
+
+layout (set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+} ubo;
+
+layout (push_constant) uniform GridParameters {
+    float cell_size;
+    float fade_distance;
+    float opacity;
+    float major_every;
+} grid;
+
+layout (location = 0) out vec4 theColour;
+
+// Coverage of lines spaced `spacing` apart, antialiased over one pixel.
+float lines(vec2 coordinate, float spacing) {
+    vec2 scaled = coordinate / spacing;
+    vec2 width = fwidth(scaled);
+    vec2 distance_to_line = abs(fract(scaled - 0.5) - 0.5) / width;
+    return 1.0 - min(min(distance_to_line.x, distance_to_line.y), 1.0);
+}
+
+void main() {
+    // Intersect the view ray with the y = 0 plane.
+    float t = -camera_position.y / view_ray.y;
+    if (t <= 0.0) {
+        discard;
+    }
+    vec3 world_position = camera_position + t * view_ray;
+
+    vec4 clip_position = ubo.projection_matrix * ubo.view_matrix * vec4(world_position, 1.0);
+    gl_FragDepth = clip_position.z / clip_position.w;
+
+    vec2 coordinate = world_position.xz;
+    float minor = lines(coordinate, grid.cell_size);
+    float major = lines(coordinate, grid.cell_size * grid.major_every);
+    vec3 colour = vec3(0.35);
+    float alpha = max(0.4 * minor, major);
+    colour = mix(colour, vec3(0.6), major);
+
+    vec2 axis_width = fwidth(coordinate);
+    if (abs(coordinate.y) < axis_width.y) {
+        colour = vec3(0.9, 0.2, 0.2); // x axis
+        alpha = 1.0;
+    }
+    if (abs(coordinate.x) < axis_width.x) {
+        colour = vec3(0.2, 0.3, 0.9); // z axis
+        alpha = 1.0;
+    }
+
+    float distance_to_camera = length(world_position - camera_position);
+    alpha *= grid.opacity * (1.0 - smoothstep(0.5 * grid.fade_distance, grid.fade_distance, distance_to_camera));
+    if (alpha < 0.01) {
+        discard;
+    }
+    theColour = vec4(colour, alpha);
+}
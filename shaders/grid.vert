@@ -0,0 +1,18 @@
+#version 450
+
+layout (set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+} ubo;
+
+layout (location = 0) out vec3 view_ray;
+layout (location = 1) out vec3 camera_position;
+
+void main() {
+    vec2 ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(ndc, 0.0, 1.0);
+
+    vec4 view_position = inverse(ubo.projection_matrix) * vec4(ndc, 1.0, 1.0);
+    view_ray = transpose(mat3(ubo.view_matrix)) * view_position.xyz;
+    camera_position = -transpose(mat3(ubo.view_matrix)) * ubo.view_matrix[3].xyz;
+}
@@ -0,0 +1,61 @@
+#version 450
+
+layout (location = 0) out vec4 colour;
+
+layout (set = 0, binding = 1) uniform sampler2D depth;
+
+layout (push_constant) uniform LensFlare {
+    // The light's position and the centre of the viewport, in pixels.
+    vec4 light_and_centre;
+    // Light colour scaled by the intensity, then the cleared depth the light shows through.
+    vec4 colour_and_clear_depth;
+    // Ghost count, ghost spacing along the axis, halo radius and occlusion test radius in
+    // pixels.
+    vec4 ghosts_and_radii;
+} flare;
+
+const int OCCLUSION_TAPS = 5;
+const int MAX_GHOSTS = 16;
+
+// Fraction of a grid of taps around the light where nothing was drawn in front of it.
+// Taps off the screen count as hidden, so the flare fades out at the edges.
+float visibility(vec2 light) {
+    ivec2 size = textureSize(depth, 0);
+    float spacing = flare.ghosts_and_radii.w / float(OCCLUSION_TAPS / 2);
+    float visible = 0.0;
+    for (int y = 0; y < OCCLUSION_TAPS; y++) {
+        for (int x = 0; x < OCCLUSION_TAPS; x++) {
+            ivec2 tap = ivec2(light + (vec2(x, y) - float(OCCLUSION_TAPS / 2)) * spacing);
+            if (all(greaterThanEqual(tap, ivec2(0))) && all(lessThan(tap, size))
+                && texelFetch(depth, tap, 0).r >= flare.colour_and_clear_depth.w) {
+                visible += 1.0;
+            }
+        }
+    }
+    return visible / float(OCCLUSION_TAPS * OCCLUSION_TAPS);
+}
+
+void main() {
+    vec2 light = flare.light_and_centre.xy;
+    float visible = visibility(light);
+    if (visible <= 0.0) {
+        discard;
+    }
+    vec2 pixel = gl_FragCoord.xy;
+    vec2 axis = flare.light_and_centre.zw - light;
+    float halo_radius = flare.ghosts_and_radii.z;
+
+    float halo = exp(-distance(pixel, light) / max(halo_radius, 1.0));
+    vec3 sum = vec3(halo);
+    int ghost_count = min(int(flare.ghosts_and_radii.x), MAX_GHOSTS);
+    for (int i = 0; i < ghost_count; i++) {
+        // Ghosts are reflections inside the lens, spread along the axis through the
+        // centre and past it, each its own size and tint.
+        vec2 ghost = light + axis * (float(i + 1) * flare.ghosts_and_radii.y);
+        float radius = halo_radius * (0.25 + 0.75 * fract(0.618034 * float(i + 1)));
+        float disc = 1.0 - smoothstep(0.6 * radius, radius, distance(pixel, ghost));
+        vec3 tint = 0.5 + 0.5 * cos(6.28318 * (vec3(0.0, 0.33, 0.67) + 0.21 * float(i)));
+        sum += 0.15 * disc * tint;
+    }
+    colour = vec4(sum * flare.colour_and_clear_depth.rgb * visible, 1.0);
+}
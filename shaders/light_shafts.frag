@@ -0,0 +1,68 @@
+#version 450
+
+layout (location = 0) out vec4 colour;
+
+layout (set = 0, binding = 1) uniform sampler2D depth;
+
+layout (push_constant) uniform LightShafts {
+    // The light's position in pixels, the cleared depth the light shines through, and
+    // the samples per pixel.
+    vec4 light_and_samples;
+    // Light colour scaled by the intensity and the scattering, then the anisotropy.
+    vec4 colour_and_anisotropy;
+    // Fraction of the way to the light marched, decay per sample, and the projection's
+    // scale of view space x and y at unit depth, inverted.
+    vec4 march_and_projection;
+    // Direction towards the light in view space.
+    vec4 light_direction;
+    // Offset and size in pixels of the viewport the scene is framed in.
+    vec4 viewport;
+} shafts;
+
+const float PI = 3.14159265;
+// Must match `MAX_LIGHT_SHAFT_SAMPLES` in `src/light_shafts.rs`.
+const int MAX_SAMPLES = 64;
+
+float henyey_greenstein(float cos_theta, float g) {
+    float denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+void main() {
+    vec2 pixel = gl_FragCoord.xy;
+    vec2 light = shafts.light_and_samples.xy;
+    float clear_depth = shafts.light_and_samples.z;
+    int sample_count = clamp(int(shafts.light_and_samples.w), 1, MAX_SAMPLES);
+    float decay = shafts.march_and_projection.y;
+
+    // Marches from the pixel towards the light, gathering the samples where nothing was
+    // drawn: the air there is lit. Samples off the screen count as covered.
+    ivec2 size = textureSize(depth, 0);
+    vec2 step = (light - pixel) * shafts.march_and_projection.x / float(sample_count);
+    vec2 position = pixel;
+    float weight = 1.0;
+    float gathered = 0.0;
+    float total_weight = 0.0;
+    for (int i = 0; i < sample_count; i++) {
+        position += step;
+        ivec2 tap = ivec2(position);
+        if (all(greaterThanEqual(tap, ivec2(0))) && all(lessThan(tap, size))
+            && texelFetch(depth, tap, 0).r >= clear_depth) {
+            gathered += weight;
+        }
+        total_weight += weight;
+        weight *= decay;
+    }
+    float lit = gathered / total_weight;
+    if (lit <= 0.0) {
+        discard;
+    }
+
+    vec2 ndc = 2.0 * (pixel - shafts.viewport.xy) / shafts.viewport.zw - 1.0;
+    vec3 view_direction = normalize(vec3(ndc * shafts.march_and_projection.zw, 1.0));
+    float phase = henyey_greenstein(
+        dot(view_direction, shafts.light_direction.xyz),
+        shafts.colour_and_anisotropy.w
+    );
+    colour = vec4(shafts.colour_and_anisotropy.rgb * lit * 4.0 * PI * phase, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 450
+
+layout (input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput accumulation;
+layout (input_attachment_index = 1, set = 0, binding = 1) uniform subpassInput revealage;
+
+layout (location = 0) out vec4 theColour;
+
+void main() {
+    vec4 accumulated = subpassLoad(accumulation);
+    float revealed = subpassLoad(revealage).r;
+    if (revealed >= 1.0) {
+        discard;
+    }
+    vec3 average_colour = accumulated.rgb / max(accumulated.a, 1e-5);
+    theColour = vec4(average_colour, 1.0 - revealed);
+}
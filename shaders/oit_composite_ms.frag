@@ -0,0 +1,25 @@
+#version 450
+
+layout (constant_id = 0) const int SAMPLE_COUNT = 4;
+
+layout (input_attachment_index = 0, set = 0, binding = 0) uniform subpassInputMS accumulation;
+layout (input_attachment_index = 1, set = 0, binding = 1) uniform subpassInputMS revealage;
+
+layout (location = 0) out vec4 theColour;
+
+// Averages the samples so the composite still runs once per pixel.
+void main() {
+    vec4 accumulated = vec4(0.0);
+    float revealed = 0.0;
+    for (int i = 0; i < SAMPLE_COUNT; i++) {
+        accumulated += subpassLoad(accumulation, i);
+        revealed += subpassLoad(revealage, i).r;
+    }
+    accumulated /= float(SAMPLE_COUNT);
+    revealed /= float(SAMPLE_COUNT);
+    if (revealed >= 1.0) {
+        discard;
+    }
+    vec3 average_colour = accumulated.rgb / max(accumulated.a, 1e-5);
+    theColour = vec4(average_colour, 1.0 - revealed);
+}
@@ -0,0 +1,12 @@
+#version 450
+
+layout (push_constant) uniform OutlineParameters {
+    vec4 colour;
+    vec4 width_and_viewport;
+} outline;
+
+layout (location = 0) out vec4 theColour;
+
+void main() {
+    theColour = outline.colour;
+}
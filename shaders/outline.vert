@@ -0,0 +1,30 @@
+#version 450
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in mat4 model_matrix;
+layout (location = 6) in mat4 inverse_model_matrix;
+
+layout (set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+} ubo;
+
+layout (push_constant) uniform OutlineParameters {
+    vec4 colour;
+    vec4 width_and_viewport;
+} outline;
+
+void main() {
+    mat4 view_projection = ubo.projection_matrix * ubo.view_matrix;
+    vec4 clip_position = view_projection * model_matrix * vec4(position, 1.0);
+
+    // Push the vertex outwards in screen space so the outline has a constant pixel width.
+    vec3 world_normal = transpose(mat3(inverse_model_matrix)) * normal;
+    vec2 screen_normal = (view_projection * vec4(world_normal, 0.0)).xy;
+    if (dot(screen_normal, screen_normal) > 0.0) {
+        vec2 pixel_to_ndc = 2.0 / outline.width_and_viewport.yz;
+        clip_position.xy += normalize(screen_normal) * outline.width_and_viewport.x
+            * pixel_to_ndc * clip_position.w;
+    }
+    gl_Position = clip_position;
+}
@@ -0,0 +1,53 @@
+#version 450
+
+// 1: linear scRGB, 2: HDR10 (BT.2020 + PQ)
+layout (constant_id = 0) const int ENCODING = 2;
+
+layout (input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput scene;
+
+layout (push_constant) uniform Output {
+    float paper_white;
+    // Camera exposure the scene is scaled by, 1 unless lights are in physical units.
+    float exposure;
+} output_parameters;
+
+layout (location = 0) out vec4 theColour;
+
+// The scene is shaded for an sRGB display, so decode it to linear light first.
+vec3 srgb_to_linear(vec3 colour) {
+    vec3 magnitude = abs(colour);
+    vec3 linear = mix(
+        magnitude / 12.92,
+        pow((magnitude + 0.055) / 1.055, vec3(2.4)),
+        greaterThan(magnitude, vec3(0.04045))
+    );
+    return sign(colour) * linear;
+}
+
+vec3 pq_encode(vec3 normalised_luminance) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(normalised_luminance, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+const mat3 BT709_TO_BT2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+void main() {
+    vec4 colour = subpassLoad(scene);
+    vec3 linear = srgb_to_linear(colour.rgb) * output_parameters.exposure;
+    if (ENCODING == 1) {
+        // scRGB 1.0 is 80 nits.
+        theColour = vec4(linear * output_parameters.paper_white / 80.0, colour.a);
+    } else {
+        vec3 bt2020 = BT709_TO_BT2020 * linear;
+        theColour = vec4(pq_encode(bt2020 * output_parameters.paper_white / 10000.0), colour.a);
+    }
+}
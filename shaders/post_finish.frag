@@ -0,0 +1,38 @@
+#version 450
+
+// Blended as 2 * colour * destination, so 0.5 leaves the frame as it was.
+layout (location = 0) out vec4 colour;
+
+layout (push_constant) uniform Finish {
+    // Offset and size in pixels of the viewport the scene is framed in.
+    vec4 viewport;
+    // Vignette radius and softness as fractions of the distance to the corners, vignette
+    // strength, grain strength.
+    vec4 settings;
+    // Reseeds the grain every frame.
+    uint frame;
+} finish;
+
+uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+void main() {
+    vec2 half_size = 0.5 * finish.viewport.zw;
+    vec2 offset = gl_FragCoord.xy - finish.viewport.xy - half_size;
+    float distance_to_centre = length(offset) / length(half_size);
+    float radius = finish.settings.x;
+    float vignette = 1.0 - finish.settings.z
+        * smoothstep(radius, radius + max(finish.settings.y, 1e-4), distance_to_centre);
+
+    uvec2 pixel = uvec2(gl_FragCoord.xy);
+    float noise = float(hash(pixel.x + hash(pixel.y + hash(finish.frame)))) / 4294967295.0;
+    float grain = 1.0 + finish.settings.w * (2.0 * noise - 1.0);
+
+    colour = vec4(vec3(0.5 * vignette * grain), 1.0);
+}
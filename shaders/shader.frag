@@ -1,11 +1,237 @@
 #version 450
 
 layout (location = 0) out vec4 theColour;
+// Only written by the weighted-blended transparency pipeline.
+layout (location = 1) out float revealage;
+
+layout (constant_id = 1) const bool WEIGHTED_BLENDED = false;
 
 layout (location = 0) in vec4 aColor;
 layout (location = 1) in vec3 normal;
+layout (location = 2) in vec3 view_ray;
+layout (location = 3) flat in uint instance_id;
+
+layout (set = 0, binding = 1) uniform DirectionalLight {
+    vec4 direction_and_ambient;
+    vec4 colour;
+    vec4 fog_colour_and_density;
+    vec4 fog_scattering_and_anisotropy;
+    // `RenderMode` in `src/render_mode.rs`, then the distance the depth mode shows as white.
+    vec4 render_mode_and_depth_range;
+} light;
+
+const float PI = 3.14159265;
+
+#ifdef CUTOUT
+layout (location = 4) flat in float alpha_cutoff;
+layout (location = 5) flat in uint cutout_layer;
+layout (location = 6) in vec3 object_position;
+layout (location = 7) in vec3 object_normal;
+
+// Set when the pipeline turns alpha into coverage, which needs multisampling.
+layout (constant_id = 2) const bool ALPHA_TO_COVERAGE = false;
+
+layout (set = 1, binding = 0) uniform sampler2DArray cutout_masks;
+
+// The mask's alpha, projected along the object-space axis the surface faces most, so
+// -1 to 1 across the other two axes covers the mask once. Rows run along +y, which is
+// down, or along +z for surfaces facing up or down.
+float mask_alpha() {
+    vec3 facing = abs(object_normal);
+    vec2 uv;
+    if (facing.x >= facing.y && facing.x >= facing.z) {
+        uv = object_position.zy;
+    } else if (facing.y >= facing.z) {
+        uv = object_position.xz;
+    } else {
+        uv = object_position.xy;
+    }
+    return texture(cutout_masks, vec3(uv * 0.5 + 0.5, float(cutout_layer))).a;
+}
+#endif
+
+#ifdef REFLECTIONS
+// Must match `MAX_REFLECTION_PROBES` in `src/reflection.rs`.
+const int MAX_REFLECTION_PROBES = 8;
+const uint PROBE_BOX = 1u;
+
+layout (set = 0, binding = 0) uniform Camera {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+} camera;
+
+struct ReflectionProbe {
+    // Centre, then 0 for a sphere or 1 for a box.
+    vec4 position_and_shape;
+    // Radius or half extents, then the distance over which the probe fades out inside its edge.
+    vec4 extents_and_blend;
+};
+
+layout (set = 1, binding = 0) uniform Reflections {
+    // Mirror plane, the points p with dot(xyz, p) + w = 0.
+    vec4 plane;
+    // Offset and size in pixels of the viewport the planar reflection was drawn for.
+    vec4 viewport;
+    // Reflectance at normal incidence elsewhere and on the plane, probe count, and 1 when
+    // the planar reflection is drawn.
+    vec4 settings;
+    ReflectionProbe probes[MAX_REFLECTION_PROBES];
+} reflections;
+layout (set = 1, binding = 1) uniform samplerCubeArray probe_cubemaps;
+layout (set = 1, binding = 2) uniform sampler2D planar_reflection;
+
+// Where the ray from `position` along `direction` leaves the probe's volume, seen from its
+// centre, so nearby walls reflect where they are rather than at infinity.
+vec3 parallax_corrected(ReflectionProbe probe, vec3 position, vec3 direction) {
+    vec3 centre = probe.position_and_shape.xyz;
+    vec3 extents = probe.extents_and_blend.xyz;
+    float travel;
+    if (uint(probe.position_and_shape.w) == PROBE_BOX) {
+        vec3 furthest = max(
+            (centre + extents - position) / direction,
+            (centre - extents - position) / direction
+        );
+        travel = min(min(furthest.x, furthest.y), furthest.z);
+    } else {
+        vec3 offset = position - centre;
+        float b = dot(offset, direction);
+        float c = dot(offset, offset) - extents.x * extents.x;
+        travel = -b + sqrt(max(b * b - c, 0.0));
+    }
+    return position + direction * max(travel, 0.0) - centre;
+}
+
+// 1 well inside the probe's volume, fading to 0 at its edge.
+float probe_weight(ReflectionProbe probe, vec3 position) {
+    vec3 offset = position - probe.position_and_shape.xyz;
+    vec3 extents = probe.extents_and_blend.xyz;
+    float depth_inside;
+    if (uint(probe.position_and_shape.w) == PROBE_BOX) {
+        vec3 margins = extents - abs(offset);
+        depth_inside = min(min(margins.x, margins.y), margins.z);
+    } else {
+        depth_inside = extents.x - length(offset);
+    }
+    return clamp(depth_inside / max(probe.extents_and_blend.w, 1e-4), 0.0, 1.0);
+}
+
+// Blends the reflection of the surroundings into `surface` by Schlick's Fresnel term:
+// the planar reflection on the mirror plane, the probes covering the point elsewhere.
+vec3 reflect_surroundings(vec3 surface, vec3 normal, vec3 view_ray) {
+    vec3 camera_position = -transpose(mat3(camera.view_matrix)) * camera.view_matrix[3].xyz;
+    vec3 position = camera_position + view_ray;
+    vec3 view_direction = normalize(view_ray);
+
+    vec3 reflection = vec3(0.0);
+    float coverage = 0.0;
+    float reflectance = reflections.settings.x;
+    bool on_plane = reflections.settings.w > 0.5
+        && abs(dot(reflections.plane.xyz, position) + reflections.plane.w) < 1e-2
+        && abs(dot(reflections.plane.xyz, normal)) > 0.99;
+    if (on_plane) {
+        vec2 uv = (gl_FragCoord.xy - reflections.viewport.xy) / reflections.viewport.zw;
+        reflection = texture(planar_reflection, uv).rgb;
+        coverage = 1.0;
+        reflectance = reflections.settings.y;
+    } else {
+        vec3 direction = reflect(view_direction, normal);
+        int probe_count = min(int(reflections.settings.z), MAX_REFLECTION_PROBES);
+        for (int i = 0; i < probe_count; i++) {
+            float weight = probe_weight(reflections.probes[i], position);
+            if (weight > 0.0) {
+                vec3 lookup = parallax_corrected(reflections.probes[i], position, direction);
+                reflection += weight * texture(probe_cubemaps, vec4(lookup, float(i))).rgb;
+                coverage += weight;
+            }
+        }
+        if (coverage > 0.0) {
+            reflection /= coverage;
+        }
+    }
+    float cos_theta = clamp(-dot(view_direction, normal), 0.0, 1.0);
+    float fresnel = reflectance + (1.0 - reflectance) * pow(1.0 - cos_theta, 5.0);
+    return mix(surface, reflection, fresnel * min(coverage, 1.0));
+}
+#endif
+
+float henyey_greenstein(float cos_theta, float g) {
+    float denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+const uint RENDER_MODE_LIT = 0u;
+const uint RENDER_MODE_ALBEDO = 1u;
+const uint RENDER_MODE_NORMALS = 2u;
+const uint RENDER_MODE_DEPTH = 3u;
+const uint RENDER_MODE_OVERDRAW = 4u;
+const uint RENDER_MODE_INSTANCE_ID = 5u;
+
+// What a debug render mode shows in place of the lit colour.
+vec3 debug_colour(uint render_mode, vec3 albedo, vec3 normal, vec3 view_ray, uint instance) {
+    if (render_mode == RENDER_MODE_ALBEDO) {
+        return albedo;
+    } else if (render_mode == RENDER_MODE_NORMALS) {
+        return normalize(normal) * 0.5 + 0.5;
+    } else if (render_mode == RENDER_MODE_DEPTH) {
+        return vec3(clamp(length(view_ray) / light.render_mode_and_depth_range.y, 0.0, 1.0));
+    } else if (render_mode == RENDER_MODE_OVERDRAW) {
+        // Drawn at 10% opacity, so every layer brings the pixel a tenth closer to white.
+        return vec3(1.0);
+    }
+    uint hash = (instance + 1u) * 2654435761u;
+    return vec3((hash >> 8) & 255u, (hash >> 16) & 255u, (hash >> 24) & 255u) / 255.0;
+}
 
 void main() {
-    vec3 direction_to_light = normalize(vec3(-1, -1, 0));
-    theColour = 0.5 * (1 + max(dot(normal, direction_to_light), 0)) * aColor;
-}
\ No newline at end of file
+    vec3 direction_to_light = normalize(light.direction_and_ambient.xyz);
+    float diffuse = max(dot(normalize(normal), direction_to_light), 0);
+    vec3 lighting = light.direction_and_ambient.w + 0.5 * diffuse * light.colour.rgb;
+    vec3 surface = lighting * aColor.rgb;
+#ifdef REFLECTIONS
+    surface = reflect_surroundings(surface, normalize(normal), view_ray);
+#endif
+
+    float density = light.fog_colour_and_density.w;
+    float distance_to_camera = length(view_ray);
+    float transmittance = exp(-density * distance_to_camera);
+    float phase = henyey_greenstein(
+        dot(view_ray / max(distance_to_camera, 1e-4), direction_to_light),
+        light.fog_scattering_and_anisotropy.y
+    );
+    vec3 in_scattered = light.fog_colour_and_density.rgb * light.direction_and_ambient.w
+        + light.fog_scattering_and_anisotropy.x * 4.0 * PI * phase * light.colour.rgb;
+
+    vec4 colour = vec4(mix(in_scattered, surface, transmittance), aColor.a);
+#ifdef CUTOUT
+    float alpha = aColor.a * mask_alpha();
+    if (ALPHA_TO_COVERAGE) {
+        // Sharpened so coverage goes from none to full over a pixel around the cutoff,
+        // rather than dithering the whole range of alpha.
+        colour.a = clamp((alpha - alpha_cutoff) / max(fwidth(alpha), 1e-4) + 0.5, 0.0, 1.0);
+    } else if (alpha < alpha_cutoff) {
+        discard;
+    } else {
+        colour.a = 1.0;
+    }
+#endif
+
+    uint render_mode = uint(light.render_mode_and_depth_range.x);
+    if (render_mode != RENDER_MODE_LIT) {
+#ifdef CUTOUT
+        // Cutouts keep their coverage; the overdraw mode sees them as one layer.
+        float opacity = colour.a;
+#else
+        float opacity = render_mode == RENDER_MODE_OVERDRAW ? 0.1 : aColor.a;
+#endif
+        colour = vec4(debug_colour(render_mode, aColor.rgb, normal, view_ray, instance_id), opacity);
+    }
+
+    if (WEIGHTED_BLENDED) {
+        // McGuire and Bavoil's depth weight, favouring fragments close to the camera.
+        float weight = clamp(colour.a * 3e3 * pow(1.0 - gl_FragCoord.z, 3.0), 1e-2, 3e3);
+        theColour = vec4(colour.rgb * colour.a, colour.a) * weight;
+        revealage = colour.a;
+    } else {
+        theColour = colour;
+    }
+}
@@ -4,17 +4,76 @@ layout (location = 1) in vec3 normal;
 layout (location = 2) in mat4 model_matrix;
 layout (location = 6) in mat4 inverse_model_matrix;
 layout (location = 10) in vec3 colour;
+layout (location = 11) in float opacity;
+layout (location = 12) in float alpha_cutoff;
+layout (location = 13) in uint cutout_layer;
+
+// The opaque pipelines skip translucent instances and the transparent ones skip opaque
+// instances. Cutouts are left to the `CUTOUT` variant, which draws nothing else.
+layout (constant_id = 0) const bool TRANSPARENT_PASS = false;
 
 layout (set = 0, binding = 0) uniform UniformBufferObject {
     mat4 view_matrix;
     mat4 projection_matrix;
+#ifdef WIND
+    // Seconds since the first frame, and since the previous one.
+    vec4 time;
+#endif
 } ubo;
 
+#ifdef WIND
+layout (push_constant) uniform Wind {
+    // xz direction the wind blows in, strength and gust frequency.
+    vec4 direction_strength_frequency;
+} wind;
+#endif
+
+// The depth pre-pass relies on both passes computing bit-identical depths.
+invariant gl_Position;
+
 layout (location = 0) out vec4 aColor;
 layout (location = 1) out vec3 out_normal;
+layout (location = 2) out vec3 view_ray;
+layout (location = 3) flat out uint instance_id;
+#ifdef CUTOUT
+layout (location = 4) flat out float out_alpha_cutoff;
+layout (location = 5) flat out uint out_cutout_layer;
+// The mask is projected in object space.
+layout (location = 6) out vec3 object_position;
+layout (location = 7) out vec3 object_normal;
+#endif
 
 void main() {
-    gl_Position = ubo.projection_matrix * ubo.view_matrix * model_matrix * vec4(position, 1.0);
-    aColor = vec4(colour, 1.0);
+    // Only read when drawing points.
+    gl_PointSize = 1.0;
+#ifdef CUTOUT
+    bool skipped = alpha_cutoff <= 0.0;
+#else
+    bool skipped = alpha_cutoff > 0.0 || (opacity < 1.0) != TRANSPARENT_PASS;
+#endif
+    if (skipped) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+    vec4 world_position = model_matrix * vec4(position, 1.0);
+#ifdef WIND
+    // Bend by the height above the instance's origin, up being -y, so roots stay put.
+    float height = max(model_matrix[3].y - world_position.y, 0.0);
+    float phase = dot(model_matrix[3].xz, vec2(0.37, 0.71));
+    float gust = 0.6 + 0.4 * sin(ubo.time.x * wind.direction_strength_frequency.w + phase);
+    world_position.xz += wind.direction_strength_frequency.xy
+        * wind.direction_strength_frequency.z * gust * height * height;
+#endif
+    gl_Position = ubo.projection_matrix * ubo.view_matrix * world_position;
+    aColor = vec4(colour, opacity);
     out_normal = transpose(mat3(inverse_model_matrix)) * normal;
-}
\ No newline at end of file
+    vec3 camera_position = -transpose(mat3(ubo.view_matrix)) * ubo.view_matrix[3].xyz;
+    view_ray = world_position.xyz - camera_position;
+    instance_id = uint(gl_InstanceIndex);
+#ifdef CUTOUT
+    out_alpha_cutoff = alpha_cutoff;
+    out_cutout_layer = cutout_layer;
+    object_position = position;
+    object_normal = normal;
+#endif
+}
@@ -0,0 +1,66 @@
+#version 450
+
+layout (location = 0) in vec3 view_ray;
+
+layout (push_constant) uniform SkyParameters {
+    vec4 sun_direction_and_turbidity;
+} sky;
+
+layout (location = 0) out vec4 theColour;
+
+const float PI = 3.14159265;
+const vec3 UP = vec3(0.0, -1.0, 0.0);
+
+// Perez luminance distribution
+float perez(float cos_theta, float gamma, float cos_gamma, float A, float B, float C, float D, float E) {
+    return (1.0 + A * exp(B / max(cos_theta, 0.01))) * (1.0 + C * exp(D * gamma) + E * cos_gamma * cos_gamma);
+}
+
+float perez_ratio(vec3 view, vec3 sun, float T, float A, float B, float C, float D, float E) {
+    float cos_theta = dot(view, UP);
+    float cos_gamma = clamp(dot(view, sun), -1.0, 1.0);
+    float gamma = acos(cos_gamma);
+    float theta_s = acos(clamp(dot(sun, UP), -1.0, 1.0));
+    return perez(cos_theta, gamma, cos_gamma, A, B, C, D, E)
+        / perez(1.0, theta_s, cos(theta_s), A, B, C, D, E);
+}
+
+void main() {
+    vec3 view = normalize(view_ray);
+    vec3 sun = normalize(sky.sun_direction_and_turbidity.xyz);
+    float T = sky.sun_direction_and_turbidity.w;
+    float theta_s = acos(clamp(dot(sun, UP), 0.0, 1.0));
+    float t2 = theta_s * theta_s;
+    float t3 = t2 * theta_s;
+
+    float x_zenith = T * T * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * theta_s)
+        + T * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * theta_s + 0.00394)
+        + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * theta_s + 0.25886);
+    float y_zenith = T * T * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * theta_s)
+        + T * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * theta_s + 0.00516)
+        + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * theta_s + 0.26688);
+
+    float Y = perez_ratio(view, sun, T,
+        0.1787 * T - 1.4630, -0.3554 * T + 0.4275, -0.0227 * T + 5.3251,
+        0.1206 * T - 2.5771, -0.0670 * T + 0.3703);
+    float x = x_zenith * perez_ratio(view, sun, T,
+        -0.0193 * T - 0.2592, -0.0665 * T + 0.0008, -0.0004 * T + 0.2125,
+        -0.0641 * T - 0.8989, -0.0033 * T + 0.0452);
+    float y = y_zenith * perez_ratio(view, sun, T,
+        -0.0167 * T - 0.2608, -0.0950 * T + 0.0092, -0.0079 * T + 0.2102,
+        -0.0441 * T - 1.6537, -0.0109 * T + 0.0529);
+
+    // Relative luminance, compressed into displayable range
+    Y = 1.0 - exp(-0.5 * Y);
+    vec3 XYZ = vec3(x / y * Y, Y, (1.0 - x - y) / y * Y);
+    vec3 rgb = mat3(
+        3.2406, -0.9689, 0.0557,
+        -1.5372, 1.8758, -0.2040,
+        -0.4986, 0.0415, 1.0570) * XYZ;
+
+    float sun_disk = smoothstep(0.9995, 0.9999, dot(view, sun));
+    float below_horizon = smoothstep(0.0, -0.1, dot(view, UP));
+    rgb = mix(rgb + vec3(sun_disk), vec3(0.2, 0.2, 0.22), below_horizon);
+
+    theColour = vec4(max(rgb, vec3(0.0)), 1.0);
+}
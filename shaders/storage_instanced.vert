@@ -0,0 +1,55 @@
+#version 450
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+
+// Floats from one instance to the next: the instance type's size, custom fields included.
+layout (constant_id = 0) const uint INSTANCE_STRIDE = 40;
+
+layout (set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+} ubo;
+
+// Read as floats so any instance type can be uploaded as is; `InstanceData` comes first.
+layout (set = 1, binding = 0, std430) readonly buffer Instances {
+    float instances[];
+};
+
+invariant gl_Position;
+
+layout (location = 0) out vec4 aColor;
+layout (location = 1) out vec3 out_normal;
+layout (location = 2) out vec3 view_ray;
+layout (location = 3) flat out uint instance_id;
+
+mat4 read_matrix(uint offset) {
+    return mat4(
+        instances[offset], instances[offset + 1], instances[offset + 2], instances[offset + 3],
+        instances[offset + 4], instances[offset + 5], instances[offset + 6], instances[offset + 7],
+        instances[offset + 8], instances[offset + 9], instances[offset + 10], instances[offset + 11],
+        instances[offset + 12], instances[offset + 13], instances[offset + 14], instances[offset + 15]
+    );
+}
+
+void main() {
+    uint base = uint(gl_InstanceIndex) * INSTANCE_STRIDE;
+    mat4 model_matrix = read_matrix(base);
+    mat4 inverse_model_matrix = read_matrix(base + 16);
+    vec3 colour = vec3(instances[base + 32], instances[base + 33], instances[base + 34]);
+    float opacity = instances[base + 35];
+    float alpha_cutoff = instances[base + 36];
+
+    gl_PointSize = 1.0;
+    // Opaque instances only, like the main pipeline.
+    if (opacity < 1.0 || alpha_cutoff > 0.0) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+    vec4 world_position = model_matrix * vec4(position, 1.0);
+    gl_Position = ubo.projection_matrix * ubo.view_matrix * world_position;
+    aColor = vec4(colour, opacity);
+    out_normal = transpose(mat3(inverse_model_matrix)) * normal;
+    vec3 camera_position = -transpose(mat3(ubo.view_matrix)) * ubo.view_matrix[3].xyz;
+    view_ray = world_position.xyz - camera_position;
+    instance_id = uint(gl_InstanceIndex);
+}
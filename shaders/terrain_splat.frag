@@ -0,0 +1,106 @@
+#version 450
+
+layout (location = 0) out vec4 theColour;
+
+layout (location = 0) in vec3 world_position;
+layout (location = 1) in vec3 normal;
+layout (location = 2) in vec3 view_ray;
+
+layout (set = 0, binding = 1) uniform DirectionalLight {
+    vec4 direction_and_ambient;
+    vec4 colour;
+    vec4 fog_colour_and_density;
+    vec4 fog_scattering_and_anisotropy;
+    // `RenderMode` in `src/render_mode.rs`, then the distance the depth mode shows as white.
+    vec4 render_mode_and_depth_range;
+} light;
+
+layout (set = 1, binding = 0) uniform SplatParameters {
+    // World-space x and z of the splat map's corner, then the size it covers.
+    vec4 origin_and_size;
+    // World units per repetition of each layer's textures.
+    vec4 tile_sizes;
+    // How far each layer's normal map bends the surface; 0 ignores it.
+    vec4 normal_strengths;
+    uint layer_count;
+} parameters;
+layout (set = 1, binding = 1) uniform sampler2DArray splat_map;
+layout (set = 1, binding = 2) uniform sampler2DArray albedo;
+layout (set = 1, binding = 3) uniform sampler2DArray normal_maps;
+
+const float PI = 3.14159265;
+
+float henyey_greenstein(float cos_theta, float g) {
+    float denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+const uint RENDER_MODE_LIT = 0u;
+const uint RENDER_MODE_ALBEDO = 1u;
+const uint RENDER_MODE_NORMALS = 2u;
+const uint RENDER_MODE_DEPTH = 3u;
+const uint RENDER_MODE_OVERDRAW = 4u;
+const uint RENDER_MODE_INSTANCE_ID = 5u;
+
+// What a debug render mode shows in place of the lit colour.
+vec3 debug_colour(uint render_mode, vec3 albedo, vec3 normal, vec3 view_ray, uint instance) {
+    if (render_mode == RENDER_MODE_ALBEDO) {
+        return albedo;
+    } else if (render_mode == RENDER_MODE_NORMALS) {
+        return normalize(normal) * 0.5 + 0.5;
+    } else if (render_mode == RENDER_MODE_DEPTH) {
+        return vec3(clamp(length(view_ray) / light.render_mode_and_depth_range.y, 0.0, 1.0));
+    } else if (render_mode == RENDER_MODE_OVERDRAW) {
+        // Drawn at 10% opacity, so every layer brings the pixel a tenth closer to white.
+        return vec3(1.0);
+    }
+    uint hash = (instance + 1u) * 2654435761u;
+    return vec3((hash >> 8) & 255u, (hash >> 16) & 255u, (hash >> 24) & 255u) / 255.0;
+}
+
+void main() {
+    vec2 splat_uv = (world_position.xz - parameters.origin_and_size.xy)
+        / parameters.origin_and_size.zw;
+    vec4 weights = texture(splat_map, vec3(splat_uv, 0.0));
+    weights *= vec4(lessThan(uvec4(0, 1, 2, 3), uvec4(parameters.layer_count)));
+    float total = dot(weights, vec4(1.0));
+    weights = total > 0.0 ? weights / total : vec4(1.0, 0.0, 0.0, 0.0);
+
+    // The heightfield's tangent frame: u runs along +x and v along +z.
+    vec3 surface_normal = normalize(normal);
+    vec3 tangent = normalize(vec3(1.0, 0.0, 0.0) - surface_normal * surface_normal.x);
+    vec3 bitangent = cross(surface_normal, tangent);
+
+    // Every layer is sampled, as skipping some would break the derivatives mipmapping needs.
+    vec3 base_colour = vec3(0.0);
+    vec3 bent_normal = vec3(0.0);
+    for (int layer = 0; layer < 4; layer++) {
+        vec3 uv = vec3(world_position.xz / parameters.tile_sizes[layer], layer);
+        base_colour += weights[layer] * texture(albedo, uv).rgb;
+        vec3 tangent_normal = texture(normal_maps, uv).xyz * 2.0 - 1.0;
+        tangent_normal.xy *= parameters.normal_strengths[layer];
+        bent_normal += weights[layer] * (tangent * tangent_normal.x
+            + bitangent * tangent_normal.y + surface_normal * tangent_normal.z);
+    }
+
+    vec3 direction_to_light = normalize(light.direction_and_ambient.xyz);
+    float diffuse = max(dot(normalize(bent_normal), direction_to_light), 0);
+    vec3 lighting = light.direction_and_ambient.w + 0.5 * diffuse * light.colour.rgb;
+    vec3 surface = lighting * base_colour;
+
+    float density = light.fog_colour_and_density.w;
+    float distance_to_camera = length(view_ray);
+    float transmittance = exp(-density * distance_to_camera);
+    float phase = henyey_greenstein(
+        dot(view_ray / max(distance_to_camera, 1e-4), direction_to_light),
+        light.fog_scattering_and_anisotropy.y
+    );
+    vec3 in_scattered = light.fog_colour_and_density.rgb * light.direction_and_ambient.w
+        + light.fog_scattering_and_anisotropy.x * 4.0 * PI * phase * light.colour.rgb;
+
+    theColour = vec4(mix(in_scattered, surface, transmittance), 1.0);
+    uint render_mode = uint(light.render_mode_and_depth_range.x);
+    if (render_mode != RENDER_MODE_LIT) {
+        theColour = vec4(debug_colour(render_mode, base_colour, bent_normal, view_ray, 0u), 1.0);
+    }
+}
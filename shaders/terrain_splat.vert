@@ -0,0 +1,21 @@
+#version 450
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec3 normal;
+
+layout (set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+} ubo;
+
+layout (location = 0) out vec3 world_position;
+layout (location = 1) out vec3 out_normal;
+layout (location = 2) out vec3 view_ray;
+
+// Terrain chunks are generated in world space, so there is no model matrix.
+void main() {
+    gl_Position = ubo.projection_matrix * ubo.view_matrix * vec4(position, 1.0);
+    world_position = position;
+    out_normal = normal;
+    vec3 camera_position = -transpose(mat3(ubo.view_matrix)) * ubo.view_matrix[3].xyz;
+    view_ray = position - camera_position;
+}
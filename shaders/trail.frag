@@ -0,0 +1,9 @@
+#version 450
+
+layout (location = 0) in vec4 vertex_colour;
+
+layout (location = 0) out vec4 theColour;
+
+void main() {
+    theColour = vertex_colour;
+}
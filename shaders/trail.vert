@@ -0,0 +1,15 @@
+#version 450
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec4 colour;
+
+layout (set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view_matrix;
+    mat4 projection_matrix;
+} ubo;
+
+layout (location = 0) out vec4 vertex_colour;
+
+void main() {
+    gl_Position = ubo.projection_matrix * ubo.view_matrix * vec4(position, 1.0);
+    vertex_colour = colour;
+}
@@ -0,0 +1,59 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+/// Environment variable forcing the adapter index, overriding [`crate::krakatoa_builder::KrakatoaBuilder::adapter`].
+pub const ADAPTER_ENV_VAR: &str = "KRAKATOA_GPU";
+
+/// Environment variable that, set to anything but `0`, prefers software rasterizers
+/// such as lavapipe or SwiftShader, like
+/// [`crate::krakatoa_builder::KrakatoaBuilder::prefer_software`].
+pub const SOFTWARE_ENV_VAR: &str = "KRAKATOA_SOFTWARE";
+
+/// A physical device as reported by the driver, for choosing one by index.
+#[derive(Clone, Debug)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    /// Total size of the device-local heaps in bytes.
+    pub device_local_memory: u64,
+}
+
+pub fn enumerate_adapters(instance: &ash::Instance) -> Result<Vec<AdapterInfo>> {
+    let physical_devices = unsafe { instance.enumerate_physical_devices()? };
+
+    Ok(physical_devices
+        .into_iter()
+        .enumerate()
+        .map(|(index, physical_device)| {
+            let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+            let memory_properties =
+                unsafe { instance.get_physical_device_memory_properties(physical_device) };
+            let name = unsafe { std::ffi::CStr::from_ptr(properties.device_name.as_ptr()) };
+            AdapterInfo {
+                index,
+                name: name.to_string_lossy().into_owned(),
+                device_type: properties.device_type,
+                device_local_memory: memory_properties.memory_heaps
+                    [..memory_properties.memory_heap_count as usize]
+                    .iter()
+                    .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+                    .map(|heap| heap.size)
+                    .sum(),
+            }
+        })
+        .collect())
+}
+
+/// The adapter index forced through [`ADAPTER_ENV_VAR`], if it is set to a number.
+pub fn adapter_from_env() -> Option<usize> {
+    std::env::var(ADAPTER_ENV_VAR).ok()?.trim().parse().ok()
+}
+
+/// Whether [`SOFTWARE_ENV_VAR`] asks for a software rasterizer.
+pub fn software_from_env() -> bool {
+    std::env::var(SOFTWARE_ENV_VAR).is_ok_and(|value| {
+        let value = value.trim();
+        !value.is_empty() && value != "0"
+    })
+}
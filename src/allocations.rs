@@ -0,0 +1,147 @@
+use std::backtrace::Backtrace;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use ash::vk::{self, Handle};
+
+/// (device, object type, handle) of a tracked object.
+type Key = (u64, i32, u64);
+
+/// Every buffer, image and device memory allocation the engine has made and not yet
+/// destroyed, across all devices.
+static LIVE: Mutex<BTreeMap<Key, Allocation>> = Mutex::new(BTreeMap::new());
+
+/// A buffer, image or block of device memory that is still alive.
+#[derive(Clone, Debug)]
+pub struct Allocation {
+    pub object_type: vk::ObjectType,
+    pub handle: u64,
+    /// Bytes of device memory; for buffers and images, what they asked to be bound.
+    pub size: u64,
+    /// What the object was created for, such as its usage flags.
+    pub usage: String,
+    /// Set with [`set_name`].
+    pub name: Option<String>,
+    /// Where the object was created. Only captured in debug builds.
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+fn live() -> MutexGuard<'static, BTreeMap<Key, Allocation>> {
+    // A panic while holding the lock leaves the map itself intact.
+    LIVE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn key<H: Handle>(logical_device: &ash::Device, handle: H) -> Key {
+    (
+        logical_device.handle().as_raw(),
+        H::TYPE.as_raw(),
+        handle.as_raw(),
+    )
+}
+
+/// Records a newly created buffer, image or device memory allocation.
+pub(crate) fn track<H: Handle>(
+    logical_device: &ash::Device,
+    handle: H,
+    size: u64,
+    usage: impl Into<String>,
+) {
+    let key = key(logical_device, handle);
+    let backtrace = cfg!(debug_assertions).then(|| Arc::new(Backtrace::force_capture()));
+    let allocation = Allocation {
+        object_type: H::TYPE,
+        handle: key.2,
+        size,
+        usage: usage.into(),
+        name: None,
+        backtrace,
+    };
+    live().insert(key, allocation);
+}
+
+/// Forgets an object that is being destroyed or freed.
+pub(crate) fn untrack<H: Handle>(logical_device: &ash::Device, handle: H) {
+    live().remove(&key(logical_device, handle));
+}
+
+/// Names a tracked object in allocation reports. Untracked handles are ignored.
+pub fn set_name<H: Handle>(logical_device: &ash::Device, handle: H, name: &str) {
+    if let Some(allocation) = live().get_mut(&key(logical_device, handle)) {
+        allocation.name = Some(name.to_owned());
+    }
+}
+
+/// The objects alive on one device, printed as a summary per object type followed by
+/// each object, largest first.
+#[derive(Clone, Debug)]
+pub struct AllocationReport {
+    pub allocations: Vec<Allocation>,
+}
+
+impl AllocationReport {
+    pub fn for_device(logical_device: &ash::Device) -> Self {
+        let device = logical_device.handle().as_raw();
+        let mut allocations: Vec<Allocation> = live()
+            .range((device, i32::MIN, 0)..=(device, i32::MAX, u64::MAX))
+            .map(|(_, allocation)| allocation.clone())
+            .collect();
+        allocations.sort_by_key(|allocation| std::cmp::Reverse(allocation.size));
+        AllocationReport { allocations }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocations.is_empty()
+    }
+
+    /// Bytes of device memory still allocated.
+    pub fn memory_bytes(&self) -> u64 {
+        self.allocations
+            .iter()
+            .filter(|allocation| allocation.object_type == vk::ObjectType::DEVICE_MEMORY)
+            .map(|allocation| allocation.size)
+            .sum()
+    }
+}
+
+impl fmt::Display for AllocationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut totals = BTreeMap::<i32, (vk::ObjectType, usize, u64)>::new();
+        for allocation in &self.allocations {
+            let total = totals.entry(allocation.object_type.as_raw()).or_insert((
+                allocation.object_type,
+                0,
+                0,
+            ));
+            total.1 += 1;
+            total.2 += allocation.size;
+        }
+        writeln!(
+            f,
+            "{} objects alive holding {} bytes of device memory",
+            self.allocations.len(),
+            self.memory_bytes()
+        )?;
+        for (object_type, count, bytes) in totals.values() {
+            writeln!(f, "  {:?}: {} ({} bytes)", object_type, count, bytes)?;
+        }
+        for allocation in &self.allocations {
+            writeln!(
+                f,
+                "{:?} {:#x}, {} bytes, {}{}",
+                allocation.object_type,
+                allocation.handle,
+                allocation.size,
+                allocation.usage,
+                allocation
+                    .name
+                    .as_ref()
+                    .map_or(String::new(), |name| format!(", \"{}\"", name))
+            )?;
+            if let Some(backtrace) = &allocation.backtrace {
+                writeln!(f, "{}", backtrace)?;
+            }
+        }
+        std::fmt::Result::Ok(())
+    }
+}
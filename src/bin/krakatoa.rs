@@ -1,8 +1,10 @@
 use anyhow::Result;
-use ash::vk;
-use krakatoa::camera::Camera;
+use krakatoa::camera::{Camera, FlyController};
+use krakatoa::input::Input;
 use krakatoa::krakatoa::Krakatoa;
-use krakatoa::model::{InstanceData, Model};
+use krakatoa::model::{InstanceData, Mesh, Model};
+use krakatoa::sun_cycle::SunCycle;
+use krakatoa::timing::FrameTimer;
 use nalgebra::Matrix4;
 use winit::event::VirtualKeyCode;
 use winit::event_loop::EventLoop;
@@ -15,161 +17,168 @@ fn main() -> Result<()> {
         .with_title("Krakatoa")
         .build(&event_loop)?;
     let mut krakatoa = Krakatoa::init(window)?;
-    let mut sphere = Model::sphere(3);
-    sphere.insert_visibly(InstanceData::from_matrix_and_colour(
-        Matrix4::new_scaling(0.5),
-        [0.5, 0.0, 0.0],
-    ));
+    let mut sphere = Model::new(Mesh::sphere(3));
+    sphere
+        .instances
+        .insert_visibly(InstanceData::from_matrix_and_colour(
+            Matrix4::new_scaling(0.5),
+            [0.5, 0.0, 0.0],
+        ));
 
-    sphere.update_vertex_buffer(
-        &krakatoa.logical_device,
-        krakatoa.physical_device_memory_properties,
-    )?;
-    sphere.update_index_buffer(
-        &krakatoa.logical_device,
-        krakatoa.physical_device_memory_properties,
-    )?;
-    sphere.update_instance_buffer(
+    sphere.upload(
         &krakatoa.logical_device,
         krakatoa.physical_device_memory_properties,
     )?;
 
     krakatoa.models = vec![sphere];
+    krakatoa.enable_sky()?;
+    krakatoa.fog.density = 0.02;
 
-    let mut camera = Camera::builder().build();
+    let mut camera = Camera::builder().aspect(krakatoa.aspect_ratio()).build();
+    let mut input = Input::new();
+    let controller = FlyController::new();
+    let mut timer = FrameTimer::new();
+    let mut sun_cycle = SunCycle::new();
 
-    use winit::event::{Event, WindowEvent};
-    event_loop.run(move |event, _, controlflow| match event {
-        Event::WindowEvent {
-            event: WindowEvent::KeyboardInput { input, .. },
-            ..
-        } => match input {
-            winit::event::KeyboardInput {
-                state: winit::event::ElementState::Pressed,
-                virtual_keycode: Some(keycode),
+    use winit::event::{Event, MouseButton, WindowEvent};
+    event_loop.run(move |event, _, controlflow| {
+        input.process_event(&event);
+        match event {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            winit::event::KeyboardInput {
+                                state: winit::event::ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    },
                 ..
-            } => match keycode {
-                VirtualKeyCode::Right | VirtualKeyCode::D => {
-                    camera.turn_right(0.1);
-                }
-                VirtualKeyCode::Left | VirtualKeyCode::A => {
-                    camera.turn_left(0.1);
-                }
-                VirtualKeyCode::Up | VirtualKeyCode::W => {
-                    camera.move_forward(0.05);
-                }
-                VirtualKeyCode::Down | VirtualKeyCode::S => {
-                    camera.move_backward(0.05);
-                }
-                VirtualKeyCode::PageUp | VirtualKeyCode::Q => {
-                    camera.turn_up(0.02);
-                }
-                VirtualKeyCode::PageDown | VirtualKeyCode::E => {
-                    camera.turn_down(0.02);
-                }
-                _ => {}
-            },
-            _ => {}
-        },
-        Event::WindowEvent {
-            event: WindowEvent::CloseRequested,
-            ..
-        } => {
-            *controlflow = winit::event_loop::ControlFlow::Exit;
-        }
-        Event::MainEventsCleared => {
-            krakatoa.window.request_redraw();
-        }
-        Event::RedrawRequested(_) => {
-            krakatoa.swapchain.current_image =
-                (krakatoa.swapchain.current_image + 1) % krakatoa.swapchain.amount_of_images;
-
-            let (image_index, _) = unsafe {
-                krakatoa
-                    .swapchain
-                    .swapchain_loader
-                    .acquire_next_image(
-                        krakatoa.swapchain.swapchain,
-                        std::u64::MAX,
-                        krakatoa.swapchain.image_available[krakatoa.swapchain.current_image],
-                        vk::Fence::null(),
-                    )
-                    .expect("Image acquisition failed.")
-            };
-
-            unsafe {
-                krakatoa
-                    .logical_device
-                    .wait_for_fences(
-                        &[krakatoa.swapchain.may_begin_drawing[krakatoa.swapchain.current_image]],
-                        true,
-                        std::u64::MAX,
-                    )
-                    .expect("Waiting fences.");
-
-                krakatoa
-                    .logical_device
-                    .reset_fences(&[
-                        krakatoa.swapchain.may_begin_drawing[krakatoa.swapchain.current_image]
-                    ])
-                    .expect("Resetting fences.");
-
-                camera.update_buffer(
-                    &krakatoa.logical_device,
-                    krakatoa.physical_device_memory_properties,
-                    &mut krakatoa.uniform_buffer,
-                );
-
-                krakatoa.models.iter_mut().for_each(|m| {
-                    m.update_instance_buffer(
-                        &krakatoa.logical_device,
-                        krakatoa.physical_device_memory_properties,
-                    )
-                    .expect("Updating instance buffer.")
-                });
-
-                krakatoa
-                    .update(image_index as usize)
-                    .expect("Updating the command buffer.");
+            } if input.cursor_captured => {
+                input
+                    .set_cursor_captured(&krakatoa.window, false)
+                    .expect("Releasing the cursor.");
             }
-
-            let semaphores_available =
-                [krakatoa.swapchain.image_available[krakatoa.swapchain.current_image]];
-            let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-            let semaphores_finished =
-                [krakatoa.swapchain.rendering_finished[krakatoa.swapchain.current_image]];
-            let command_buffers = [krakatoa.command_buffers[image_index as usize]];
-            let submit_info = [vk::SubmitInfo::builder()
-                .wait_semaphores(&semaphores_available)
-                .wait_dst_stage_mask(&waiting_stages)
-                .command_buffers(&command_buffers)
-                .signal_semaphores(&semaphores_finished)
-                .build()];
-            unsafe {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            winit::event::KeyboardInput {
+                                state: winit::event::ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::G),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                krakatoa.toggle_grid().expect("Toggling the grid.");
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            winit::event::KeyboardInput {
+                                state: winit::event::ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::V),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let render_mode = krakatoa.cycle_render_mode();
                 krakatoa
-                    .logical_device
-                    .queue_submit(
-                        krakatoa.queues.graphics_queue,
-                        &submit_info,
-                        krakatoa.swapchain.may_begin_drawing[krakatoa.swapchain.current_image],
-                    )
-                    .expect("Queue submission.");
-            };
-
-            let swapchains = [krakatoa.swapchain.swapchain];
-            let indices = [image_index];
-            let present_info = vk::PresentInfoKHR::builder()
-                .wait_semaphores(&semaphores_finished)
-                .swapchains(&swapchains)
-                .image_indices(&indices);
-            unsafe {
+                    .window
+                    .set_title(&format!("Krakatoa ({:?})", render_mode));
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            winit::event::KeyboardInput {
+                                state: winit::event::ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Return),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } if input.is_key_pressed(VirtualKeyCode::LAlt)
+                || input.is_key_pressed(VirtualKeyCode::RAlt) =>
+            {
+                krakatoa.toggle_fullscreen().expect("Toggling fullscreen.");
+                camera.set_aspect(krakatoa.aspect_ratio());
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: winit::event::ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } if !input.cursor_captured => {
+                input
+                    .set_cursor_captured(&krakatoa.window, true)
+                    .expect("Capturing the cursor.");
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Focused(false),
+                ..
+            } if input.cursor_captured => {
+                input
+                    .set_cursor_captured(&krakatoa.window, false)
+                    .expect("Releasing the cursor.");
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                ..
+            } => {
                 krakatoa
-                    .swapchain
-                    .swapchain_loader
-                    .queue_present(krakatoa.queues.graphics_queue, &present_info)
-                    .expect("Queue presentation.");
+                    .recreate_swapchain()
+                    .expect("Recreating the swapchain.");
+                camera.set_aspect(krakatoa.aspect_ratio());
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Occluded(occluded),
+                ..
+            } => {
+                krakatoa.occluded = occluded;
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                *controlflow = winit::event_loop::ControlFlow::Exit;
             }
+            Event::MainEventsCleared if krakatoa.is_paused() => {
+                // Sleep until the next event instead of spinning while nothing is shown.
+                *controlflow = winit::event_loop::ControlFlow::Wait;
+                timer.tick();
+            }
+            Event::MainEventsCleared => {
+                *controlflow = winit::event_loop::ControlFlow::Poll;
+                let delta_time = input.frame_delta(timer.tick());
+                input.update_gamepads();
+                controller.update(&input, &mut camera, delta_time);
+                camera.update(delta_time);
+                krakatoa.sort_transparent(&camera.position);
+                sun_cycle.advance(&timer);
+                sun_cycle.apply(&mut krakatoa.light, krakatoa.sky.as_mut());
+                input.end_frame();
+                krakatoa.window.request_redraw();
+            }
+            Event::RedrawRequested(_) if krakatoa.is_paused() => {}
+            Event::RedrawRequested(_) => {
+                let recreated = krakatoa.render_frame(&camera).expect("Rendering a frame.");
+                if recreated {
+                    camera.set_aspect(krakatoa.aspect_ratio());
+                }
+            }
+            _ => {}
         }
-        _ => {}
     });
 }
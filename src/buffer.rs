@@ -6,6 +6,7 @@ use ash::{
     vk::{self, DeviceMemory, MemoryRequirements},
 };
 
+use crate::allocations;
 use crate::find_memorytype_index;
 
 pub struct Buffer {
@@ -33,6 +34,12 @@ impl Buffer {
             )?
         };
         let requirements = unsafe { logical_device.get_buffer_memory_requirements(buffer) };
+        allocations::track(
+            logical_device,
+            buffer,
+            requirements.size,
+            format!("{:?} buffer", usage),
+        );
         let memory_index = find_memorytype_index(
             &requirements,
             &memory_properties,
@@ -40,10 +47,22 @@ impl Buffer {
         )
         .expect("Unable to find suitable memorytype for the vertex buffer.");
 
-        let allocate_info = vk::MemoryAllocateInfo::builder()
+        // Buffers read through their device address need memory allocated for it.
+        let mut allocate_flags =
+            vk::MemoryAllocateFlagsInfo::builder().flags(vk::MemoryAllocateFlags::DEVICE_ADDRESS);
+        let mut allocate_info = vk::MemoryAllocateInfo::builder()
             .allocation_size(requirements.size)
             .memory_type_index(memory_index);
+        if usage.contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS) {
+            allocate_info = allocate_info.push_next(&mut allocate_flags);
+        }
         let memory = unsafe { logical_device.allocate_memory(&allocate_info, None) }?;
+        allocations::track(
+            logical_device,
+            memory,
+            requirements.size,
+            format!("memory of a {:?} buffer", usage),
+        );
         unsafe { logical_device.bind_buffer_memory(buffer, memory, 0) }?;
 
         Ok(Self {
@@ -66,7 +85,7 @@ impl Buffer {
     {
         let bytes_to_write = std::mem::size_of_val(data);
         if bytes_to_write > self.size_in_bytes {
-            unsafe { logical_device.destroy_buffer(self.buffer, None) };
+            self.cleanup(logical_device);
             let new_buffer = Buffer::init(
                 bytes_to_write,
                 self.usage,
@@ -93,4 +112,45 @@ impl Buffer {
 
         Ok(())
     }
+
+    /// Copies the whole buffer out as `T`s once the device has finished with it, such as
+    /// after a compute dispatch writing to it. Every buffer is host visible, so this needs
+    /// no staging copy, but it waits for the device to go idle.
+    pub fn download<T: Copy>(&self, logical_device: &ash::Device) -> Result<Vec<T>> {
+        let count = self.size_in_bytes / std::mem::size_of::<T>().max(1);
+        unsafe {
+            logical_device.device_wait_idle()?;
+            let data = logical_device.map_memory(
+                self.memory,
+                0,
+                self.requirements.size,
+                vk::MemoryMapFlags::empty(),
+            )? as *const T;
+            let values = std::slice::from_raw_parts(data, count).to_vec();
+            logical_device.unmap_memory(self.memory);
+            Ok(values)
+        }
+    }
+
+    /// Needs `SHADER_DEVICE_ADDRESS` usage and the `bufferDeviceAddress` feature.
+    pub fn device_address(&self, logical_device: &ash::Device) -> vk::DeviceAddress {
+        let info = vk::BufferDeviceAddressInfo::builder().buffer(self.buffer);
+        unsafe { logical_device.get_buffer_device_address(&info) }
+    }
+
+    /// Names the buffer and its memory in [`allocations::AllocationReport`]s.
+    pub fn set_name(&self, logical_device: &ash::Device, name: &str) {
+        allocations::set_name(logical_device, self.buffer, name);
+        allocations::set_name(logical_device, self.memory, name);
+    }
+
+    /// Destroys the buffer and releases its memory.
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        allocations::untrack(logical_device, self.buffer);
+        allocations::untrack(logical_device, self.memory);
+        unsafe {
+            logical_device.destroy_buffer(self.buffer, None);
+            logical_device.free_memory(self.memory, None);
+        }
+    }
 }
@@ -0,0 +1,84 @@
+use nalgebra::{UnitQuaternion, Vector3};
+use winit::event::MouseButton;
+
+use crate::input::Input;
+
+use super::camera::Camera;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArcballController {
+    pub pivot: Vector3<f32>,
+    pub distance: f32,
+    /// Rotation from camera space (x right, y down, z forward) to world space.
+    pub orientation: UnitQuaternion<f32>,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub zoom_sensitivity: f32,
+    pub last_sphere_point: Option<Vector3<f32>>,
+}
+
+impl ArcballController {
+    pub fn new(pivot: Vector3<f32>, camera: &Camera) -> Self {
+        Self {
+            pivot,
+            distance: (pivot - camera.position).norm(),
+            orientation: camera.orientation(),
+            min_distance: 0.1,
+            max_distance: 100.,
+            zoom_sensitivity: 0.1,
+            last_sphere_point: None,
+        }
+    }
+
+    /// Maps a cursor position onto the virtual trackball in camera space, using a
+    /// hyperbolic sheet outside the sphere so drags past the edge stay smooth.
+    pub fn project_to_sphere(cursor: (f64, f64), window_size: (f32, f32)) -> Vector3<f32> {
+        let size = window_size.0.min(window_size.1);
+        let x = (2.0 * cursor.0 as f32 - window_size.0) / size;
+        let y = (2.0 * cursor.1 as f32 - window_size.1) / size;
+        let d2 = x * x + y * y;
+        let z = if d2 <= 0.5 {
+            (1.0 - d2).sqrt()
+        } else {
+            0.5 / d2.sqrt()
+        };
+        Vector3::new(x, y, -z).normalize()
+    }
+
+    pub fn drag(&mut self, from: Vector3<f32>, to: Vector3<f32>) {
+        if let Some(rotation) = UnitQuaternion::rotation_between(&to, &from) {
+            self.orientation *= rotation;
+        }
+    }
+
+    pub fn zoom(&mut self, amount: f32) {
+        self.distance =
+            (self.distance * (-amount).exp()).clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Left-drag rotates around the pivot, the scroll wheel zooms.
+    pub fn update(&mut self, input: &Input, camera: &mut Camera, window_size: (f32, f32)) {
+        if input.is_button_pressed(MouseButton::Left) {
+            let current = Self::project_to_sphere(input.cursor_position, window_size);
+            if let Some(last) = self.last_sphere_point {
+                self.drag(last, current);
+            }
+            self.last_sphere_point = Some(current);
+        } else {
+            self.last_sphere_point = None;
+        }
+        if input.scroll_delta != 0.0 {
+            self.zoom(input.scroll_delta * self.zoom_sensitivity);
+        }
+        self.apply(camera);
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        let view_direction = self.orientation * Vector3::z();
+        camera.set_view(
+            self.pivot - self.distance * view_direction,
+            view_direction,
+            self.orientation * Vector3::y(),
+        );
+    }
+}
@@ -1,12 +1,18 @@
-use std::f32::consts::FRAC_PI_3;
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_3};
 
 use ash::vk;
-use nalgebra::{Matrix4, Rotation3, Unit, Vector3};
+use nalgebra::{Matrix4, Rotation3, Unit, UnitQuaternion, Vector3};
 
 use crate::buffer::Buffer;
 
 use super::camera_builder::CameraBuilder;
+use super::camera_preset::CameraPreset;
+use super::frustum::Frustum;
 
+/// How far mouse-look pitches up or down, short of the vertical where yaw degenerates.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Camera {
     pub view_matrix: Matrix4<f32>,
     pub position: Vector3<f32>,
@@ -17,6 +23,14 @@ pub struct Camera {
     pub near: f32,
     pub far: f32,
     pub projection_matrix: Matrix4<f32>,
+    /// Aperture as an f-number, e.g. 16.0 for f/16.
+    pub aperture: f32,
+    /// Shutter time in seconds.
+    pub shutter_speed: f32,
+    pub iso: f32,
+    pub smoothing_half_life: Option<f32>,
+    pub smoothed_position: Vector3<f32>,
+    pub smoothed_orientation: UnitQuaternion<f32>,
 }
 
 impl Camera {
@@ -29,6 +43,9 @@ impl Camera {
             aspect: 800. / 600.,
             near: 0.1,
             far: 100.,
+            aperture: 16.0,
+            shutter_speed: 1.0 / 125.0,
+            iso: 100.0,
         }
     }
     pub fn update_buffer(
@@ -43,6 +60,107 @@ impl Camera {
             .unwrap();
     }
 
+    pub fn set_view(
+        &mut self,
+        position: Vector3<f32>,
+        view_direction: Vector3<f32>,
+        down_direction: Vector3<f32>,
+    ) {
+        self.position = position;
+        self.view_direction = Unit::new_normalize(view_direction);
+        self.down_direction = Unit::new_normalize(
+            down_direction
+                - down_direction.dot(self.view_direction.as_ref()) * *self.view_direction,
+        );
+        self.update_view_matrix();
+    }
+
+    pub fn preset(&self) -> CameraPreset {
+        CameraPreset {
+            position: self.position,
+            view_direction: *self.view_direction,
+            down_direction: *self.down_direction,
+            fovy: self.fovy,
+            near: self.near,
+            far: self.far.is_finite().then_some(self.far),
+        }
+    }
+
+    pub fn apply_preset(&mut self, preset: &CameraPreset) {
+        self.fovy = preset.fovy;
+        self.near = preset.near;
+        self.far = preset.far.unwrap_or(f32::INFINITY);
+        self.update_projection_matrix();
+        self.set_view(
+            preset.position,
+            preset.view_direction,
+            preset.down_direction,
+        );
+    }
+
+    /// [`Camera::set_view`] from another math library's vectors; see [`crate::math`].
+    #[cfg(feature = "mint")]
+    pub fn set_view_mint(
+        &mut self,
+        position: impl Into<mint::Vector3<f32>>,
+        view_direction: impl Into<mint::Vector3<f32>>,
+        down_direction: impl Into<mint::Vector3<f32>>,
+    ) {
+        self.set_view(
+            crate::math::vector3(position),
+            crate::math::vector3(view_direction),
+            crate::math::vector3(down_direction),
+        );
+    }
+
+    /// The view matrix as another math library's: `camera.view_matrix_as::<glam::Mat4>()`.
+    #[cfg(feature = "mint")]
+    pub fn view_matrix_as<M: From<mint::ColumnMatrix4<f32>>>(&self) -> M {
+        crate::math::to_matrix4(self.view_matrix)
+    }
+
+    #[cfg(feature = "mint")]
+    pub fn projection_matrix_as<M: From<mint::ColumnMatrix4<f32>>>(&self) -> M {
+        crate::math::to_matrix4(self.projection_matrix)
+    }
+
+    #[cfg(feature = "mint")]
+    pub fn position_as<V: From<mint::Vector3<f32>>>(&self) -> V {
+        crate::math::to_vector3(self.position)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn save_preset(&self, name: &str) -> anyhow::Result<()> {
+        let path = super::camera_preset::preset_path(name);
+        std::fs::create_dir_all(super::camera_preset::PRESET_DIRECTORY)?;
+        std::fs::write(path, serde_json::to_string_pretty(&self.preset())?)?;
+        anyhow::Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn load_preset(&mut self, name: &str) -> anyhow::Result<()> {
+        let path = super::camera_preset::preset_path(name);
+        let preset: CameraPreset = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        self.apply_preset(&preset);
+        anyhow::Ok(())
+    }
+
+    /// Exposure value normalized to ISO 100.
+    pub fn ev100(&self) -> f32 {
+        (self.aperture * self.aperture / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    /// Scale that maps scene luminance in cd/m² to the [0, 1] range before tonemapping,
+    /// using the saturation-based sensitivity model (max luminance = 1.2 * 2^EV100).
+    /// HDR outputs apply it with [`crate::hdr::OutputEncode::physical_exposure`].
+    pub fn exposure(&self) -> f32 {
+        1.0 / (1.2 * 2.0_f32.powf(self.ev100()))
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&(self.projection_matrix * self.view_matrix))
+    }
+
     pub fn move_forward(&mut self, distance: f32) {
         self.position += distance * self.view_direction.as_ref();
         self.update_view_matrix();
@@ -52,6 +170,25 @@ impl Camera {
         self.move_forward(-distance);
     }
 
+    pub fn move_right(&mut self, distance: f32) {
+        let right = Unit::new_normalize(self.down_direction.cross(&self.view_direction));
+        self.position += distance * right.as_ref();
+        self.update_view_matrix();
+    }
+
+    pub fn move_left(&mut self, distance: f32) {
+        self.move_right(-distance);
+    }
+
+    pub fn move_down(&mut self, distance: f32) {
+        self.position += distance * self.down_direction.as_ref();
+        self.update_view_matrix();
+    }
+
+    pub fn move_up(&mut self, distance: f32) {
+        self.move_down(-distance);
+    }
+
     pub fn turn_right(&mut self, angle: f32) {
         let rotation = Rotation3::from_axis_angle(&self.down_direction, angle);
         self.view_direction = rotation * self.view_direction;
@@ -74,21 +211,88 @@ impl Camera {
         self.turn_up(-angle);
     }
 
+    /// Mouse-look: yaws about the world's vertical axis and pitches about the camera's
+    /// right axis, so the horizon stays level, stopping short of straight up or down.
+    pub fn rotate_by_mouse_delta(&mut self, delta: (f64, f64), sensitivity: f32) {
+        // The world is y-down.
+        let world_down = Vector3::y_axis();
+        let yaw = Rotation3::from_axis_angle(&world_down, delta.0 as f32 * sensitivity);
+        let view = yaw * *self.view_direction;
+        let horizontal = Vector3::new(view.x, 0.0, view.z);
+        // Looking straight up or down, the heading follows the right axis instead.
+        let heading = if horizontal.norm_squared() > 1e-8 {
+            horizontal.normalize()
+        } else {
+            let right = yaw * self.down_direction.cross(&self.view_direction);
+            Vector3::new(right.x, 0.0, right.z)
+                .normalize()
+                .cross(&world_down)
+        };
+        let pitch = (-view.y).clamp(-1.0, 1.0).asin();
+        let pitch = (pitch - delta.1 as f32 * sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        let view_direction = heading * pitch.cos() - *world_down * pitch.sin();
+        let right = world_down.cross(&heading);
+        self.view_direction = Unit::new_normalize(view_direction);
+        self.down_direction = Unit::new_normalize(view_direction.cross(&right));
+        self.update_view_matrix();
+    }
+
+    /// Rotation from camera space (x right, y down, z forward) to world space.
+    pub fn orientation(&self) -> UnitQuaternion<f32> {
+        let right = self.down_direction.cross(&self.view_direction).normalize();
+        UnitQuaternion::from_rotation_matrix(&Rotation3::from_basis_unchecked(&[
+            right,
+            *self.down_direction,
+            *self.view_direction,
+        ]))
+    }
+
+    /// Makes the rendered view trail the position/orientation set by the movement
+    /// methods, closing half of the remaining gap every `half_life` seconds.
+    /// A non-positive half-life turns smoothing off.
+    pub fn set_smoothing(&mut self, half_life: f32) {
+        self.smoothing_half_life = (half_life > 0.0).then_some(half_life);
+        self.smoothed_position = self.position;
+        self.smoothed_orientation = self.orientation();
+        self.update_view_matrix();
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        if let Some(half_life) = self.smoothing_half_life {
+            let t = 1.0 - 0.5_f32.powf(delta_time / half_life);
+            let target_orientation = self.orientation();
+            self.smoothed_position = self.smoothed_position.lerp(&self.position, t);
+            self.smoothed_orientation = self
+                .smoothed_orientation
+                .try_slerp(&target_orientation, t, 1.0e-6)
+                .unwrap_or(target_orientation);
+            self.update_view_matrix();
+        }
+    }
+
     pub fn update_view_matrix(&mut self) {
-        let right = Unit::new_normalize(self.down_direction.cross(&self.view_direction));
+        let (position, view_direction, down_direction) = match self.smoothing_half_life {
+            Some(_) => (
+                self.smoothed_position,
+                self.smoothed_orientation * Vector3::z_axis(),
+                self.smoothed_orientation * Vector3::y_axis(),
+            ),
+            None => (self.position, self.view_direction, self.down_direction),
+        };
+        let right = Unit::new_normalize(down_direction.cross(&view_direction));
         let m = Matrix4::new(
             right.x,
             right.y,
             right.z,
-            -right.dot(&self.position), //
-            self.down_direction.x,
-            self.down_direction.y,
-            self.down_direction.z,
-            -self.down_direction.dot(&self.position), //
-            self.view_direction.x,
-            self.view_direction.y,
-            self.view_direction.z,
-            -self.view_direction.dot(&self.position), //
+            -right.dot(&position), //
+            down_direction.x,
+            down_direction.y,
+            down_direction.z,
+            -down_direction.dot(&position), //
+            view_direction.x,
+            view_direction.y,
+            view_direction.z,
+            -view_direction.dot(&position), //
             0.0,
             0.0,
             0.0,
@@ -97,8 +301,33 @@ impl Camera {
         self.view_matrix = m;
     }
 
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+        self.update_projection_matrix();
+    }
+
+    /// Pushes the far plane to infinity, so depth approaches 1 only asymptotically
+    /// and no scene scale needs an arbitrary far value.
+    pub fn set_infinite_far(&mut self) {
+        self.far = f32::INFINITY;
+        self.update_projection_matrix();
+    }
+
+    pub fn set_far(&mut self, far: f32) {
+        self.far = far;
+        self.update_projection_matrix();
+    }
+
     pub fn update_projection_matrix(&mut self) {
         let d = 1.0 / (0.5 * self.fovy).tan();
+        let (depth_scale, depth_offset) = if self.far.is_infinite() {
+            (1.0, -self.near)
+        } else {
+            (
+                self.far / (self.far - self.near),
+                -self.near * self.far / (self.far - self.near),
+            )
+        };
         self.projection_matrix = Matrix4::new(
             d / self.aspect,
             0.0,
@@ -110,8 +339,8 @@ impl Camera {
             0.0,
             0.0,
             0.0,
-            self.far / (self.far - self.near),
-            -self.near * self.far / (self.far - self.near),
+            depth_scale,
+            depth_offset,
             0.0,
             0.0,
             1.0,
@@ -119,3 +348,30 @@ impl Camera {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_look_keeps_the_horizon_level() {
+        let mut camera = Camera::builder().build();
+        for _ in 0..50 {
+            camera.rotate_by_mouse_delta((13.0, -7.0), 0.01);
+            let right = camera.down_direction.cross(&camera.view_direction);
+            assert!(right.y.abs() < 1e-4, "rolled: right axis {:?}", right);
+            assert!(camera.down_direction.y > 0.0);
+        }
+    }
+
+    #[test]
+    fn mouse_look_stops_short_of_vertical() {
+        let mut camera = Camera::builder().build();
+        camera.rotate_by_mouse_delta((0.0, -1000.0), 0.01);
+        let pitch = (-camera.view_direction.y).asin();
+        assert!((pitch - MAX_PITCH).abs() < 1e-4);
+        camera.rotate_by_mouse_delta((0.0, 2000.0), 0.01);
+        let pitch = (-camera.view_direction.y).asin();
+        assert!((pitch + MAX_PITCH).abs() < 1e-4);
+    }
+}
@@ -1,7 +1,8 @@
-use nalgebra::{Vector3, Unit, Matrix4};
+use nalgebra::{Matrix4, Unit, UnitQuaternion, Vector3};
 
 use super::camera::Camera;
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CameraBuilder {
     pub position: Vector3<f32>,
     pub view_direction: Unit<Vector3<f32>>,
@@ -10,6 +11,9 @@ pub struct CameraBuilder {
     pub aspect: f32,
     pub near: f32,
     pub far: f32,
+    pub aperture: f32,
+    pub shutter_speed: f32,
+    pub iso: f32,
 }
 
 impl CameraBuilder {
@@ -35,8 +39,14 @@ impl CameraBuilder {
             aspect: self.aspect,
             near: self.near,
             far: self.far,
+            aperture: self.aperture,
+            shutter_speed: self.shutter_speed,
+            iso: self.iso,
             view_matrix: Matrix4::identity(),
             projection_matrix: Matrix4::identity(),
+            smoothing_half_life: None,
+            smoothed_position: self.position,
+            smoothed_orientation: UnitQuaternion::identity(),
         };
         cam.update_projection_matrix();
         cam.update_view_matrix();
@@ -68,6 +78,18 @@ impl CameraBuilder {
         self.far = far;
         self
     }
+    pub fn aperture(mut self, aperture: f32) -> CameraBuilder {
+        self.aperture = aperture.max(0.5);
+        self
+    }
+    pub fn shutter_speed(mut self, shutter_speed: f32) -> CameraBuilder {
+        self.shutter_speed = shutter_speed.max(1.0e-6);
+        self
+    }
+    pub fn iso(mut self, iso: f32) -> CameraBuilder {
+        self.iso = iso.max(1.0);
+        self
+    }
     pub fn view_direction(mut self, direction: Vector3<f32>) -> CameraBuilder {
         self.view_direction = Unit::new_normalize(direction);
         self
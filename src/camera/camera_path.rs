@@ -0,0 +1,151 @@
+use nalgebra::{UnitQuaternion, Vector3};
+
+use super::camera::Camera;
+use crate::curve::{catmull_rom, CatmullRom};
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub position: Vector3<f32>,
+    pub orientation: UnitQuaternion<f32>,
+}
+
+impl CameraKeyframe {
+    pub fn from_camera(time: f32, camera: &Camera) -> Self {
+        Self {
+            time,
+            position: camera.position,
+            orientation: camera.orientation(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+    pub time: f32,
+    pub speed: f32,
+    pub playing: bool,
+    pub looping: bool,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            time: 0.0,
+            speed: 1.0,
+            playing: false,
+            looping: false,
+        }
+    }
+
+    /// Inserts the keyframe keeping the list ordered by time.
+    pub fn add_keyframe(&mut self, keyframe: CameraKeyframe) {
+        let index = self
+            .keyframes
+            .partition_point(|other| other.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration());
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.time >= self.duration()
+    }
+
+    /// The keyframe positions as a curve, for drawing the path with
+    /// [`crate::model::Model::tube_from_curve`]. It ignores the keyframe times, so it
+    /// follows the same shape but not the same pace as [`CameraPath::sample`].
+    pub fn curve(&self) -> CatmullRom {
+        CatmullRom::new(
+            self.keyframes
+                .iter()
+                .map(|keyframe| keyframe.position)
+                .collect(),
+        )
+    }
+
+    /// Catmull-Rom interpolated position and slerped orientation at `time`.
+    pub fn sample(&self, time: f32) -> Option<(Vector3<f32>, UnitQuaternion<f32>)> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if time <= first.time {
+            return Some((first.position, first.orientation));
+        }
+        if time >= last.time {
+            return Some((last.position, last.orientation));
+        }
+
+        let i = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time)
+            - 1;
+        let k1 = &self.keyframes[i];
+        let k2 = &self.keyframes[i + 1];
+        let p0 = self.keyframes[i.saturating_sub(1)].position;
+        let p3 = self.keyframes[(i + 2).min(self.keyframes.len() - 1)].position;
+        let (p1, p2) = (k1.position, k2.position);
+
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 {
+            (time - k1.time) / span
+        } else {
+            0.0
+        };
+        let position = catmull_rom(p0, p1, p2, p3, t);
+        let orientation = k1
+            .orientation
+            .try_slerp(&k2.orientation, t, 1.0e-6)
+            .unwrap_or(k2.orientation);
+
+        Some((position, orientation))
+    }
+
+    pub fn update(&mut self, delta_time: f32, camera: &mut Camera) {
+        if self.playing {
+            self.time += delta_time * self.speed;
+            let duration = self.duration();
+            if self.time >= duration {
+                if self.looping && duration > 0.0 {
+                    self.time %= duration;
+                } else {
+                    self.time = duration;
+                    self.playing = false;
+                }
+            }
+        }
+        self.apply(camera);
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        if let Some((position, orientation)) = self.sample(self.time) {
+            camera.set_view(
+                position,
+                orientation * Vector3::z(),
+                orientation * Vector3::y(),
+            );
+        }
+    }
+}
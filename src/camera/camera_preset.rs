@@ -0,0 +1,21 @@
+use nalgebra::Vector3;
+
+/// Directory, relative to the working directory, that named presets are stored in.
+pub const PRESET_DIRECTORY: &str = "camera_presets";
+
+/// A bookmarked viewpoint. The far plane is `None` for an infinite projection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraPreset {
+    pub position: Vector3<f32>,
+    pub view_direction: Vector3<f32>,
+    pub down_direction: Vector3<f32>,
+    pub fovy: f32,
+    pub near: f32,
+    pub far: Option<f32>,
+}
+
+#[cfg(feature = "serde")]
+pub fn preset_path(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(PRESET_DIRECTORY).join(format!("{}.json", name))
+}
@@ -0,0 +1,98 @@
+use nalgebra::{Matrix4, Rotation3, Translation3};
+
+use super::camera::Camera;
+
+/// Trauma-based shake: events add trauma, which decays over time, and the shake
+/// strength is trauma squared so small hits stay subtle.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraShake {
+    pub trauma: f32,
+    /// Trauma removed per second.
+    pub decay: f32,
+    pub max_offset: f32,
+    /// Maximum yaw, pitch and roll in radians.
+    pub max_angle: f32,
+    /// Noise samples per second; higher values shake more violently.
+    pub frequency: f32,
+    pub seed: u32,
+    pub time: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraShake {
+    pub fn new() -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 1.0,
+            max_offset: 0.1,
+            max_angle: 0.05,
+            frequency: 15.0,
+            seed: 0,
+            time: 0.0,
+        }
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.time += delta_time;
+        self.trauma = (self.trauma - self.decay * delta_time).max(0.0);
+    }
+
+    /// Camera-space transform to apply this frame.
+    pub fn offset(&self) -> Matrix4<f32> {
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.frequency;
+        let channel = |i: u32| shake * value_noise(self.seed.wrapping_add(i), t);
+
+        let translation = Translation3::new(
+            self.max_offset * channel(0),
+            self.max_offset * channel(1),
+            self.max_offset * channel(2),
+        );
+        let rotation = Rotation3::from_euler_angles(
+            self.max_angle * channel(3),
+            self.max_angle * channel(4),
+            self.max_angle * channel(5),
+        );
+        translation.to_homogeneous() * rotation.to_homogeneous()
+    }
+
+    /// Rebuilds the camera's view matrix from its current state and adds the shake,
+    /// so whatever controller moved the camera this frame keeps working unchanged.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.update_view_matrix();
+        if self.trauma > 0.0 {
+            let inverse_offset = self
+                .offset()
+                .try_inverse()
+                .unwrap_or_else(Matrix4::identity);
+            camera.view_matrix = inverse_offset * camera.view_matrix;
+        }
+    }
+}
+
+fn hash(seed: u32, x: i32) -> f32 {
+    let mut h = seed.wrapping_mul(0x9E37_79B9) ^ (x as u32).wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xC2B2_AE35);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// Smoothly interpolated 1D value noise in [-1, 1].
+fn value_noise(seed: u32, t: f32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let smooth = f * f * (3.0 - 2.0 * f);
+    let a = hash(seed, i as i32);
+    let b = hash(seed, i as i32 + 1);
+    a + (b - a) * smooth
+}
@@ -0,0 +1,73 @@
+use crate::input::{Action, Input};
+
+use super::camera::Camera;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlyController {
+    /// Movement speed in units per second.
+    pub speed: f32,
+    /// Turning speed in radians per second for key/stick driven turning.
+    pub turn_speed: f32,
+    pub mouse_sensitivity: f32,
+    pub fast_multiplier: f32,
+    pub slow_multiplier: f32,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlyController {
+    pub fn new() -> Self {
+        Self {
+            speed: 1.0,
+            turn_speed: 1.5,
+            mouse_sensitivity: 0.002,
+            fast_multiplier: 4.0,
+            slow_multiplier: 0.25,
+        }
+    }
+
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn turn_speed(mut self, turn_speed: f32) -> Self {
+        self.turn_speed = turn_speed;
+        self
+    }
+
+    pub fn mouse_sensitivity(mut self, mouse_sensitivity: f32) -> Self {
+        self.mouse_sensitivity = mouse_sensitivity;
+        self
+    }
+
+    /// Moves and turns the camera from the current input state; `delta_time` is in seconds.
+    pub fn update(&self, input: &Input, camera: &mut Camera, delta_time: f32) {
+        let mut speed = self.speed;
+        if input.is_action_pressed(Action::SpeedUp) {
+            speed *= self.fast_multiplier;
+        }
+        if input.is_action_pressed(Action::SlowDown) {
+            speed *= self.slow_multiplier;
+        }
+        let distance = speed * delta_time;
+        let angle = self.turn_speed * delta_time;
+
+        let axis = |positive: Action, negative: Action| {
+            input.action_value(positive) - input.action_value(negative)
+        };
+        camera.move_forward(distance * axis(Action::MoveForward, Action::MoveBackward));
+        camera.move_right(distance * axis(Action::MoveRight, Action::MoveLeft));
+        camera.move_up(distance * axis(Action::MoveUp, Action::MoveDown));
+        camera.turn_right(angle * axis(Action::TurnRight, Action::TurnLeft));
+        camera.turn_up(angle * axis(Action::TurnUp, Action::TurnDown));
+
+        if input.cursor_captured {
+            camera.rotate_by_mouse_delta(input.mouse_delta, self.mouse_sensitivity);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use nalgebra::Vector3;
+
+use crate::model::{Aabb, Instance, Model, VertexData};
+
+use super::camera::Camera;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FollowController {
+    /// Handle of the followed instance.
+    pub target: usize,
+    /// World-space offset from the target to the desired camera position.
+    pub offset: Vector3<f32>,
+    /// Half-life in seconds of the camera catching up with the desired position.
+    pub lag: f32,
+    /// Distance kept between the camera and obstacles blocking the view.
+    pub collision_padding: f32,
+    pub current_position: Option<Vector3<f32>>,
+}
+
+impl FollowController {
+    pub fn new(target: usize, offset: Vector3<f32>) -> Self {
+        Self {
+            target,
+            offset,
+            lag: 0.2,
+            collision_padding: 0.05,
+            current_position: None,
+        }
+    }
+
+    /// Camera position as close to `desired` as possible without an obstacle
+    /// between it and the target. Boxes containing the target are ignored.
+    pub fn pull_back(
+        &self,
+        target: &Vector3<f32>,
+        desired: &Vector3<f32>,
+        obstacles: &[Aabb],
+    ) -> Vector3<f32> {
+        let direction = desired - target;
+        let distance = direction.norm();
+        if distance <= f32::EPSILON {
+            return *desired;
+        }
+        let direction = direction / distance;
+        let hit = obstacles
+            .iter()
+            .filter(|aabb| !aabb.contains(target))
+            .filter_map(|aabb| aabb.ray_intersection(target, &direction))
+            .fold(distance, f32::min);
+
+        target + direction * (hit - self.collision_padding).clamp(0.0, distance)
+    }
+
+    pub fn update<I: Instance>(
+        &mut self,
+        model: &Model<VertexData, I>,
+        obstacles: &[Aabb],
+        camera: &mut Camera,
+        delta_time: f32,
+    ) {
+        let Some(instance) = model.instances.get(self.target) else {
+            return;
+        };
+        let target = Vector3::from(instance.base().position());
+        let desired = self.pull_back(&target, &(target + self.offset), obstacles);
+
+        let position = match self.current_position {
+            Some(current) if self.lag > 0.0 => {
+                let t = 1.0 - 0.5_f32.powf(delta_time / self.lag);
+                current.lerp(&desired, t)
+            }
+            _ => desired,
+        };
+        self.current_position = Some(position);
+
+        let view_direction = target - position;
+        if view_direction.norm() > f32::EPSILON {
+            let down = if view_direction.cross(&Vector3::y()).norm() > f32::EPSILON {
+                Vector3::y()
+            } else {
+                *camera.down_direction
+            };
+            camera.set_view(position, view_direction, down);
+        }
+    }
+}
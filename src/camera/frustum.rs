@@ -0,0 +1,99 @@
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+/// Plane `normal · p + distance = 0`, with the normal pointing into the frustum.
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = row.xyz();
+        let length = normal.norm();
+        if length <= f32::EPSILON {
+            // The far plane of an infinite projection; it never rejects anything.
+            return Self {
+                normal: Vector3::zeros(),
+                distance: row.w.abs(),
+            };
+        }
+        Self {
+            normal: normal / length,
+            distance: row.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: &Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    /// Left, right, top, bottom, near, far.
+    pub planes: [Plane; 6],
+    /// Near corners followed by far corners, each as
+    /// (-x, -y), (+x, -y), (+x, +y), (-x, +y) in normalized device coordinates.
+    /// The far corners are not finite for an infinite far plane.
+    pub corners: [Vector3<f32>; 8],
+}
+
+impl Frustum {
+    /// Extracts the planes of a Vulkan-style (depth 0 to 1) view-projection matrix.
+    pub fn from_matrix(view_projection: &Matrix4<f32>) -> Self {
+        let r0 = view_projection.row(0).transpose();
+        let r1 = view_projection.row(1).transpose();
+        let r2 = view_projection.row(2).transpose();
+        let r3 = view_projection.row(3).transpose();
+        let planes = [
+            Plane::from_row(r3 + r0),
+            Plane::from_row(r3 - r0),
+            Plane::from_row(r3 + r1),
+            Plane::from_row(r3 - r1),
+            Plane::from_row(r2),
+            Plane::from_row(r3 - r2),
+        ];
+
+        let inverse = view_projection
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let mut corners = [Vector3::zeros(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let z = if i < 4 { 0.0 } else { 1.0 };
+            let (x, y) = match i % 4 {
+                0 => (-1.0, -1.0),
+                1 => (1.0, -1.0),
+                2 => (1.0, 1.0),
+                _ => (-1.0, 1.0),
+            };
+            *corner = inverse.transform_point(&Point3::new(x, y, z)).coords;
+        }
+
+        Self { planes, corners }
+    }
+
+    pub fn contains_point(&self, point: &Vector3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: &Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(center) >= -radius)
+    }
+
+    /// Conservative test: may report boxes near frustum edges as visible.
+    pub fn intersects_aabb(&self, min: &Vector3<f32>, max: &Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let positive_vertex = Vector3::new(
+                if plane.normal.x >= 0.0 { max.x } else { min.x },
+                if plane.normal.y >= 0.0 { max.y } else { min.y },
+                if plane.normal.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.signed_distance(&positive_vertex) >= 0.0
+        })
+    }
+}
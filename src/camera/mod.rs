@@ -1,5 +1,21 @@
+mod arcball_controller;
 mod camera;
 mod camera_builder;
+mod camera_path;
+mod camera_preset;
+mod camera_shake;
+mod fly_controller;
+mod follow_controller;
+mod frustum;
+mod orbit_controller;
 
+pub use arcball_controller::ArcballController;
 pub use camera::Camera;
 pub use camera_builder::CameraBuilder;
+pub use camera_path::{CameraKeyframe, CameraPath};
+pub use camera_preset::{CameraPreset, PRESET_DIRECTORY};
+pub use camera_shake::CameraShake;
+pub use fly_controller::FlyController;
+pub use follow_controller::FollowController;
+pub use frustum::{Frustum, Plane};
+pub use orbit_controller::OrbitController;
@@ -0,0 +1,92 @@
+use std::f32::consts::FRAC_PI_2;
+
+use nalgebra::Vector3;
+use winit::event::MouseButton;
+
+use crate::input::Input;
+
+use super::camera::Camera;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrbitController {
+    pub target: Vector3<f32>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub rotate_sensitivity: f32,
+    pub pan_sensitivity: f32,
+    pub zoom_sensitivity: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: Vector3<f32>, distance: f32) -> Self {
+        Self {
+            target,
+            distance,
+            yaw: 0.0,
+            pitch: std::f32::consts::FRAC_PI_4,
+            min_distance: 0.1,
+            max_distance: 100.,
+            rotate_sensitivity: 0.005,
+            pan_sensitivity: 0.001,
+            zoom_sensitivity: 0.1,
+        }
+    }
+
+    /// Unit vector from the camera towards the target. The world is y-down,
+    /// so a positive pitch looks down onto the target.
+    pub fn forward(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    pub fn down(&self) -> Vector3<f32> {
+        Vector3::new(
+            -self.pitch.sin() * self.yaw.sin(),
+            self.pitch.cos(),
+            -self.pitch.sin() * self.yaw.cos(),
+        )
+    }
+
+    pub fn rotate(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        self.yaw += delta_yaw;
+        self.pitch = (self.pitch + delta_pitch).clamp(-FRAC_PI_2 + 0.01, FRAC_PI_2 - 0.01);
+    }
+
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let forward = self.forward();
+        let down = self.down();
+        let right = down.cross(&forward);
+        self.target -= (right * dx + down * dy) * self.distance;
+    }
+
+    pub fn zoom(&mut self, amount: f32) {
+        self.distance =
+            (self.distance * (-amount).exp()).clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Left-drag orbits, middle-drag pans and the scroll wheel zooms.
+    pub fn update(&mut self, input: &Input, camera: &mut Camera) {
+        let (dx, dy) = (input.mouse_delta.0 as f32, input.mouse_delta.1 as f32);
+        if input.is_button_pressed(MouseButton::Left) {
+            self.rotate(dx * self.rotate_sensitivity, dy * self.rotate_sensitivity);
+        }
+        if input.is_button_pressed(MouseButton::Middle) {
+            self.pan(dx * self.pan_sensitivity, dy * self.pan_sensitivity);
+        }
+        if input.scroll_delta != 0.0 {
+            self.zoom(input.scroll_delta * self.zoom_sensitivity);
+        }
+        self.apply(camera);
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        let forward = self.forward();
+        camera.set_view(self.target - self.distance * forward, forward, self.down());
+    }
+}
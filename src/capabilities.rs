@@ -0,0 +1,328 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::device_extension_supported;
+
+/// Highest Vulkan version the engine knows how to use.
+pub const MAX_API_VERSION: u32 = vk::API_VERSION_1_3;
+
+/// Never core; acceleration structures need deferred host operations even when built on
+/// the device.
+const RAY_QUERY_EXTENSIONS: [fn() -> &'static std::ffi::CStr; 3] = [
+    vk::KhrAccelerationStructureFn::name,
+    vk::KhrRayQueryFn::name,
+    vk::KhrDeferredHostOperationsFn::name,
+];
+
+const GRAPHICS_PIPELINE_LIBRARY_EXTENSIONS: [fn() -> &'static std::ffi::CStr; 2] = [
+    vk::KhrPipelineLibraryFn::name,
+    vk::ExtGraphicsPipelineLibraryFn::name,
+];
+
+/// Vulkan version and optional features available on the chosen device. Each feature
+/// is core from some version and an extension before it; both routes are checked, and
+/// whatever is found is enabled at device creation.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeviceCapabilities {
+    /// The lower of the instance's and the device's API version.
+    pub api_version: u32,
+    pub separate_depth_stencil_layouts: bool,
+    pub timeline_semaphore: bool,
+    pub synchronization2: bool,
+    pub dynamic_rendering: bool,
+    /// Task and mesh shaders from `VK_EXT_mesh_shader`, which is never core. Needs 1.2
+    /// for the SPIR-V 1.4 its shaders are written in.
+    pub mesh_shader: bool,
+    /// `VK_KHR_ray_query` with the acceleration structures it traces against, plus the
+    /// buffer device addresses they are built from. Needs 1.2, like `mesh_shader`.
+    pub ray_query: bool,
+    /// `VK_KHR_push_descriptor`, never core, for pushing bindings straight into a command
+    /// buffer; see [`crate::push_descriptor::PushDescriptors`].
+    pub push_descriptor: bool,
+    /// `VK_EXT_graphics_pipeline_library`, never core, for compiling pipelines in parts
+    /// and linking them; see [`crate::pipeline::PipelineLibrary`].
+    pub graphics_pipeline_library: bool,
+    /// `VK_KHR_draw_indirect_count`, letting the GPU decide how many indirect draws run.
+    /// Core in 1.2, but only behind a `Vulkan12Features` flag that cannot be enabled
+    /// alongside the per-feature structs used here, so the extension is what is enabled.
+    pub draw_indirect_count: bool,
+    /// `VK_EXT_conditional_rendering`, never core, for skipping draws on a value in a
+    /// buffer; see [`crate::conditional_rendering::ConditionalRendering`].
+    pub conditional_rendering: bool,
+    /// `VK_EXT_device_fault`, never core, for describing why the device was lost; see
+    /// [`crate::crash_diagnostics::CrashDiagnostics`], like the two below.
+    pub device_fault: bool,
+    /// `VK_NV_device_diagnostic_checkpoints`, for markers each pipeline stage reports.
+    pub diagnostic_checkpoints: bool,
+    /// `VK_AMD_buffer_marker`, for markers written to memory as commands execute.
+    pub buffer_marker: bool,
+    /// `VK_EXT_robustness2`'s features the device has: bounds-checked buffer and image
+    /// access, and null descriptors. Costs performance, so [`crate::krakatoa::Krakatoa`]
+    /// only keeps it when asked for with
+    /// [`crate::krakatoa_builder::KrakatoaBuilder::robustness`].
+    pub robustness2: Option<vk::PhysicalDeviceRobustness2FeaturesEXT>,
+    /// Set on portability implementations such as MoltenVK, which must enable
+    /// `VK_KHR_portability_subset`; lists which of its features the device has.
+    pub portability_subset: Option<vk::PhysicalDevicePortabilitySubsetFeaturesKHR>,
+}
+
+impl DeviceCapabilities {
+    pub fn query(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        instance_api_version: u32,
+    ) -> Result<Self> {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let api_version = instance_api_version.min(properties.api_version);
+        let portability = device_extension_supported(
+            instance,
+            physical_device,
+            vk::KhrPortabilitySubsetFn::name(),
+        )?;
+        // Feature queries through `vkGetPhysicalDeviceFeatures2` need 1.1; without it,
+        // assume a portability device lacks every subset feature.
+        if api_version < vk::API_VERSION_1_1 {
+            return Ok(Self {
+                api_version,
+                portability_subset: portability
+                    .then(vk::PhysicalDevicePortabilitySubsetFeaturesKHR::default),
+                ..Default::default()
+            });
+        }
+
+        let available = |core: u32, extension: &std::ffi::CStr| -> Result<bool> {
+            Ok(api_version >= core
+                || device_extension_supported(instance, physical_device, extension)?)
+        };
+        let mut separate_depth_stencil_layouts =
+            vk::PhysicalDeviceSeparateDepthStencilLayoutsFeatures::default();
+        let mut timeline_semaphore = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut synchronization2 = vk::PhysicalDeviceSynchronization2Features::default();
+        let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut mesh_shader = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
+        let mut acceleration_structure =
+            vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+        let mut ray_query = vk::PhysicalDeviceRayQueryFeaturesKHR::default();
+        let mut buffer_device_address = vk::PhysicalDeviceBufferDeviceAddressFeatures::default();
+        let mut graphics_pipeline_library =
+            vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT::default();
+        let mut conditional_rendering =
+            vk::PhysicalDeviceConditionalRenderingFeaturesEXT::default();
+        let mut device_fault = vk::PhysicalDeviceFaultFeaturesEXT::default();
+        let robustness2_extension =
+            device_extension_supported(instance, physical_device, vk::ExtRobustness2Fn::name())?;
+        let mut robustness2 = vk::PhysicalDeviceRobustness2FeaturesEXT::default();
+        let mut portability_subset = vk::PhysicalDevicePortabilitySubsetFeaturesKHR::default();
+        let mut features = vk::PhysicalDeviceFeatures2::builder();
+        if portability {
+            features = features.push_next(&mut portability_subset);
+        }
+        if available(
+            vk::API_VERSION_1_2,
+            vk::KhrSeparateDepthStencilLayoutsFn::name(),
+        )? {
+            features = features.push_next(&mut separate_depth_stencil_layouts);
+        }
+        if available(vk::API_VERSION_1_2, vk::KhrTimelineSemaphoreFn::name())? {
+            features = features.push_next(&mut timeline_semaphore);
+        }
+        if available(vk::API_VERSION_1_3, vk::KhrSynchronization2Fn::name())? {
+            features = features.push_next(&mut synchronization2);
+        }
+        if available(vk::API_VERSION_1_3, vk::KhrDynamicRenderingFn::name())? {
+            features = features.push_next(&mut dynamic_rendering);
+        }
+        if api_version >= vk::API_VERSION_1_2
+            && device_extension_supported(instance, physical_device, vk::ExtMeshShaderFn::name())?
+        {
+            features = features.push_next(&mut mesh_shader);
+        }
+        let mut ray_query_extensions = true;
+        for extension in RAY_QUERY_EXTENSIONS {
+            ray_query_extensions &=
+                device_extension_supported(instance, physical_device, extension())?;
+        }
+        if api_version >= vk::API_VERSION_1_2 && ray_query_extensions {
+            features = features
+                .push_next(&mut acceleration_structure)
+                .push_next(&mut ray_query)
+                .push_next(&mut buffer_device_address);
+        }
+        let mut graphics_pipeline_library_extensions = true;
+        for extension in GRAPHICS_PIPELINE_LIBRARY_EXTENSIONS {
+            graphics_pipeline_library_extensions &=
+                device_extension_supported(instance, physical_device, extension())?;
+        }
+        if graphics_pipeline_library_extensions {
+            features = features.push_next(&mut graphics_pipeline_library);
+        }
+        if device_extension_supported(
+            instance,
+            physical_device,
+            vk::ExtConditionalRenderingFn::name(),
+        )? {
+            features = features.push_next(&mut conditional_rendering);
+        }
+        if device_extension_supported(instance, physical_device, vk::ExtDeviceFaultFn::name())? {
+            features = features.push_next(&mut device_fault);
+        }
+        if robustness2_extension {
+            features = features.push_next(&mut robustness2);
+        }
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        portability_subset.p_next = std::ptr::null_mut();
+        robustness2.p_next = std::ptr::null_mut();
+
+        Ok(Self {
+            api_version,
+            separate_depth_stencil_layouts: separate_depth_stencil_layouts
+                .separate_depth_stencil_layouts
+                == vk::TRUE,
+            timeline_semaphore: timeline_semaphore.timeline_semaphore == vk::TRUE,
+            synchronization2: synchronization2.synchronization2 == vk::TRUE,
+            dynamic_rendering: dynamic_rendering.dynamic_rendering == vk::TRUE,
+            mesh_shader: mesh_shader.task_shader == vk::TRUE && mesh_shader.mesh_shader == vk::TRUE,
+            push_descriptor: device_extension_supported(
+                instance,
+                physical_device,
+                vk::KhrPushDescriptorFn::name(),
+            )?,
+            ray_query: acceleration_structure.acceleration_structure == vk::TRUE
+                && ray_query.ray_query == vk::TRUE
+                && buffer_device_address.buffer_device_address == vk::TRUE,
+            graphics_pipeline_library: graphics_pipeline_library.graphics_pipeline_library
+                == vk::TRUE,
+            draw_indirect_count: device_extension_supported(
+                instance,
+                physical_device,
+                vk::KhrDrawIndirectCountFn::name(),
+            )?,
+            conditional_rendering: conditional_rendering.conditional_rendering == vk::TRUE,
+            device_fault: device_fault.device_fault == vk::TRUE,
+            diagnostic_checkpoints: device_extension_supported(
+                instance,
+                physical_device,
+                vk::NvDeviceDiagnosticCheckpointsFn::name(),
+            )?,
+            buffer_marker: device_extension_supported(
+                instance,
+                physical_device,
+                vk::AmdBufferMarkerFn::name(),
+            )?,
+            robustness2: robustness2_extension.then_some(robustness2),
+            portability_subset: portability.then_some(portability_subset),
+        })
+    }
+
+    /// Names of the `VK_KHR_portability_subset` features the device lacks, so pipelines
+    /// can avoid them; empty on devices that implement all of Vulkan.
+    pub fn missing_portability_features(&self) -> Vec<&'static str> {
+        let Some(subset) = &self.portability_subset else {
+            return vec![];
+        };
+        [
+            (
+                subset.constant_alpha_color_blend_factors,
+                "constantAlphaColorBlendFactors",
+            ),
+            (subset.events, "events"),
+            (
+                subset.image_view_format_reinterpretation,
+                "imageViewFormatReinterpretation",
+            ),
+            (subset.image_view_format_swizzle, "imageViewFormatSwizzle"),
+            (subset.image_view2_d_on3_d_image, "imageView2DOn3DImage"),
+            (subset.multisample_array_image, "multisampleArrayImage"),
+            (
+                subset.mutable_comparison_samplers,
+                "mutableComparisonSamplers",
+            ),
+            (subset.point_polygons, "pointPolygons"),
+            (subset.sampler_mip_lod_bias, "samplerMipLodBias"),
+            (subset.separate_stencil_mask_ref, "separateStencilMaskRef"),
+            (
+                subset.shader_sample_rate_interpolation_functions,
+                "shaderSampleRateInterpolationFunctions",
+            ),
+            (subset.tessellation_isolines, "tessellationIsolines"),
+            (subset.tessellation_point_mode, "tessellationPointMode"),
+            (subset.triangle_fans, "triangleFans"),
+            (
+                subset.vertex_attribute_access_beyond_stride,
+                "vertexAttributeAccessBeyondStride",
+            ),
+        ]
+        .into_iter()
+        .filter(|(supported, _)| *supported != vk::TRUE)
+        .map(|(_, name)| name)
+        .collect()
+    }
+
+    /// Device extensions providing the available features the API version lacks, plus
+    /// `VK_EXT_mesh_shader`, the ray query extensions, `VK_KHR_push_descriptor`, the
+    /// graphics pipeline library extensions, `VK_KHR_draw_indirect_count`,
+    /// `VK_EXT_conditional_rendering`, the crash diagnostics extensions,
+    /// `VK_EXT_robustness2` and `VK_KHR_portability_subset` where they are used.
+    pub fn extensions(&self) -> Vec<&'static std::ffi::CStr> {
+        let mut extensions: Vec<_> = [
+            (
+                self.separate_depth_stencil_layouts,
+                vk::API_VERSION_1_2,
+                vk::KhrSeparateDepthStencilLayoutsFn::name(),
+            ),
+            (
+                self.timeline_semaphore,
+                vk::API_VERSION_1_2,
+                vk::KhrTimelineSemaphoreFn::name(),
+            ),
+            (
+                self.synchronization2,
+                vk::API_VERSION_1_3,
+                vk::KhrSynchronization2Fn::name(),
+            ),
+            (
+                self.dynamic_rendering,
+                vk::API_VERSION_1_3,
+                vk::KhrDynamicRenderingFn::name(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(enabled, core, _)| *enabled && self.api_version < *core)
+        .map(|(_, _, name)| name)
+        .collect();
+        if self.mesh_shader {
+            extensions.push(vk::ExtMeshShaderFn::name());
+        }
+        if self.ray_query {
+            extensions.extend(RAY_QUERY_EXTENSIONS.map(|name| name()));
+        }
+        if self.push_descriptor {
+            extensions.push(vk::KhrPushDescriptorFn::name());
+        }
+        if self.graphics_pipeline_library {
+            extensions.extend(GRAPHICS_PIPELINE_LIBRARY_EXTENSIONS.map(|name| name()));
+        }
+        if self.draw_indirect_count {
+            extensions.push(vk::KhrDrawIndirectCountFn::name());
+        }
+        if self.conditional_rendering {
+            extensions.push(vk::ExtConditionalRenderingFn::name());
+        }
+        if self.device_fault {
+            extensions.push(vk::ExtDeviceFaultFn::name());
+        }
+        if self.diagnostic_checkpoints {
+            extensions.push(vk::NvDeviceDiagnosticCheckpointsFn::name());
+        }
+        if self.buffer_marker {
+            extensions.push(vk::AmdBufferMarkerFn::name());
+        }
+        if self.robustness2.is_some() {
+            extensions.push(vk::ExtRobustness2Fn::name());
+        }
+        if self.portability_subset.is_some() {
+            extensions.push(vk::KhrPortabilitySubsetFn::name());
+        }
+        extensions
+    }
+}
@@ -0,0 +1,54 @@
+use ash::vk;
+
+/// What the scene's attachments are cleared to at the start of every frame.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClearValues {
+    /// Background where nothing is drawn, in the space the fragment shaders output.
+    /// Covered by the sky when it is enabled.
+    pub colour: [f32; 4],
+    /// 1 is the far plane; the depth tests pass for anything nearer than this.
+    pub depth: f32,
+    /// Only used when the depth format has a stencil aspect.
+    pub stencil: u32,
+}
+
+impl Default for ClearValues {
+    fn default() -> Self {
+        Self {
+            colour: [0.4, 0.5, 0.6, 1.0],
+            depth: 1.0,
+            stencil: 0,
+        }
+    }
+}
+
+impl ClearValues {
+    /// One value per attachment of the main render pass, in attachment order. The
+    /// order-independent transparency targets always start from no coverage.
+    pub fn to_render_pass(&self) -> [vk::ClearValue; 4] {
+        [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.colour,
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: self.depth,
+                    stencil: self.stencil,
+                },
+            },
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 0.0],
+                },
+            },
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [1.0, 0.0, 0.0, 0.0],
+                },
+            },
+        ]
+    }
+}
@@ -0,0 +1,71 @@
+use ash::vk;
+
+/// Predicates draws and dispatches on a 32-bit value the GPU wrote to a buffer
+/// (`VK_EXT_conditional_rendering`), such as an occlusion query result copied out or a
+/// count from a culling pass, so they are skipped without reading the value back. The
+/// buffer needs `CONDITIONAL_RENDERING_EXT` usage, and a barrier to
+/// `CONDITIONAL_RENDERING_READ_EXT` after whatever wrote it.
+#[derive(Clone)]
+pub struct ConditionalRendering {
+    pub fp: vk::ExtConditionalRenderingFn,
+}
+
+impl ConditionalRendering {
+    pub fn init(instance: &ash::Instance, logical_device: &ash::Device) -> Self {
+        let handle = logical_device.handle();
+        let fp = vk::ExtConditionalRenderingFn::load(|name| unsafe {
+            std::mem::transmute(instance.get_device_proc_addr(handle, name.as_ptr()))
+        });
+        Self { fp }
+    }
+
+    /// Skips what is recorded until [`ConditionalRendering::end`] when the `u32` at
+    /// `offset` in `buffer` is 0, or when it is not with `inverted`.
+    ///# Safety
+    ///
+    /// `offset` must be a multiple of 4, and a block begun inside a render pass instance
+    /// must end in the same subpass.
+    pub unsafe fn begin(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        inverted: bool,
+    ) {
+        let flags = if inverted {
+            vk::ConditionalRenderingFlagsEXT::INVERTED
+        } else {
+            vk::ConditionalRenderingFlagsEXT::empty()
+        };
+        let begin_info = vk::ConditionalRenderingBeginInfoEXT::builder()
+            .buffer(buffer)
+            .offset(offset)
+            .flags(flags);
+        (self.fp.cmd_begin_conditional_rendering_ext)(command_buffer, &*begin_info);
+    }
+
+    ///# Safety
+    ///
+    /// Must follow a [`ConditionalRendering::begin`] in the same subpass, or outside a
+    /// render pass if it began there.
+    pub unsafe fn end(&self, command_buffer: vk::CommandBuffer) {
+        (self.fp.cmd_end_conditional_rendering_ext)(command_buffer);
+    }
+
+    /// Records `record` between a [`ConditionalRendering::begin`] and an end.
+    ///# Safety
+    ///
+    /// As for [`ConditionalRendering::begin`]; `record` must not leave the subpass.
+    pub unsafe fn predicated(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        inverted: bool,
+        record: impl FnOnce(),
+    ) {
+        self.begin(command_buffer, buffer, offset, inverted);
+        record();
+        self.end(command_buffer);
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+use std::sync::Mutex;
+
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::buffer::Buffer;
+use crate::capabilities::DeviceCapabilities;
+
+/// What the device could tell about how it was lost. Passes are named as
+/// [`crate::frame_graph::FramePass::name`]s.
+#[derive(Clone, Debug, Default)]
+pub struct CrashReport {
+    /// The driver's description of the fault, from `VK_EXT_device_fault`.
+    pub description: Option<String>,
+    /// Addresses involved in the fault and what was done with them.
+    pub addresses: Vec<String>,
+    /// Vendor-specific fault codes with their descriptions.
+    pub vendor_faults: Vec<String>,
+    /// The last pass each pipeline stage reached, from `VK_NV_device_diagnostic_checkpoints`.
+    pub checkpoints: Vec<(vk::PipelineStageFlags, &'static str)>,
+    /// The last pass whose commands began executing, from `VK_AMD_buffer_marker`.
+    pub last_started: Option<&'static str>,
+    /// The last pass whose commands all finished, from `VK_AMD_buffer_marker`.
+    pub last_finished: Option<&'static str>,
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Device lost")?;
+        if let Some(description) = &self.description {
+            write!(f, ": {}", description)?;
+        }
+        if let Some(pass) = self.last_started {
+            write!(f, "\n  last pass started: {}", pass)?;
+        }
+        if let Some(pass) = self.last_finished {
+            write!(f, "\n  last pass finished: {}", pass)?;
+        }
+        for (stage, pass) in &self.checkpoints {
+            write!(f, "\n  {:?} reached: {}", stage, pass)?;
+        }
+        for address in &self.addresses {
+            write!(f, "\n  address: {}", address)?;
+        }
+        for fault in &self.vendor_faults {
+            write!(f, "\n  vendor fault: {}", fault)?;
+        }
+        std::fmt::Result::Ok(())
+    }
+}
+
+/// Leaves markers in command buffers as each pass begins, through whichever of
+/// `VK_NV_device_diagnostic_checkpoints` and `VK_AMD_buffer_marker` the device has, and
+/// collects them with `VK_EXT_device_fault`'s description once the device is lost.
+pub struct CrashDiagnostics {
+    device: vk::Device,
+    device_fault: Option<vk::ExtDeviceFaultFn>,
+    checkpoints: Option<ash::extensions::nv::DeviceDiagnosticCheckpoints>,
+    buffer_marker: Option<vk::AmdBufferMarkerFn>,
+    /// The last pass started, then the last finished, as indices into `passes` plus one.
+    markers: Option<Buffer>,
+    /// Every pass marked so far; markers refer to them by index.
+    passes: Mutex<Vec<&'static str>>,
+}
+
+impl CrashDiagnostics {
+    pub fn init(
+        instance: &ash::Instance,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        capabilities: &DeviceCapabilities,
+    ) -> Result<Self> {
+        if !(capabilities.device_fault
+            || capabilities.diagnostic_checkpoints
+            || capabilities.buffer_marker)
+        {
+            bail!("The device has no crash diagnostics extensions.");
+        }
+        let handle = logical_device.handle();
+        let device_fault = capabilities.device_fault.then(|| {
+            vk::ExtDeviceFaultFn::load(|name| unsafe {
+                std::mem::transmute(instance.get_device_proc_addr(handle, name.as_ptr()))
+            })
+        });
+        let checkpoints = capabilities.diagnostic_checkpoints.then(|| {
+            ash::extensions::nv::DeviceDiagnosticCheckpoints::new(instance, logical_device)
+        });
+        let buffer_marker = capabilities.buffer_marker.then(|| {
+            vk::AmdBufferMarkerFn::load(|name| unsafe {
+                std::mem::transmute(instance.get_device_proc_addr(handle, name.as_ptr()))
+            })
+        });
+        let markers = match buffer_marker {
+            Some(_) => {
+                let mut markers = Buffer::init(
+                    8,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    memory_properties,
+                    logical_device,
+                )?;
+                markers.fill(logical_device, &[0u32, 0], memory_properties)?;
+                markers.set_name(logical_device, "crash markers");
+                Some(markers)
+            }
+            None => None,
+        };
+        Ok(Self {
+            device: handle,
+            device_fault,
+            checkpoints,
+            buffer_marker,
+            markers,
+            passes: Mutex::new(vec![]),
+        })
+    }
+
+    /// Records that `pass` begins here.
+    ///# Safety
+    ///
+    /// `command_buffer` must be recording.
+    pub unsafe fn mark(&self, command_buffer: vk::CommandBuffer, pass: &'static str) {
+        let marker = {
+            let mut passes = self.passes.lock().unwrap_or_else(|e| e.into_inner());
+            let index = match passes.iter().position(|known| *known == pass) {
+                Some(index) => index,
+                None => {
+                    passes.push(pass);
+                    passes.len() - 1
+                }
+            };
+            index as u32 + 1
+        };
+        if let Some(checkpoints) = &self.checkpoints {
+            checkpoints.cmd_set_checkpoint(command_buffer, marker as usize as *const _);
+        }
+        if let (Some(buffer_marker), Some(markers)) = (&self.buffer_marker, &self.markers) {
+            let write = buffer_marker.cmd_write_buffer_marker_amd;
+            write(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                markers.buffer,
+                0,
+                marker,
+            );
+            write(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                markers.buffer,
+                4,
+                marker,
+            );
+        }
+    }
+
+    /// What the device left behind; only meaningful once it reported `ERROR_DEVICE_LOST`.
+    pub fn report(&self, logical_device: &ash::Device, queue: vk::Queue) -> CrashReport {
+        let passes = self.passes.lock().unwrap_or_else(|e| e.into_inner());
+        let pass = |marker: u32| {
+            (marker as usize)
+                .checked_sub(1)
+                .and_then(|index| passes.get(index).copied())
+        };
+        let mut report = CrashReport::default();
+
+        if let Some(checkpoints) = &self.checkpoints {
+            unsafe {
+                let mut data = vec![
+                    vk::CheckpointDataNV::default();
+                    checkpoints.get_queue_checkpoint_data_len(queue)
+                ];
+                checkpoints.get_queue_checkpoint_data(queue, &mut data);
+                report.checkpoints = data
+                    .iter()
+                    .filter_map(|checkpoint| {
+                        pass(checkpoint.p_checkpoint_marker as usize as u32)
+                            .map(|pass| (checkpoint.stage, pass))
+                    })
+                    .collect();
+            }
+        }
+
+        if let Some(markers) = &self.markers {
+            // The memory is host coherent, so it can be read without the lost device.
+            let values = unsafe {
+                logical_device
+                    .map_memory(markers.memory, 0, 8, vk::MemoryMapFlags::empty())
+                    .map(|data| {
+                        let values = *(data as *const [u32; 2]);
+                        logical_device.unmap_memory(markers.memory);
+                        values
+                    })
+            };
+            if let std::result::Result::Ok([started, finished]) = values {
+                report.last_started = pass(started);
+                report.last_finished = pass(finished);
+            }
+        }
+
+        if let Some(device_fault) = &self.device_fault {
+            let mut counts = vk::DeviceFaultCountsEXT::default();
+            let counted = unsafe {
+                (device_fault.get_device_fault_info_ext)(
+                    self.device,
+                    &mut counts,
+                    std::ptr::null_mut(),
+                )
+            };
+            if counted == vk::Result::SUCCESS {
+                let mut addresses = vec![
+                    vk::DeviceFaultAddressInfoEXT::default();
+                    counts.address_info_count as usize
+                ];
+                let mut vendor_infos = vec![
+                    vk::DeviceFaultVendorInfoEXT::default();
+                    counts.vendor_info_count as usize
+                ];
+                // The vendor binary is not asked for.
+                counts.vendor_binary_size = 0;
+                let mut info = vk::DeviceFaultInfoEXT {
+                    p_address_infos: addresses.as_mut_ptr(),
+                    p_vendor_infos: vendor_infos.as_mut_ptr(),
+                    ..Default::default()
+                };
+                let filled = unsafe {
+                    (device_fault.get_device_fault_info_ext)(self.device, &mut counts, &mut info)
+                };
+                if filled == vk::Result::SUCCESS || filled == vk::Result::INCOMPLETE {
+                    report.description = Some(c_string(&info.description));
+                    addresses.truncate(counts.address_info_count as usize);
+                    report.addresses = addresses
+                        .iter()
+                        .map(|address| {
+                            format!(
+                                "{:?} at {:#x} (within {:#x})",
+                                address.address_type,
+                                address.reported_address,
+                                address.address_precision
+                            )
+                        })
+                        .collect();
+                    vendor_infos.truncate(counts.vendor_info_count as usize);
+                    report.vendor_faults = vendor_infos
+                        .iter()
+                        .map(|vendor| {
+                            format!(
+                                "{} (code {:#x}, data {:#x})",
+                                c_string(&vendor.description),
+                                vendor.vendor_fault_code,
+                                vendor.vendor_fault_data
+                            )
+                        })
+                        .collect();
+                }
+            }
+        }
+        report
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        if let Some(markers) = &self.markers {
+            markers.cleanup(logical_device);
+        }
+    }
+}
+
+fn c_string(characters: &[std::ffi::c_char]) -> String {
+    let bytes: Vec<u8> = characters
+        .iter()
+        .take_while(|character| **character != 0)
+        .map(|character| *character as u8)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,246 @@
+use nalgebra::Vector3;
+
+/// A curve through space, parameterized over [0, 1] from its start to its end.
+pub trait SpaceCurve {
+    fn point(&self, t: f32) -> Vector3<f32>;
+
+    /// Direction of travel at `t`, not normalized; a central difference unless overridden.
+    fn tangent(&self, t: f32) -> Vector3<f32> {
+        let h = 1.0e-3;
+        let (a, b) = ((t - h).max(0.0), (t + h).min(1.0));
+        (self.point(b) - self.point(a)) / (b - a)
+    }
+
+    /// `segments + 1` evenly spaced points in parameter, from the start to the end.
+    fn sample(&self, segments: usize) -> Vec<Vector3<f32>> {
+        let segments = segments.max(1);
+        (0..=segments)
+            .map(|i| self.point(i as f32 / segments as f32))
+            .collect()
+    }
+
+    /// Length of the polyline through 64 samples.
+    fn approximate_length(&self) -> f32 {
+        self.sample(64)
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).norm())
+            .sum()
+    }
+}
+
+/// Cubic Bezier segments sharing end points: 3n + 1 control points make n segments.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bezier {
+    pub control_points: Vec<Vector3<f32>>,
+}
+
+impl Bezier {
+    pub fn new(control_points: Vec<Vector3<f32>>) -> Self {
+        Self { control_points }
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.control_points.len().saturating_sub(1) / 3
+    }
+
+    /// Which segment `t` falls in, and the parameter within it.
+    fn locate(&self, t: f32) -> (usize, f32) {
+        let segments = self.segment_count();
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let segment = (scaled as usize).min(segments - 1);
+        (segment, scaled - segment as f32)
+    }
+}
+
+impl SpaceCurve for Bezier {
+    /// Panics with fewer than four control points.
+    fn point(&self, t: f32) -> Vector3<f32> {
+        let (segment, t) = self.locate(t);
+        let [p0, p1, p2, p3] = std::array::from_fn(|i| self.control_points[3 * segment + i]);
+        let u = 1.0 - t;
+        p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+    }
+
+    fn tangent(&self, t: f32) -> Vector3<f32> {
+        let (segment, t) = self.locate(t);
+        let [p0, p1, p2, p3] = std::array::from_fn(|i| self.control_points[3 * segment + i]);
+        let u = 1.0 - t;
+        ((p1 - p0) * (u * u) + (p2 - p1) * (2.0 * u * t) + (p3 - p2) * (t * t)) * 3.0
+    }
+}
+
+/// A curve passing through every point, with the tangent at each taken from its
+/// neighbours. The end points are repeated unless the curve is closed.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CatmullRom {
+    pub points: Vec<Vector3<f32>>,
+    /// Joins the last point back to the first.
+    pub closed: bool,
+}
+
+impl CatmullRom {
+    pub fn new(points: Vec<Vector3<f32>>) -> Self {
+        Self {
+            points,
+            closed: false,
+        }
+    }
+
+    pub fn closed(mut self) -> Self {
+        self.closed = true;
+        self
+    }
+
+    pub fn segment_count(&self) -> usize {
+        if self.closed {
+            self.points.len()
+        } else {
+            self.points.len().saturating_sub(1)
+        }
+    }
+
+    /// The four points around segment `segment`.
+    fn neighbourhood(&self, segment: usize) -> [Vector3<f32>; 4] {
+        let n = self.points.len();
+        if self.closed {
+            std::array::from_fn(|i| self.points[(segment + n + i - 1) % n])
+        } else {
+            std::array::from_fn(|i| self.points[(segment + i).saturating_sub(1).min(n - 1)])
+        }
+    }
+}
+
+impl SpaceCurve for CatmullRom {
+    /// Panics without points.
+    fn point(&self, t: f32) -> Vector3<f32> {
+        let segments = self.segment_count();
+        if segments == 0 {
+            return self.points[0];
+        }
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let segment = (scaled as usize).min(segments - 1);
+        let [p0, p1, p2, p3] = self.neighbourhood(segment);
+        catmull_rom(p0, p1, p2, p3, scaled - segment as f32)
+    }
+}
+
+/// The uniform Catmull-Rom segment from `p1` to `p2` at `t` in [0, 1].
+pub fn catmull_rom(
+    p0: Vector3<f32>,
+    p1: Vector3<f32>,
+    p2: Vector3<f32>,
+    p3: Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// Frames along `points` that twist as little as possible, by parallel transport: each
+/// point's normal and binormal, perpendicular to the curve and to each other.
+pub fn transported_frames(points: &[Vector3<f32>]) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+    let n = points.len();
+    if n < 2 {
+        return vec![(Vector3::x(), Vector3::z()); n];
+    }
+    let tangent_at = |i: usize| {
+        (points[(i + 1).min(n - 1)] - points[i.saturating_sub(1)])
+            .try_normalize(1.0e-6)
+            .unwrap_or_else(Vector3::y)
+    };
+
+    let first = tangent_at(0);
+    let helper = if first.y.abs() < 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let mut normal = first.cross(&helper).normalize();
+    let mut previous = first;
+    let mut frames = Vec::with_capacity(n);
+    for i in 0..n {
+        let tangent = tangent_at(i);
+        // Rotate the normal by the rotation taking the previous tangent to this one.
+        let axis = previous.cross(&tangent);
+        if let Some(axis) = nalgebra::Unit::try_new(axis, 1.0e-6) {
+            let angle = previous.dot(&tangent).clamp(-1.0, 1.0).acos();
+            normal = nalgebra::Rotation3::from_axis_angle(&axis, angle) * normal;
+        }
+        // Keep it exactly perpendicular against accumulated drift.
+        normal = (normal - tangent * normal.dot(&tangent)).normalize();
+        frames.push((normal, tangent.cross(&normal)));
+        previous = tangent;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vector3<f32>, b: Vector3<f32>, tolerance: f32) {
+        assert!((a - b).norm() < tolerance, "{a:?} is not {b:?}");
+    }
+
+    #[test]
+    fn bezier_joins_its_segment_end_points() {
+        let bezier = Bezier::new(vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 2.0, 0.0),
+            Vector3::new(2.0, 2.0, 0.0),
+            Vector3::new(3.0, 0.0, 0.0),
+            Vector3::new(4.0, -2.0, 1.0),
+            Vector3::new(5.0, -2.0, 1.0),
+            Vector3::new(6.0, 0.0, 2.0),
+        ]);
+        assert_eq!(bezier.segment_count(), 2);
+        assert_near(bezier.point(0.0), bezier.control_points[0], 1e-6);
+        assert_near(bezier.point(0.5), bezier.control_points[3], 1e-6);
+        assert_near(bezier.point(1.0), bezier.control_points[6], 1e-6);
+        // The exact tangent points along the central difference.
+        let numeric = bezier.point(0.3 + 1e-3) - bezier.point(0.3 - 1e-3);
+        assert_near(bezier.tangent(0.3).normalize(), numeric.normalize(), 1e-3);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_its_points() {
+        let points = vec![
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 1.0),
+            Vector3::new(2.0, 1.0, 0.0),
+            Vector3::new(3.0, 0.0, -1.0),
+        ];
+        let open = CatmullRom::new(points.clone());
+        for (i, point) in points.iter().enumerate() {
+            assert_near(open.point(i as f32 / 3.0), *point, 1e-5);
+        }
+        let closed = CatmullRom::new(points.clone()).closed();
+        assert_eq!(closed.segment_count(), 4);
+        assert_near(closed.point(0.75), points[3], 1e-5);
+        assert_near(closed.point(1.0), points[0], 1e-5);
+    }
+
+    #[test]
+    fn transported_frames_stay_orthonormal() {
+        let helix = (0..32).map(|i| {
+            let angle = i as f32 * 0.3;
+            Vector3::new(angle.cos(), 0.2 * i as f32, angle.sin())
+        });
+        let points: Vec<_> = helix.collect();
+        let frames = transported_frames(&points);
+        assert_eq!(frames.len(), points.len());
+        for (i, (normal, binormal)) in frames.iter().enumerate() {
+            let tangent = (points[(i + 1).min(31)] - points[i.saturating_sub(1)]).normalize();
+            assert!((normal.norm() - 1.0).abs() < 1e-4);
+            assert!((binormal.norm() - 1.0).abs() < 1e-4);
+            assert!(normal.dot(&tangent).abs() < 1e-4);
+            assert!(normal.dot(binormal).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::foliage::Foliage;
+use crate::model::Instance;
+use crate::pipeline::Pipeline;
+use crate::texture::Texture;
+
+/// Draws cutout instances, those with an [`crate::model::InstanceData::alpha_cutoff`]
+/// above 0, such as leaf cards or fences, which every other pipeline skips. Each cutout
+/// is cut by one layer of a mask texture, projected in object space along the axis its
+/// surface faces most so that -1 to 1 across the other two axes covers the mask once.
+/// Where the instance's opacity times the mask's alpha falls below the cutoff the
+/// surface is cut away: through alpha to coverage when multisampling, so the edges are
+/// smoothed, and discarded otherwise. Faces are not culled, as cards show from both
+/// sides.
+pub struct CutoutPass {
+    pub pipeline: vk::Pipeline,
+    /// Sways [`Foliage`] layers like [`Foliage::pipeline`] does.
+    pub wind_pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// Set 1, holding the masks. Set 0 is the main pipeline's.
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// One layer per mask, picked by [`crate::model::InstanceData::cutout_layer`].
+    pub masks: Texture,
+    pub sampler: vk::Sampler,
+}
+
+impl CutoutPass {
+    /// Builds the pass around uploaded `masks` and `sampler`, both of which it owns from
+    /// then on.
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+        masks: Texture,
+        sampler: vk::Sampler,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info =
+            vk::ShaderModuleCreateInfo::builder().code(vk_shader_macros::include_glsl!(
+                "shaders/shader.vert",
+                kind: vert,
+                define: CUTOUT,
+            ));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let wind_vertex_info =
+            vk::ShaderModuleCreateInfo::builder().code(vk_shader_macros::include_glsl!(
+                "shaders/shader.vert",
+                kind: vert,
+                define: CUTOUT,
+                define: WIND,
+            ));
+        let wind_vertex_module =
+            unsafe { logical_device.create_shader_module(&wind_vertex_info, None) }?;
+
+        let fragment_info =
+            vk::ShaderModuleCreateInfo::builder().code(vk_shader_macros::include_glsl!(
+                "shaders/shader.frag",
+                kind: frag,
+                define: CUTOUT,
+            ));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        // Alpha to coverage needs samples to cover; without them the shader discards.
+        let alpha_to_coverage = vk::Bool32::from(pipeline.multisampling.is_enabled());
+        let specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 2,
+            offset: 0,
+            size: std::mem::size_of::<vk::Bool32>(),
+        }];
+        let specialization_data = alpha_to_coverage.to_ne_bytes();
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&specialization_data);
+        let stages = |vertex_module| {
+            [
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::VERTEX)
+                    .module(vertex_module)
+                    .name(&main_function_name)
+                    .build(),
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::FRAGMENT)
+                    .module(fragment_module)
+                    .name(&main_function_name)
+                    .specialization_info(&specialization_info)
+                    .build(),
+            ]
+        };
+        let shader_stages = stages(vertex_module);
+        let wind_shader_stages = stages(wind_vertex_module);
+
+        /* Fixed Functions */
+        let vertex_input_info = pipeline.vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = pipeline.multisampling.cutout_state_info();
+        let colour_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colour_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colour_blend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Descriptors */
+        let descriptor_set_layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let image_info = [vk::DescriptorImageInfo {
+            sampler,
+            image_view: masks.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info)
+            .build()];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+
+        /* Pipeline */
+        let set_layouts = [pipeline.descriptor_set_layouts[0], descriptor_set_layout];
+        // The wind, only read by the wind pipeline.
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: 16,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = |stages: &[vk::PipelineShaderStageCreateInfo]| {
+            vk::GraphicsPipelineCreateInfo::builder()
+                .stages(stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&depth_stencil_info)
+                .color_blend_state(&colour_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(layout)
+                .render_pass(*renderpass)
+                .subpass(0)
+                .build()
+        };
+        let graphics_pipelines = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[
+                        pipeline_info(&shader_stages),
+                        pipeline_info(&wind_shader_stages),
+                    ],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        };
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(wind_vertex_module, None);
+            logical_device.destroy_shader_module(vertex_module, None);
+        }
+
+        Ok(CutoutPass {
+            pipeline: graphics_pipelines[0],
+            wind_pipeline: graphics_pipelines[1],
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            masks,
+            sampler,
+        })
+    }
+
+    /// Binds the pass into subpass 0, with the main pipeline's set 0 `descriptor_set`;
+    /// draw triangle list meshes after it as with the main pipeline.
+    pub fn bind(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set, self.descriptor_set],
+                &[],
+            );
+        }
+    }
+
+    /// Draws the cutouts among `foliage`'s layers in its wind; the pass must be bound.
+    pub fn draw_foliage<I: Instance>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        foliage: &Foliage<I>,
+    ) {
+        let parameters = foliage.wind_parameters();
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                parameters.as_ptr() as *const u8,
+                std::mem::size_of_val(&parameters),
+            )
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.wind_pipeline,
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytes,
+            );
+        }
+        for layer in &foliage.layers {
+            if layer
+                .instances
+                .visible()
+                .iter()
+                .any(|instance| instance.base().is_cutout())
+            {
+                layer.draw(logical_device, command_buffer);
+            }
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            self.masks.cleanup(logical_device);
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_pipeline(self.wind_pipeline, None);
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
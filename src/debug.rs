@@ -1,3 +1,5 @@
+use std::sync::Mutex;
+
 use ash::vk;
 
 use crate::vulkan_debug_utils_callback;
@@ -5,10 +7,20 @@ use crate::vulkan_debug_utils_callback;
 pub struct Debug {
     pub loader: ash::extensions::ext::DebugUtils,
     pub messenger: vk::DebugUtilsMessengerEXT,
+    // Boxed so the messenger's user data pointer survives `Debug` being moved.
+    messages: Box<Messages>,
+}
+
+/// What [`collecting_callback`] keeps, behind the messenger's user data pointer.
+#[derive(Default)]
+struct Messages {
+    errors: Mutex<Vec<String>>,
+    shader_prints: Mutex<Vec<String>>,
 }
 
 impl Debug {
     pub fn init(entry: &ash::Entry, instance: &ash::Instance) -> Result<Debug, vk::Result> {
+        let messages = Box::<Messages>::default();
         let debugcreateinfo = vk::DebugUtilsMessengerCreateInfoEXT::builder()
             .message_severity(
                 vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
@@ -21,11 +33,91 @@ impl Debug {
                     | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
                     | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
             )
-            .pfn_user_callback(Some(vulkan_debug_utils_callback));
+            .pfn_user_callback(Some(collecting_callback))
+            .user_data(&*messages as *const Messages as *mut std::ffi::c_void);
 
         let loader = ash::extensions::ext::DebugUtils::new(entry, instance);
         let messenger = unsafe { loader.create_debug_utils_messenger(&debugcreateinfo, None)? };
 
-        Ok(Debug { loader, messenger })
+        Ok(Debug {
+            loader,
+            messenger,
+            messages,
+        })
+    }
+
+    /// ERROR messages reported since the last call, oldest first.
+    pub fn take_errors(&self) -> Vec<String> {
+        std::mem::take(
+            &mut *self
+                .messages
+                .errors
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        )
+    }
+
+    /// What shaders printed with `debugPrintfEXT` since the last call, oldest first.
+    /// Empty unless [`crate::KrakatoaBuilder::shader_debug_printf`] was set.
+    pub fn take_shader_prints(&self) -> Vec<String> {
+        std::mem::take(
+            &mut *self
+                .messages
+                .shader_prints
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        )
+    }
+
+    /// Panics listing the ERROR messages reported since the last check, for integration
+    /// tests of the engine's own paths. Passes vacuously when the validation layer is
+    /// not installed.
+    #[track_caller]
+    pub fn assert_no_validation_errors(&self) {
+        let errors = self.take_errors();
+        assert!(
+            errors.is_empty(),
+            "{} validation errors:\n{}",
+            errors.len(),
+            errors.join("\n")
+        );
+    }
+}
+
+/// Prints like [`vulkan_debug_utils_callback`] and keeps ERROR messages for
+/// [`Debug::take_errors`]. Shader prints are printed as `[Shader]` lines and kept for
+/// [`Debug::take_shader_prints`] instead.
+unsafe extern "system" fn collecting_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut std::ffi::c_void,
+) -> vk::Bool32 {
+    let messages = &*(p_user_data as *const Messages);
+    let id_name = (*p_callback_data).p_message_id_name;
+    if !id_name.is_null()
+        && std::ffi::CStr::from_ptr(id_name)
+            .to_string_lossy()
+            .contains("DEBUG-PRINTF")
+    {
+        let message = std::ffi::CStr::from_ptr((*p_callback_data).p_message).to_string_lossy();
+        // The layer puts the printed text after its object and message id preamble.
+        let printed = message.rsplit(" | ").next().unwrap_or(&message).to_owned();
+        println!("[Shader] {}", printed);
+        messages
+            .shader_prints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(printed);
+        return vk::FALSE;
+    }
+    if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        let message = std::ffi::CStr::from_ptr((*p_callback_data).p_message);
+        messages
+            .errors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(message.to_string_lossy().into_owned());
     }
+    vulkan_debug_utils_callback(message_severity, message_type, p_callback_data, p_user_data)
 }
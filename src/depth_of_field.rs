@@ -0,0 +1,208 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::camera::Camera;
+
+/// Thin-lens depth of field, blurring each pixel by its circle of confusion. The lens is
+/// the camera's: its f-number `aperture` and field of view, on a sensor of
+/// `sensor_height`. Drawn as a [`crate::post::PostProcess`] effect; needs single-sampled
+/// depth.
+pub struct DepthOfField {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// Distance from the camera in focus, in world units.
+    pub focal_distance: f32,
+    /// Height of the sensor in world units, 0.024 for a full-frame camera in metres.
+    /// Smaller sensors give deeper focus at the same field of view.
+    pub sensor_height: f32,
+    /// Upper bound on the blur radius in pixels, which also bounds the cost.
+    pub max_radius: f32,
+    /// Depth scale and offset of the camera's projection.
+    projection: [f32; 2],
+    /// Focal length over f-number.
+    aperture_diameter: f32,
+    focal_length: f32,
+}
+
+impl DepthOfField {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/oit_composite.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/depth_of_field.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        /* Pipeline */
+        let descriptor_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: 20,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&descriptor_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colourblend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(DepthOfField {
+            pipeline,
+            layout,
+            focal_distance: 5.0,
+            sensor_height: 0.024,
+            max_radius: 16.0,
+            projection: [1.0, 0.0],
+            aperture_diameter: 0.0,
+            focal_length: 0.0,
+        })
+    }
+
+    /// Takes the lens and projection from `camera`; called for every frame it renders.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.projection = [
+            camera.projection_matrix[(2, 2)],
+            camera.projection_matrix[(2, 3)],
+        ];
+        self.focal_length = 0.5 * self.sensor_height / (0.5 * camera.fovy).tan();
+        self.aperture_diameter = self.focal_length / camera.aperture.max(f32::EPSILON);
+    }
+
+    /// Draws into the [`crate::post::PostProcess`] render pass, for a swapchain `height`
+    /// pixels tall.
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        height: u32,
+    ) {
+        // Radius on the sensor per unit of (distance - focus) / distance, in pixels.
+        let focal_distance = self.focal_distance.max(self.focal_length * 1.001);
+        let coc_scale = 0.5 * self.aperture_diameter * self.focal_length
+            / (focal_distance - self.focal_length)
+            / self.sensor_height
+            * height as f32;
+        let parameters = [
+            self.projection[0],
+            self.projection[1],
+            focal_distance,
+            coc_scale,
+            self.max_radius.max(0.0),
+        ];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                parameters.as_ptr() as *const u8,
+                std::mem::size_of_val(&parameters),
+            )
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                bytes,
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::pipeline::Pipeline;
+
+/// Depth-only pass over the opaque geometry, after which the colour pass shades each
+/// pixel once by testing for EQUAL depth. Pays off when fragment shading dominates.
+pub struct DepthPrepass {
+    /// Writes depth only, no fragment shader.
+    pub depth_pipeline: vk::Pipeline,
+    /// The main pipeline with an EQUAL depth test and depth writes off.
+    pub colour_pipeline: vk::Pipeline,
+}
+
+impl DepthPrepass {
+    /// Both pipelines share the main pipeline's layout, so the same descriptor sets bind to them.
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = pipeline.vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = pipeline.multisampling.state_info();
+
+        let depth_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(vk::ColorComponentFlags::empty())
+            .build()];
+        let depth_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&depth_blend_attachments);
+        let depth_depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        let colour_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colour_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colour_blend_attachments);
+        let colour_depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::EQUAL);
+
+        /* Pipelines */
+        let pipeline_infos = [
+            vk::GraphicsPipelineCreateInfo::builder()
+                .stages(&shader_stages[..1])
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&depth_depth_stencil_info)
+                .color_blend_state(&depth_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline.layout)
+                .render_pass(*renderpass)
+                .subpass(0)
+                .build(),
+            vk::GraphicsPipelineCreateInfo::builder()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&colour_depth_stencil_info)
+                .color_blend_state(&colour_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline.layout)
+                .render_pass(*renderpass)
+                .subpass(0)
+                .build(),
+        ];
+        let pipelines = unsafe {
+            logical_device
+                .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_infos, None)
+                .map_err(|(_, e)| e)?
+        };
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(DepthPrepass {
+            depth_pipeline: pipelines[0],
+            colour_pipeline: pipelines[1],
+        })
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.depth_pipeline, None);
+            logical_device.destroy_pipeline(self.colour_pipeline, None);
+        }
+    }
+}
@@ -0,0 +1,884 @@
+use std::path::Path;
+
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::allocations;
+use crate::buffer::Buffer;
+use crate::find_memorytype_index;
+use crate::format_has_stencil;
+use crate::occlusion::init_compute_pipeline;
+use crate::ray_query::{begin_one_time, end_one_time};
+use crate::reflection::{init_framebuffer, REFLECTION_FORMAT};
+use crate::swapchain::Attachment;
+
+/// Format of the captured and the baked cubemaps.
+pub const ENVIRONMENT_FORMAT: vk::Format = REFLECTION_FORMAT;
+
+/// Bytes of one `ENVIRONMENT_FORMAT` texel.
+const TEXEL_SIZE: usize = 8;
+/// Invocations per workgroup of `shaders/environment_convolve.comp`, along each axis.
+const WORKGROUP_SIZE: u32 = 8;
+const FILE_MAGIC: &[u8; 4] = b"KENV";
+const FILE_VERSION: u32 = 1;
+
+/// How [`crate::krakatoa::Krakatoa::bake_environment`] captures and filters the scene.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BakeSettings {
+    /// Width and height of the faces the scene is captured into.
+    pub capture_resolution: u32,
+    pub irradiance_resolution: u32,
+    /// Of the sharpest specular level; every further level halves it.
+    pub specular_resolution: u32,
+    /// Levels prefiltered for roughnesses spread evenly from 0 to 1.
+    pub specular_levels: u32,
+    /// Samples per texel of the convolutions.
+    pub sample_count: u32,
+    /// Clip distances of the capture.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self {
+        Self {
+            capture_resolution: 256,
+            irradiance_resolution: 32,
+            specular_resolution: 128,
+            specular_levels: 5,
+            sample_count: 1024,
+            near: 0.05,
+            far: 100.0,
+        }
+    }
+}
+
+/// The lighting around a point, filtered for image-based lighting: the diffuse irradiance,
+/// divided by pi so it multiplies albedo directly, and the specular reflection
+/// prefiltered for increasing roughness. Each is the six faces of a cubemap in layer
+/// order, as `ENVIRONMENT_FORMAT` texels with rows from the top.
+#[derive(Clone, Debug)]
+pub struct BakedEnvironment {
+    pub irradiance_resolution: u32,
+    pub specular_resolution: u32,
+    pub irradiance: Vec<u8>,
+    /// Sharpest first, each level half the size of the one before.
+    pub specular: Vec<Vec<u8>>,
+}
+
+impl BakedEnvironment {
+    /// The roughness specular level `level` was prefiltered for.
+    pub fn level_roughness(&self, level: usize) -> f32 {
+        level_roughness(level as u32, self.specular.len() as u32)
+    }
+
+    /// A header followed by the texels, little endian, as [`BakedEnvironment::save`]
+    /// writes it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = FILE_MAGIC.to_vec();
+        for value in [
+            FILE_VERSION,
+            self.irradiance_resolution,
+            self.specular_resolution,
+            self.specular.len() as u32,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.irradiance);
+        for level in &self.specular {
+            bytes.extend_from_slice(level);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 20 || &bytes[..4] != FILE_MAGIC {
+            bail!("Not a baked environment.");
+        }
+        let [version, irradiance_resolution, specular_resolution, levels] =
+            std::array::from_fn(|i| {
+                u32::from_le_bytes(bytes[4 + 4 * i..8 + 4 * i].try_into().unwrap())
+            });
+        if version != FILE_VERSION {
+            bail!(
+                "Baked environments of version {} are not supported.",
+                version
+            );
+        }
+        let mut rest = &bytes[20..];
+        let mut take = |size: usize| {
+            if rest.len() < size {
+                bail!("The baked environment is cut short.");
+            }
+            let (taken, remaining) = rest.split_at(size);
+            rest = remaining;
+            Ok(taken.to_vec())
+        };
+        let irradiance = take(cube_level_size(irradiance_resolution, 0))?;
+        let specular = (0..levels)
+            .map(|level| take(cube_level_size(specular_resolution, level)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            irradiance_resolution,
+            specular_resolution,
+            irradiance,
+            specular,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let levels = self.specular.len() as u32;
+        if self.irradiance_resolution == 0
+            || self.specular_resolution == 0
+            || levels == 0
+            || levels > mip_levels(self.specular_resolution)
+        {
+            bail!("A baked environment needs texels and at most a full mip chain.");
+        }
+        let sizes_match = self.irradiance.len() == cube_level_size(self.irradiance_resolution, 0)
+            && (0..levels).all(|level| {
+                self.specular[level as usize].len()
+                    == cube_level_size(self.specular_resolution, level)
+            });
+        if !sizes_match {
+            bail!("The baked environment's texels do not match its resolutions.");
+        }
+        Ok(())
+    }
+}
+
+/// A cubemap the scene is drawn into face by face with the reflection capture pipeline,
+/// whose render pass leaves it ready for sampling.
+pub struct CaptureCube {
+    pub resolution: u32,
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    /// A `CUBE` view, for sampling.
+    pub view: vk::ImageView,
+    /// One 2D view and framebuffer per face.
+    pub face_views: Vec<vk::ImageView>,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub depth: Attachment,
+}
+
+impl CaptureCube {
+    /// Targets for `renderpass`, which is [`crate::reflection::Reflections::renderpass`].
+    pub fn init(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        renderpass: vk::RenderPass,
+        depth_format: vk::Format,
+        resolution: u32,
+    ) -> Result<Self> {
+        if resolution == 0 {
+            bail!("A capture cube needs at least one pixel.");
+        }
+        let (image, memory) = init_cube(
+            logical_device,
+            memory_properties,
+            resolution,
+            1,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            "environment capture cubemap",
+        )?;
+        let view = init_view(logical_device, image, vk::ImageViewType::CUBE, 0, 0, 6)?;
+        let face_views = (0..6)
+            .map(|face| {
+                init_view(
+                    logical_device,
+                    image,
+                    vk::ImageViewType::TYPE_2D,
+                    0,
+                    face,
+                    1,
+                )
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let extent = vk::Extent2D {
+            width: resolution,
+            height: resolution,
+        };
+        let depth = Attachment::init(
+            logical_device,
+            memory_properties,
+            extent,
+            depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            if format_has_stencil(depth_format) {
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+            } else {
+                vk::ImageAspectFlags::DEPTH
+            },
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+        let framebuffers = face_views
+            .iter()
+            .map(|face_view| {
+                init_framebuffer(logical_device, renderpass, *face_view, depth.view, extent)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            resolution,
+            image,
+            memory,
+            view,
+            face_views,
+            framebuffers,
+            depth,
+        })
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            for framebuffer in &self.framebuffers {
+                logical_device.destroy_framebuffer(*framebuffer, None);
+            }
+            self.depth.cleanup(logical_device);
+            for view in self.face_views.iter().chain([&self.view]) {
+                logical_device.destroy_image_view(*view, None);
+            }
+            allocations::untrack(logical_device, self.image);
+            allocations::untrack(logical_device, self.memory);
+            logical_device.destroy_image(self.image, None);
+            logical_device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// Filters the cubemap `source` into the irradiance and specular levels `settings`
+/// asks for on the device, and copies them to the host.
+pub fn convolve(
+    logical_device: &ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    source: vk::ImageView,
+    settings: &BakeSettings,
+) -> Result<BakedEnvironment> {
+    let levels = settings.specular_levels;
+    if settings.irradiance_resolution == 0
+        || settings.specular_resolution == 0
+        || settings.sample_count == 0
+    {
+        bail!("Baking needs at least one texel and one sample.");
+    }
+    if levels == 0 || levels > mip_levels(settings.specular_resolution) {
+        bail!(
+            "A {} texel cubemap has between 1 and {} specular levels.",
+            settings.specular_resolution,
+            mip_levels(settings.specular_resolution)
+        );
+    }
+
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+    let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+    let bindings = [
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build(),
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(1)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build(),
+    ];
+    let set_layout = unsafe {
+        logical_device.create_descriptor_set_layout(
+            &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings),
+            None,
+        )
+    }?;
+    let (pipeline, layout) = init_compute_pipeline(
+        logical_device,
+        vk_shader_macros::include_glsl!("shaders/environment_convolve.comp", kind: comp),
+        set_layout,
+        16,
+        None,
+    )?;
+
+    let usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC;
+    let (irradiance, irradiance_memory) = init_cube(
+        logical_device,
+        memory_properties,
+        settings.irradiance_resolution,
+        1,
+        usage,
+        "baked irradiance cubemap",
+    )?;
+    let (specular, specular_memory) = init_cube(
+        logical_device,
+        memory_properties,
+        settings.specular_resolution,
+        levels,
+        usage,
+        "baked specular cubemap",
+    )?;
+    // Every target as the image, its width and the push constants it is filtered with.
+    let targets: Vec<(vk::Image, u32, u32, [u32; 4])> = std::iter::once((
+        irradiance,
+        0,
+        settings.irradiance_resolution,
+        [
+            settings.irradiance_resolution,
+            0.0f32.to_bits(),
+            settings.sample_count,
+            1,
+        ],
+    ))
+    .chain((0..levels).map(|level| {
+        let size = (settings.specular_resolution >> level).max(1);
+        (
+            specular,
+            level,
+            size,
+            [
+                size,
+                level_roughness(level, levels).to_bits(),
+                settings.sample_count,
+                0,
+            ],
+        )
+    }))
+    .collect();
+    let target_views = targets
+        .iter()
+        .map(|(image, level, _, _)| {
+            init_view(
+                logical_device,
+                *image,
+                vk::ImageViewType::TYPE_2D_ARRAY,
+                *level,
+                0,
+                6,
+            )
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let pool_sizes = [
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: targets.len() as u32,
+        },
+        vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_IMAGE,
+            descriptor_count: targets.len() as u32,
+        },
+    ];
+    let descriptor_pool = unsafe {
+        logical_device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::builder()
+                .max_sets(targets.len() as u32)
+                .pool_sizes(&pool_sizes),
+            None,
+        )
+    }?;
+    let set_layouts = vec![set_layout; targets.len()];
+    let descriptor_sets = unsafe {
+        logical_device.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(descriptor_pool)
+                .set_layouts(&set_layouts),
+        )
+    }?;
+    for (set, target_view) in descriptor_sets.iter().zip(&target_views) {
+        let source_info = [vk::DescriptorImageInfo {
+            sampler,
+            image_view: source,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let target_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: *target_view,
+            image_layout: vk::ImageLayout::GENERAL,
+        }];
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(*set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&source_info)
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(*set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&target_info)
+                .build(),
+        ];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    let irradiance_size = cube_level_size(settings.irradiance_resolution, 0);
+    let specular_sizes: Vec<usize> = (0..levels)
+        .map(|level| cube_level_size(settings.specular_resolution, level))
+        .collect();
+    let staging = Buffer::init(
+        irradiance_size + specular_sizes.iter().sum::<usize>(),
+        vk::BufferUsageFlags::TRANSFER_DST,
+        memory_properties,
+        logical_device,
+    )?;
+
+    let baked = begin_one_time(logical_device, command_pool).and_then(|command_buffer| {
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[irradiance, specular].map(|image| {
+                    layout_barrier(
+                        image,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::GENERAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::SHADER_WRITE,
+                    )
+                }),
+            );
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline,
+            );
+            for ((_, _, size, push_constants), set) in targets.iter().zip(&descriptor_sets) {
+                logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    layout,
+                    0,
+                    &[*set],
+                    &[],
+                );
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    std::slice::from_raw_parts(
+                        push_constants.as_ptr() as *const u8,
+                        std::mem::size_of_val(push_constants),
+                    ),
+                );
+                logical_device.cmd_dispatch(
+                    command_buffer,
+                    size.div_ceil(WORKGROUP_SIZE),
+                    size.div_ceil(WORKGROUP_SIZE),
+                    6,
+                );
+            }
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[irradiance, specular].map(|image| {
+                    layout_barrier(
+                        image,
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::SHADER_WRITE,
+                        vk::AccessFlags::TRANSFER_READ,
+                    )
+                }),
+            );
+            logical_device.cmd_copy_image_to_buffer(
+                command_buffer,
+                irradiance,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                staging.buffer,
+                &cube_copies(settings.irradiance_resolution, 1, 0),
+            );
+            logical_device.cmd_copy_image_to_buffer(
+                command_buffer,
+                specular,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                staging.buffer,
+                &cube_copies(settings.specular_resolution, levels, irradiance_size),
+            );
+        }
+        end_one_time(logical_device, command_pool, queue, command_buffer)?;
+
+        let texels = staging.download::<u8>(logical_device)?;
+        let (irradiance_texels, mut rest) = texels.split_at(irradiance_size);
+        let specular_texels = specular_sizes
+            .iter()
+            .map(|size| {
+                let (level, remaining) = rest.split_at(*size);
+                rest = remaining;
+                level.to_vec()
+            })
+            .collect();
+        Ok(BakedEnvironment {
+            irradiance_resolution: settings.irradiance_resolution,
+            specular_resolution: settings.specular_resolution,
+            irradiance: irradiance_texels.to_vec(),
+            specular: specular_texels,
+        })
+    });
+
+    staging.cleanup(logical_device);
+    unsafe {
+        logical_device.destroy_descriptor_pool(descriptor_pool, None);
+        for view in target_views {
+            logical_device.destroy_image_view(view, None);
+        }
+        for (image, memory) in [(irradiance, irradiance_memory), (specular, specular_memory)] {
+            allocations::untrack(logical_device, image);
+            allocations::untrack(logical_device, memory);
+            logical_device.destroy_image(image, None);
+            logical_device.free_memory(memory, None);
+        }
+        logical_device.destroy_pipeline(pipeline, None);
+        logical_device.destroy_pipeline_layout(layout, None);
+        logical_device.destroy_descriptor_set_layout(set_layout, None);
+        logical_device.destroy_sampler(sampler, None);
+    }
+    baked
+}
+
+/// A [`BakedEnvironment`] on the device, for shaders to sample. The specular levels are
+/// the mips of one cubemap, so a surface of roughness `r` samples level
+/// `r * (specular_levels - 1)`.
+pub struct EnvironmentMap {
+    pub irradiance_image: vk::Image,
+    pub irradiance_memory: vk::DeviceMemory,
+    pub irradiance_view: vk::ImageView,
+    pub specular_image: vk::Image,
+    pub specular_memory: vk::DeviceMemory,
+    pub specular_view: vk::ImageView,
+    pub specular_levels: u32,
+    /// Trilinear, for both cubemaps.
+    pub sampler: vk::Sampler,
+}
+
+impl EnvironmentMap {
+    pub fn upload(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        baked: &BakedEnvironment,
+    ) -> Result<Self> {
+        baked.validate()?;
+        let levels = baked.specular.len() as u32;
+        let usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+        let (irradiance_image, irradiance_memory) = init_cube(
+            logical_device,
+            memory_properties,
+            baked.irradiance_resolution,
+            1,
+            usage,
+            "environment irradiance cubemap",
+        )?;
+        let (specular_image, specular_memory) = init_cube(
+            logical_device,
+            memory_properties,
+            baked.specular_resolution,
+            levels,
+            usage,
+            "environment specular cubemap",
+        )?;
+
+        let texels = baked.to_bytes()[20..].to_vec();
+        let mut staging = Buffer::init(
+            texels.len(),
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            memory_properties,
+            logical_device,
+        )?;
+        let uploaded = staging
+            .fill(logical_device, &texels, memory_properties)
+            .and_then(|_| begin_one_time(logical_device, command_pool))
+            .and_then(|command_buffer| {
+                unsafe {
+                    logical_device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &[irradiance_image, specular_image].map(|image| {
+                            layout_barrier(
+                                image,
+                                vk::ImageLayout::UNDEFINED,
+                                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                                vk::AccessFlags::empty(),
+                                vk::AccessFlags::TRANSFER_WRITE,
+                            )
+                        }),
+                    );
+                    logical_device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        staging.buffer,
+                        irradiance_image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &cube_copies(baked.irradiance_resolution, 1, 0),
+                    );
+                    logical_device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        staging.buffer,
+                        specular_image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &cube_copies(baked.specular_resolution, levels, baked.irradiance.len()),
+                    );
+                    logical_device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::FRAGMENT_SHADER,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &[irradiance_image, specular_image].map(|image| {
+                            layout_barrier(
+                                image,
+                                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                                vk::AccessFlags::TRANSFER_WRITE,
+                                vk::AccessFlags::SHADER_READ,
+                            )
+                        }),
+                    );
+                }
+                end_one_time(logical_device, command_pool, queue, command_buffer)
+            });
+        staging.cleanup(logical_device);
+        uploaded?;
+
+        let irradiance_view = init_view(
+            logical_device,
+            irradiance_image,
+            vk::ImageViewType::CUBE,
+            0,
+            0,
+            6,
+        )?;
+        let specular_view = init_view(
+            logical_device,
+            specular_image,
+            vk::ImageViewType::CUBE,
+            0,
+            0,
+            6,
+        )?;
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        Ok(Self {
+            irradiance_image,
+            irradiance_memory,
+            irradiance_view,
+            specular_image,
+            specular_memory,
+            specular_view,
+            specular_levels: levels,
+            sampler,
+        })
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_sampler(self.sampler, None);
+            for (image, memory, view) in [
+                (
+                    self.irradiance_image,
+                    self.irradiance_memory,
+                    self.irradiance_view,
+                ),
+                (
+                    self.specular_image,
+                    self.specular_memory,
+                    self.specular_view,
+                ),
+            ] {
+                logical_device.destroy_image_view(view, None);
+                allocations::untrack(logical_device, image);
+                allocations::untrack(logical_device, memory);
+                logical_device.destroy_image(image, None);
+                logical_device.free_memory(memory, None);
+            }
+        }
+    }
+}
+
+fn level_roughness(level: u32, levels: u32) -> f32 {
+    if levels > 1 {
+        level as f32 / (levels - 1) as f32
+    } else {
+        0.0
+    }
+}
+
+/// Mips down to a single texel of a cubemap `resolution` wide.
+fn mip_levels(resolution: u32) -> u32 {
+    32 - resolution.leading_zeros()
+}
+
+/// Bytes of the six faces of level `level` of a cubemap `resolution` wide.
+fn cube_level_size(resolution: u32, level: u32) -> usize {
+    let size = (resolution >> level).max(1) as usize;
+    6 * size * size * TEXEL_SIZE
+}
+
+/// Copies of the first `levels` levels of a cubemap `resolution` wide to or from a
+/// buffer holding them one after the other from `offset`.
+fn cube_copies(resolution: u32, levels: u32, offset: usize) -> Vec<vk::BufferImageCopy> {
+    let mut offset = offset;
+    (0..levels)
+        .map(|level| {
+            let size = (resolution >> level).max(1);
+            let copy = vk::BufferImageCopy {
+                buffer_offset: offset as u64,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count: 6,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: vk::Extent3D {
+                    width: size,
+                    height: size,
+                    depth: 1,
+                },
+            };
+            offset += cube_level_size(resolution, level);
+            copy
+        })
+        .collect()
+}
+
+/// A transition of every level and face of `image`.
+fn layout_barrier(
+    image: vk::Image,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_access_mask: vk::AccessFlags,
+    dst_access_mask: vk::AccessFlags,
+) -> vk::ImageMemoryBarrier {
+    vk::ImageMemoryBarrier::builder()
+        .image(image)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: vk::REMAINING_MIP_LEVELS,
+            base_array_layer: 0,
+            layer_count: vk::REMAINING_ARRAY_LAYERS,
+        })
+        .build()
+}
+
+fn init_cube(
+    logical_device: &ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    resolution: u32,
+    levels: u32,
+    usage: vk::ImageUsageFlags,
+    name: &str,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let image_info = vk::ImageCreateInfo::builder()
+        .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(ENVIRONMENT_FORMAT)
+        .extent(vk::Extent3D {
+            width: resolution,
+            height: resolution,
+            depth: 1,
+        })
+        .mip_levels(levels)
+        .array_layers(6)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let image = unsafe { logical_device.create_image(&image_info, None) }?;
+    let memory_requirements = unsafe { logical_device.get_image_memory_requirements(image) };
+    allocations::track(logical_device, image, memory_requirements.size, name);
+    let memory_index = find_memorytype_index(
+        &memory_requirements,
+        &memory_properties,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )
+    .expect("Unable to find suitable memory index for an environment cubemap.");
+    let allocate_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(memory_requirements.size)
+        .memory_type_index(memory_index);
+    let memory = unsafe { logical_device.allocate_memory(&allocate_info, None) }?;
+    allocations::track(
+        logical_device,
+        memory,
+        memory_requirements.size,
+        format!("memory of the {}", name),
+    );
+    unsafe { logical_device.bind_image_memory(image, memory, 0) }?;
+    Ok((image, memory))
+}
+
+fn init_view(
+    logical_device: &ash::Device,
+    image: vk::Image,
+    view_type: vk::ImageViewType,
+    level: u32,
+    base_array_layer: u32,
+    layer_count: u32,
+) -> std::result::Result<vk::ImageView, vk::Result> {
+    let level_count = if view_type == vk::ImageViewType::CUBE {
+        vk::REMAINING_MIP_LEVELS
+    } else {
+        1
+    };
+    let view_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(view_type)
+        .format(ENVIRONMENT_FORMAT)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: level,
+            level_count,
+            base_array_layer,
+            layer_count,
+        });
+    unsafe { logical_device.create_image_view(&view_info, None) }
+}
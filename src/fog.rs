@@ -0,0 +1,42 @@
+/// Analytic participating medium applied in the scene's fragment shader.
+/// Light is scattered towards the camera with a Henyey-Greenstein phase function,
+/// so looking towards the sun through the fog brightens it. Nothing shadows it; for
+/// shafts through the gaps in what covers the light, see [`crate::light_shafts::LightShafts`].
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fog {
+    /// Extinction per world unit; 0 disables the fog.
+    pub density: f32,
+    /// Fraction of the extinguished light that is scattered back towards the camera.
+    pub scattering: f32,
+    /// Henyey-Greenstein asymmetry in (-1, 1); positive values scatter forward.
+    pub anisotropy: f32,
+    /// Colour of the fog under ambient light alone.
+    pub colour: [f32; 3],
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            density: 0.0,
+            scattering: 0.5,
+            anisotropy: 0.6,
+            colour: [0.6, 0.65, 0.7],
+        }
+    }
+}
+
+impl Fog {
+    /// std140 layout of the fog members of the fragment shader's light block.
+    pub fn to_uniform(&self) -> [[f32; 4]; 2] {
+        [
+            [self.colour[0], self.colour[1], self.colour[2], self.density],
+            [
+                self.scattering,
+                self.anisotropy.clamp(-0.99, 0.99),
+                0.0,
+                0.0,
+            ],
+        ]
+    }
+}
@@ -0,0 +1,377 @@
+use std::f32::consts::PI;
+
+use anyhow::{Ok, Result};
+use ash::vk;
+use nalgebra::{Matrix4, Vector3};
+
+use crate::model::{Instance, InstanceData, Model, VertexData};
+use crate::noise::XorShift;
+use crate::pipeline::Pipeline;
+
+/// How much foliage grows where, from 0 for none to 1 for the full density, over a
+/// rectangle of the xz plane.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DensityMap {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major, rows along z.
+    pub values: Vec<f32>,
+    /// World x and z of the first value.
+    pub origin: [f32; 2],
+    /// World extent along x and z.
+    pub size: [f32; 2],
+}
+
+impl DensityMap {
+    /// The same density everywhere in the rectangle.
+    pub fn uniform(origin: [f32; 2], size: [f32; 2], density: f32) -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            values: vec![density],
+            origin,
+            size,
+        }
+    }
+
+    /// `f(x, z)` at `resolution` × `resolution` points of the rectangle.
+    pub fn from_fn(
+        origin: [f32; 2],
+        size: [f32; 2],
+        resolution: usize,
+        f: impl Fn(f32, f32) -> f32,
+    ) -> Self {
+        let resolution = resolution.max(2);
+        let step = [0, 1].map(|i| size[i] / (resolution - 1) as f32);
+        let values = (0..resolution * resolution)
+            .map(|i| {
+                let (column, row) = (i % resolution, i / resolution);
+                f(
+                    origin[0] + column as f32 * step[0],
+                    origin[1] + row as f32 * step[1],
+                )
+            })
+            .collect();
+        Self {
+            width: resolution,
+            height: resolution,
+            values,
+            origin,
+            size,
+        }
+    }
+
+    /// White is full density, with the image's rows along z.
+    #[cfg(feature = "image")]
+    pub fn from_image(image: &image::GrayImage, origin: [f32; 2], size: [f32; 2]) -> Self {
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            values: image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / 255.0)
+                .collect(),
+            origin,
+            size,
+        }
+    }
+
+    /// Bilinearly filtered density at a world position; 0 outside the rectangle.
+    pub fn sample(&self, x: f32, z: f32) -> f32 {
+        let u = (x - self.origin[0]) / self.size[0];
+        let v = (z - self.origin[1]) / self.size[1];
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) || self.values.is_empty() {
+            return 0.0;
+        }
+        let fx = u * (self.width - 1) as f32;
+        let fz = v * (self.height - 1) as f32;
+        let (x0, z0) = (fx as usize, fz as usize);
+        let (x1, z1) = ((x0 + 1).min(self.width - 1), (z0 + 1).min(self.height - 1));
+        let (tx, tz) = (fx - x0 as f32, fz - z0 as f32);
+        let at = |column: usize, row: usize| self.values[row * self.width + column];
+        let near = at(x0, z0) + (at(x1, z0) - at(x0, z0)) * tx;
+        let far = at(x0, z1) + (at(x1, z1) - at(x0, z1)) * tx;
+        (near + (far - near) * tz).clamp(0.0, 1.0)
+    }
+}
+
+/// How [`scatter`] places instances.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScatterSettings {
+    /// Instances per square world unit where the density map is 1.
+    pub instances_per_unit: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    pub colour: [f32; 3],
+    /// Each instance's colour is scaled by up to this fraction either way.
+    pub colour_variation: f32,
+    pub seed: u32,
+}
+
+impl Default for ScatterSettings {
+    fn default() -> Self {
+        Self {
+            instances_per_unit: 16.0,
+            min_scale: 0.8,
+            max_scale: 1.2,
+            colour: [0.3, 0.6, 0.2],
+            colour_variation: 0.15,
+            seed: 1,
+        }
+    }
+}
+
+/// Instances spread over `density`'s rectangle, standing on the surface `height_at`
+/// gives for an x and z, such as [`crate::model::NoiseTerrain::height_at`]. A jittered
+/// grid keeps them from clumping; each has a random turn about the vertical and scale.
+pub fn scatter(
+    density: &DensityMap,
+    settings: &ScatterSettings,
+    height_at: impl Fn(f32, f32) -> f32,
+) -> Vec<InstanceData> {
+    let mut rng = XorShift::new(settings.seed);
+    let spacing = 1.0 / settings.instances_per_unit.max(1.0e-6).sqrt();
+    let columns = (density.size[0] / spacing).ceil() as usize;
+    let rows = (density.size[1] / spacing).ceil() as usize;
+    let mut instances = vec![];
+    for row in 0..rows {
+        for column in 0..columns {
+            let x = density.origin[0] + (column as f32 + rng.unit()) * spacing;
+            let z = density.origin[1] + (row as f32 + rng.unit()) * spacing;
+            let keep = rng.unit() < density.sample(x, z);
+            let yaw = rng.range(0.0, 2.0 * PI);
+            let scale = rng.range(settings.min_scale, settings.max_scale);
+            let shade = 1.0 + rng.range(-1.0, 1.0) * settings.colour_variation;
+            if !keep {
+                continue;
+            }
+            let matrix = Matrix4::new_translation(&Vector3::new(x, height_at(x, z), z))
+                * Matrix4::from_axis_angle(&Vector3::y_axis(), yaw)
+                * Matrix4::new_scaling(scale);
+            instances.push(InstanceData::from_matrix_and_colour(
+                matrix,
+                settings
+                    .colour
+                    .map(|channel| (channel * shade).clamp(0.0, 1.0)),
+            ));
+        }
+    }
+    instances
+}
+
+/// Models drawn with the main shading and a vertex shader that sways them in the wind,
+/// bending more the higher a vertex is above its instance's origin. Faces are not
+/// culled, so single-sided cards and blades show from both sides.
+pub struct Foliage<I: Copy> {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub layers: Vec<Model<VertexData, I>>,
+    /// Direction on the xz plane the wind blows towards.
+    pub wind_direction: [f32; 2],
+    /// Sideways displacement per squared unit of height.
+    pub wind_strength: f32,
+    /// Gusts per second, in radians.
+    pub wind_frequency: f32,
+}
+
+impl<I: Instance> Foliage<I> {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info =
+            vk::ShaderModuleCreateInfo::builder().code(vk_shader_macros::include_glsl!(
+                "shaders/shader.vert",
+                kind: vert,
+                define: WIND,
+            ));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = pipeline.vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = pipeline.multisampling.state_info();
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Pipeline */
+        let set_layouts = [pipeline.descriptor_set_layouts[0]];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: 16,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colourblend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let graphics_pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None);
+        }
+
+        Ok(Foliage {
+            pipeline: graphics_pipeline,
+            layout,
+            layers: vec![],
+            wind_direction: [1.0, 0.0],
+            wind_strength: 0.05,
+            wind_frequency: 1.5,
+        })
+    }
+
+    /// Uploads `model` and draws it from now on; returns its index in [`Foliage::layers`].
+    pub fn add_layer(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        mut model: Model<VertexData, I>,
+    ) -> Result<usize> {
+        model.upload(logical_device, memory_properties)?;
+        self.layers.push(model);
+        Ok(self.layers.len() - 1)
+    }
+
+    /// The `Wind` push constants of `shaders/shader.vert`: the normalised direction, the
+    /// strength, then the frequency.
+    pub fn wind_parameters(&self) -> [f32; 4] {
+        let length = (self.wind_direction[0].powi(2) + self.wind_direction[1].powi(2)).sqrt();
+        let direction = if length > 0.0 {
+            self.wind_direction.map(|component| component / length)
+        } else {
+            [0.0, 0.0]
+        };
+        [
+            direction[0],
+            direction[1],
+            self.wind_strength,
+            self.wind_frequency,
+        ]
+    }
+
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        let parameters = self.wind_parameters();
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                parameters.as_ptr() as *const u8,
+                std::mem::size_of_val(&parameters),
+            )
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytes,
+            );
+        }
+        for layer in &self.layers {
+            layer.draw(logical_device, command_buffer);
+        }
+    }
+
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        for layer in &mut self.layers {
+            layer.cleanup(logical_device);
+        }
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
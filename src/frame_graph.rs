@@ -0,0 +1,205 @@
+use std::fmt::Write;
+
+/// What kind of work a [`FramePass`] records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PassKind {
+    Compute,
+    /// A subpass of a render pass, see [`FramePass::render_pass`].
+    Graphics,
+    Transfer,
+    Present,
+}
+
+/// One pass of the frame, with the resources it reads and writes by name.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FramePass {
+    pub name: String,
+    pub kind: PassKind,
+    pub queue: String,
+    /// The render pass a graphics pass is a subpass of; dependencies within one are
+    /// subpass dependencies rather than barriers.
+    pub render_pass: Option<String>,
+    pub reads: Vec<String>,
+    pub writes: Vec<String>,
+    /// What is drawn or dispatched in the pass, in recording order.
+    pub draws: Vec<String>,
+}
+
+/// Why one pass has to wait for another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Hazard {
+    ReadAfterWrite,
+    WriteAfterWrite,
+    WriteAfterRead,
+    /// Read before anything in the frame writes it, so the previous frame's write is
+    /// what is read.
+    PreviousFrame,
+}
+
+/// A synchronisation edge between two passes, by index into [`FrameGraph::passes`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameDependency {
+    pub from: usize,
+    pub to: usize,
+    pub resource: String,
+    pub hazard: Hazard,
+    /// Whether a subpass dependency of their shared render pass covers it, rather than a
+    /// pipeline barrier.
+    pub subpass_dependency: bool,
+}
+
+/// The passes of a frame in recording order, with the dependencies between them derived
+/// from what each reads and writes. Made by [`crate::krakatoa::Krakatoa::frame_graph`]
+/// for seeing why passes run in the order they do and where barriers go.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameGraph {
+    pub passes: Vec<FramePass>,
+    pub dependencies: Vec<FrameDependency>,
+}
+
+impl FrameGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a pass and returns its index. Dependencies are derived by
+    /// [`FrameGraph::connect`] once every pass is in.
+    pub fn pass(
+        &mut self,
+        name: &str,
+        kind: PassKind,
+        render_pass: Option<&str>,
+        reads: &[&str],
+        writes: &[&str],
+        draws: Vec<String>,
+    ) -> usize {
+        let owned = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        self.passes.push(FramePass {
+            name: name.to_owned(),
+            kind,
+            queue: "graphics".to_owned(),
+            render_pass: render_pass.map(ToOwned::to_owned),
+            reads: owned(reads),
+            writes: owned(writes),
+            draws,
+        });
+        self.passes.len() - 1
+    }
+
+    /// Replaces the dependencies with those the passes' reads and writes imply: each
+    /// access waits for the last earlier write of its resource, and writes also for the
+    /// reads since. Reads with no earlier write wait for the frame's last write.
+    pub fn connect(&mut self) {
+        let mut dependencies = vec![];
+        for (to, pass) in self.passes.iter().enumerate() {
+            let last_write = |resource: &String, before: usize| {
+                self.passes[..before]
+                    .iter()
+                    .rposition(|pass| pass.writes.contains(resource))
+            };
+            let mut depend = |from: usize, resource: &String, hazard| {
+                let subpass_dependency = from < to
+                    && pass.render_pass.is_some()
+                    && self.passes[from].render_pass == pass.render_pass;
+                dependencies.push(FrameDependency {
+                    from,
+                    to,
+                    resource: resource.clone(),
+                    hazard,
+                    subpass_dependency,
+                });
+            };
+            for resource in &pass.reads {
+                match last_write(resource, to) {
+                    Some(from) => depend(from, resource, Hazard::ReadAfterWrite),
+                    None => {
+                        if let Some(from) = last_write(resource, self.passes.len()) {
+                            depend(from, resource, Hazard::PreviousFrame);
+                        }
+                    }
+                }
+            }
+            for resource in &pass.writes {
+                let previous_write = last_write(resource, to);
+                if let Some(from) = previous_write {
+                    depend(from, resource, Hazard::WriteAfterWrite);
+                }
+                let readers = (previous_write.map_or(0, |from| from + 1)..to)
+                    .filter(|from| self.passes[*from].reads.contains(resource));
+                for from in readers {
+                    depend(from, resource, Hazard::WriteAfterRead);
+                }
+            }
+        }
+        self.dependencies = dependencies;
+    }
+
+    /// The graph in GraphViz's DOT language: subpasses clustered by render pass, barriers
+    /// solid, subpass dependencies dashed and dependencies on the previous frame dotted.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph frame {\n    rankdir=LR;\n    node [shape=box];\n");
+        let mut render_passes: Vec<&str> = vec![];
+        for pass in &self.passes {
+            if let Some(render_pass) = pass.render_pass.as_deref() {
+                if !render_passes.contains(&render_pass) {
+                    render_passes.push(render_pass);
+                }
+            }
+        }
+        let node = |dot: &mut String, index: usize, indent: &str| {
+            let pass = &self.passes[index];
+            let mut label = format!("{}\\n{:?} on {}", pass.name, pass.kind, pass.queue);
+            for draw in &pass.draws {
+                let _ = write!(label, "\\n- {}", draw);
+            }
+            let _ = writeln!(dot, "{}p{} [label=\"{}\"];", indent, index, escape(&label));
+        };
+        for (cluster, render_pass) in render_passes.iter().enumerate() {
+            let _ = writeln!(dot, "    subgraph cluster_{} {{", cluster);
+            let _ = writeln!(dot, "        label=\"{}\";", escape(render_pass));
+            for (index, pass) in self.passes.iter().enumerate() {
+                if pass.render_pass.as_deref() == Some(*render_pass) {
+                    node(&mut dot, index, "        ");
+                }
+            }
+            dot.push_str("    }\n");
+        }
+        for (index, pass) in self.passes.iter().enumerate() {
+            if pass.render_pass.is_none() {
+                node(&mut dot, index, "    ");
+            }
+        }
+        for dependency in &self.dependencies {
+            let style = match (dependency.hazard, dependency.subpass_dependency) {
+                (Hazard::PreviousFrame, _) => "dotted",
+                (_, true) => "dashed",
+                (_, false) => "solid",
+            };
+            let _ = writeln!(
+                dot,
+                "    p{} -> p{} [label=\"{} ({:?})\", style={}];",
+                dependency.from,
+                dependency.to,
+                escape(&dependency.resource),
+                dependency.hazard,
+                style
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('"', "\\\"")
+}
@@ -0,0 +1,125 @@
+use ash::vk;
+
+use crate::allocations::AllocationReport;
+use crate::frame_graph::FrameGraph;
+
+/// A rectangle in swapchain pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PixelRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<vk::Rect2D> for PixelRect {
+    fn from(rect: vk::Rect2D) -> Self {
+        PixelRect {
+            x: rect.offset.x,
+            y: rect.offset.y,
+            width: rect.extent.width,
+            height: rect.extent.height,
+        }
+    }
+}
+
+/// A pipeline the frame binds, by the raw handle its draws refer to.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PipelineState {
+    pub name: String,
+    pub handle: u64,
+}
+
+/// One mesh drawn by the main pipelines: a model, or an instance set of a shared mesh.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DrawState {
+    /// Index into the models, then past them into the instance sets, as occlusion
+    /// culling and clip rectangles count them.
+    pub index: usize,
+    /// Whether the mesh is one of `Krakatoa::models` rather than of its instance sets.
+    pub model: bool,
+    pub topology: String,
+    pub primitive_restart: bool,
+    pub vertex_count: usize,
+    pub index_count: usize,
+    /// Visible instances, which are the ones drawn.
+    pub instance_count: usize,
+    /// The pipeline the mesh is drawn with, `None` while its variant is still compiling
+    /// and the mesh is skipped.
+    pub pipeline: Option<u64>,
+    pub clip_rect: Option<PixelRect>,
+    /// Whether occlusion culling decides if it is drawn.
+    pub occlusion_culled: bool,
+}
+
+/// A descriptor of the main pipeline's set 0, which every frame binds.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: String,
+    pub buffer: u64,
+    /// Bytes bound, `None` for the whole buffer.
+    pub range: Option<u64>,
+}
+
+/// A buffer alive on the device, from the [`AllocationReport`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BufferState {
+    pub name: Option<String>,
+    pub handle: u64,
+    pub size: u64,
+    pub usage: String,
+}
+
+impl BufferState {
+    pub fn from_report(report: &AllocationReport) -> Vec<BufferState> {
+        report
+            .allocations
+            .iter()
+            .filter(|allocation| allocation.object_type == vk::ObjectType::BUFFER)
+            .map(|allocation| BufferState {
+                name: allocation.name.clone(),
+                handle: allocation.handle,
+                size: allocation.size,
+                usage: allocation.usage.clone(),
+            })
+            .collect()
+    }
+}
+
+/// What the last frame submitted, for attaching to bug reports and reading offline. Made
+/// by [`crate::krakatoa::Krakatoa::frame_state`]; settings changed since the frame was
+/// rendered show their new values, except for the camera.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameState {
+    pub frame: u64,
+    pub elapsed: f32,
+    pub delta: f32,
+    pub extent: [u32; 2],
+    pub viewport: PixelRect,
+    pub render_mode: String,
+    pub blend_mode: String,
+    pub transparency_mode: String,
+    /// Column-major view and projection matrices, `None` before the first frame.
+    pub view: Option<[[f32; 4]; 4]>,
+    pub projection: Option<[[f32; 4]; 4]>,
+    pub pipelines: Vec<PipelineState>,
+    pub draws: Vec<DrawState>,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub buffers: Vec<BufferState>,
+    pub passes: FrameGraph,
+}
+
+impl FrameState {
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
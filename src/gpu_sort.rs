@@ -0,0 +1,270 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::buffer::Buffer;
+use crate::ray_query::{begin_one_time, end_one_time};
+
+/// Invocations per workgroup in `shaders/bitonic_sort.comp`.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// `Step` in `shaders/bitonic_sort.comp`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SortStep {
+    count: u32,
+    block: u32,
+    distance: u32,
+    flags: u32,
+}
+
+crate::assert_std_layout!(
+    Std430,
+    SortStep {
+        count: Scalar,
+        block: Scalar,
+        distance: Scalar,
+        flags: Scalar,
+    }
+);
+
+/// A `u32` ordered as `value` is among floats, so depths can be sorted as keys. NaNs
+/// sort beyond the infinities.
+pub fn float_key(value: f32) -> u32 {
+    let bits = value.to_bits();
+    if bits >> 31 == 1 {
+        !bits
+    } else {
+        bits | 1 << 31
+    }
+}
+
+/// Sorts `(key, value)` pairs of `u32`s in a storage buffer with a bitonic sorting
+/// network, one compute dispatch per step, so buffers of millions of particles or
+/// transparent instances can be ordered by depth each frame without reaching the host.
+/// Keys compare as unsigned integers; [`float_key`] makes them from depths. Equal keys
+/// may come out in any order.
+pub struct GpuSort {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl GpuSort {
+    pub fn init(logical_device: &ash::Device) -> Result<Self> {
+        /* Descriptors */
+        let descriptor_set_layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build()];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        /* Pipeline */
+        let compute_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/bitonic_sort.comp", kind: comp));
+        let compute_module = unsafe { logical_device.create_shader_module(&compute_info, None) }?;
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(compute_module)
+            .name(&main_function_name);
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<SortStep>() as u32)
+            .build()];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage.build())
+            .layout(layout);
+        let pipeline = unsafe {
+            logical_device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+                .map_err(|(_, e)| e)?
+        }[0];
+        unsafe { logical_device.destroy_shader_module(compute_module, None) };
+
+        Ok(GpuSort {
+            pipeline,
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+        })
+    }
+
+    /// Points the sort at `pairs`, which needs `STORAGE_BUFFER` usage. No sort recorded
+    /// for the previous buffer may still be pending.
+    pub fn bind(&self, logical_device: &ash::Device, pairs: &Buffer) {
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer: pairs.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_info);
+        unsafe { logical_device.update_descriptor_sets(&[write.build()], &[]) };
+    }
+
+    /// Records sorting the first `count` pairs of the bound buffer, smallest key first
+    /// unless `descending`. Earlier compute writes to the pairs are waited for, and the
+    /// result is visible to later compute shaders and to vertex input and shaders.
+    pub fn record(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        count: u32,
+        descending: bool,
+    ) {
+        if count < 2 {
+            return;
+        }
+        let padded = count.next_power_of_two();
+        let groups = (padded / 2).div_ceil(WORKGROUP_SIZE);
+        let direction = if descending { 2 } else { 0 };
+        let between_steps = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build();
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[between_steps],
+                &[],
+                &[],
+            );
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            let mut block = 2;
+            while block <= padded {
+                let mut distance = block / 2;
+                let mut flip = 1;
+                while distance > 0 {
+                    let step = SortStep {
+                        count,
+                        block,
+                        distance,
+                        flags: flip | direction,
+                    };
+                    logical_device.cmd_push_constants(
+                        command_buffer,
+                        self.layout,
+                        vk::ShaderStageFlags::COMPUTE,
+                        0,
+                        std::slice::from_raw_parts(
+                            &step as *const SortStep as *const u8,
+                            std::mem::size_of::<SortStep>(),
+                        ),
+                    );
+                    logical_device.cmd_dispatch(command_buffer, groups, 1, 1);
+                    logical_device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::PipelineStageFlags::COMPUTE_SHADER,
+                        vk::DependencyFlags::empty(),
+                        &[between_steps],
+                        &[],
+                        &[],
+                    );
+                    distance /= 2;
+                    flip = 0;
+                }
+                block *= 2;
+            }
+            let sorted = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(
+                    vk::AccessFlags::SHADER_READ
+                        | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                        | vk::AccessFlags::INDEX_READ,
+                );
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::VERTEX_INPUT
+                    | vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[sorted.build()],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    /// Binds `pairs` and sorts their first `count` on `queue`, waiting until done.
+    pub fn sort(
+        &self,
+        logical_device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        pairs: &Buffer,
+        count: u32,
+        descending: bool,
+    ) -> Result<()> {
+        if pairs.size_in_bytes < count as usize * 8 {
+            bail!(
+                "Sorting {} pairs needs {} bytes, not {}.",
+                count,
+                count as usize * 8,
+                pairs.size_in_bytes
+            );
+        }
+        self.bind(logical_device, pairs);
+        let command_buffer = begin_one_time(logical_device, command_pool)?;
+        self.record(logical_device, command_buffer, count, descending);
+        end_one_time(logical_device, command_pool, queue, command_buffer)
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::multisample::Multisampling;
+
+/// Editor-style reference grid on the y = 0 plane, raycast per pixel from a
+/// fullscreen triangle and blended over the scene after the models are drawn.
+pub struct Grid {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// World-space distance between minor lines.
+    pub cell_size: f32,
+    /// Distance from the camera at which the grid has faded out completely.
+    pub fade_distance: f32,
+    pub opacity: f32,
+    /// Minor cells per major line.
+    pub major_every: u32,
+}
+
+impl Grid {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        multisampling: &Multisampling,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/grid.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/grid.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = multisampling.state_info();
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Pipeline */
+        let descriptor_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: 16,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&descriptor_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colourblend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(Grid {
+            pipeline,
+            layout,
+            cell_size: 1.0,
+            fade_distance: 100.0,
+            opacity: 1.0,
+            major_every: 10,
+        })
+    }
+
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        let parameters = [
+            self.cell_size,
+            self.fade_distance,
+            self.opacity,
+            self.major_every.max(1) as f32,
+        ];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                parameters.as_ptr() as *const u8,
+                std::mem::size_of_val(&parameters),
+            )
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                bytes,
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::camera::Camera;
+use crate::swapchain::Swapchain;
+
+/// Format of the intermediate scene image when the swapchain is encoded for an HDR display.
+pub const SCENE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Colour space the swapchain is presented in. HDR outputs need
+/// `VK_EXT_swapchain_colorspace` and a surface that reports a matching format,
+/// otherwise the swapchain falls back to SDR.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputColourSpace {
+    #[default]
+    Sdr,
+    /// Linear scRGB in a floating point swapchain, values above 1 brighter than SDR white.
+    ExtendedSrgb,
+    /// BT.2020 primaries with the SMPTE ST 2084 (PQ) transfer function.
+    Hdr10,
+}
+
+impl OutputColourSpace {
+    pub fn from_colour_space(colour_space: vk::ColorSpaceKHR) -> Self {
+        match colour_space {
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => Self::ExtendedSrgb,
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => Self::Hdr10,
+            _ => Self::Sdr,
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        *self != Self::Sdr
+    }
+
+    /// Picks the surface format for this output, or the surface's preferred one for SDR
+    /// and when the display does not offer the requested colour space.
+    pub fn choose_format(&self, formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+        let matches = |format: &&vk::SurfaceFormatKHR| match self {
+            Self::Sdr => false,
+            Self::ExtendedSrgb => {
+                format.color_space == vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+                    && format.format == vk::Format::R16G16B16A16_SFLOAT
+            }
+            Self::Hdr10 => {
+                format.color_space == vk::ColorSpaceKHR::HDR10_ST2084_EXT
+                    && matches!(
+                        format.format,
+                        vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32
+                    )
+            }
+        };
+        *formats.iter().find(matches).unwrap_or(&formats[0])
+    }
+
+    fn encoding(&self) -> i32 {
+        match self {
+            Self::Sdr => 0,
+            Self::ExtendedSrgb => 1,
+            Self::Hdr10 => 2,
+        }
+    }
+}
+
+/// Final subpass for HDR output: reads the scene, which is shaded as for an sRGB display,
+/// and applies the output's transfer function into the swapchain image.
+pub struct OutputEncode {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// Luminance of SDR white in nits.
+    pub paper_white: f32,
+    /// Scales the scene by the camera's [`Camera::exposure`] before encoding, so lights
+    /// can be authored in physical units, such as a sun of around 100 000 lux. Off, the
+    /// scene is encoded as shaded.
+    pub physical_exposure: bool,
+    exposure: f32,
+}
+
+impl OutputEncode {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        swapchain: &Swapchain,
+    ) -> Result<Self> {
+        let output = OutputColourSpace::from_colour_space(swapchain.surface_format.color_space);
+
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/oit_composite.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/output_encode.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let encoding = output.encoding().to_ne_bytes();
+        let specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<i32>(),
+        }];
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&encoding);
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .specialization_info(&specialization_info)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let colour_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colour_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colour_blend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        /* Descriptors */
+        let descriptor_set_layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        /* Pipeline */
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: 8,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colour_blend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(3);
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        let output_encode = OutputEncode {
+            pipeline,
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            paper_white: 200.0,
+            physical_exposure: false,
+            exposure: 1.0,
+        };
+        output_encode.update_descriptor_set(logical_device, swapchain);
+
+        Ok(output_encode)
+    }
+
+    /// Points the encode at the swapchain's current scene image; call after recreating it.
+    pub fn update_descriptor_set(&self, logical_device: &ash::Device, swapchain: &Swapchain) {
+        let Some(scene) = &swapchain.scene else {
+            return;
+        };
+        let scene_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: scene.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+            .image_info(&scene_info)
+            .build()];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Takes the exposure from `camera` when [`OutputEncode::physical_exposure`] is set;
+    /// called for every frame it renders.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.exposure = if self.physical_exposure {
+            camera.exposure()
+        } else {
+            1.0
+        };
+    }
+
+    /// Records subpass 3.
+    pub fn draw(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let parameters: Vec<u8> = [self.paper_white, self.exposure]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                &parameters,
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
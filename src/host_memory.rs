@@ -0,0 +1,267 @@
+use std::alloc::{self, Layout};
+use std::ffi::c_void;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ash::vk;
+
+/// `vk::SystemAllocationScope`s, in the order of their raw values.
+const SCOPES: [vk::SystemAllocationScope; 5] = [
+    vk::SystemAllocationScope::COMMAND,
+    vk::SystemAllocationScope::OBJECT,
+    vk::SystemAllocationScope::CACHE,
+    vk::SystemAllocationScope::DEVICE,
+    vk::SystemAllocationScope::INSTANCE,
+];
+
+/// Written just before every block handed to the driver, which frees blocks by pointer
+/// alone.
+#[repr(C)]
+struct Header {
+    size: usize,
+    alignment: usize,
+    scope: vk::SystemAllocationScope,
+}
+
+#[derive(Default)]
+struct ScopeTally {
+    bytes: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    allocations: AtomicUsize,
+    total_allocations: AtomicUsize,
+    internal_bytes: AtomicUsize,
+}
+
+#[derive(Default)]
+struct Tally {
+    scopes: [ScopeTally; 5],
+}
+
+impl Tally {
+    fn scope(&self, scope: vk::SystemAllocationScope) -> &ScopeTally {
+        &self.scopes[(scope.as_raw().max(0) as usize).min(SCOPES.len() - 1)]
+    }
+}
+
+/// `vk::AllocationCallbacks` that serve the driver's host memory from the global
+/// allocator and tally it per allocation scope. Passed when the instance and device are
+/// created and destroyed, so it sees their allocations and, on most drivers, those of
+/// every object created without callbacks of its own.
+pub struct HostAllocator {
+    // Boxed so the driver's user data pointer survives the allocator being moved.
+    tally: Box<Tally>,
+}
+
+impl Default for HostAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostAllocator {
+    pub fn new() -> Self {
+        Self {
+            tally: Box::default(),
+        }
+    }
+
+    /// The callbacks to create and destroy the instance and device with. They must not
+    /// outlive `self`.
+    pub fn callbacks(&self) -> vk::AllocationCallbacks {
+        vk::AllocationCallbacks {
+            p_user_data: &*self.tally as *const Tally as *mut c_void,
+            pfn_allocation: Some(allocation),
+            pfn_reallocation: Some(reallocation),
+            pfn_free: Some(free),
+            pfn_internal_allocation: Some(internal_allocation),
+            pfn_internal_free: Some(internal_free),
+        }
+    }
+
+    pub fn stats(&self) -> HostMemoryStats {
+        HostMemoryStats {
+            scopes: SCOPES
+                .iter()
+                .zip(&self.tally.scopes)
+                .map(|(&scope, tally)| ScopeStats {
+                    scope,
+                    bytes: tally.bytes.load(Ordering::Relaxed),
+                    peak_bytes: tally.peak_bytes.load(Ordering::Relaxed),
+                    allocations: tally.allocations.load(Ordering::Relaxed),
+                    total_allocations: tally.total_allocations.load(Ordering::Relaxed),
+                    internal_bytes: tally.internal_bytes.load(Ordering::Relaxed),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Host memory the driver holds in one allocation scope.
+#[derive(Clone, Copy, Debug)]
+pub struct ScopeStats {
+    pub scope: vk::SystemAllocationScope,
+    /// Bytes allocated through the callbacks and not yet freed.
+    pub bytes: usize,
+    pub peak_bytes: usize,
+    /// Blocks allocated and not yet freed.
+    pub allocations: usize,
+    /// Blocks allocated since the instance was created, reallocations included.
+    pub total_allocations: usize,
+    /// Bytes the driver allocated itself and only reported, such as executable memory
+    /// for compiled shaders.
+    pub internal_bytes: usize,
+}
+
+/// A snapshot of the driver's host memory use, one entry per allocation scope.
+#[derive(Clone, Debug)]
+pub struct HostMemoryStats {
+    pub scopes: Vec<ScopeStats>,
+}
+
+impl HostMemoryStats {
+    /// Bytes currently allocated across all scopes, internal allocations included.
+    pub fn bytes(&self) -> usize {
+        self.scopes
+            .iter()
+            .map(|scope| scope.bytes + scope.internal_bytes)
+            .sum()
+    }
+
+    pub fn scope(&self, scope: vk::SystemAllocationScope) -> Option<&ScopeStats> {
+        self.scopes.iter().find(|stats| stats.scope == scope)
+    }
+}
+
+impl fmt::Display for HostMemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} bytes of driver host memory", self.bytes())?;
+        for scope in &self.scopes {
+            writeln!(
+                f,
+                "  {:?}: {} bytes in {} blocks (peak {} bytes, {} allocations), {} internal bytes",
+                scope.scope,
+                scope.bytes,
+                scope.allocations,
+                scope.peak_bytes,
+                scope.total_allocations,
+                scope.internal_bytes
+            )?;
+        }
+        std::fmt::Result::Ok(())
+    }
+}
+
+/// Bytes reserved in front of a block for its `Header`, keeping the block aligned.
+fn header_space(alignment: usize) -> usize {
+    alignment.max(std::mem::size_of::<Header>().next_power_of_two())
+}
+
+fn layout(size: usize, alignment: usize) -> Option<Layout> {
+    let alignment = alignment.max(std::mem::align_of::<Header>());
+    Layout::from_size_align(size.checked_add(header_space(alignment))?, alignment).ok()
+}
+
+unsafe fn header(memory: *mut c_void) -> *mut Header {
+    (memory as *mut u8).sub(std::mem::size_of::<Header>()) as *mut Header
+}
+
+unsafe extern "system" fn allocation(
+    p_user_data: *mut c_void,
+    size: usize,
+    alignment: usize,
+    allocation_scope: vk::SystemAllocationScope,
+) -> *mut c_void {
+    if size == 0 || !alignment.is_power_of_two() {
+        return std::ptr::null_mut();
+    }
+    let Some(layout) = layout(size, alignment) else {
+        return std::ptr::null_mut();
+    };
+    let block = alloc::alloc(layout);
+    if block.is_null() {
+        return std::ptr::null_mut();
+    }
+    let memory = block.add(header_space(layout.align())) as *mut c_void;
+    header(memory).write(Header {
+        size,
+        alignment: layout.align(),
+        scope: allocation_scope,
+    });
+
+    let tally = (*(p_user_data as *const Tally)).scope(allocation_scope);
+    let bytes = tally.bytes.fetch_add(size, Ordering::Relaxed) + size;
+    tally.peak_bytes.fetch_max(bytes, Ordering::Relaxed);
+    tally.allocations.fetch_add(1, Ordering::Relaxed);
+    tally.total_allocations.fetch_add(1, Ordering::Relaxed);
+    memory
+}
+
+unsafe extern "system" fn reallocation(
+    p_user_data: *mut c_void,
+    p_original: *mut c_void,
+    size: usize,
+    alignment: usize,
+    allocation_scope: vk::SystemAllocationScope,
+) -> *mut c_void {
+    if p_original.is_null() {
+        return allocation(p_user_data, size, alignment, allocation_scope);
+    }
+    if size == 0 {
+        free(p_user_data, p_original);
+        return std::ptr::null_mut();
+    }
+    let memory = allocation(p_user_data, size, alignment, allocation_scope);
+    if memory.is_null() {
+        // The original stays valid, as the spec requires.
+        return memory;
+    }
+    let original_size = (*header(p_original)).size;
+    std::ptr::copy_nonoverlapping(
+        p_original as *const u8,
+        memory as *mut u8,
+        original_size.min(size),
+    );
+    free(p_user_data, p_original);
+    memory
+}
+
+unsafe extern "system" fn free(p_user_data: *mut c_void, p_memory: *mut c_void) {
+    if p_memory.is_null() {
+        return;
+    }
+    let Header {
+        size,
+        alignment,
+        scope,
+    } = header(p_memory).read();
+    let tally = (*(p_user_data as *const Tally)).scope(scope);
+    tally.bytes.fetch_sub(size, Ordering::Relaxed);
+    tally.allocations.fetch_sub(1, Ordering::Relaxed);
+
+    let space = header_space(alignment);
+    let block = (p_memory as *mut u8).sub(space);
+    alloc::dealloc(
+        block,
+        Layout::from_size_align_unchecked(size + space, alignment),
+    );
+}
+
+unsafe extern "system" fn internal_allocation(
+    p_user_data: *mut c_void,
+    size: usize,
+    _allocation_type: vk::InternalAllocationType,
+    allocation_scope: vk::SystemAllocationScope,
+) {
+    let tally = (*(p_user_data as *const Tally)).scope(allocation_scope);
+    tally.internal_bytes.fetch_add(size, Ordering::Relaxed);
+}
+
+unsafe extern "system" fn internal_free(
+    p_user_data: *mut c_void,
+    size: usize,
+    _allocation_type: vk::InternalAllocationType,
+    allocation_scope: vk::SystemAllocationScope,
+) {
+    let tally = (*(p_user_data as *const Tally)).scope(allocation_scope);
+    tally.internal_bytes.fetch_sub(size, Ordering::Relaxed);
+}
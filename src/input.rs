@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+
+use std::time::Instant;
+
+use anyhow::{Ok, Result};
+use winit::event::{Event, MouseButton, VirtualKeyCode};
+use winit::window::{CursorGrabMode, Window};
+
+use crate::input_recording::{InputEvent, InputMode, InputRecording, RecordedEvent};
+
+#[cfg(feature = "gamepad")]
+const GAMEPAD_DEADZONE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    SpeedUp,
+    SlowDown,
+    TurnLeft,
+    TurnRight,
+    TurnUp,
+    TurnDown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    MouseButton(MouseButton),
+    #[cfg(feature = "gamepad")]
+    GamepadButton(gilrs::Button),
+    /// Gamepad axis plus the direction (`1.0` or `-1.0`) that triggers the action.
+    #[cfg(feature = "gamepad")]
+    GamepadAxis(gilrs::Axis, f32),
+}
+
+pub struct Input {
+    pub pressed_keys: HashSet<VirtualKeyCode>,
+    pub pressed_buttons: HashSet<MouseButton>,
+    pub cursor_position: (f64, f64),
+    pub mouse_delta: (f64, f64),
+    pub scroll_delta: f32,
+    pub cursor_captured: bool,
+    pub bindings: HashMap<Action, Vec<Binding>>,
+    /// Change through [`Input::start_recording`] and [`Input::start_replay`].
+    pub mode: InputMode,
+    #[cfg(feature = "gamepad")]
+    pub gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        let mut input = Self {
+            pressed_keys: HashSet::new(),
+            pressed_buttons: HashSet::new(),
+            cursor_position: (0.0, 0.0),
+            mouse_delta: (0.0, 0.0),
+            scroll_delta: 0.0,
+            cursor_captured: false,
+            bindings: HashMap::new(),
+            mode: InputMode::Live,
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new().ok(),
+        };
+
+        input.bind(Action::MoveForward, Binding::Key(VirtualKeyCode::Up));
+        input.bind(Action::MoveForward, Binding::Key(VirtualKeyCode::W));
+        input.bind(Action::MoveBackward, Binding::Key(VirtualKeyCode::Down));
+        input.bind(Action::MoveBackward, Binding::Key(VirtualKeyCode::S));
+        input.bind(Action::MoveLeft, Binding::Key(VirtualKeyCode::A));
+        input.bind(Action::MoveRight, Binding::Key(VirtualKeyCode::D));
+        input.bind(Action::MoveUp, Binding::Key(VirtualKeyCode::Space));
+        input.bind(Action::MoveDown, Binding::Key(VirtualKeyCode::C));
+        input.bind(Action::SpeedUp, Binding::Key(VirtualKeyCode::LShift));
+        input.bind(Action::SlowDown, Binding::Key(VirtualKeyCode::LControl));
+        input.bind(Action::TurnLeft, Binding::Key(VirtualKeyCode::Left));
+        input.bind(Action::TurnRight, Binding::Key(VirtualKeyCode::Right));
+        input.bind(Action::TurnUp, Binding::Key(VirtualKeyCode::PageUp));
+        input.bind(Action::TurnUp, Binding::Key(VirtualKeyCode::Q));
+        input.bind(Action::TurnDown, Binding::Key(VirtualKeyCode::PageDown));
+        input.bind(Action::TurnDown, Binding::Key(VirtualKeyCode::E));
+
+        #[cfg(feature = "gamepad")]
+        {
+            use gilrs::Axis;
+            input.bind(
+                Action::MoveForward,
+                Binding::GamepadAxis(Axis::LeftStickY, 1.0),
+            );
+            input.bind(
+                Action::MoveBackward,
+                Binding::GamepadAxis(Axis::LeftStickY, -1.0),
+            );
+            input.bind(
+                Action::TurnLeft,
+                Binding::GamepadAxis(Axis::RightStickX, -1.0),
+            );
+            input.bind(
+                Action::TurnRight,
+                Binding::GamepadAxis(Axis::RightStickX, 1.0),
+            );
+            input.bind(Action::TurnUp, Binding::GamepadAxis(Axis::RightStickY, 1.0));
+            input.bind(
+                Action::TurnDown,
+                Binding::GamepadAxis(Axis::RightStickY, -1.0),
+            );
+        }
+
+        input
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.bindings.entry(action).or_default().push(binding);
+    }
+
+    pub fn unbind_all(&mut self, action: Action) {
+        self.bindings.remove(&action);
+    }
+
+    /// How strongly the action is requested this frame, between 0 and 1.
+    /// Keys and buttons are either 0 or 1, gamepad axes are analog.
+    pub fn action_value(&self, action: Action) -> f32 {
+        self.bindings.get(&action).map_or(0.0, |bindings| {
+            bindings
+                .iter()
+                .map(|binding| self.binding_value(binding))
+                .fold(0.0, f32::max)
+        })
+    }
+
+    pub fn is_action_pressed(&self, action: Action) -> bool {
+        self.action_value(action) > 0.5
+    }
+
+    fn binding_value(&self, binding: &Binding) -> f32 {
+        match binding {
+            Binding::Key(keycode) => self.is_key_pressed(*keycode) as u8 as f32,
+            Binding::MouseButton(button) => self.is_button_pressed(*button) as u8 as f32,
+            // Gamepads are not recorded, so they would make a replay diverge.
+            #[cfg(feature = "gamepad")]
+            Binding::GamepadButton(_) | Binding::GamepadAxis(..) if self.is_replaying() => 0.0,
+            #[cfg(feature = "gamepad")]
+            Binding::GamepadButton(button) => self.gilrs.as_ref().map_or(0.0, |gilrs| {
+                gilrs
+                    .gamepads()
+                    .any(|(_, gamepad)| gamepad.is_pressed(*button)) as u8 as f32
+            }),
+            #[cfg(feature = "gamepad")]
+            Binding::GamepadAxis(axis, direction) => self.gilrs.as_ref().map_or(0.0, |gilrs| {
+                gilrs
+                    .gamepads()
+                    .map(|(_, gamepad)| gamepad.value(*axis) * direction)
+                    .filter(|value| *value > GAMEPAD_DEADZONE)
+                    .fold(0.0, f32::max)
+                    .min(1.0)
+            }),
+        }
+    }
+
+    /// Drains pending gamepad events so the cached gamepad state is current.
+    /// Call once per frame before reading actions.
+    pub fn update_gamepads(&mut self) {
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = &mut self.gilrs {
+            while gilrs.next_event().is_some() {}
+        }
+    }
+
+    /// Reads the input in `event`, recording it when recording. Ignored while replaying.
+    pub fn process_event<T>(&mut self, event: &Event<T>) {
+        let Some(event) = InputEvent::from_event(event) else {
+            return;
+        };
+        match &mut self.mode {
+            InputMode::Live => {}
+            InputMode::Recording { recording, started } => {
+                recording.events.push(RecordedEvent {
+                    frame: recording.frames,
+                    time: started.elapsed().as_secs_f32(),
+                    event,
+                });
+            }
+            // Live input would make the replay diverge.
+            InputMode::Replaying { .. } => return,
+        }
+        self.apply(event);
+    }
+
+    pub fn apply(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Key { key, pressed: true } => {
+                self.pressed_keys.insert(key);
+            }
+            InputEvent::Key {
+                key,
+                pressed: false,
+            } => {
+                self.pressed_keys.remove(&key);
+            }
+            InputEvent::MouseButton {
+                button,
+                pressed: true,
+            } => {
+                self.pressed_buttons.insert(button);
+            }
+            InputEvent::MouseButton {
+                button,
+                pressed: false,
+            } => {
+                self.pressed_buttons.remove(&button);
+            }
+            InputEvent::CursorMoved { x, y } => self.cursor_position = (x, y),
+            InputEvent::Scroll(lines) => self.scroll_delta += lines,
+            InputEvent::MouseMotion { x, y } => {
+                self.mouse_delta.0 += x;
+                self.mouse_delta.1 += y;
+            }
+            InputEvent::FocusLost => self.release_all(),
+        }
+    }
+
+    /// Records the events processed from now on, each frame advancing by `timestep`
+    /// seconds; see [`Input::frame_delta`].
+    pub fn start_recording(&mut self, timestep: f32) {
+        self.mode = InputMode::Recording {
+            recording: InputRecording::new(timestep),
+            started: Instant::now(),
+        };
+    }
+
+    /// The recording so far, if recording; input is live again afterwards.
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        match std::mem::take(&mut self.mode) {
+            InputMode::Recording { recording, .. } => Some(recording),
+            mode => {
+                self.mode = mode;
+                None
+            }
+        }
+    }
+
+    /// Feeds `recording` back frame by frame in place of the live events, from a released
+    /// state, until its last frame ends and input is live again. Applications that
+    /// advance by [`Input::frame_delta`] then run as they did while it was recorded.
+    pub fn start_replay(&mut self, recording: InputRecording) {
+        self.release_all();
+        self.cursor_position = (0.0, 0.0);
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll_delta = 0.0;
+        self.mode = InputMode::Replaying {
+            recording,
+            frame: 0,
+        };
+        self.replay_frame();
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.mode, InputMode::Replaying { .. })
+    }
+
+    /// Seconds the frame should advance the application by: the recording's fixed step
+    /// while recording or replaying, `delta` otherwise.
+    pub fn frame_delta(&self, delta: f32) -> f32 {
+        match &self.mode {
+            InputMode::Live => delta,
+            InputMode::Recording { recording, .. } | InputMode::Replaying { recording, .. } => {
+                recording.timestep
+            }
+        }
+    }
+
+    /// Applies the replayed events of the current frame.
+    fn replay_frame(&mut self) {
+        let InputMode::Replaying { recording, frame } = &self.mode else {
+            return;
+        };
+        let events: Vec<InputEvent> = recording.events_of(*frame).copied().collect();
+        for event in events {
+            self.apply(event);
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.pressed_keys.clear();
+        self.pressed_buttons.clear();
+    }
+
+    pub fn is_key_pressed(&self, keycode: VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&keycode)
+    }
+
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    /// Grabs and hides the cursor so relative mouse motion can drive the camera.
+    /// Not every platform supports both grab modes, so `Confined` falls back to `Locked`.
+    pub fn set_cursor_captured(&mut self, window: &Window, captured: bool) -> Result<()> {
+        if captured {
+            window
+                .set_cursor_grab(CursorGrabMode::Confined)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Locked))?;
+        } else {
+            window.set_cursor_grab(CursorGrabMode::None)?;
+        }
+        window.set_cursor_visible(!captured);
+        self.cursor_captured = captured;
+        self.mouse_delta = (0.0, 0.0);
+
+        Ok(())
+    }
+
+    /// Resets the per-frame deltas; call once after the frame consumed them. Moves a
+    /// recording or replay on to the next frame.
+    pub fn end_frame(&mut self) {
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll_delta = 0.0;
+        match &mut self.mode {
+            InputMode::Live => {}
+            InputMode::Recording { recording, .. } => recording.frames += 1,
+            InputMode::Replaying { recording, frame } => {
+                *frame += 1;
+                if *frame >= recording.frames {
+                    self.mode = InputMode::Live;
+                    self.release_all();
+                } else {
+                    self.replay_frame();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::time::Instant;
+
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+/// What [`crate::input::Input`] takes from one window or device event, free of winit's
+/// lifetimes so it can be recorded and fed back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InputEvent {
+    Key {
+        key: VirtualKeyCode,
+        pressed: bool,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    /// In lines; pixel deltas are converted at 20 pixels a line.
+    Scroll(f32),
+    MouseMotion {
+        x: f64,
+        y: f64,
+    },
+    /// Releases every key and button.
+    FocusLost,
+}
+
+impl InputEvent {
+    /// The input `event` carries, if it is one the input subsystem reads.
+    pub fn from_event<T>(event: &Event<T>) -> Option<Self> {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(keycode),
+                            ..
+                        },
+                    ..
+                } => Some(InputEvent::Key {
+                    key: *keycode,
+                    pressed: *state == ElementState::Pressed,
+                }),
+                WindowEvent::MouseInput { state, button, .. } => Some(InputEvent::MouseButton {
+                    button: *button,
+                    pressed: *state == ElementState::Pressed,
+                }),
+                WindowEvent::CursorMoved { position, .. } => Some(InputEvent::CursorMoved {
+                    x: position.x,
+                    y: position.y,
+                }),
+                WindowEvent::MouseWheel { delta, .. } => Some(InputEvent::Scroll(match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                })),
+                WindowEvent::Focused(false) => Some(InputEvent::FocusLost),
+                _ => None,
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => Some(InputEvent::MouseMotion {
+                x: delta.0,
+                y: delta.1,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An event with the frame it arrived in and when.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordedEvent {
+    /// Frames ended before the event, counted by [`crate::input::Input::end_frame`].
+    pub frame: u64,
+    /// Seconds since the recording started. Replays go by `frame`; this is for reading.
+    pub time: f32,
+    pub event: InputEvent,
+}
+
+/// Input events over a number of frames, each advancing the application by `timestep`
+/// seconds, see [`crate::input::Input::start_recording`]. Gamepads are not recorded.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputRecording {
+    pub timestep: f32,
+    pub frames: u64,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl InputRecording {
+    pub fn new(timestep: f32) -> Self {
+        InputRecording {
+            timestep,
+            frames: 0,
+            events: vec![],
+        }
+    }
+
+    /// The events of `frame`, in the order they arrived.
+    pub fn events_of(&self, frame: u64) -> impl Iterator<Item = &InputEvent> {
+        let first = self.events.partition_point(|event| event.frame < frame);
+        self.events[first..]
+            .iter()
+            .take_while(move |event| event.frame == frame)
+            .map(|event| &event.event)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        anyhow::Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        anyhow::Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// Whether [`crate::input::Input`] reads the live events, records them or replays a
+/// recording in their place.
+#[derive(Debug, Default)]
+pub enum InputMode {
+    #[default]
+    Live,
+    Recording {
+        recording: InputRecording,
+        started: Instant,
+    },
+    /// At frame `frame` of `recording`.
+    Replaying {
+        recording: InputRecording,
+        frame: u64,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalPosition;
+    use winit::event::{DeviceId, ModifiersState, TouchPhase};
+    use winit::window::WindowId;
+
+    use super::*;
+    use crate::input::{Action, Input};
+
+    const ACTIONS: [Action; 12] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::SpeedUp,
+        Action::SlowDown,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::TurnUp,
+        Action::TurnDown,
+    ];
+
+    fn window_event(event: WindowEvent<'static>) -> Event<'static, ()> {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn key(key: VirtualKeyCode, pressed: bool) -> Event<'static, ()> {
+        window_event(WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state: if pressed {
+                    ElementState::Pressed
+                } else {
+                    ElementState::Released
+                },
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        })
+    }
+
+    fn mouse_motion(x: f64, y: f64) -> Event<'static, ()> {
+        Event::DeviceEvent {
+            device_id: unsafe { DeviceId::dummy() },
+            event: DeviceEvent::MouseMotion { delta: (x, y) },
+        }
+    }
+
+    #[allow(deprecated)]
+    fn scroll(lines: f32) -> Event<'static, ()> {
+        window_event(WindowEvent::MouseWheel {
+            device_id: unsafe { DeviceId::dummy() },
+            delta: MouseScrollDelta::LineDelta(0.0, lines),
+            phase: TouchPhase::Moved,
+            modifiers: ModifiersState::empty(),
+        })
+    }
+
+    #[allow(deprecated)]
+    fn cursor(x: f64, y: f64) -> Event<'static, ()> {
+        window_event(WindowEvent::CursorMoved {
+            device_id: unsafe { DeviceId::dummy() },
+            position: PhysicalPosition::new(x, y),
+            modifiers: ModifiersState::empty(),
+        })
+    }
+
+    /// Everything a frame reads from the input before it ends.
+    fn state(input: &Input) -> (Vec<f32>, (f64, f64), (f64, f64), f32) {
+        (
+            ACTIONS.map(|action| input.action_value(action)).to_vec(),
+            input.cursor_position,
+            input.mouse_delta,
+            input.scroll_delta,
+        )
+    }
+
+    #[test]
+    fn replaying_a_recording_reproduces_every_frame() {
+        let frames: Vec<Vec<Event<'static, ()>>> = vec![
+            vec![key(VirtualKeyCode::W, true), mouse_motion(3.0, -1.0)],
+            vec![],
+            vec![key(VirtualKeyCode::LShift, true), cursor(12.0, 40.0)],
+            vec![mouse_motion(-2.0, 0.5), mouse_motion(1.0, 1.0), scroll(2.0)],
+            vec![
+                key(VirtualKeyCode::W, false),
+                key(VirtualKeyCode::Left, true),
+            ],
+            vec![window_event(WindowEvent::Focused(false))],
+            vec![key(VirtualKeyCode::E, true)],
+        ];
+
+        let mut input = Input::new();
+        input.start_recording(1.0 / 60.0);
+        let mut recorded_states = vec![];
+        for events in &frames {
+            for event in events {
+                input.process_event(event);
+            }
+            recorded_states.push(state(&input));
+            input.end_frame();
+        }
+        let recording = input.stop_recording().unwrap();
+        assert_eq!(recording.frames, frames.len() as u64);
+        assert_eq!(recording.events_of(3).count(), 3);
+
+        let mut replay = Input::new();
+        replay.start_replay(recording);
+        assert_eq!(replay.frame_delta(0.5), 1.0 / 60.0);
+        for recorded in &recorded_states {
+            // Live input is ignored while replaying.
+            replay.process_event(&key(VirtualKeyCode::S, true));
+            replay.process_event(&mouse_motion(100.0, 100.0));
+            assert_eq!(&state(&replay), recorded);
+            replay.end_frame();
+        }
+        assert!(!replay.is_replaying());
+        assert!(replay.pressed_keys.is_empty());
+    }
+}
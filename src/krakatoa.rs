@@ -1,55 +1,316 @@
+use std::collections::HashMap;
+
+use crate::adapter::{adapter_from_env, enumerate_adapters, software_from_env, AdapterInfo};
+use crate::allocations::AllocationReport;
 use crate::buffer::Buffer;
+use crate::camera::Camera;
+use crate::capabilities::DeviceCapabilities;
+use crate::clear::ClearValues;
+use crate::conditional_rendering::ConditionalRendering;
+use crate::crash_diagnostics::{CrashDiagnostics, CrashReport};
 use crate::create_command_buffers;
-use crate::model::{InstanceData, Model, VertexData};
-use crate::pipeline::Pipeline;
+use crate::cutout::CutoutPass;
+use crate::depth_of_field::DepthOfField;
+use crate::depth_prepass::DepthPrepass;
+use crate::environment::{convolve, BakeSettings, BakedEnvironment, CaptureCube, EnvironmentMap};
+use crate::fog::Fog;
+use crate::foliage::Foliage;
+use crate::frame_graph::{FrameGraph, PassKind};
+use crate::frame_state::{
+    BufferState, DescriptorBinding, DrawState, FrameState, PipelineState, PixelRect,
+};
+use crate::gpu_sort::GpuSort;
+use crate::grid::Grid;
+use crate::hdr::{OutputColourSpace, OutputEncode};
+use crate::host_memory::{HostAllocator, HostMemoryStats};
+use crate::krakatoa_builder::KrakatoaBuilder;
+use crate::layout_cache::LayoutCache;
+use crate::lens_flare::LensFlare;
+use crate::light::DirectionalLight;
+use crate::light_shafts::LightShafts;
+use crate::marching_cubes::{DensityGrid, MarchingCubes};
+use crate::mesh_shader::MeshShaderPass;
+use crate::model::{
+    Instance, InstanceData, InstanceSet, Mesh, MeshHandle, MeshLibrary, Model, NoiseTerrain,
+    TerrainStreamer, VertexData,
+};
+use crate::occlusion::OcclusionCulling;
+use crate::oit::WeightedBlendedOit;
+use crate::outline::Outline;
+use crate::pipeline::{BlendMode, Pipeline, Topology};
+use crate::pipeline_registry::{PipelineRegistry, PipelineStatus};
 use crate::pools::Pools;
+use crate::post::{PostProcess, PostSettings};
+use crate::push_descriptor::PushDescriptors;
+use crate::raw_context::RawContext;
+use crate::ray_query::{begin_one_time, end_one_time, RayQueryScene};
+use crate::readback::{self, AttachmentId};
+use crate::reflection::{ReflectionProbe, Reflections, MAX_REFLECTION_PROBES};
+use crate::render_mode::RenderMode;
+use crate::render_targets::RenderTargetCache;
+use crate::sampler::create_sampler;
+use crate::sky::Sky;
+use crate::splat_terrain::{SplatMaterial, SplatTerrainPass, SplatTextures};
+use crate::storage_instancing::StorageInstancedPass;
+use crate::texture::Texture;
+use crate::timing::{DisplayTiming, FrameTimer};
+use crate::trail::Trails;
+use crate::transparency::{TransparencyMode, TransparentPass};
+use crate::window_mode::WindowMode;
 use crate::{
+    choose_depth_format,
     debug::Debug,
-    init_device_and_queues, init_instance, init_physical_device_and_properties, init_renderpass,
+    device_extension_supported, format_has_stencil, init_descriptor_sets, init_device_and_queues,
+    init_instance, init_physical_device_and_properties, init_renderpass, instance_api_version,
+    instance_extension_supported, instance_layer_supported,
     queue::{QueueFamilies, Queues},
     surface::Surface,
     swapchain::Swapchain,
 };
-use anyhow::{Ok, Result};
-use ash::vk::{self};
+use anyhow::{bail, Ok, Result};
+use ash::vk::{self, Handle};
 use nalgebra::{Matrix4, Vector3};
 
-pub struct Krakatoa {
+/// Records extra commands into a frame, see [`Krakatoa::render_callback`]. Receives the
+/// device, the frame's command buffer and the swapchain image index.
+pub type RenderCallback = Box<dyn FnMut(&ash::Device, vk::CommandBuffer, usize)>;
+
+/// The camera distance [`RenderMode::Depth`] shows as white unless changed.
+const DEFAULT_DEPTH_RANGE: f32 = 100.0;
+
+/// An image outside the swapchain that each frame is also scaled into, see
+/// [`Krakatoa::blit_target`].
+#[derive(Clone, Copy, Debug)]
+pub struct BlitTarget {
+    pub image: vk::Image,
+    pub extent: vk::Extent2D,
+    /// Layout the image is left in once the frame has been copied.
+    pub final_layout: vk::ImageLayout,
+}
+
+/// The renderer. `I` is the per-instance data of every model, [`InstanceData`] unless
+/// built with [`Krakatoa::init_custom`].
+pub struct Krakatoa<I: Instance = InstanceData> {
     pub window: winit::window::Window,
     pub entry: ash::Entry,
     pub instance: ash::Instance,
-    pub debug: Debug,
+    /// Absent when built with [`Krakatoa::from_raw`].
+    pub debug: Option<Debug>,
+    /// False when built with [`Krakatoa::from_raw`]: the device and instance belong to
+    /// the application and outlive the engine.
+    pub owns_context: bool,
+    /// Serves the driver's host memory when [`KrakatoaBuilder::track_host_memory`] is set.
+    /// The device and instance are destroyed with it, so it must outlive them.
+    host_allocator: Option<HostAllocator>,
     pub surface: Surface,
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
+    pub physical_device_features: vk::PhysicalDeviceFeatures,
+    /// API version and optional features in use; check before relying on any of them.
+    pub capabilities: DeviceCapabilities,
+    /// Default sampler anisotropy, already clamped to what the device supports.
+    pub max_anisotropy: f32,
     pub physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub queue_families: QueueFamilies,
     pub queues: Queues,
     pub logical_device: ash::Device,
     pub swapchain: Swapchain,
+    /// Owns the swapchain's attachments and framebuffers, kept across recreation.
+    pub render_targets: RenderTargetCache,
     pub renderpass: vk::RenderPass,
     pub pipeline: Pipeline,
+    /// Variants of `pipeline` for other topologies, compiled in the background as meshes
+    /// first need them.
+    pub pipeline_registry: PipelineRegistry<(Topology, bool)>,
+    /// Owns the layouts of `pipeline` and of the pipelines sharing its interface.
+    pub layout_cache: LayoutCache,
     pub pools: Pools,
     pub command_buffers: Vec<vk::CommandBuffer>,
-    pub models: Vec<Model<VertexData, InstanceData>>,
+    pub models: Vec<Model<VertexData, I>>,
+    /// Meshes shared by `instance_sets`, see [`Krakatoa::add_mesh`].
+    pub meshes: MeshLibrary<VertexData>,
+    /// Instances drawn with a mesh from `meshes`. Only the opaque pipelines draw them; the
+    /// transparent, outline, ray query and meshlet paths work on `models`.
+    pub instance_sets: Vec<(MeshHandle, InstanceSet<I>)>,
+    /// The camera's view and projection, then the time from [`Krakatoa::clock`].
     pub uniform_buffer: Buffer,
+    /// The view and projection matrices of the last frame, for [`Krakatoa::frame_state`].
+    last_camera: Option<[[[f32; 4]; 4]; 2]>,
+    /// Ticked once per rendered frame; shaders animate by its elapsed time.
+    pub clock: FrameTimer,
+    pub light: DirectionalLight,
+    pub fog: Fog,
+    /// Read when each frame is recorded, see [`Krakatoa::set_clear_colour`].
+    pub clear: ClearValues,
+    /// Width over height the scene is framed at, with black bars filling the rest of the
+    /// window. Follows the window when `None`.
+    pub aspect_lock: Option<f32>,
+    /// Scissor rectangles, in swapchain pixels, clipping the models at their indices;
+    /// see [`Krakatoa::set_clip_rect`].
+    pub clip_rects: HashMap<usize, vk::Rect2D>,
+    /// What the fragment shaders output; anything but `Lit` is for debugging.
+    pub render_mode: RenderMode,
+    /// The camera distance [`RenderMode::Depth`] shows as white.
+    pub depth_range: f32,
+    pub light_buffer: Buffer,
     pub descriptor_pool: vk::DescriptorPool,
     pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub display_timing: Option<DisplayTiming>,
+    /// Present when `capabilities.push_descriptor` is.
+    pub push_descriptors: Option<PushDescriptors>,
+    /// Present when `capabilities.conditional_rendering` is.
+    pub conditional_rendering: Option<ConditionalRendering>,
+    pub sky: Option<Sky>,
+    pub grid: Option<Grid>,
+    /// Ribbons behind moving things, see [`Krakatoa::enable_trails`].
+    pub trails: Option<Trails>,
+    /// Instances swaying in the wind, see [`Krakatoa::enable_foliage`].
+    pub foliage: Option<Foliage<I>>,
+    /// Draws cutout instances, see [`Krakatoa::enable_cutouts`].
+    pub cutouts: Option<CutoutPass>,
+    /// GPU culling against last frame's depth, see [`Krakatoa::enable_occlusion_culling`].
+    pub occlusion_culling: Option<OcclusionCulling>,
+    /// Baked lighting for shaders to sample, see [`Krakatoa::set_environment`].
+    pub environment: Option<EnvironmentMap>,
+    pub outline: Option<Outline>,
+    pub depth_prepass: Option<DepthPrepass>,
+    /// Models drawn as meshlets, see [`Krakatoa::enable_mesh_shader`].
+    pub mesh_shader: Option<MeshShaderPass>,
+    /// Models whose instances are read from storage buffers, see
+    /// [`Krakatoa::enable_storage_instancing`].
+    pub storage_instancing: Option<StorageInstancedPass>,
+    /// Acceleration structures over `models`, see [`Krakatoa::enable_ray_query`].
+    pub ray_query: Option<RayQueryScene>,
+    /// Meshes density fields on the GPU, see [`Krakatoa::enable_marching_cubes`].
+    pub marching_cubes: Option<MarchingCubes>,
+    /// Sorts key and value pairs on the GPU, see [`Krakatoa::sort_pairs`].
+    pub gpu_sort: Option<GpuSort>,
+    /// Markers for explaining a lost device, see [`Krakatoa::enable_crash_diagnostics`].
+    pub crash_diagnostics: Option<CrashDiagnostics>,
+    /// Present while any post-processing effect is enabled.
+    pub post: Option<PostProcess>,
+    pub depth_of_field: Option<DepthOfField>,
+    pub lens_flare: Option<LensFlare>,
+    pub light_shafts: Option<LightShafts>,
+    /// Planar reflections and reflection probes, see [`Krakatoa::enable_reflections`].
+    pub reflections: Option<Reflections>,
+    pub transparent_pass: TransparentPass,
+    pub transparency_mode: TransparencyMode,
+    pub oit: WeightedBlendedOit,
+    /// Present when the swapchain was created for an HDR colour space.
+    pub output_encode: Option<OutputEncode>,
+    /// Selected instances as (index into `models`, instance handle) pairs.
+    pub selected: Vec<(usize, usize)>,
+    pub terrain: Option<TerrainStreamer<I>>,
+    /// Draws `terrain` with a splat-map material, see [`Krakatoa::enable_splat_terrain`].
+    pub splat_terrain: Option<SplatTerrainPass>,
+    /// Change through [`Krakatoa::set_window_mode`] so the swapchain follows.
+    pub window_mode: WindowMode,
+    /// Set from `WindowEvent::Occluded`; nothing is rendered while the window is hidden.
+    pub occluded: bool,
+    /// Between [`Krakatoa::suspend`] and [`Krakatoa::resume`], without a surface or swapchain.
+    pub suspended: bool,
+    /// Called inside the render pass each frame, in subpass 0 after the opaque geometry,
+    /// grid and outline, with the viewport and scissor covering the swapchain. Pipelines
+    /// must be made for [`Krakatoa::render_pass`] subpass 0 and the engine's sample count.
+    pub render_callback: Option<RenderCallback>,
+    /// Receives a linearly filtered copy of every finished frame, after the render pass.
+    /// Needs `TRANSFER_SRC` in the swapchain's image usage and `TRANSFER_DST` on the image.
+    pub blit_target: Option<BlitTarget>,
+    /// Swapchain image the latest frame was recorded for.
+    pub image_index: usize,
+    /// Extensions asked for through the builder that the loader or GPU did not offer.
+    pub unavailable_extensions: Vec<std::ffi::CString>,
+    /// Layers asked for through the builder that are not installed.
+    pub unavailable_layers: Vec<std::ffi::CString>,
 }
 
 impl Krakatoa {
+    pub fn builder() -> KrakatoaBuilder {
+        KrakatoaBuilder::default()
+    }
+
+    /// Lists the physical devices, whose indices [`KrakatoaBuilder::adapter`] and the
+    /// `KRAKATOA_GPU` environment variable accept. Uses a short-lived instance of its own.
+    pub fn enumerate_adapters() -> Result<Vec<AdapterInfo>> {
+        let entry = ash::Entry::linked();
+        let instance = init_instance(&entry, &[], &[], false, None)?;
+        let adapters = enumerate_adapters(&instance);
+        unsafe { instance.destroy_instance(None) };
+        adapters
+    }
+
     pub fn init(window: winit::window::Window) -> Result<Self> {
+        Self::builder().build(window)
+    }
+
+    pub fn init_with(window: winit::window::Window, builder: KrakatoaBuilder) -> Result<Self> {
+        Self::init_custom(window, builder)
+    }
+
+    /// Builds the renderer on a Vulkan context the application already owns, such as an
+    /// OpenXR runtime's or a host program's, instead of creating its own. Only the surface
+    /// and swapchain for `window` are created; extensions and host memory tracking
+    /// requested through the builder are ignored, and neither the device nor the instance
+    /// is destroyed on drop.
+    pub fn from_raw(
+        window: winit::window::Window,
+        context: RawContext,
+        builder: KrakatoaBuilder,
+    ) -> Result<Self> {
+        Self::from_raw_custom(window, context, builder)
+    }
+}
+
+impl<I: Instance> Krakatoa<I> {
+    /// [`Krakatoa::init_with`] for models whose instances are `I`, whose extra fields the
+    /// shaders find in the locations after [`InstanceData`]'s.
+    pub fn init_custom(window: winit::window::Window, builder: KrakatoaBuilder) -> Result<Self> {
         let entry = ash::Entry::linked();
-        let instance = init_instance(&entry)?;
+        let mut unavailable_extensions = vec![];
+        let mut unavailable_layers = vec![];
+        let mut instance_extensions = vec![];
+        for name in &builder.instance_extensions {
+            if instance_extension_supported(&entry, name)? {
+                instance_extensions.push(name.as_c_str());
+            } else {
+                unavailable_extensions.push(name.clone());
+            }
+        }
+        let mut layers = vec![];
+        for name in &builder.layers {
+            if instance_layer_supported(&entry, name)? {
+                layers.push(name.as_c_str());
+            } else {
+                unavailable_layers.push(name.clone());
+            }
+        }
+        let host_allocator = builder.track_host_memory.then(HostAllocator::new);
+        let allocation_callbacks = host_allocator.as_ref().map(HostAllocator::callbacks);
+        let instance = init_instance(
+            &entry,
+            &instance_extensions,
+            &layers,
+            builder.shader_debug_printf,
+            allocation_callbacks.as_ref(),
+        )?;
         let debug = Debug::init(&entry, &instance)?;
 
-        let (physical_device, physical_device_properties, physical_device_features) =
-            init_physical_device_and_properties(&instance)?;
+        let (physical_device, _, physical_device_features) = init_physical_device_and_properties(
+            &instance,
+            adapter_from_env().or(builder.adapter),
+            builder.prefer_software || software_from_env(),
+        )?;
 
-        let memory_properties =
-            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let mut capabilities =
+            DeviceCapabilities::query(&instance, physical_device, instance_api_version(&entry)?)?;
+        if !builder.robustness {
+            capabilities.robustness2 = None;
+        }
 
-        let surface = Surface::init(&window, &entry, &instance)?;
+        // Only mutated for exclusive fullscreen, which is Windows only.
+        #[cfg_attr(not(target_os = "windows"), allow(unused_mut))]
+        let mut surface = Surface::init(&window, &entry, &instance)?;
 
         /* Queues */
 
@@ -57,42 +318,227 @@ impl Krakatoa {
 
         /* Logical Device */
 
+        let display_timing_supported = device_extension_supported(
+            &instance,
+            physical_device,
+            vk::GoogleDisplayTimingFn::name(),
+        )?;
+        let mut extra_device_extensions = vec![];
+        if display_timing_supported {
+            extra_device_extensions.push(vk::GoogleDisplayTimingFn::name());
+        }
+        #[cfg(target_os = "windows")]
+        if builder.full_screen_exclusive
+            && device_extension_supported(
+                &instance,
+                physical_device,
+                vk::ExtFullScreenExclusiveFn::name(),
+            )?
+        {
+            use winit::platform::windows::MonitorHandleExtWindows;
+            extra_device_extensions.push(vk::ExtFullScreenExclusiveFn::name());
+            surface.full_screen_exclusive = window
+                .current_monitor()
+                .map(|monitor| monitor.hmonitor() as vk::HMONITOR);
+        }
+
+        // `debugPrintfEXT` compiles to a non-semantic instruction, core since 1.3.
+        if builder.shader_debug_printf
+            && capabilities.api_version < vk::API_VERSION_1_3
+            && device_extension_supported(
+                &instance,
+                physical_device,
+                vk::KhrShaderNonSemanticInfoFn::name(),
+            )?
+        {
+            extra_device_extensions.push(vk::KhrShaderNonSemanticInfoFn::name());
+        }
+
+        for name in &builder.device_extensions {
+            if device_extension_supported(&instance, physical_device, name)? {
+                extra_device_extensions.push(name.as_c_str());
+            } else {
+                unavailable_extensions.push(name.clone());
+            }
+        }
+
         let (logical_device, queues) = init_device_and_queues(
             &instance,
             physical_device,
             physical_device_features,
+            &capabilities,
             &queue_families,
+            &extra_device_extensions,
+            allocation_callbacks.as_ref(),
         )?;
 
+        let mut krakatoa = Self::init_from_context(
+            window,
+            RawContext {
+                entry,
+                instance,
+                physical_device,
+                device: logical_device,
+                queue_families,
+                queues,
+                features: physical_device_features,
+                capabilities,
+            },
+            surface,
+            builder,
+        )?;
+        krakatoa.owns_context = true;
+        krakatoa.host_allocator = host_allocator;
+        krakatoa.debug = Some(debug);
+        krakatoa.unavailable_extensions = unavailable_extensions;
+        krakatoa.unavailable_layers = unavailable_layers;
+
+        /* Display Timing */
+        if display_timing_supported {
+            krakatoa.display_timing = Some(DisplayTiming::init(
+                &krakatoa.instance,
+                &krakatoa.logical_device,
+                &krakatoa.swapchain,
+            )?);
+        }
+
+        Ok(krakatoa)
+    }
+
+    /// [`Krakatoa::from_raw`] for models whose instances are `I`.
+    pub fn from_raw_custom(
+        window: winit::window::Window,
+        context: RawContext,
+        builder: KrakatoaBuilder,
+    ) -> Result<Self> {
+        let surface = Surface::init(&window, &context.entry, &context.instance)?;
+        let Some(graphics_q_index) = context.queue_families.graphics_q_index else {
+            bail!("the raw context has no graphics queue family");
+        };
+        let presentable = unsafe {
+            surface.surface_loader.get_physical_device_surface_support(
+                context.physical_device,
+                graphics_q_index,
+                surface.surface,
+            )?
+        };
+        if !presentable {
+            bail!("the raw context's graphics queue cannot present to the window");
+        }
+
+        Self::init_from_context(window, context, surface, builder)
+    }
+
+    /// Everything past device creation, shared by [`Krakatoa::init_custom`] and
+    /// [`Krakatoa::from_raw_custom`]. The result owns neither the device nor the instance.
+    fn init_from_context(
+        window: winit::window::Window,
+        context: RawContext,
+        mut surface: Surface,
+        builder: KrakatoaBuilder,
+    ) -> Result<Self> {
+        let RawContext {
+            entry,
+            instance,
+            physical_device,
+            device: logical_device,
+            queue_families,
+            queues,
+            features: physical_device_features,
+            capabilities,
+        } = context;
+        let physical_device_properties =
+            unsafe { instance.get_physical_device_properties(physical_device) };
+        let memory_properties =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+        /* Every supported feature is enabled, samplerAnisotropy included */
+        let max_anisotropy = if physical_device_features.sampler_anisotropy == vk::TRUE {
+            builder
+                .max_anisotropy
+                .min(physical_device_properties.limits.max_sampler_anisotropy)
+        } else {
+            1.0
+        };
+
+        let multisampling = builder
+            .multisampling
+            .supported(&physical_device_properties, &physical_device_features);
+
+        surface.output = builder.output;
+        if let Some(extent) = builder.extent {
+            window.set_inner_size(winit::dpi::PhysicalSize::new(extent.width, extent.height));
+            surface.desired_extent = extent;
+        }
+
         /* Renderpass */
-        let renderpass = init_renderpass(&logical_device, physical_device, &surface)?;
+        let depth_format = choose_depth_format(&instance, physical_device);
+        let renderpass = init_renderpass(
+            &logical_device,
+            physical_device,
+            &surface,
+            depth_format,
+            multisampling.samples,
+        )?;
 
         /* Swapchain */
+        let mut render_targets = RenderTargetCache::new();
         let mut swapchain = Swapchain::init(
             &instance,
             physical_device,
             &logical_device,
             &surface,
             &queue_families,
-            &queues,
+            multisampling.samples,
             memory_properties,
+            &mut render_targets,
         )?;
-        swapchain.create_framebuffers(&logical_device, renderpass)?;
+        swapchain.create_framebuffers(&logical_device, renderpass, &mut render_targets)?;
 
         /* Pipeline */
-        let pipeline = Pipeline::init(&logical_device, &swapchain, &renderpass)?;
+        let mut layout_cache = LayoutCache::new();
+        let mut pipeline = match builder.shaders.clone() {
+            Some(shaders) => Pipeline::init_with_shaders::<VertexData, I>(
+                &logical_device,
+                &renderpass,
+                multisampling,
+                &mut layout_cache,
+                shaders,
+            )?,
+            None => Pipeline::init::<VertexData, I>(
+                &logical_device,
+                &renderpass,
+                multisampling,
+                &mut layout_cache,
+            )?,
+        };
+        pipeline.set_blend_mode(&logical_device, &renderpass, builder.blend_mode)?;
+        if capabilities.graphics_pipeline_library {
+            pipeline.enable_library(&logical_device, &renderpass)?;
+        }
+        let transparent_pass = TransparentPass::init(&logical_device, &renderpass, &pipeline)?;
+        let oit = WeightedBlendedOit::init(&logical_device, &renderpass, &pipeline, &swapchain)?;
+        let output_encode = if swapchain.scene.is_some() {
+            Some(OutputEncode::init(
+                &logical_device,
+                &renderpass,
+                &swapchain,
+            )?)
+        } else {
+            None
+        };
 
         /* Mem Allocation */
-        let mut cube = Model::cube();
+        let mut cube = Model::new(Mesh::cube());
         let angle = 0.2;
-        cube.insert_visibly(InstanceData::from_matrix_and_colour(
-            Matrix4::from_scaled_axis(Vector3::new(0.0, 0.0, angle))
-                * Matrix4::new_translation(&Vector3::new(0.0, 0.5, 0.0))
-                * Matrix4::new_scaling(0.1),
-            [0.0, 0.5, 0.0],
-        ));
-        cube.update_vertex_buffer(&logical_device, memory_properties)?;
-        cube.update_instance_buffer(&logical_device, memory_properties)?;
+        cube.instances
+            .insert_visibly(I::from(InstanceData::from_matrix_and_colour(
+                Matrix4::from_scaled_axis(Vector3::new(0.0, 0.0, angle))
+                    * Matrix4::new_translation(&Vector3::new(0.0, 0.5, 0.0))
+                    * Matrix4::new_scaling(0.1),
+                [0.0, 0.5, 0.0],
+            )));
+        cube.upload(&logical_device, memory_properties)?;
 
         let models = vec![cube];
 
@@ -103,166 +549,2623 @@ impl Krakatoa {
 
         /* Uniform Buffers */
         let mut uniform_buffer = Buffer::init(
-            128,
+            std::mem::size_of::<FrameUniforms>(),
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             memory_properties,
             &logical_device,
         )?;
-        let camera_transforms: [[[f32; 4]; 4]; 2] =
-            [Matrix4::identity().into(), Matrix4::identity().into()];
-        uniform_buffer.fill(&logical_device, &camera_transforms, memory_properties)?;
-
-        /* Descriptor Pool */
-        let pool_sizes = [vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: swapchain.amount_of_images as u32,
-        }];
-        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
-            .max_sets(swapchain.amount_of_images as u32)
-            .pool_sizes(&pool_sizes);
-        let descriptor_pool =
-            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
-
-        let desc_layouts = vec![pipeline.descriptor_set_layouts[0]; swapchain.amount_of_images];
-        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
-            .descriptor_pool(descriptor_pool)
-            .set_layouts(&desc_layouts);
-        let descriptor_sets =
-            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?;
-
-        descriptor_sets.iter().for_each(|descset| {
-            let buffer_infos = [vk::DescriptorBufferInfo {
-                buffer: uniform_buffer.buffer,
-                offset: 0,
-                range: 128,
-            }];
-            let desc_sets_write = [vk::WriteDescriptorSet::builder()
-                .dst_set(*descset)
-                .dst_binding(0)
-                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .buffer_info(&buffer_infos)
-                .build()];
-            unsafe { logical_device.update_descriptor_sets(&desc_sets_write, &[]) };
-        });
+        let camera_transforms = FrameUniforms {
+            view: Matrix4::identity().into(),
+            projection: Matrix4::identity().into(),
+            time: [0.0; 4],
+        };
+        uniform_buffer.fill(&logical_device, &[camera_transforms], memory_properties)?;
+        uniform_buffer.set_name(&logical_device, "camera uniforms");
+
+        let light = DirectionalLight::default();
+        let fog = Fog::default();
+        let mut light_buffer = Buffer::init(
+            std::mem::size_of::<LightUniforms>(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            memory_properties,
+            &logical_device,
+        )?;
+        light_buffer.fill(
+            &logical_device,
+            &[light_uniform(
+                &light,
+                &fog,
+                RenderMode::Lit,
+                DEFAULT_DEPTH_RANGE,
+            )],
+            memory_properties,
+        )?;
+        light_buffer.set_name(&logical_device, "light uniforms");
+
+        /* Descriptor Sets */
+        let (descriptor_pool, descriptor_sets) = init_descriptor_sets(
+            &logical_device,
+            &pipeline,
+            &uniform_buffer,
+            &light_buffer,
+            swapchain.amount_of_images,
+        )?;
+        let push_descriptors = capabilities
+            .push_descriptor
+            .then(|| PushDescriptors::init(&instance, &logical_device, physical_device));
+        let conditional_rendering = capabilities
+            .conditional_rendering
+            .then(|| ConditionalRendering::init(&instance, &logical_device));
 
         Ok(Self {
             window,
             entry,
             instance,
-            debug,
+            debug: None,
+            owns_context: false,
+            host_allocator: None,
             surface,
             physical_device,
             physical_device_properties,
+            physical_device_features,
+            capabilities,
+            max_anisotropy,
             physical_device_memory_properties: memory_properties,
             queue_families,
             queues,
             logical_device,
             swapchain,
+            render_targets,
             renderpass,
             pipeline,
+            pipeline_registry: PipelineRegistry::new(),
+            layout_cache,
             pools,
             command_buffers,
             models,
+            meshes: MeshLibrary::new(),
+            instance_sets: Vec::new(),
             uniform_buffer,
+            last_camera: None,
+            clock: FrameTimer::new(),
+            light,
+            fog,
+            clear: ClearValues::default(),
+            aspect_lock: None,
+            clip_rects: HashMap::new(),
+            render_mode: RenderMode::Lit,
+            depth_range: DEFAULT_DEPTH_RANGE,
+            light_buffer,
             descriptor_pool,
             descriptor_sets,
+            display_timing: None,
+            push_descriptors,
+            conditional_rendering,
+            sky: None,
+            grid: None,
+            trails: None,
+            foliage: None,
+            cutouts: None,
+            occlusion_culling: None,
+            environment: None,
+            outline: None,
+            depth_prepass: None,
+            mesh_shader: None,
+            storage_instancing: None,
+            ray_query: None,
+            marching_cubes: None,
+            gpu_sort: None,
+            crash_diagnostics: None,
+            post: None,
+            depth_of_field: None,
+            lens_flare: None,
+            light_shafts: None,
+            reflections: None,
+            transparent_pass,
+            transparency_mode: TransparencyMode::default(),
+            oit,
+            output_encode,
+            selected: vec![],
+            window_mode: WindowMode::default(),
+            occluded: false,
+            suspended: false,
+            render_callback: None,
+            blit_target: None,
+            image_index: 0,
+            unavailable_extensions: vec![],
+            unavailable_layers: vec![],
+            terrain: None,
+            splat_terrain: None,
         })
     }
 
-    pub fn update(&mut self, index: usize) -> Result<()> {
-        let command_buffer = self.command_buffers[index];
-        let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
-        unsafe {
-            self.logical_device
-                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-        }?;
+    /// Sets the background colour, from the next recorded frame on.
+    pub fn set_clear_colour(&mut self, colour: [f32; 4]) {
+        self.clear.colour = colour;
+    }
 
-        let clear_values = [
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.4, 0.5, 0.6, 1.0],
-                },
-            },
-            vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 1.0,
-                    stencil: 0,
-                },
-            },
-        ];
+    /// Blends the main pipeline and its topology variants with `blend_mode`. Variants
+    /// compiled for the old mode are dropped and compiled again as they are drawn.
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) -> Result<()> {
+        if blend_mode == self.pipeline.blend_mode {
+            return Ok(());
+        }
+        unsafe { self.logical_device.device_wait_idle() }?;
+        self.pipeline_registry.cleanup(&self.logical_device);
+        self.pipeline_registry = PipelineRegistry::new();
+        self.pipeline
+            .set_blend_mode(&self.logical_device, &self.renderpass, blend_mode)
+    }
 
-        let renderpass_begin_info = vk::RenderPassBeginInfo::builder()
-            .render_pass(self.renderpass)
-            .framebuffer(self.swapchain.framebuffers[index])
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent: self.swapchain.extent,
-            })
-            .clear_values(&clear_values);
-        unsafe {
-            self.logical_device.cmd_begin_render_pass(
-                command_buffer,
-                &renderpass_begin_info,
-                vk::SubpassContents::INLINE,
-            );
-            self.logical_device.cmd_bind_pipeline(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline.pipeline,
-            );
-            self.logical_device.cmd_bind_descriptor_sets(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline.layout,
-                0,
-                &[self.descriptor_sets[index]],
-                &[],
-            );
-            self.models
-                .iter()
-                .for_each(|m| m.draw(&self.logical_device, command_buffer));
-            self.logical_device.cmd_end_render_pass(command_buffer);
-            self.logical_device.end_command_buffer(command_buffer)?;
+    /// Replaces the flat clear colour with the procedural sky.
+    pub fn enable_sky(&mut self) -> Result<&mut Sky> {
+        if self.sky.is_none() {
+            self.sky = Some(Sky::init(
+                &self.logical_device,
+                &self.renderpass,
+                self.pipeline.descriptor_set_layouts[0],
+                &self.pipeline.multisampling,
+            )?);
         }
+        Ok(self.sky.as_mut().unwrap())
+    }
 
+    pub fn disable_sky(&mut self) -> Result<()> {
+        if let Some(sky) = self.sky.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            sky.cleanup(&self.logical_device);
+        }
         Ok(())
     }
-}
 
-impl Drop for Krakatoa {
-    fn drop(&mut self) {
-        unsafe {
-            self.logical_device
-                .device_wait_idle()
-                .expect("Something wrong while waiting.");
-            self.logical_device
-                .destroy_buffer(self.uniform_buffer.buffer, None);
-            self.logical_device
-                .destroy_descriptor_pool(self.descriptor_pool, None);
-            for m in &self.models {
-                if let Some(vb) = &m.vertex_buffer {
-                    self.logical_device.destroy_buffer(vb.buffer, None);
-                }
-                if let Some(ib) = &m.instance_buffer {
-                    self.logical_device.destroy_buffer(ib.buffer, None);
-                }
-                if let Some(ib) = &m.index_buffer {
-                    self.logical_device.destroy_buffer(ib.buffer, None);
-                };
-            }
-            self.pools.cleanup(&self.logical_device);
-            self.pipeline.cleanup(&self.logical_device);
-            self.swapchain.cleanup(&self.logical_device);
-            self.logical_device
-                .destroy_render_pass(self.renderpass, None);
-            self.surface
-                .surface_loader
-                .destroy_surface(self.surface.surface, None);
-            self.debug
-                .loader
-                .destroy_debug_utils_messenger(self.debug.messenger, None);
-            self.logical_device.destroy_device(None);
-            self.instance.destroy_instance(None);
+    /// Draws the reference grid on the y = 0 plane.
+    pub fn enable_grid(&mut self) -> Result<&mut Grid> {
+        if self.grid.is_none() {
+            self.grid = Some(Grid::init(
+                &self.logical_device,
+                &self.renderpass,
+                self.pipeline.descriptor_set_layouts[0],
+                &self.pipeline.multisampling,
+            )?);
+        }
+        Ok(self.grid.as_mut().unwrap())
+    }
+
+    pub fn disable_grid(&mut self) -> Result<()> {
+        if let Some(grid) = self.grid.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            grid.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    pub fn toggle_grid(&mut self) -> Result<()> {
+        if self.grid.is_some() {
+            self.disable_grid()
+        } else {
+            self.enable_grid().map(|_| ())
+        }
+    }
+
+    /// Draws the trails added to the returned [`Trails`]; advance them with the frame time.
+    pub fn enable_trails(&mut self) -> Result<&mut Trails> {
+        if self.trails.is_none() {
+            self.trails = Some(Trails::init(
+                &self.logical_device,
+                &self.renderpass,
+                self.pipeline.descriptor_set_layouts[0],
+                &self.pipeline.multisampling,
+            )?);
+        }
+        Ok(self.trails.as_mut().unwrap())
+    }
+
+    pub fn disable_trails(&mut self) -> Result<()> {
+        if let Some(trails) = self.trails.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            trails.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Sets up the wind-animated instancing path; add models to it with
+    /// [`Foliage::add_layer`], placing their instances with [`crate::foliage::scatter`].
+    pub fn enable_foliage(&mut self) -> Result<&mut Foliage<I>> {
+        if self.foliage.is_none() {
+            self.foliage = Some(Foliage::init(
+                &self.logical_device,
+                &self.renderpass,
+                &self.pipeline,
+            )?);
+        }
+        Ok(self.foliage.as_mut().unwrap())
+    }
+
+    pub fn disable_foliage(&mut self) -> Result<()> {
+        if let Some(mut foliage) = self.foliage.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            foliage.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Draws cutout instances, of models and foliage alike, cut by `masks`: RGBA8 layers
+    /// of `extent`, whose alpha is read and picked by
+    /// [`crate::model::InstanceData::cutout_layer`]. Until then cutouts are not drawn.
+    pub fn enable_cutouts(
+        &mut self,
+        extent: vk::Extent2D,
+        masks: &[&[u8]],
+    ) -> Result<&mut CutoutPass> {
+        self.disable_cutouts()?;
+        let masks = Texture::init(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            self.pools.graphics_command_pool,
+            self.queues.graphics_queue,
+            extent,
+            vk::Format::R8G8B8A8_UNORM,
+            masks,
+        )?;
+        let sampler = self.create_sampler(None)?;
+        self.cutouts = Some(CutoutPass::init(
+            &self.logical_device,
+            &self.renderpass,
+            &self.pipeline,
+            masks,
+            sampler,
+        )?);
+        Ok(self.cutouts.as_mut().unwrap())
+    }
+
+    pub fn disable_cutouts(&mut self) -> Result<()> {
+        if let Some(cutouts) = self.cutouts.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            cutouts.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Culls the instances of triangle-list models on the GPU, against the frustum and
+    /// the previous frame's depth, and draws the rest indirectly. Needs multisampling off.
+    pub fn enable_occlusion_culling(&mut self) -> Result<&mut OcclusionCulling> {
+        if self.occlusion_culling.is_none() {
+            self.occlusion_culling = Some(OcclusionCulling::init::<I>(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                &self.swapchain,
+                self.pipeline.multisampling.samples,
+                self.capabilities.draw_indirect_count.then(|| {
+                    ash::extensions::khr::DrawIndirectCount::new(
+                        &self.instance,
+                        &self.logical_device,
+                    )
+                }),
+                self.conditional_rendering.clone(),
+            )?);
+        }
+        Ok(self.occlusion_culling.as_mut().unwrap())
+    }
+
+    pub fn disable_occlusion_culling(&mut self) -> Result<()> {
+        if let Some(occlusion_culling) = self.occlusion_culling.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            occlusion_culling.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// The buffers, images and device memory currently allocated on the device.
+    pub fn allocation_report(&self) -> AllocationReport {
+        AllocationReport::for_device(&self.logical_device)
+    }
+
+    /// Panics listing the validation layer's ERROR messages since the last check, for
+    /// integration tests. Checks nothing when built with [`Krakatoa::from_raw`], which
+    /// installs no messenger, or when the validation layer is not installed.
+    #[track_caller]
+    pub fn assert_no_validation_errors(&self) {
+        if let Some(debug) = &self.debug {
+            debug.assert_no_validation_errors();
+        }
+    }
+
+    /// The driver's host memory use, when built with
+    /// [`KrakatoaBuilder::track_host_memory`].
+    pub fn host_memory_stats(&self) -> Option<HostMemoryStats> {
+        self.host_allocator.as_ref().map(HostAllocator::stats)
+    }
+
+    /// Switches to the next [`RenderMode`], back to `Lit` after the last.
+    pub fn cycle_render_mode(&mut self) -> RenderMode {
+        self.render_mode = self.render_mode.next();
+        self.render_mode
+    }
+
+    pub fn set_window_mode(&mut self, window_mode: WindowMode) -> Result<()> {
+        self.window
+            .set_fullscreen(window_mode.fullscreen(&self.window));
+        self.window_mode = window_mode;
+        self.recreate_swapchain()
+    }
+
+    /// Switches between windowed and borderless fullscreen.
+    pub fn toggle_fullscreen(&mut self) -> Result<()> {
+        if self.window_mode == WindowMode::Windowed {
+            self.set_window_mode(WindowMode::Borderless)
+        } else {
+            self.set_window_mode(WindowMode::Windowed)
+        }
+    }
+
+    /// Re-sorts the translucent instances back to front; call once per frame after the
+    /// camera or the instances moved.
+    pub fn sort_transparent(&mut self, camera_position: &Vector3<f32>) {
+        self.transparent_pass.sort(&self.models, camera_position);
+    }
+
+    /// Uploads `mesh` and keeps it in `meshes`, for instance sets to share.
+    pub fn add_mesh(&mut self, mut mesh: Mesh<VertexData>) -> Result<MeshHandle> {
+        mesh.upload(&self.logical_device, self.physical_device_memory_properties)?;
+        Ok(self.meshes.insert(mesh))
+    }
+
+    /// Adds an empty instance set drawn with the mesh behind `mesh`; returns its index
+    /// into `instance_sets`. Its visible instances are uploaded every frame.
+    pub fn add_instance_set(&mut self, mesh: MeshHandle) -> Result<usize> {
+        if self.meshes.get(mesh).is_none() {
+            bail!("No mesh behind {:?}.", mesh);
+        }
+        self.instance_sets.push((mesh, InstanceSet::new()));
+        Ok(self.instance_sets.len() - 1)
+    }
+
+    /// Re-sorts the visible instances of every model for `camera_position` with
+    /// [`InstanceSet::sort_for_camera`] and uploads them, then the translucent draw order. An
+    /// alternative to [`Krakatoa::sort_transparent`] for scenes where overdraw matters.
+    pub fn sort_instances(&mut self, camera_position: &Vector3<f32>) -> Result<()> {
+        for model in &mut self.models {
+            model.instances.sort_for_camera(camera_position);
+            model
+                .instances
+                .update_buffer(&self.logical_device, self.physical_device_memory_properties)?;
+        }
+        self.sort_transparent(camera_position);
+        Ok(())
+    }
+
+    /// Lays down the depth of the models and terrain before shading them.
+    pub fn enable_depth_prepass(&mut self) -> Result<()> {
+        if self.depth_prepass.is_none() {
+            self.depth_prepass = Some(DepthPrepass::init(
+                &self.logical_device,
+                &self.renderpass,
+                &self.pipeline,
+            )?);
+        }
+        Ok(())
+    }
+
+    pub fn disable_depth_prepass(&mut self) -> Result<()> {
+        if let Some(depth_prepass) = self.depth_prepass.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            depth_prepass.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Sets up the task and mesh shader path. Needs `capabilities.mesh_shader`.
+    pub fn enable_mesh_shader(&mut self) -> Result<&mut MeshShaderPass> {
+        if !self.capabilities.mesh_shader {
+            bail!("The device does not support VK_EXT_mesh_shader.");
+        }
+        if self.mesh_shader.is_none() {
+            self.mesh_shader = Some(MeshShaderPass::init(
+                &self.instance,
+                &self.logical_device,
+                &self.renderpass,
+                &self.pipeline.multisampling,
+            )?);
+        }
+        Ok(self.mesh_shader.as_mut().unwrap())
+    }
+
+    pub fn disable_mesh_shader(&mut self) -> Result<()> {
+        if let Some(mesh_shader) = self.mesh_shader.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            mesh_shader.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Draws `model` through the mesh shader path instead of adding it to `models`.
+    /// Returns its index into the pass's models.
+    pub fn add_meshlet_model(&mut self, model: &Model<VertexData, I>) -> Result<usize> {
+        let Some(mesh_shader) = &mut self.mesh_shader else {
+            bail!("Enable the mesh shader path before adding meshlet models.");
+        };
+        mesh_shader.add_model(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            model,
+            &self.uniform_buffer,
+            &self.light_buffer,
+        )
+    }
+
+    /// Sets up the path drawing models whose instances are read from a storage buffer at
+    /// `gl_InstanceIndex`, with `vertex_spirv` or `shaders/storage_instanced.vert`.
+    pub fn enable_storage_instancing(
+        &mut self,
+        vertex_spirv: Option<&[u32]>,
+    ) -> Result<&mut StorageInstancedPass> {
+        if self.storage_instancing.is_none() {
+            self.storage_instancing = Some(StorageInstancedPass::init(
+                &self.logical_device,
+                &self.renderpass,
+                &self.pipeline,
+                std::mem::size_of::<I>(),
+                vertex_spirv,
+            )?);
+        }
+        Ok(self.storage_instancing.as_mut().unwrap())
+    }
+
+    pub fn disable_storage_instancing(&mut self) -> Result<()> {
+        if let Some(storage_instancing) = self.storage_instancing.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            storage_instancing.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Draws `model` through the storage buffer instancing path instead of adding it to
+    /// `models`. Returns its index into the pass's models.
+    pub fn add_storage_instanced_model(&mut self, model: &Model<VertexData, I>) -> Result<usize> {
+        let Some(storage_instancing) = &mut self.storage_instancing else {
+            bail!("Enable storage buffer instancing before adding models to it.");
+        };
+        storage_instancing.add_model(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            model,
+        )
+    }
+
+    /// Builds acceleration structures over `models` for shaders using ray queries and for
+    /// [`Krakatoa::pick`]. Needs `capabilities.ray_query`; call
+    /// [`Krakatoa::update_ray_query`] after the models or their instances change.
+    pub fn enable_ray_query(&mut self) -> Result<&mut RayQueryScene> {
+        if !self.capabilities.ray_query {
+            bail!("The device does not support VK_KHR_ray_query.");
+        }
+        if self.ray_query.is_none() {
+            self.ray_query = Some(RayQueryScene::init(
+                &self.instance,
+                &self.logical_device,
+                self.physical_device_memory_properties,
+            )?);
+            self.update_ray_query()?;
+        }
+        Ok(self.ray_query.as_mut().unwrap())
+    }
+
+    pub fn disable_ray_query(&mut self) -> Result<()> {
+        if let Some(ray_query) = self.ray_query.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            ray_query.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the TLAS from the visible instances of `models`.
+    pub fn update_ray_query(&mut self) -> Result<()> {
+        if let Some(ray_query) = &mut self.ray_query {
+            ray_query.update(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                self.pools.graphics_command_pool,
+                self.queues.graphics_queue,
+                &self.models,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The (index into `models`, instance handle) the ray hits first and its distance,
+    /// traced against the triangles rather than bounding boxes. Needs the ray query scene.
+    pub fn pick(
+        &self,
+        origin: &Vector3<f32>,
+        direction: &Vector3<f32>,
+        max_distance: f32,
+    ) -> Result<Option<(usize, usize, f32)>> {
+        let Some(ray_query) = &self.ray_query else {
+            bail!("Enable ray queries before picking.");
         };
+        ray_query.pick(
+            &self.logical_device,
+            self.pools.graphics_command_pool,
+            self.queues.graphics_queue,
+            origin,
+            direction,
+            max_distance,
+        )
+    }
+
+    /// Sets up the compute pass behind [`Krakatoa::mesh_density`].
+    pub fn enable_marching_cubes(&mut self) -> Result<&mut MarchingCubes> {
+        if self.marching_cubes.is_none() {
+            self.marching_cubes = Some(MarchingCubes::init(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+            )?);
+        }
+        Ok(self.marching_cubes.as_mut().unwrap())
+    }
+
+    pub fn disable_marching_cubes(&mut self) -> Result<()> {
+        if let Some(marching_cubes) = self.marching_cubes.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            marching_cubes.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Marks each pass in the frame's command buffer, so that when the device is lost the
+    /// error [`Krakatoa::render_frame`] returns says which pass was executing, with the
+    /// driver's description of the fault. Needs `VK_EXT_device_fault`,
+    /// `VK_NV_device_diagnostic_checkpoints` or `VK_AMD_buffer_marker`.
+    pub fn enable_crash_diagnostics(&mut self) -> Result<&mut CrashDiagnostics> {
+        if self.crash_diagnostics.is_none() {
+            self.crash_diagnostics = Some(CrashDiagnostics::init(
+                &self.instance,
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                &self.capabilities,
+            )?);
+        }
+        Ok(self.crash_diagnostics.as_mut().unwrap())
+    }
+
+    pub fn disable_crash_diagnostics(&mut self) -> Result<()> {
+        if let Some(crash_diagnostics) = self.crash_diagnostics.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            crash_diagnostics.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// What crash diagnostics found out about the device being lost, if enabled.
+    pub fn crash_report(&self) -> Option<CrashReport> {
+        self.crash_diagnostics.as_ref().map(|crash_diagnostics| {
+            crash_diagnostics.report(&self.logical_device, self.queues.graphics_queue)
+        })
+    }
+
+    /// `error` as returned from the frame, with the crash report when the device was lost.
+    fn frame_error(&self, error: vk::Result) -> anyhow::Error {
+        match self.crash_report() {
+            Some(report) if error == vk::Result::ERROR_DEVICE_LOST => {
+                anyhow::Error::new(error).context(report.to_string())
+            }
+            _ => error.into(),
+        }
+    }
+
+    /// Sets up the compute pass behind [`Krakatoa::sort_pairs`].
+    pub fn enable_gpu_sort(&mut self) -> Result<&mut GpuSort> {
+        if self.gpu_sort.is_none() {
+            self.gpu_sort = Some(GpuSort::init(&self.logical_device)?);
+        }
+        Ok(self.gpu_sort.as_mut().unwrap())
+    }
+
+    pub fn disable_gpu_sort(&mut self) -> Result<()> {
+        if let Some(gpu_sort) = self.gpu_sort.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            gpu_sort.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Blurs what is out of focus, after the scene is drawn. Needs MSAA off and swapchain
+    /// images that can be copied from.
+    pub fn enable_depth_of_field(&mut self) -> Result<&mut DepthOfField> {
+        if self.depth_of_field.is_none() {
+            if self.pipeline.multisampling.is_enabled() {
+                bail!("Depth of field needs a single-sampled depth buffer.");
+            }
+            self.init_post_process()?;
+            let post = self.post.as_ref().unwrap();
+            let depth_of_field = DepthOfField::init(
+                &self.logical_device,
+                &post.renderpass,
+                post.descriptor_set_layout,
+            )?;
+            self.depth_of_field = Some(depth_of_field);
+        }
+        Ok(self.depth_of_field.as_mut().unwrap())
+    }
+
+    pub fn disable_depth_of_field(&mut self) -> Result<()> {
+        if let Some(depth_of_field) = self.depth_of_field.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            depth_of_field.cleanup(&self.logical_device);
+            self.release_post_process();
+        }
+        Ok(())
+    }
+
+    /// Adds the light's halo and ghosts to the frame while it is in view. Needs MSAA off
+    /// and swapchain images that can be copied from.
+    pub fn enable_lens_flare(&mut self) -> Result<&mut LensFlare> {
+        if self.lens_flare.is_none() {
+            if self.pipeline.multisampling.is_enabled() {
+                bail!("Lens flares need a single-sampled depth buffer.");
+            }
+            self.init_post_process()?;
+            let post = self.post.as_ref().unwrap();
+            let lens_flare = LensFlare::init(
+                &self.logical_device,
+                &post.renderpass,
+                post.descriptor_set_layout,
+            )?;
+            self.lens_flare = Some(lens_flare);
+        }
+        Ok(self.lens_flare.as_mut().unwrap())
+    }
+
+    pub fn disable_lens_flare(&mut self) -> Result<()> {
+        if let Some(lens_flare) = self.lens_flare.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            lens_flare.cleanup(&self.logical_device);
+            self.release_post_process();
+        }
+        Ok(())
+    }
+
+    /// Adds shafts of the light through the air around what covers it, raymarched
+    /// through the depth buffer. Needs MSAA off, swapchain images that can be copied
+    /// from, and an output other than HDR10, which post-processing sees encoded.
+    pub fn enable_light_shafts(&mut self) -> Result<&mut LightShafts> {
+        if self.light_shafts.is_none() {
+            if self.pipeline.multisampling.is_enabled() {
+                bail!("Light shafts need a single-sampled depth buffer.");
+            }
+            if self.output_colour_space() == OutputColourSpace::Hdr10 {
+                bail!("Light shafts are added in linear light, which an HDR10 output is not.");
+            }
+            self.init_post_process()?;
+            let post = self.post.as_ref().unwrap();
+            let light_shafts = LightShafts::init(
+                &self.logical_device,
+                &post.renderpass,
+                post.descriptor_set_layout,
+            )?;
+            self.light_shafts = Some(light_shafts);
+        }
+        Ok(self.light_shafts.as_mut().unwrap())
+    }
+
+    pub fn disable_light_shafts(&mut self) -> Result<()> {
+        if let Some(light_shafts) = self.light_shafts.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            light_shafts.cleanup(&self.logical_device);
+            self.release_post_process();
+        }
+        Ok(())
+    }
+
+    /// The vignette and film grain, drawn over the finished frame while either is on.
+    /// Creates the post-processing stage, which needs swapchain images that can be
+    /// copied from.
+    pub fn post_settings(&mut self) -> Result<&mut PostSettings> {
+        self.init_post_process()?;
+        Ok(&mut self.post.as_mut().unwrap().settings)
+    }
+
+    /// Turns the vignette and film grain off.
+    pub fn disable_vignette_and_grain(&mut self) -> Result<()> {
+        if let Some(post) = &mut self.post {
+            post.settings = PostSettings::default();
+            unsafe { self.logical_device.device_wait_idle() }?;
+            self.release_post_process();
+        }
+        Ok(())
+    }
+
+    /// Creates the post-processing stage for the first effect enabled.
+    fn init_post_process(&mut self) -> Result<()> {
+        if self.post.is_none() {
+            self.post = Some(PostProcess::init(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                &self.swapchain,
+                self.pipeline.multisampling.samples,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Drops the post-processing stage once the last effect is gone, its vignette and
+    /// grain included. The device must be idle.
+    fn release_post_process(&mut self) {
+        let finishing = self
+            .post
+            .as_ref()
+            .is_some_and(|post| post.settings.is_active());
+        if self.depth_of_field.is_none()
+            && self.lens_flare.is_none()
+            && self.light_shafts.is_none()
+            && !finishing
+        {
+            if let Some(post) = self.post.take() {
+                post.cleanup(&self.logical_device);
+            }
+        }
+    }
+
+    /// Sets up planar reflections, drawn at `planar_extent` while a plane is set, and
+    /// reflection probes with faces `probe_resolution` pixels wide. Opaque triangle lists
+    /// are then shaded with what they reflect. Needs the `imageCubeArray` feature.
+    pub fn enable_reflections(
+        &mut self,
+        probe_resolution: u32,
+        planar_extent: vk::Extent2D,
+    ) -> Result<&mut Reflections> {
+        if self.physical_device_features.image_cube_array != vk::TRUE {
+            bail!("Reflection probes need the imageCubeArray feature.");
+        }
+        self.disable_reflections()?;
+        self.reflections = Some(Reflections::init::<I>(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            self.pools.graphics_command_pool,
+            self.queues.graphics_queue,
+            &self.renderpass,
+            &self.pipeline,
+            &mut self.layout_cache,
+            &self.light_buffer,
+            self.swapchain.depth_format,
+            probe_resolution,
+            planar_extent,
+        )?);
+        Ok(self.reflections.as_mut().unwrap())
+    }
+
+    pub fn disable_reflections(&mut self) -> Result<()> {
+        if let Some(reflections) = self.reflections.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            reflections.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Draws the scene into the cubemap of every reflection probe, from the probe's
+    /// position. Waits for the device to go idle; call again after the scene or the
+    /// probes change.
+    pub fn capture_reflection_probes(&mut self) -> Result<()> {
+        let Some(reflections) = &mut self.reflections else {
+            bail!("Enable reflections before capturing probes.");
+        };
+        unsafe { self.logical_device.device_wait_idle() }?;
+        let probes: Vec<_> = reflections
+            .probes
+            .iter()
+            .take(MAX_REFLECTION_PROBES)
+            .copied()
+            .collect();
+        let face_extent = vk::Extent2D {
+            width: reflections.probe_resolution,
+            height: reflections.probe_resolution,
+        };
+        for (probe_index, probe) in probes.iter().enumerate() {
+            self.capture_faces(probe, face_extent, |reflections, face| {
+                reflections.face_framebuffer(probe_index, face)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Captures the scene around `position` and filters it on the device into the
+    /// irradiance and prefiltered specular levels of a [`BakedEnvironment`], to
+    /// [`BakedEnvironment::save`] and load again instead of capturing at runtime. Draws
+    /// with the reflection capture pipeline, so reflections need to be enabled. Waits for
+    /// the device to go idle.
+    pub fn bake_environment(
+        &mut self,
+        position: Vector3<f32>,
+        settings: &BakeSettings,
+    ) -> Result<BakedEnvironment> {
+        let Some(reflections) = &self.reflections else {
+            bail!("Enable reflections before baking environments.");
+        };
+        unsafe { self.logical_device.device_wait_idle() }?;
+        let capture = CaptureCube::init(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            reflections.renderpass,
+            self.swapchain.depth_format,
+            settings.capture_resolution,
+        )?;
+        let probe = ReflectionProbe {
+            near: settings.near,
+            far: settings.far,
+            ..ReflectionProbe::sphere(position, settings.far)
+        };
+        let face_extent = vk::Extent2D {
+            width: capture.resolution,
+            height: capture.resolution,
+        };
+        let baked = self
+            .capture_faces(&probe, face_extent, |_, face| capture.framebuffers[face])
+            .and_then(|_| {
+                convolve(
+                    &self.logical_device,
+                    self.physical_device_memory_properties,
+                    self.pools.graphics_command_pool,
+                    self.queues.graphics_queue,
+                    capture.view,
+                    settings,
+                )
+            });
+        capture.cleanup(&self.logical_device);
+        baked
+    }
+
+    /// Uploads `baked` as [`Krakatoa::environment`], replacing the one there.
+    pub fn set_environment(&mut self, baked: &BakedEnvironment) -> Result<&EnvironmentMap> {
+        let environment = EnvironmentMap::upload(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            self.pools.graphics_command_pool,
+            self.queues.graphics_queue,
+            baked,
+        )?;
+        self.clear_environment()?;
+        Ok(self.environment.insert(environment))
+    }
+
+    pub fn clear_environment(&mut self) -> Result<()> {
+        if let Some(environment) = self.environment.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            environment.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Draws the six faces of `probe` with the reflection capture pipeline, each into
+    /// the framebuffer `framebuffer` gives for its index, one submission at a time.
+    fn capture_faces(
+        &mut self,
+        probe: &ReflectionProbe,
+        face_extent: vk::Extent2D,
+        framebuffer: impl Fn(&Reflections, usize) -> vk::Framebuffer,
+    ) -> Result<()> {
+        let projection = probe.face_projection();
+        for (face, view) in probe.face_views().iter().enumerate() {
+            let reflections = self.reflections.as_mut().unwrap();
+            reflections.set_capture_camera(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                view,
+                &projection,
+            )?;
+            let reflections = self.reflections.as_ref().unwrap();
+            let command_buffer =
+                begin_one_time(&self.logical_device, self.pools.graphics_command_pool)?;
+            unsafe {
+                reflections.begin(
+                    &self.logical_device,
+                    command_buffer,
+                    framebuffer(reflections, face),
+                    face_extent,
+                    self.clear.colour,
+                );
+                self.draw_reflected_scene(command_buffer);
+                reflections.end(&self.logical_device, command_buffer);
+            }
+            end_one_time(
+                &self.logical_device,
+                self.pools.graphics_command_pool,
+                self.queues.graphics_queue,
+                command_buffer,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draws the opaque triangle lists of the models, instance sets and terrain with
+    /// whatever pipeline and descriptor set 0 are bound.
+    unsafe fn draw_reflected_scene(&self, command_buffer: vk::CommandBuffer) {
+        let drawables = self.models.iter().map(|m| (&m.mesh, &m.instances)).chain(
+            self.instance_sets.iter().filter_map(|(handle, instances)| {
+                self.meshes.get(*handle).map(|mesh| (mesh, instances))
+            }),
+        );
+        drawables
+            .filter(|(mesh, _)| mesh.topology == Topology::TriangleList)
+            .for_each(|(mesh, instances)| {
+                mesh.draw(&self.logical_device, command_buffer, instances)
+            });
+        if let Some(terrain) = &self.terrain {
+            terrain.draw(&self.logical_device, command_buffer);
+        }
+    }
+
+    /// An uploaded mesh of the surface in `density`, a storage buffer sampled on `grid`
+    /// such as one from [`DensityGrid::upload`]. Needs marching cubes enabled.
+    pub fn mesh_density(&self, density: &Buffer, grid: &DensityGrid) -> Result<Mesh<VertexData>> {
+        let Some(marching_cubes) = &self.marching_cubes else {
+            bail!("Enable marching cubes before meshing density fields.");
+        };
+        marching_cubes.generate(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            self.pools.graphics_command_pool,
+            self.queues.graphics_queue,
+            density,
+            grid,
+        )
+    }
+
+    /// Sorts the first `count` `(key, value)` pairs in `pairs`, a storage buffer, by key,
+    /// waiting until they are. Needs the GPU sort enabled; to sort within a frame's own
+    /// command buffer, record [`GpuSort::record`] instead.
+    pub fn sort_pairs(&self, pairs: &Buffer, count: u32, descending: bool) -> Result<()> {
+        let Some(gpu_sort) = &self.gpu_sort else {
+            bail!("Enable the GPU sort before sorting on the GPU.");
+        };
+        gpu_sort.sort(
+            &self.logical_device,
+            self.pools.graphics_command_pool,
+            self.queues.graphics_queue,
+            pairs,
+            count,
+            descending,
+        )
+    }
+
+    /// Outlines the given (index into `models`, instance handle) pairs.
+    /// An empty slice clears the selection.
+    pub fn set_selected(&mut self, handles: &[(usize, usize)]) -> Result<()> {
+        if !handles.is_empty() && self.outline.is_none() {
+            if !format_has_stencil(self.swapchain.depth_format) {
+                bail!("Selection outlines need a depth format with a stencil aspect.");
+            }
+            self.outline = Some(Outline::init(
+                &self.logical_device,
+                &self.renderpass,
+                self.pipeline.descriptor_set_layouts[0],
+                &self.pipeline.multisampling,
+                &self.pipeline.vertex_input,
+            )?);
+        }
+        self.selected = handles.to_vec();
+        Ok(())
+    }
+
+    /// Streams chunks of `terrain` around the camera; see [`Krakatoa::update_terrain`].
+    pub fn enable_terrain(&mut self, terrain: NoiseTerrain) -> Result<&mut TerrainStreamer<I>> {
+        self.disable_terrain()?;
+        self.terrain = Some(TerrainStreamer::new(
+            terrain,
+            self.swapchain.amount_of_images,
+        ));
+        Ok(self.terrain.as_mut().unwrap())
+    }
+
+    pub fn disable_terrain(&mut self) -> Result<()> {
+        if let Some(mut terrain) = self.terrain.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            terrain.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    /// Shades the streamed terrain with `material` instead of its flat colour. The
+    /// terrain is then left out of the depth prepass.
+    pub fn enable_splat_terrain(
+        &mut self,
+        material: &SplatMaterial,
+    ) -> Result<&mut SplatTerrainPass> {
+        self.disable_splat_terrain()?;
+        let textures = SplatTextures::upload(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            self.pools.graphics_command_pool,
+            self.queues.graphics_queue,
+            material,
+        )?;
+        let sampler = self.create_sampler(None)?;
+        self.splat_terrain = Some(SplatTerrainPass::init(
+            &self.logical_device,
+            self.physical_device_memory_properties,
+            &self.renderpass,
+            &self.pipeline,
+            textures,
+            sampler,
+            material,
+        )?);
+        Ok(self.splat_terrain.as_mut().unwrap())
+    }
+
+    pub fn disable_splat_terrain(&mut self) -> Result<()> {
+        if let Some(splat_terrain) = self.splat_terrain.take() {
+            unsafe { self.logical_device.device_wait_idle() }?;
+            splat_terrain.cleanup(&self.logical_device);
+        }
+        Ok(())
+    }
+
+    pub fn update_terrain(&mut self, camera_position: &Vector3<f32>) -> Result<()> {
+        if let Some(terrain) = &mut self.terrain {
+            terrain.update(
+                camera_position,
+                &self.logical_device,
+                self.physical_device_memory_properties,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Creates a sampler using `max_anisotropy`, or the engine default when `None`.
+    /// The caller owns the sampler and destroys it.
+    pub fn create_sampler(&self, max_anisotropy: Option<f32>) -> Result<vk::Sampler> {
+        let device_limit = if self.physical_device_features.sampler_anisotropy == vk::TRUE {
+            self.physical_device_properties
+                .limits
+                .max_sampler_anisotropy
+        } else {
+            1.0
+        };
+        let max_anisotropy = max_anisotropy.map_or(self.max_anisotropy, |anisotropy| {
+            anisotropy.clamp(1.0, device_limit)
+        });
+        create_sampler(&self.logical_device, max_anisotropy)
+    }
+
+    /// The colour space actually presented in, which is SDR when the requested one is unavailable.
+    pub fn output_colour_space(&self) -> OutputColourSpace {
+        OutputColourSpace::from_colour_space(self.swapchain.surface_format.color_space)
+    }
+
+    /// The aspect ratio the scene is framed at, for the camera: the locked one if any,
+    /// otherwise the window's.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_lock
+            .unwrap_or(self.swapchain.extent.width as f32 / self.swapchain.extent.height as f32)
+    }
+
+    /// Frames the scene at `aspect`, width over height, letterboxed or pillarboxed to fit
+    /// the window from the next recorded frame on; `None` fills the window again. The
+    /// camera needs the new [`Krakatoa::aspect_ratio`].
+    pub fn lock_aspect_ratio(&mut self, aspect: Option<f32>) {
+        self.aspect_lock = aspect.filter(|aspect| aspect.is_finite() && *aspect > 0.0);
+    }
+
+    /// Clips model `model` to `rect`, in swapchain pixels, from the next recorded frame
+    /// on, so a minimap or widget region can be drawn into without a render target of its
+    /// own. Only the part inside [`Krakatoa::viewport_rect`] is drawn. `None` unclips it.
+    pub fn set_clip_rect(&mut self, model: usize, rect: Option<vk::Rect2D>) {
+        match rect {
+            Some(rect) => self.clip_rects.insert(model, rect),
+            None => self.clip_rects.remove(&model),
+        };
+    }
+
+    /// The part of the swapchain the scene is drawn to: all of it unless the aspect ratio
+    /// is locked, otherwise the largest centred rectangle of that ratio.
+    pub fn viewport_rect(&self) -> vk::Rect2D {
+        let extent = self.swapchain.extent;
+        let Some(aspect) = self.aspect_lock else {
+            return vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            };
+        };
+        let framed = if extent.width as f32 > extent.height as f32 * aspect {
+            vk::Extent2D {
+                width: ((extent.height as f32 * aspect).round() as u32).clamp(1, extent.width),
+                height: extent.height,
+            }
+        } else {
+            vk::Extent2D {
+                width: extent.width,
+                height: ((extent.width as f32 / aspect).round() as u32).clamp(1, extent.height),
+            }
+        };
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: ((extent.width - framed.width) / 2) as i32,
+                y: ((extent.height - framed.height) / 2) as i32,
+            },
+            extent: framed,
+        }
+    }
+
+    pub fn instance_handle(&self) -> vk::Instance {
+        self.instance.handle()
+    }
+
+    pub fn device_handle(&self) -> vk::Device {
+        self.logical_device.handle()
+    }
+
+    pub fn graphics_queue(&self) -> vk::Queue {
+        self.queues.graphics_queue
+    }
+
+    pub fn transfer_queue(&self) -> vk::Queue {
+        self.queues.transfer_queue
+    }
+
+    /// Indexed by the image index handed to [`Krakatoa::update`]; replaced whenever the
+    /// swapchain is recreated.
+    pub fn swapchain_images(&self) -> &[vk::Image] {
+        &self.swapchain.images
+    }
+
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.renderpass
+    }
+
+    /// The command buffer of the frame being recorded, or last recorded.
+    pub fn current_command_buffer(&self) -> vk::CommandBuffer {
+        self.command_buffers[self.image_index]
+    }
+
+    /// Whether the window is minimized or occluded, when frames should be neither
+    /// acquired, submitted nor presented.
+    pub fn is_paused(&self) -> bool {
+        let size = self.window.inner_size();
+        self.suspended || self.occluded || size.width == 0 || size.height == 0
+    }
+
+    /// Releases the surface and swapchain, for when the platform takes the native window
+    /// away, as Android does on `Event::Suspended`. Nothing renders until [`Krakatoa::resume`].
+    pub fn suspend(&mut self) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
+        unsafe {
+            self.logical_device.device_wait_idle()?;
+            self.swapchain
+                .cleanup(&self.logical_device, &mut self.render_targets);
+        }
+        self.surface.destroy();
+        self.suspended = true;
+        Ok(())
+    }
+
+    /// Creates a surface and swapchain for the window's new native window; call on `Event::Resumed`.
+    pub fn resume(&mut self) -> Result<()> {
+        if !self.suspended {
+            return Ok(());
+        }
+        self.surface
+            .recreate(&self.window, &self.entry, &self.instance)?;
+        self.init_swapchain()?;
+        self.suspended = false;
+        Ok(())
+    }
+
+    /// Rebuilds the swapchain and everything sized by it, e.g. after a window resize
+    /// or when presentation reports the swapchain as out of date. Does nothing while
+    /// the surface has no area, as a zero-sized swapchain cannot be created; the
+    /// resize that ends a minimization recreates it.
+    pub fn recreate_swapchain(&mut self) -> Result<()> {
+        if self.suspended {
+            return Ok(());
+        }
+        let size = self.window.inner_size();
+        self.surface.desired_extent = vk::Extent2D {
+            width: size.width,
+            height: size.height,
+        };
+        let extent = self
+            .surface
+            .choose_extent(&self.surface.get_capabilities(self.physical_device)?);
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(());
+        }
+        unsafe {
+            self.logical_device.device_wait_idle()?;
+            self.swapchain
+                .cleanup(&self.logical_device, &mut self.render_targets);
+        }
+        self.init_swapchain()
+    }
+
+    /// Creates the swapchain and what depends on it, the previous one already cleaned up.
+    fn init_swapchain(&mut self) -> Result<()> {
+        let size = self.window.inner_size();
+        self.surface.desired_extent = vk::Extent2D {
+            width: size.width,
+            height: size.height,
+        };
+        self.swapchain = Swapchain::init(
+            &self.instance,
+            self.physical_device,
+            &self.logical_device,
+            &self.surface,
+            &self.queue_families,
+            self.pipeline.multisampling.samples,
+            self.physical_device_memory_properties,
+            &mut self.render_targets,
+        )?;
+        self.swapchain.create_framebuffers(
+            &self.logical_device,
+            self.renderpass,
+            &mut self.render_targets,
+        )?;
+        self.render_targets.evict_unused(&self.logical_device);
+        self.oit
+            .update_descriptor_set(&self.logical_device, &self.swapchain);
+        if let Some(output_encode) = &self.output_encode {
+            output_encode.update_descriptor_set(&self.logical_device, &self.swapchain);
+        }
+        if let Some(post) = &mut self.post {
+            post.resize(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                &self.swapchain,
+                self.pipeline.multisampling.samples,
+            )?;
+        }
+        if let Some(occlusion_culling) = &mut self.occlusion_culling {
+            occlusion_culling.resize(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                &self.swapchain,
+            )?;
+        }
+
+        if self.command_buffers.len() != self.swapchain.amount_of_images {
+            unsafe {
+                self.logical_device
+                    .free_command_buffers(self.pools.graphics_command_pool, &self.command_buffers);
+                self.logical_device
+                    .destroy_descriptor_pool(self.descriptor_pool, None);
+            }
+            self.command_buffers = create_command_buffers(
+                &self.logical_device,
+                &self.pools,
+                self.swapchain.amount_of_images,
+            )?;
+            (self.descriptor_pool, self.descriptor_sets) = init_descriptor_sets(
+                &self.logical_device,
+                &self.pipeline,
+                &self.uniform_buffer,
+                &self.light_buffer,
+                self.swapchain.amount_of_images,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Acquires a swapchain image, records and submits the frame as seen by `camera`, and
+    /// presents it. Returns whether the swapchain was recreated on the way, after which
+    /// the camera needs the new [`Krakatoa::aspect_ratio`].
+    pub fn render_frame(&mut self, camera: &Camera) -> Result<bool> {
+        self.swapchain.current_image =
+            (self.swapchain.current_image + 1) % self.swapchain.amount_of_images;
+
+        let image_index = match unsafe {
+            self.swapchain.swapchain_loader.acquire_next_image(
+                self.swapchain.swapchain,
+                u64::MAX,
+                self.swapchain.image_available[self.swapchain.current_image],
+                vk::Fence::null(),
+            )
+        } {
+            std::result::Result::Ok((image_index, _)) => image_index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.recreate_swapchain()?;
+                return Ok(true);
+            }
+            Err(e) => return Err(self.frame_error(e)),
+        };
+
+        unsafe {
+            self.logical_device
+                .wait_for_fences(
+                    &[self.swapchain.may_begin_drawing[self.swapchain.current_image]],
+                    true,
+                    u64::MAX,
+                )
+                .map_err(|e| self.frame_error(e))?;
+            self.logical_device
+                .reset_fences(&[self.swapchain.may_begin_drawing[self.swapchain.current_image]])?;
+        }
+
+        self.clock.tick();
+        self.uniform_buffer.fill(
+            &self.logical_device,
+            &[frame_uniforms(camera, &self.clock)],
+            self.physical_device_memory_properties,
+        )?;
+        self.last_camera = Some([camera.view_matrix.into(), camera.projection_matrix.into()]);
+        for model in &mut self.models {
+            model
+                .instances
+                .update_buffer(&self.logical_device, self.physical_device_memory_properties)?;
+        }
+        for (_, instances) in &mut self.instance_sets {
+            instances
+                .update_buffer(&self.logical_device, self.physical_device_memory_properties)?;
+        }
+        if let Some(foliage) = &mut self.foliage {
+            for layer in &mut foliage.layers {
+                layer
+                    .instances
+                    .update_buffer(&self.logical_device, self.physical_device_memory_properties)?;
+            }
+        }
+        if let Some(trails) = &mut self.trails {
+            trails.update(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                &camera.position,
+                &self.models,
+            )?;
+        }
+        if let Some(depth_of_field) = &mut self.depth_of_field {
+            depth_of_field.set_camera(camera);
+        }
+        if let Some(output_encode) = &mut self.output_encode {
+            output_encode.set_camera(camera);
+        }
+        let framed = self.viewport_rect();
+        if let Some(lens_flare) = &mut self.lens_flare {
+            lens_flare.set_light(camera, &self.light, framed);
+        }
+        if let Some(light_shafts) = &mut self.light_shafts {
+            light_shafts.set_light(camera, &self.light, framed);
+        }
+        if let Some(reflections) = &mut self.reflections {
+            reflections.update(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                camera,
+                framed,
+            )?;
+        }
+        if let Some(occlusion_culling) = &mut self.occlusion_culling {
+            occlusion_culling.update(
+                &self.logical_device,
+                self.physical_device_memory_properties,
+                camera,
+                framed,
+                &self.models,
+            )?;
+        }
+        self.update(image_index as usize)?;
+
+        let semaphores_available = [self.swapchain.image_available[self.swapchain.current_image]];
+        let waiting_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        let semaphores_finished = [self.swapchain.rendering_finished[self.swapchain.current_image]];
+        let command_buffers = [self.command_buffers[image_index as usize]];
+        let submit_info = [vk::SubmitInfo::builder()
+            .wait_semaphores(&semaphores_available)
+            .wait_dst_stage_mask(&waiting_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&semaphores_finished)
+            .build()];
+        unsafe {
+            self.logical_device
+                .queue_submit(
+                    self.queues.graphics_queue,
+                    &submit_info,
+                    self.swapchain.may_begin_drawing[self.swapchain.current_image],
+                )
+                .map_err(|e| self.frame_error(e))?;
+        };
+
+        let swapchains = [self.swapchain.swapchain];
+        let indices = [image_index];
+        let present_times = self
+            .display_timing
+            .as_mut()
+            .map(|display_timing| [display_timing.next_present_time()]);
+        let mut present_times_info = vk::PresentTimesInfoGOOGLE::builder()
+            .times(present_times.as_ref().map_or(&[], |times| &times[..]));
+        let mut present_info = vk::PresentInfoKHR::builder()
+            .wait_semaphores(&semaphores_finished)
+            .swapchains(&swapchains)
+            .image_indices(&indices);
+        if present_times.is_some() {
+            present_info = present_info.push_next(&mut present_times_info);
+        }
+        let needs_recreation = match unsafe {
+            self.swapchain
+                .swapchain_loader
+                .queue_present(self.queues.graphics_queue, &present_info)
+        } {
+            std::result::Result::Ok(suboptimal) => suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => true,
+            Err(e) => return Err(self.frame_error(e)),
+        };
+        if needs_recreation {
+            self.recreate_swapchain()?;
+        }
+
+        if let Some(display_timing) = &mut self.display_timing {
+            display_timing.poll(&self.swapchain)?;
+        }
+
+        Ok(needs_recreation)
+    }
+
+    pub fn update(&mut self, index: usize) -> Result<()> {
+        self.light_buffer.fill(
+            &self.logical_device,
+            &[light_uniform(
+                &self.light,
+                &self.fog,
+                self.render_mode,
+                self.depth_range,
+            )],
+            self.physical_device_memory_properties,
+        )?;
+
+        let meshes = self
+            .models
+            .iter()
+            .map(|model| &model.mesh)
+            .chain(self.meshes.meshes.iter().flatten());
+        for mesh in meshes {
+            let key = (
+                mesh.topology,
+                mesh.primitive_restart && mesh.topology.is_strip(),
+            );
+            if key.0 == Topology::TriangleList
+                || self.pipeline.topology_pipeline(key.0, key.1).is_some()
+            {
+                continue;
+            }
+            if let Some(PipelineStatus::Failed(error)) = self.pipeline_registry.status(&key) {
+                bail!("Compiling the {:?} pipeline failed: {}", key.0, error);
+            }
+            self.pipeline_registry.request(
+                key,
+                self.pipeline.topology_pipeline_job(
+                    &self.logical_device,
+                    self.renderpass,
+                    key.0,
+                    key.1,
+                ),
+            )?;
+        }
+
+        self.image_index = index;
+        if let Some(post) = &mut self.post {
+            post.frame = post.frame.wrapping_add(1);
+        }
+        let command_buffer = self.command_buffers[index];
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder();
+        unsafe {
+            self.logical_device
+                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+        }?;
+        if let Some(occlusion_culling) = &self.occlusion_culling {
+            unsafe {
+                self.mark(command_buffer, "occlusion culling");
+                occlusion_culling.cull(&self.logical_device, command_buffer);
+            }
+        }
+
+        if let Some(reflections) = self.reflections.as_ref().filter(|r| r.plane.is_some()) {
+            unsafe {
+                self.mark(command_buffer, "planar reflection");
+                reflections.begin(
+                    &self.logical_device,
+                    command_buffer,
+                    reflections.planar_framebuffer,
+                    reflections.planar_extent,
+                    self.clear.colour,
+                );
+                self.draw_reflected_scene(command_buffer);
+                reflections.end(&self.logical_device, command_buffer);
+            }
+        }
+
+        let clear_values = self.clear.to_render_pass();
+
+        let renderpass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.renderpass)
+            .framebuffer(self.swapchain.framebuffers[index])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.swapchain.extent,
+            })
+            .clear_values(&clear_values);
+        unsafe {
+            self.mark(command_buffer, "opaque");
+            self.logical_device.cmd_begin_render_pass(
+                command_buffer,
+                &renderpass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+            let full = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.swapchain.extent,
+            };
+            let framed = self.viewport_rect();
+            self.set_viewport_and_scissor(command_buffer, framed);
+            if framed != full {
+                self.clear_bars(command_buffer, framed);
+            }
+            if let Some(sky) = &self.sky {
+                sky.draw(
+                    &self.logical_device,
+                    command_buffer,
+                    self.descriptor_sets[index],
+                );
+            }
+            self.logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.layout,
+                0,
+                &[self.descriptor_sets[index]],
+                &[],
+            );
+            let drawables = || {
+                self.models.iter().map(|m| (&m.mesh, &m.instances)).chain(
+                    self.instance_sets.iter().filter_map(|(handle, instances)| {
+                        self.meshes.get(*handle).map(|mesh| (mesh, instances))
+                    }),
+                )
+            };
+            let draw_opaque = || {
+                // Models come first, at the indices occlusion culling knows them by.
+                drawables()
+                    .enumerate()
+                    .filter(|(_, (mesh, _))| mesh.topology == Topology::TriangleList)
+                    .for_each(|(i, (mesh, instances))| {
+                        let culled = i < self.models.len()
+                            && self.occlusion_culling.as_ref().is_some_and(|occlusion| {
+                                occlusion.draw(&self.logical_device, command_buffer, i, mesh)
+                            });
+                        if !culled {
+                            self.clipped(command_buffer, framed, i, || {
+                                mesh.draw(&self.logical_device, command_buffer, instances)
+                            });
+                        }
+                    });
+                if let Some(terrain) = self
+                    .terrain
+                    .as_ref()
+                    .filter(|_| self.splat_terrain.is_none())
+                {
+                    terrain.draw(&self.logical_device, command_buffer);
+                }
+            };
+            let colour_pipeline = if let Some(depth_prepass) = &self.depth_prepass {
+                self.logical_device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    depth_prepass.depth_pipeline,
+                );
+                draw_opaque();
+                depth_prepass.colour_pipeline
+            } else {
+                self.pipeline.pipeline
+            };
+            let colour_pipeline = if self.render_mode == RenderMode::Overdraw {
+                self.pipeline.overdraw_pipeline
+            } else {
+                colour_pipeline
+            };
+            match &self.reflections {
+                Some(reflections) if self.render_mode == RenderMode::Lit => {
+                    reflections.bind_lit(&self.logical_device, command_buffer)
+                }
+                _ => self.logical_device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    colour_pipeline,
+                ),
+            }
+            draw_opaque();
+            for (i, (mesh, instances)) in drawables().enumerate() {
+                if mesh.topology == Topology::TriangleList {
+                    continue;
+                }
+                let key = (
+                    mesh.topology,
+                    mesh.primitive_restart && mesh.topology.is_strip(),
+                );
+                // Skipped until its variant is compiled: no other pipeline assembles
+                // its indices into the same primitives.
+                let pipeline = self
+                    .pipeline
+                    .topology_pipeline(key.0, key.1)
+                    .or_else(|| self.pipeline_registry.get(&key));
+                if let Some(pipeline) = pipeline {
+                    self.logical_device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    self.clipped(command_buffer, framed, i, || {
+                        mesh.draw(&self.logical_device, command_buffer, instances)
+                    });
+                }
+            }
+            if let Some(foliage) = &self.foliage {
+                foliage.draw(
+                    &self.logical_device,
+                    command_buffer,
+                    self.descriptor_sets[index],
+                );
+                // The foliage layout has push constants, which disturbs set 0 for the
+                // main layout.
+                self.logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline.layout,
+                    0,
+                    &[self.descriptor_sets[index]],
+                    &[],
+                );
+            }
+            if let Some(cutouts) = &self.cutouts {
+                cutouts.bind(
+                    &self.logical_device,
+                    command_buffer,
+                    self.descriptor_sets[index],
+                );
+                for (i, (mesh, instances)) in drawables().enumerate() {
+                    if mesh.topology == Topology::TriangleList
+                        && instances
+                            .visible()
+                            .iter()
+                            .any(|instance| instance.base().is_cutout())
+                    {
+                        self.clipped(command_buffer, framed, i, || {
+                            mesh.draw(&self.logical_device, command_buffer, instances)
+                        });
+                    }
+                }
+                if let Some(foliage) = &self.foliage {
+                    cutouts.draw_foliage(&self.logical_device, command_buffer, foliage);
+                }
+                // Likewise for the cutout layout.
+                self.logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline.layout,
+                    0,
+                    &[self.descriptor_sets[index]],
+                    &[],
+                );
+            }
+            if let Some(mesh_shader) = &self.mesh_shader {
+                mesh_shader.draw(&self.logical_device, command_buffer);
+            }
+            if let Some(storage_instancing) = &self.storage_instancing {
+                storage_instancing.draw(
+                    &self.logical_device,
+                    command_buffer,
+                    self.descriptor_sets[index],
+                );
+            }
+            if let (Some(splat_terrain), Some(terrain)) = (&self.splat_terrain, &self.terrain) {
+                splat_terrain.draw(
+                    &self.logical_device,
+                    command_buffer,
+                    self.descriptor_sets[index],
+                    terrain.chunks.values().map(|chunk| &chunk.model.mesh),
+                );
+            }
+            if self.transparency_mode == TransparencyMode::Sorted {
+                self.transparent_pass
+                    .draw(&self.logical_device, command_buffer, &self.models);
+            }
+            if let Some(grid) = &self.grid {
+                grid.draw(
+                    &self.logical_device,
+                    command_buffer,
+                    self.descriptor_sets[index],
+                );
+            }
+            if let Some(trails) = &self.trails {
+                trails.draw(
+                    &self.logical_device,
+                    command_buffer,
+                    self.descriptor_sets[index],
+                );
+            }
+            if let Some(outline) = &self.outline {
+                if !self.selected.is_empty() {
+                    outline.draw(
+                        &self.logical_device,
+                        command_buffer,
+                        self.descriptor_sets[index],
+                        framed.extent,
+                        &self.models,
+                        &self.selected,
+                    );
+                }
+            }
+            if let Some(render_callback) = &mut self.render_callback {
+                render_callback(&self.logical_device, command_buffer, index);
+            }
+            self.logical_device
+                .cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+            self.mark(command_buffer, "transparency accumulation");
+            if self.transparency_mode == TransparencyMode::WeightedBlended {
+                self.oit
+                    .draw_accumulation(&self.logical_device, command_buffer, &self.models);
+            }
+            self.logical_device
+                .cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+            self.mark(command_buffer, "transparency composite");
+            if self.transparency_mode == TransparencyMode::WeightedBlended {
+                self.oit
+                    .draw_composite(&self.logical_device, command_buffer);
+            }
+            if let Some(output_encode) = &self.output_encode {
+                self.logical_device
+                    .cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+                self.mark(command_buffer, "output encode");
+                // The bars were cleared in the scene attachment and are encoded too.
+                self.set_viewport_and_scissor(command_buffer, full);
+                output_encode.draw(&self.logical_device, command_buffer);
+            }
+            self.logical_device.cmd_end_render_pass(command_buffer);
+            if let Some(post) = &self.post {
+                self.mark(command_buffer, "post processing");
+                post.begin(&self.logical_device, command_buffer, &self.swapchain, index);
+                self.set_viewport_and_scissor(command_buffer, framed);
+                if let Some(depth_of_field) = &self.depth_of_field {
+                    depth_of_field.draw(
+                        &self.logical_device,
+                        command_buffer,
+                        post.descriptor_set,
+                        self.swapchain.extent.height,
+                    );
+                }
+                if let Some(light_shafts) = &self.light_shafts {
+                    light_shafts.draw(
+                        &self.logical_device,
+                        command_buffer,
+                        post.descriptor_set,
+                        self.clear.depth,
+                    );
+                }
+                if let Some(lens_flare) = &self.lens_flare {
+                    lens_flare.draw(
+                        &self.logical_device,
+                        command_buffer,
+                        post.descriptor_set,
+                        self.clear.depth,
+                    );
+                }
+                post.draw_finish(&self.logical_device, command_buffer, framed);
+                post.end(&self.logical_device, command_buffer, &self.swapchain);
+            }
+            if let Some(occlusion_culling) = &self.occlusion_culling {
+                self.mark(command_buffer, "hi-z pyramid");
+                occlusion_culling.build_pyramid(&self.logical_device, command_buffer);
+            }
+            if let Some(blit_target) = self.blit_target {
+                self.mark(command_buffer, "blit");
+                self.record_blit(command_buffer, self.swapchain.images[index], blit_target);
+            }
+            self.logical_device.end_command_buffer(command_buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// What the last frame submitted: pipelines, draws, descriptor bindings, buffers,
+    /// camera and passes.
+    pub fn frame_state(&self) -> FrameState {
+        let colour_pipeline = if self.render_mode == RenderMode::Overdraw {
+            self.pipeline.overdraw_pipeline
+        } else if let Some(depth_prepass) = &self.depth_prepass {
+            depth_prepass.colour_pipeline
+        } else {
+            self.pipeline.pipeline
+        };
+        let colour_pipeline = match &self.reflections {
+            Some(reflections) if self.render_mode == RenderMode::Lit => reflections.lit_pipeline,
+            _ => colour_pipeline,
+        };
+        let mut pipelines = vec![PipelineState {
+            name: "colour".to_owned(),
+            handle: colour_pipeline.as_raw(),
+        }];
+        if let Some(depth_prepass) = &self.depth_prepass {
+            pipelines.push(PipelineState {
+                name: "depth prepass".to_owned(),
+                handle: depth_prepass.depth_pipeline.as_raw(),
+            });
+        }
+
+        let framed = self.viewport_rect();
+        let drawables = self
+            .models
+            .iter()
+            .map(|model| (true, &model.mesh, &model.instances))
+            .chain(self.instance_sets.iter().filter_map(|(handle, instances)| {
+                self.meshes
+                    .get(*handle)
+                    .map(|mesh| (false, mesh, instances))
+            }));
+        let mut draws = vec![];
+        for (i, (model, mesh, instances)) in drawables.enumerate() {
+            let key = (
+                mesh.topology,
+                mesh.primitive_restart && mesh.topology.is_strip(),
+            );
+            // Chosen as `Krakatoa::update` chooses it.
+            let pipeline = if key.0 == Topology::TriangleList {
+                Some(colour_pipeline)
+            } else {
+                self.pipeline
+                    .topology_pipeline(key.0, key.1)
+                    .or_else(|| self.pipeline_registry.get(&key))
+            };
+            if let Some(pipeline) = pipeline {
+                if !pipelines
+                    .iter()
+                    .any(|known| known.handle == pipeline.as_raw())
+                {
+                    pipelines.push(PipelineState {
+                        name: format!("{:?}", key.0),
+                        handle: pipeline.as_raw(),
+                    });
+                }
+            }
+            draws.push(DrawState {
+                index: i,
+                model,
+                topology: format!("{:?}", mesh.topology),
+                primitive_restart: mesh.primitive_restart,
+                vertex_count: mesh.vertex_data.len(),
+                index_count: mesh.index_data.len(),
+                instance_count: instances.first_invisible,
+                pipeline: pipeline.map(|pipeline| pipeline.as_raw()),
+                clip_rect: self
+                    .clip_rects
+                    .get(&i)
+                    .filter(|_| model)
+                    .map(|clip| intersect(framed, *clip).into()),
+                occlusion_culled: model
+                    && key.0 == Topology::TriangleList
+                    && self.occlusion_culling.is_some(),
+            });
+        }
+
+        let descriptor_bindings = [self.uniform_buffer.buffer, self.light_buffer.buffer]
+            .into_iter()
+            .enumerate()
+            .map(|(binding, buffer)| DescriptorBinding {
+                set: 0,
+                binding: binding as u32,
+                descriptor_type: format!("{:?}", vk::DescriptorType::UNIFORM_BUFFER),
+                buffer: buffer.as_raw(),
+                range: None,
+            })
+            .collect();
+
+        FrameState {
+            frame: self.clock.frame_count,
+            elapsed: self.clock.elapsed,
+            delta: self.clock.delta,
+            extent: [self.swapchain.extent.width, self.swapchain.extent.height],
+            viewport: PixelRect::from(framed),
+            render_mode: format!("{:?}", self.render_mode),
+            blend_mode: format!("{:?}", self.pipeline.blend_mode),
+            transparency_mode: format!("{:?}", self.transparency_mode),
+            view: self.last_camera.map(|[view, _]| view),
+            projection: self.last_camera.map(|[_, projection]| projection),
+            pipelines,
+            draws,
+            descriptor_bindings,
+            buffers: BufferState::from_report(&self.allocation_report()),
+            passes: self.frame_graph(),
+        }
+    }
+
+    /// Writes [`Krakatoa::frame_state`] to `path` as JSON.
+    #[cfg(feature = "serde")]
+    pub fn dump_frame_state(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        std::fs::write(path, self.frame_state().to_json()?)?;
+        Ok(())
+    }
+
+    /// The passes [`Krakatoa::update`] records with what is enabled now, and the
+    /// dependencies between them, for dumping with [`FrameGraph::to_dot`].
+    pub fn frame_graph(&self) -> FrameGraph {
+        let mut graph = FrameGraph::new();
+        let main = Some("main render pass");
+        let hdr = self.output_encode.is_some();
+        let colour = if hdr { "scene" } else { "swapchain image" };
+        let named = |draws: &[(bool, &str)]| -> Vec<String> {
+            draws
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, name)| name.to_string())
+                .collect()
+        };
+
+        if self.occlusion_culling.is_some() {
+            graph.pass(
+                "occlusion culling",
+                PassKind::Compute,
+                None,
+                &["hi-z pyramid", "instances"],
+                &["indirect draws"],
+                vec![],
+            );
+        }
+        let planar = self
+            .reflections
+            .as_ref()
+            .is_some_and(|reflections| reflections.plane.is_some());
+        if planar {
+            graph.pass(
+                "planar reflection",
+                PassKind::Graphics,
+                Some("planar reflection"),
+                &["frame uniforms", "instances"],
+                &["planar reflection"],
+                vec!["reflected scene".to_owned()],
+            );
+        }
+
+        let mut reads = vec!["frame uniforms", "instances"];
+        if self.occlusion_culling.is_some() {
+            reads.push("indirect draws");
+        }
+        if planar {
+            reads.push("planar reflection");
+        }
+        if self.reflections.is_some() {
+            reads.push("reflection probes");
+        }
+        let sorted = self.transparency_mode == TransparencyMode::Sorted;
+        let weighted = self.transparency_mode == TransparencyMode::WeightedBlended;
+        graph.pass(
+            "opaque",
+            PassKind::Graphics,
+            main,
+            &reads,
+            &[colour, "depth"],
+            named(&[
+                (self.sky.is_some(), "sky"),
+                (self.depth_prepass.is_some(), "depth prepass"),
+                (true, "models"),
+                (self.terrain.is_some(), "terrain"),
+                (self.foliage.is_some(), "foliage"),
+                (self.cutouts.is_some(), "cutouts"),
+                (self.mesh_shader.is_some(), "mesh shader"),
+                (self.storage_instancing.is_some(), "storage instancing"),
+                (self.splat_terrain.is_some(), "splat terrain"),
+                (sorted, "sorted transparency"),
+                (self.grid.is_some(), "grid"),
+                (self.trails.is_some(), "trails"),
+                (
+                    self.outline.is_some() && !self.selected.is_empty(),
+                    "outline",
+                ),
+                (self.render_callback.is_some(), "render callback"),
+            ]),
+        );
+        graph.pass(
+            "transparency accumulation",
+            PassKind::Graphics,
+            main,
+            &["frame uniforms", "depth"],
+            &["accumulation", "revealage"],
+            named(&[(weighted, "weighted blended transparency")]),
+        );
+        graph.pass(
+            "transparency composite",
+            PassKind::Graphics,
+            main,
+            &["accumulation", "revealage"],
+            &[colour],
+            named(&[(weighted, "composite")]),
+        );
+        if hdr {
+            graph.pass(
+                "output encode",
+                PassKind::Graphics,
+                main,
+                &["scene"],
+                &["swapchain image"],
+                vec!["encode".to_owned()],
+            );
+        }
+
+        if self.post.is_some() {
+            graph.pass(
+                "post processing",
+                PassKind::Graphics,
+                Some("post processing"),
+                &["swapchain image", "depth"],
+                &["swapchain image"],
+                named(&[
+                    (self.depth_of_field.is_some(), "depth of field"),
+                    (self.light_shafts.is_some(), "light shafts"),
+                    (self.lens_flare.is_some(), "lens flare"),
+                    (true, "finish"),
+                ]),
+            );
+        }
+        if self.occlusion_culling.is_some() {
+            graph.pass(
+                "hi-z pyramid",
+                PassKind::Compute,
+                None,
+                &["depth"],
+                &["hi-z pyramid"],
+                vec![],
+            );
+        }
+        if self.blit_target.is_some() {
+            graph.pass(
+                "blit",
+                PassKind::Transfer,
+                None,
+                &["swapchain image"],
+                &["blit target"],
+                vec![],
+            );
+        }
+        graph.pass(
+            "present",
+            PassKind::Present,
+            None,
+            &["swapchain image"],
+            &[],
+            vec![],
+        );
+        graph.connect();
+        graph
+    }
+
+    /// Copies `attachment` as the latest frame left it to the host, tightly packed rows
+    /// from the top, in the layout [`AttachmentId`] describes. Waits for the device to go
+    /// idle. The colour needs `TRANSFER_SRC` in the swapchain's image usage.
+    pub fn read_attachment(&self, attachment: AttachmentId) -> Result<Vec<u8>> {
+        let (image, format, aspect_mask, layout) = match attachment {
+            AttachmentId::Colour => {
+                if !self
+                    .swapchain
+                    .image_usage
+                    .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+                {
+                    bail!("The swapchain images cannot be copied from.");
+                }
+                (
+                    self.swapchain.images[self.image_index],
+                    self.swapchain.surface_format.format,
+                    vk::ImageAspectFlags::COLOR,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                )
+            }
+            AttachmentId::Depth => {
+                if self.pipeline.multisampling.samples != vk::SampleCountFlags::TYPE_1 {
+                    bail!("The depth buffer cannot be read back with MSAA.");
+                }
+                (
+                    self.swapchain.depth_image,
+                    self.swapchain.depth_format,
+                    vk::ImageAspectFlags::DEPTH,
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                )
+            }
+        };
+        let extent = self.swapchain.extent;
+        let size =
+            readback::texel_size(attachment, format)? * extent.width as u64 * extent.height as u64;
+        let staging = Buffer::init(
+            size as usize,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            self.physical_device_memory_properties,
+            &self.logical_device,
+        )?;
+
+        let copied = unsafe { self.logical_device.device_wait_idle() }
+            .map_err(anyhow::Error::from)
+            .and_then(|_| {
+                let command_buffer =
+                    begin_one_time(&self.logical_device, self.pools.graphics_command_pool)?;
+                // Layout transitions cover both aspects of a depth/stencil image.
+                let subresource_range = vk::ImageSubresourceRange {
+                    aspect_mask: if format_has_stencil(format) {
+                        aspect_mask | vk::ImageAspectFlags::STENCIL
+                    } else {
+                        aspect_mask
+                    },
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                };
+                let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+                    vk::ImageMemoryBarrier::builder()
+                        .image(image)
+                        .old_layout(old_layout)
+                        .new_layout(new_layout)
+                        .src_access_mask(src_access_mask)
+                        .dst_access_mask(dst_access_mask)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .subresource_range(subresource_range)
+                        .build()
+                };
+                let region = vk::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D::default(),
+                    image_extent: vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    },
+                };
+                unsafe {
+                    self.logical_device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::ALL_COMMANDS,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &[barrier(
+                            layout,
+                            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                            vk::AccessFlags::MEMORY_WRITE,
+                            vk::AccessFlags::TRANSFER_READ,
+                        )],
+                    );
+                    self.logical_device.cmd_copy_image_to_buffer(
+                        command_buffer,
+                        image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        staging.buffer,
+                        &[region],
+                    );
+                    self.logical_device.cmd_pipeline_barrier(
+                        command_buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::ALL_COMMANDS,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[],
+                        &[barrier(
+                            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                            layout,
+                            vk::AccessFlags::empty(),
+                            vk::AccessFlags::empty(),
+                        )],
+                    );
+                }
+                end_one_time(
+                    &self.logical_device,
+                    self.pools.graphics_command_pool,
+                    self.queues.graphics_queue,
+                    command_buffer,
+                )?;
+
+                staging.download::<u8>(&self.logical_device)
+            });
+        staging.cleanup(&self.logical_device);
+
+        Ok(readback::convert(attachment, format, &copied?))
+    }
+
+    /// Marks where `pass`, named as in [`Krakatoa::frame_graph`], begins for crash
+    /// diagnostics, if enabled.
+    unsafe fn mark(&self, command_buffer: vk::CommandBuffer, pass: &'static str) {
+        if let Some(crash_diagnostics) = &self.crash_diagnostics {
+            crash_diagnostics.mark(command_buffer, pass);
+        }
+    }
+
+    unsafe fn set_viewport_and_scissor(&self, command_buffer: vk::CommandBuffer, rect: vk::Rect2D) {
+        self.logical_device.cmd_set_viewport(
+            command_buffer,
+            0,
+            &[vk::Viewport {
+                x: rect.offset.x as f32,
+                y: rect.offset.y as f32,
+                width: rect.extent.width as f32,
+                height: rect.extent.height as f32,
+                min_depth: 0.,
+                max_depth: 1.,
+            }],
+        );
+        self.logical_device
+            .cmd_set_scissor(command_buffer, 0, &[rect]);
+    }
+
+    /// Records `draw` with the scissor narrowed to the clip rectangle of model `model`, if
+    /// it has one, and restores `framed` afterwards.
+    unsafe fn clipped(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        framed: vk::Rect2D,
+        model: usize,
+        draw: impl FnOnce(),
+    ) {
+        let Some(clip) = self
+            .clip_rects
+            .get(&model)
+            .filter(|_| model < self.models.len())
+        else {
+            return draw();
+        };
+        self.logical_device
+            .cmd_set_scissor(command_buffer, 0, &[intersect(framed, *clip)]);
+        draw();
+        self.logical_device
+            .cmd_set_scissor(command_buffer, 0, &[framed]);
+    }
+
+    /// Blackens the colour attachment around `framed`, which is centred in the swapchain.
+    /// Must be recorded in the first subpass.
+    unsafe fn clear_bars(&self, command_buffer: vk::CommandBuffer, framed: vk::Rect2D) {
+        let extent = self.swapchain.extent;
+        let bar = |x: i32, y: i32, width: u32, height: u32| vk::ClearRect {
+            rect: vk::Rect2D {
+                offset: vk::Offset2D { x, y },
+                extent: vk::Extent2D { width, height },
+            },
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let bars = if framed.extent.width < extent.width {
+            let right = framed.offset.x as u32 + framed.extent.width;
+            [
+                bar(0, 0, framed.offset.x as u32, extent.height),
+                bar(right as i32, 0, extent.width - right, extent.height),
+            ]
+        } else {
+            let bottom = framed.offset.y as u32 + framed.extent.height;
+            [
+                bar(0, 0, extent.width, framed.offset.y as u32),
+                bar(0, bottom as i32, extent.width, extent.height - bottom),
+            ]
+        };
+        let bars: Vec<vk::ClearRect> = bars
+            .into_iter()
+            .filter(|bar| bar.rect.extent.width > 0 && bar.rect.extent.height > 0)
+            .collect();
+        self.logical_device.cmd_clear_attachments(
+            command_buffer,
+            &[vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                color_attachment: 0,
+                clear_value: vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [0.0, 0.0, 0.0, 1.0],
+                    },
+                },
+            }],
+            &bars,
+        );
+    }
+
+    /// Scales the presentable `image` into `target`, handing `image` back in
+    /// `PRESENT_SRC_KHR` for presentation.
+    unsafe fn record_blit(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        target: BlitTarget,
+    ) {
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let barrier = |image, old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .image(image)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(subresource_range)
+                .build()
+        };
+        self.logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[
+                barrier(
+                    image,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                ),
+                barrier(
+                    target.image,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                ),
+            ],
+        );
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let corner = |extent: vk::Extent2D| vk::Offset3D {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: 1,
+        };
+        let region = vk::ImageBlit {
+            src_subresource: subresource,
+            src_offsets: [vk::Offset3D::default(), corner(self.swapchain.extent)],
+            dst_subresource: subresource,
+            dst_offsets: [vk::Offset3D::default(), corner(target.extent)],
+        };
+        self.logical_device.cmd_blit_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            target.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+            vk::Filter::LINEAR,
+        );
+        self.logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[
+                barrier(
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::empty(),
+                ),
+                barrier(
+                    target.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    target.final_layout,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+                ),
+            ],
+        );
+    }
+}
+
+impl<I: Instance> Drop for Krakatoa<I> {
+    fn drop(&mut self) {
+        unsafe {
+            self.logical_device
+                .device_wait_idle()
+                .expect("Something wrong while waiting.");
+            self.uniform_buffer.cleanup(&self.logical_device);
+            self.light_buffer.cleanup(&self.logical_device);
+            self.logical_device
+                .destroy_descriptor_pool(self.descriptor_pool, None);
+            for model in &mut self.models {
+                model.cleanup(&self.logical_device);
+            }
+            self.meshes.cleanup(&self.logical_device);
+            for (_, instances) in &mut self.instance_sets {
+                instances.cleanup(&self.logical_device);
+            }
+            if let Some(terrain) = &mut self.terrain {
+                terrain.cleanup(&self.logical_device);
+            }
+            self.pools.cleanup(&self.logical_device);
+            if let Some(sky) = &self.sky {
+                sky.cleanup(&self.logical_device);
+            }
+            if let Some(grid) = &self.grid {
+                grid.cleanup(&self.logical_device);
+            }
+            if let Some(trails) = &self.trails {
+                trails.cleanup(&self.logical_device);
+            }
+            if let Some(foliage) = &mut self.foliage {
+                foliage.cleanup(&self.logical_device);
+            }
+            if let Some(cutouts) = &self.cutouts {
+                cutouts.cleanup(&self.logical_device);
+            }
+            if let Some(occlusion_culling) = &self.occlusion_culling {
+                occlusion_culling.cleanup(&self.logical_device);
+            }
+            if let Some(environment) = &self.environment {
+                environment.cleanup(&self.logical_device);
+            }
+            if let Some(outline) = &self.outline {
+                outline.cleanup(&self.logical_device);
+            }
+            if let Some(depth_prepass) = &self.depth_prepass {
+                depth_prepass.cleanup(&self.logical_device);
+            }
+            if let Some(mesh_shader) = &self.mesh_shader {
+                mesh_shader.cleanup(&self.logical_device);
+            }
+            if let Some(storage_instancing) = &self.storage_instancing {
+                storage_instancing.cleanup(&self.logical_device);
+            }
+            if let Some(splat_terrain) = &self.splat_terrain {
+                splat_terrain.cleanup(&self.logical_device);
+            }
+            if let Some(ray_query) = &self.ray_query {
+                ray_query.cleanup(&self.logical_device);
+            }
+            if let Some(marching_cubes) = &self.marching_cubes {
+                marching_cubes.cleanup(&self.logical_device);
+            }
+            if let Some(gpu_sort) = &self.gpu_sort {
+                gpu_sort.cleanup(&self.logical_device);
+            }
+            if let Some(crash_diagnostics) = &self.crash_diagnostics {
+                crash_diagnostics.cleanup(&self.logical_device);
+            }
+            if let Some(depth_of_field) = &self.depth_of_field {
+                depth_of_field.cleanup(&self.logical_device);
+            }
+            if let Some(lens_flare) = &self.lens_flare {
+                lens_flare.cleanup(&self.logical_device);
+            }
+            if let Some(light_shafts) = &self.light_shafts {
+                light_shafts.cleanup(&self.logical_device);
+            }
+            if let Some(post) = &self.post {
+                post.cleanup(&self.logical_device);
+            }
+            if let Some(reflections) = &self.reflections {
+                reflections.cleanup(&self.logical_device);
+            }
+            self.transparent_pass.cleanup(&self.logical_device);
+            self.oit.cleanup(&self.logical_device);
+            if let Some(output_encode) = &self.output_encode {
+                output_encode.cleanup(&self.logical_device);
+            }
+            self.pipeline_registry.cleanup(&self.logical_device);
+            self.pipeline.cleanup(&self.logical_device);
+            self.layout_cache.cleanup(&self.logical_device);
+            if !self.suspended {
+                self.swapchain
+                    .cleanup(&self.logical_device, &mut self.render_targets);
+            }
+            self.render_targets.cleanup(&self.logical_device);
+            self.logical_device
+                .destroy_render_pass(self.renderpass, None);
+            self.surface.destroy();
+            if let Some(debug) = &self.debug {
+                debug
+                    .loader
+                    .destroy_debug_utils_messenger(debug.messenger, None);
+            }
+            let leaked = AllocationReport::for_device(&self.logical_device);
+            if !leaked.is_empty() {
+                eprintln!("Still alive when the renderer was dropped: {}", leaked);
+            }
+            if self.owns_context {
+                let allocation_callbacks =
+                    self.host_allocator.as_ref().map(HostAllocator::callbacks);
+                self.logical_device
+                    .destroy_device(allocation_callbacks.as_ref());
+                self.instance
+                    .destroy_instance(allocation_callbacks.as_ref());
+            }
+        };
+    }
+}
+
+/// The fragment shader's light block: the light, the fog, then the render mode.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LightUniforms {
+    direction_and_ambient: [f32; 4],
+    colour: [f32; 4],
+    fog_colour_and_density: [f32; 4],
+    fog_scattering_and_anisotropy: [f32; 4],
+    render_mode_and_depth_range: [f32; 4],
+}
+
+crate::assert_std_layout!(
+    Std140,
+    LightUniforms {
+        direction_and_ambient: Vec4,
+        colour: Vec4,
+        fog_colour_and_density: Vec4,
+        fog_scattering_and_anisotropy: Vec4,
+        render_mode_and_depth_range: Vec4,
+    }
+);
+
+/// The frame uniform block: the camera's view and projection matrices, then the elapsed
+/// and frame time.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FrameUniforms {
+    view: [[f32; 4]; 4],
+    projection: [[f32; 4]; 4],
+    time: [f32; 4],
+}
+
+crate::assert_std_layout!(
+    Std140,
+    FrameUniforms {
+        view: Mat4,
+        projection: Mat4,
+        time: Vec4,
+    }
+);
+
+fn light_uniform(
+    light: &DirectionalLight,
+    fog: &Fog,
+    render_mode: RenderMode,
+    depth_range: f32,
+) -> LightUniforms {
+    let [direction_and_ambient, colour] = light.to_uniform();
+    let [fog_colour_and_density, fog_scattering_and_anisotropy] = fog.to_uniform();
+    LightUniforms {
+        direction_and_ambient,
+        colour,
+        fog_colour_and_density,
+        fog_scattering_and_anisotropy,
+        render_mode_and_depth_range: render_mode.to_uniform(depth_range),
+    }
+}
+
+fn frame_uniforms(camera: &Camera, clock: &FrameTimer) -> FrameUniforms {
+    FrameUniforms {
+        view: camera.view_matrix.into(),
+        projection: camera.projection_matrix.into(),
+        time: [clock.elapsed, clock.delta, 0.0, 0.0],
+    }
+}
+
+/// The overlap of `a` and `b`, empty when they don't overlap.
+fn intersect(a: vk::Rect2D, b: vk::Rect2D) -> vk::Rect2D {
+    let x = a.offset.x.max(b.offset.x);
+    let y = a.offset.y.max(b.offset.y);
+    let right = (a.offset.x + a.extent.width as i32).min(b.offset.x + b.extent.width as i32);
+    let bottom = (a.offset.y + a.extent.height as i32).min(b.offset.y + b.extent.height as i32);
+    vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: vk::Extent2D {
+            width: (right - x).max(0) as u32,
+            height: (bottom - y).max(0) as u32,
+        },
     }
 }
@@ -0,0 +1,155 @@
+use std::ffi::{CStr, CString};
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::hdr::OutputColourSpace;
+use crate::krakatoa::Krakatoa;
+use crate::multisample::Multisampling;
+use crate::pipeline::{BlendMode, PipelineShaders};
+
+/// Engine-wide settings chosen before the device is created.
+pub struct KrakatoaBuilder {
+    /// Default anisotropy for samplers created through [`Krakatoa::create_sampler`].
+    /// Clamped to the device limit; 1 disables anisotropic filtering.
+    pub max_anisotropy: f32,
+    /// Requested MSAA; lowered to what the device supports when the engine starts.
+    pub multisampling: Multisampling,
+    /// Requested swapchain colour space; SDR is used when the display does not offer it.
+    pub output: OutputColourSpace,
+    /// Take the display exclusively while the window is fullscreen, for the lowest
+    /// presentation latency. Windows only, ignored elsewhere.
+    pub full_screen_exclusive: bool,
+    /// Initial inner size of the window, and so of the swapchain, in physical pixels.
+    pub extent: Option<vk::Extent2D>,
+    /// Index into [`Krakatoa::enumerate_adapters`] of the GPU to use; the `KRAKATOA_GPU`
+    /// environment variable takes precedence. Picked automatically when neither is set.
+    pub adapter: Option<usize>,
+    /// Pick a CPU device, such as lavapipe or SwiftShader, over any GPU when no adapter
+    /// is forced, so rendering tests run on machines without one. Also set by the
+    /// `KRAKATOA_SOFTWARE` environment variable.
+    pub prefer_software: bool,
+    /// Instance extensions enabled on top of the engine's own, when available.
+    pub instance_extensions: Vec<CString>,
+    /// Device extensions enabled on top of the engine's own, when available.
+    pub device_extensions: Vec<CString>,
+    /// Instance layers enabled besides the validation layer, when available.
+    pub layers: Vec<CString>,
+    /// Create the instance and device with a [`crate::host_memory::HostAllocator`], whose
+    /// tallies [`Krakatoa::host_memory_stats`] returns.
+    pub track_host_memory: bool,
+    /// Shades the main pipeline instead of the built-in shaders.
+    pub shaders: Option<PipelineShaders>,
+    pub blend_mode: BlendMode,
+    /// Enable `VK_EXT_robustness2` where available, so out-of-bounds buffer and image
+    /// accesses read zeros and unbound descriptors may be left null, instead of faulting
+    /// the device. For development; it slows shaders down.
+    pub robustness: bool,
+    /// Have the validation layer, if installed, run `debugPrintfEXT` calls in shaders and
+    /// print their output through the debug messenger, where [`crate::debug::Debug`]
+    /// also keeps it. Replaces GPU-assisted validation and slows shaders down.
+    pub shader_debug_printf: bool,
+}
+
+impl Default for KrakatoaBuilder {
+    fn default() -> Self {
+        Self {
+            max_anisotropy: 16.0,
+            multisampling: Multisampling::default(),
+            output: OutputColourSpace::default(),
+            full_screen_exclusive: false,
+            extent: None,
+            adapter: None,
+            prefer_software: false,
+            instance_extensions: vec![],
+            device_extensions: vec![],
+            layers: vec![],
+            track_host_memory: false,
+            shaders: None,
+            blend_mode: BlendMode::default(),
+            robustness: false,
+            shader_debug_printf: false,
+        }
+    }
+}
+
+impl KrakatoaBuilder {
+    pub fn build(self, window: winit::window::Window) -> Result<Krakatoa> {
+        Krakatoa::init_with(window, self)
+    }
+    pub fn max_anisotropy(mut self, max_anisotropy: f32) -> KrakatoaBuilder {
+        self.max_anisotropy = max_anisotropy.max(1.0);
+        self
+    }
+    /// Samples per pixel for every attachment, rounded down to a power of two; 1 disables MSAA.
+    pub fn samples(mut self, samples: u32) -> KrakatoaBuilder {
+        let samples = samples.clamp(1, 64);
+        self.multisampling.samples =
+            vk::SampleCountFlags::from_raw(1 << (u32::BITS - 1 - samples.leading_zeros()));
+        self
+    }
+    /// Shades at least this fraction of the samples individually, smoothing aliasing
+    /// inside triangles at a higher fragment cost. Needs MSAA and `sampleRateShading`.
+    pub fn sample_shading(mut self, min_sample_shading: f32) -> KrakatoaBuilder {
+        self.multisampling.min_sample_shading = Some(min_sample_shading);
+        self
+    }
+    pub fn output(mut self, output: OutputColourSpace) -> KrakatoaBuilder {
+        self.output = output;
+        self
+    }
+    pub fn full_screen_exclusive(mut self, full_screen_exclusive: bool) -> KrakatoaBuilder {
+        self.full_screen_exclusive = full_screen_exclusive;
+        self
+    }
+    pub fn extent(mut self, width: u32, height: u32) -> KrakatoaBuilder {
+        self.extent = Some(vk::Extent2D { width, height });
+        self
+    }
+    pub fn adapter(mut self, index: usize) -> KrakatoaBuilder {
+        self.adapter = Some(index);
+        self
+    }
+    pub fn prefer_software(mut self, prefer_software: bool) -> KrakatoaBuilder {
+        self.prefer_software = prefer_software;
+        self
+    }
+    /// Requests an instance extension; see [`Krakatoa::unavailable_extensions`] for
+    /// the ones the loader did not offer.
+    pub fn instance_extension(mut self, name: &CStr) -> KrakatoaBuilder {
+        self.instance_extensions.push(name.to_owned());
+        self
+    }
+    /// Requests a device extension; see [`Krakatoa::unavailable_extensions`] for the
+    /// ones the chosen GPU did not offer.
+    pub fn device_extension(mut self, name: &CStr) -> KrakatoaBuilder {
+        self.device_extensions.push(name.to_owned());
+        self
+    }
+    /// Requests an instance layer; see [`Krakatoa::unavailable_layers`] for the ones
+    /// that are not installed.
+    pub fn layer(mut self, name: &CStr) -> KrakatoaBuilder {
+        self.layers.push(name.to_owned());
+        self
+    }
+    pub fn track_host_memory(mut self, track_host_memory: bool) -> KrakatoaBuilder {
+        self.track_host_memory = track_host_memory;
+        self
+    }
+    pub fn shaders(mut self, shaders: PipelineShaders) -> KrakatoaBuilder {
+        self.shaders = Some(shaders);
+        self
+    }
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> KrakatoaBuilder {
+        self.blend_mode = blend_mode;
+        self
+    }
+    pub fn robustness(mut self, robustness: bool) -> KrakatoaBuilder {
+        self.robustness = robustness;
+        self
+    }
+    pub fn shader_debug_printf(mut self, shader_debug_printf: bool) -> KrakatoaBuilder {
+        self.shader_debug_printf = shader_debug_printf;
+        self
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use anyhow::{Ok, Result};
+use ash::vk;
+
+/// What makes two descriptor set layouts interchangeable: their flags and bindings,
+/// sorted by binding number, with any immutable samplers.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DescriptorSetLayoutKey {
+    pub flags: vk::DescriptorSetLayoutCreateFlags,
+    pub bindings: Vec<(
+        u32,
+        vk::DescriptorType,
+        u32,
+        vk::ShaderStageFlags,
+        Vec<vk::Sampler>,
+    )>,
+}
+
+impl DescriptorSetLayoutKey {
+    pub fn new(
+        flags: vk::DescriptorSetLayoutCreateFlags,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Self {
+        let mut bindings: Vec<_> = bindings
+            .iter()
+            .map(|binding| {
+                let immutable_samplers = if binding.p_immutable_samplers.is_null() {
+                    vec![]
+                } else {
+                    unsafe {
+                        std::slice::from_raw_parts(
+                            binding.p_immutable_samplers,
+                            binding.descriptor_count as usize,
+                        )
+                    }
+                    .to_vec()
+                };
+                (
+                    binding.binding,
+                    binding.descriptor_type,
+                    binding.descriptor_count,
+                    binding.stage_flags,
+                    immutable_samplers,
+                )
+            })
+            .collect();
+        bindings.sort_by_key(|binding| binding.0);
+        Self { flags, bindings }
+    }
+}
+
+/// What makes two pipeline layouts interchangeable: their set layouts in order and their
+/// push constant ranges as stages, offset and size.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineLayoutKey {
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub push_constant_ranges: Vec<(vk::ShaderStageFlags, u32, u32)>,
+}
+
+impl PipelineLayoutKey {
+    pub fn new(
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Self {
+        Self {
+            set_layouts: set_layouts.to_vec(),
+            push_constant_ranges: push_constant_ranges
+                .iter()
+                .map(|range| (range.stage_flags, range.offset, range.size))
+                .collect(),
+        }
+    }
+}
+
+/// Descriptor set and pipeline layouts by what they were created from, so pipelines
+/// declaring the same interface share one layout instead of each making a copy. Sharing
+/// also keeps them compatible, so descriptor sets bound for one stay valid for the next.
+/// Layouts handed out belong to the cache and live until [`LayoutCache::cleanup`].
+#[derive(Default)]
+pub struct LayoutCache {
+    descriptor_set_layouts: HashMap<DescriptorSetLayoutKey, vk::DescriptorSetLayout>,
+    pipeline_layouts: HashMap<PipelineLayoutKey, vk::PipelineLayout>,
+}
+
+impl LayoutCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A set layout of `bindings`, made the first time they are asked for.
+    pub fn descriptor_set_layout(
+        &mut self,
+        logical_device: &ash::Device,
+        flags: vk::DescriptorSetLayoutCreateFlags,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<vk::DescriptorSetLayout> {
+        let key = DescriptorSetLayoutKey::new(flags, bindings);
+        if let Some(layout) = self.descriptor_set_layouts.get(&key) {
+            return Ok(*layout);
+        }
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(flags)
+            .bindings(bindings);
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&layout_info, None) }?;
+        self.descriptor_set_layouts.insert(key, layout);
+        Ok(layout)
+    }
+
+    /// A pipeline layout of `set_layouts` and `push_constant_ranges`, made the first time
+    /// they are asked for.
+    pub fn pipeline_layout(
+        &mut self,
+        logical_device: &ash::Device,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<vk::PipelineLayout> {
+        let key = PipelineLayoutKey::new(set_layouts, push_constant_ranges);
+        if let Some(layout) = self.pipeline_layouts.get(&key) {
+            return Ok(*layout);
+        }
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&layout_info, None) }?;
+        self.pipeline_layouts.insert(key, layout);
+        Ok(layout)
+    }
+
+    /// Descriptor set and pipeline layouts held.
+    pub fn counts(&self) -> (usize, usize) {
+        (
+            self.descriptor_set_layouts.len(),
+            self.pipeline_layouts.len(),
+        )
+    }
+
+    /// Destroys every layout; nothing created with them may still be in use.
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        unsafe {
+            for (_, layout) in self.pipeline_layouts.drain() {
+                logical_device.destroy_pipeline_layout(layout, None);
+            }
+            for (_, layout) in self.descriptor_set_layouts.drain() {
+                logical_device.destroy_descriptor_set_layout(layout, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+use nalgebra::Vector4;
+
+use crate::camera::Camera;
+use crate::light::DirectionalLight;
+
+/// Halo and ghosts of the directional light, as a camera lens scatters a bright source.
+/// Fades out as the depth buffer shows the light covered. Drawn as a
+/// [`crate::post::PostProcess`] effect, added onto the frame; needs single-sampled depth.
+pub struct LensFlare {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// Brightness relative to the light's colour and intensity.
+    pub intensity: f32,
+    /// Ghosts along the axis from the light through the centre, up to 16.
+    pub ghost_count: u32,
+    /// Distance between ghosts, as a fraction of the distance from the light to the centre.
+    pub ghost_spacing: f32,
+    /// Radius of the glow around the light and of the largest ghost, in pixels.
+    pub halo_radius: f32,
+    /// How far around the light the depth buffer is tested for occluders, in pixels.
+    pub occlusion_radius: f32,
+    /// Where the light is in the framebuffer, in pixels, and the centre of the viewport;
+    /// `None` while it is behind the camera.
+    light_and_centre: Option<[f32; 4]>,
+    colour: [f32; 3],
+}
+
+impl LensFlare {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/oit_composite.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/lens_flare.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // Added onto the frame, or whatever effect drew before.
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE)
+            .color_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        /* Pipeline */
+        let descriptor_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: 48,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&descriptor_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colourblend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(LensFlare {
+            pipeline,
+            layout,
+            intensity: 1.0,
+            ghost_count: 6,
+            ghost_spacing: 0.4,
+            halo_radius: 64.0,
+            occlusion_radius: 8.0,
+            light_and_centre: None,
+            colour: [0.0; 3],
+        })
+    }
+
+    /// Places `light` on the screen as `camera` sees it framed in `viewport`; called for
+    /// every frame it renders.
+    pub fn set_light(&mut self, camera: &Camera, light: &DirectionalLight, viewport: vk::Rect2D) {
+        let direction = light.direction.normalize();
+        // A point at infinity, moved by the view's rotation alone.
+        let clip = camera.projection_matrix
+            * camera.view_matrix
+            * Vector4::new(direction.x, direction.y, direction.z, 0.0);
+        let width = viewport.extent.width as f32;
+        let height = viewport.extent.height as f32;
+        let left = viewport.offset.x as f32;
+        let top = viewport.offset.y as f32;
+        self.light_and_centre = (clip.w > f32::EPSILON).then(|| {
+            [
+                left + (clip.x / clip.w * 0.5 + 0.5) * width,
+                top + (clip.y / clip.w * 0.5 + 0.5) * height,
+                left + 0.5 * width,
+                top + 0.5 * height,
+            ]
+        });
+        self.colour = light.colour.map(|channel| channel * light.intensity);
+    }
+
+    /// Draws into the [`crate::post::PostProcess`] render pass. The light shows through
+    /// where the depth buffer still holds `clear_depth`.
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        clear_depth: f32,
+    ) {
+        let Some(light_and_centre) = self.light_and_centre else {
+            return;
+        };
+        if self.intensity <= 0.0 {
+            return;
+        }
+        let intensity = self.intensity;
+        let parameters = [
+            light_and_centre,
+            [
+                self.colour[0] * intensity,
+                self.colour[1] * intensity,
+                self.colour[2] * intensity,
+                clear_depth,
+            ],
+            [
+                self.ghost_count as f32,
+                self.ghost_spacing,
+                self.halo_radius,
+                self.occlusion_radius.max(0.0),
+            ],
+        ];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                parameters.as_ptr() as *const u8,
+                std::mem::size_of_val(&parameters),
+            )
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                bytes,
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
@@ -1,18 +1,84 @@
+pub mod adapter;
+pub mod allocations;
 pub mod buffer;
 pub mod camera;
+pub mod capabilities;
+pub mod clear;
+pub mod conditional_rendering;
+pub mod crash_diagnostics;
+pub mod curve;
+pub mod cutout;
 pub mod debug;
+pub mod depth_of_field;
+pub mod depth_prepass;
+pub mod environment;
+pub mod fog;
+pub mod foliage;
+pub mod frame_graph;
+pub mod frame_state;
+pub mod gpu_sort;
+pub mod grid;
+pub mod hdr;
+pub mod host_memory;
+pub mod input;
+pub mod input_recording;
 pub mod krakatoa;
+pub mod krakatoa_builder;
+pub mod layout_cache;
+pub mod lens_flare;
+pub mod light;
+pub mod light_shafts;
+pub mod marching_cubes;
+#[cfg(feature = "mint")]
+pub mod math;
+pub mod mesh_shader;
 pub mod model;
+pub mod multisample;
+pub mod noise;
+pub mod occlusion;
+pub mod oit;
+pub mod outline;
+pub mod particles;
 pub mod pipeline;
+pub mod pipeline_registry;
 pub mod pools;
+pub mod post;
+pub mod push_descriptor;
 pub mod queue;
+pub mod raw_context;
+pub mod ray_query;
+pub mod readback;
+pub mod reflection;
+pub mod render_mode;
+pub mod render_targets;
+pub mod sampler;
+#[cfg(feature = "shader-compiler")]
+pub mod shader_compiler;
+pub mod sky;
+pub mod splat_terrain;
+pub mod std_layout;
+pub mod storage_instancing;
+pub mod sun_cycle;
 pub mod surface;
 pub mod swapchain;
+pub mod texture;
+pub mod timing;
+pub mod trail;
+pub mod transparency;
+pub mod vertex_layout;
+#[cfg(feature = "wgsl")]
+pub mod wgsl;
+pub mod window_mode;
+#[cfg(feature = "openxr")]
+pub mod xr;
 
 use anyhow::{Ok, Result};
 use ash::extensions::ext::DebugUtils;
 use ash::vk::{self, ApplicationInfo, ExtMetalSurfaceFn, InstanceCreateFlags, InstanceCreateInfo};
 use ash::{Entry, Instance};
+use buffer::Buffer;
+use capabilities::DeviceCapabilities;
+use pipeline::Pipeline;
 use pools::Pools;
 use queue::{QueueFamilies, Queues};
 use surface::Surface;
@@ -33,14 +99,34 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
     vk::FALSE
 }
 
-pub fn init_instance(entry: &Entry) -> Result<Instance, ash::vk::Result> {
+/// The Vulkan version to create the instance with: the loader's, up to
+/// [`capabilities::MAX_API_VERSION`]. A 1.0 loader cannot report its version.
+pub fn instance_api_version(entry: &Entry) -> Result<u32, ash::vk::Result> {
+    entry.try_enumerate_instance_version().map(|version| {
+        version
+            .unwrap_or(vk::API_VERSION_1_0)
+            .min(capabilities::MAX_API_VERSION)
+    })
+}
+
+/// Creates the instance with the engine's extensions plus `extra_extensions` and
+/// `extra_layers`, which must already be known to be available. With `debug_printf`,
+/// the validation layer, if installed, runs `debugPrintfEXT` in shaders and reports what
+/// they print through the debug messenger, in place of GPU-assisted validation.
+pub fn init_instance(
+    entry: &Entry,
+    extra_extensions: &[&std::ffi::CStr],
+    extra_layers: &[&std::ffi::CStr],
+    debug_printf: bool,
+    allocation_callbacks: Option<&vk::AllocationCallbacks>,
+) -> Result<Instance, ash::vk::Result> {
     /* App Info */
     let engine_name = std::ffi::CString::new("UnknownGameEngine").unwrap();
     let app_name = std::ffi::CString::new("Learn Vulkan").unwrap();
     let app_info = ApplicationInfo::builder()
         .application_name(&app_name)
         .engine_name(&engine_name)
-        .api_version(vk::API_VERSION_1_2)
+        .api_version(instance_api_version(entry)?)
         .build();
 
     /* Debug Info */
@@ -59,8 +145,17 @@ pub fn init_instance(entry: &Entry) -> Result<Instance, ash::vk::Result> {
         .build();
 
     /* Instance Create Info */
-    let layer_names: Vec<std::ffi::CString> =
-        vec![std::ffi::CString::new("VK_LAYER_KHRONOS_validation").unwrap()];
+    let validation_layer = std::ffi::CString::new("VK_LAYER_KHRONOS_validation").unwrap();
+    let mut layer_names = vec![];
+    let validation_supported = instance_layer_supported(entry, &validation_layer)?;
+    if validation_supported {
+        layer_names.push(validation_layer.as_c_str());
+    }
+    for layer_name in extra_layers {
+        if !layer_names.contains(layer_name) {
+            layer_names.push(layer_name);
+        }
+    }
     let layer_name_pointers: Vec<*const i8> = layer_names
         .iter()
         .map(|layer_name| layer_name.as_ptr())
@@ -69,6 +164,17 @@ pub fn init_instance(entry: &Entry) -> Result<Instance, ash::vk::Result> {
         DebugUtils::name().as_ptr(),
         ash::extensions::khr::Surface::name().as_ptr(),
     ];
+    // Needed for the HDR colour spaces, harmless where the display has none.
+    if instance_extension_supported(entry, vk::ExtSwapchainColorspaceFn::name())? {
+        extension_names.push(vk::ExtSwapchainColorspaceFn::name().as_ptr());
+    }
+    // Required by `VK_EXT_full_screen_exclusive`.
+    #[cfg(target_os = "windows")]
+    if instance_extension_supported(entry, vk::KhrGetSurfaceCapabilities2Fn::name())? {
+        extension_names.push(vk::KhrGetSurfaceCapabilities2Fn::name().as_ptr());
+    }
+    #[cfg(target_os = "android")]
+    extension_names.push(vk::KhrAndroidSurfaceFn::name().as_ptr());
     #[cfg(any(target_os = "macos", target_os = "ios"))]
     {
         extension_names.push(vk::KhrPortabilityEnumerationFn::name().as_ptr());
@@ -76,23 +182,50 @@ pub fn init_instance(entry: &Entry) -> Result<Instance, ash::vk::Result> {
         extension_names.push(vk::KhrGetPhysicalDeviceProperties2Fn::name().as_ptr());
         extension_names.push(ExtMetalSurfaceFn::name().as_ptr());
     }
-    let create_info = InstanceCreateInfo::builder()
+    // `VK_EXT_validation_features` is provided by the validation layer itself.
+    let debug_printf = debug_printf
+        && validation_supported
+        && entry
+            .enumerate_instance_extension_properties(Some(&validation_layer))?
+            .iter()
+            .any(|ext| unsafe { std::ffi::CStr::from_ptr(ext.extension_name.as_ptr()) } == vk::ExtValidationFeaturesFn::name());
+    if debug_printf {
+        extension_names.push(vk::ExtValidationFeaturesFn::name().as_ptr());
+    }
+    for extension_name in extra_extensions {
+        if !extension_names
+            .iter()
+            .any(|&name| unsafe { std::ffi::CStr::from_ptr(name) } == *extension_name)
+        {
+            extension_names.push(extension_name.as_ptr());
+        }
+    }
+    let enabled_validation_features = [vk::ValidationFeatureEnableEXT::DEBUG_PRINTF];
+    let mut validation_features = vk::ValidationFeaturesEXT::builder()
+        .enabled_validation_features(&enabled_validation_features);
+    let mut create_info = InstanceCreateInfo::builder()
         .push_next(&mut debug_create_info)
         .application_info(&app_info)
         .enabled_layer_names(&layer_name_pointers)
         .flags(InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR)
-        .enabled_extension_names(&extension_names)
-        .build();
+        .enabled_extension_names(&extension_names);
+    if debug_printf {
+        create_info = create_info.push_next(&mut validation_features);
+    }
+    let create_info = create_info.build();
 
     /* Setup */
-    unsafe { entry.create_instance(&create_info, None) }
+    unsafe { entry.create_instance(&create_info, allocation_callbacks) }
 }
 
 pub fn init_device_and_queues(
     instance: &ash::Instance,
     physical_device: vk::PhysicalDevice,
     physical_device_features: vk::PhysicalDeviceFeatures,
+    capabilities: &DeviceCapabilities,
     queue_families: &QueueFamilies,
+    extra_extensions: &[&std::ffi::CStr],
+    allocation_callbacks: Option<&vk::AllocationCallbacks>,
 ) -> Result<(ash::Device, Queues)> {
     let priorities = [1.0f32];
     let queue_infos = [
@@ -105,21 +238,90 @@ pub fn init_device_and_queues(
             .queue_priorities(&priorities)
             .build(),
     ];
-    let device_extension_name_pointers: Vec<*const i8> = vec![
-        ash::extensions::khr::Swapchain::name().as_ptr(),
-        vk::KhrPortabilitySubsetFn::name().as_ptr(),
-    ];
+    let mut device_extension_names = vec![ash::extensions::khr::Swapchain::name()];
+    device_extension_names.extend(capabilities.extensions());
+    for extension_name in extra_extensions {
+        if !device_extension_names.contains(extension_name) {
+            device_extension_names.push(extension_name);
+        }
+    }
+    let device_extension_name_pointers: Vec<*const i8> = device_extension_names
+        .iter()
+        .map(|name| name.as_ptr())
+        .collect();
+
+    /* Optional features, chained only when available */
     let mut physical_device_separate_depth =
         vk::PhysicalDeviceSeparateDepthStencilLayoutsFeatures::builder()
             .separate_depth_stencil_layouts(true);
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    let mut timeline_semaphore =
+        vk::PhysicalDeviceTimelineSemaphoreFeatures::builder().timeline_semaphore(true);
+    let mut synchronization2 =
+        vk::PhysicalDeviceSynchronization2Features::builder().synchronization2(true);
+    let mut dynamic_rendering =
+        vk::PhysicalDeviceDynamicRenderingFeatures::builder().dynamic_rendering(true);
+    let mut mesh_shader = vk::PhysicalDeviceMeshShaderFeaturesEXT::builder()
+        .task_shader(true)
+        .mesh_shader(true);
+    let mut acceleration_structure =
+        vk::PhysicalDeviceAccelerationStructureFeaturesKHR::builder().acceleration_structure(true);
+    let mut ray_query = vk::PhysicalDeviceRayQueryFeaturesKHR::builder().ray_query(true);
+    let mut buffer_device_address =
+        vk::PhysicalDeviceBufferDeviceAddressFeatures::builder().buffer_device_address(true);
+    let mut graphics_pipeline_library =
+        vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT::builder()
+            .graphics_pipeline_library(true);
+    let mut conditional_rendering =
+        vk::PhysicalDeviceConditionalRenderingFeaturesEXT::builder().conditional_rendering(true);
+    let mut device_fault = vk::PhysicalDeviceFaultFeaturesEXT::builder().device_fault(true);
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
         .enabled_extension_names(&device_extension_name_pointers)
-        .enabled_features(&physical_device_features)
-        .push_next(&mut physical_device_separate_depth);
+        .enabled_features(&physical_device_features);
+    if capabilities.separate_depth_stencil_layouts {
+        device_create_info = device_create_info.push_next(&mut physical_device_separate_depth);
+    }
+    if capabilities.timeline_semaphore {
+        device_create_info = device_create_info.push_next(&mut timeline_semaphore);
+    }
+    if capabilities.synchronization2 {
+        device_create_info = device_create_info.push_next(&mut synchronization2);
+    }
+    if capabilities.dynamic_rendering {
+        device_create_info = device_create_info.push_next(&mut dynamic_rendering);
+    }
+    if capabilities.mesh_shader {
+        device_create_info = device_create_info.push_next(&mut mesh_shader);
+    }
+    if capabilities.ray_query {
+        device_create_info = device_create_info
+            .push_next(&mut acceleration_structure)
+            .push_next(&mut ray_query)
+            .push_next(&mut buffer_device_address);
+    }
+    if capabilities.graphics_pipeline_library {
+        device_create_info = device_create_info.push_next(&mut graphics_pipeline_library);
+    }
+    if capabilities.conditional_rendering {
+        device_create_info = device_create_info.push_next(&mut conditional_rendering);
+    }
+    if capabilities.device_fault {
+        device_create_info = device_create_info.push_next(&mut device_fault);
+    }
+    // Robust buffer access 2 builds on the core robust buffer access.
+    let mut robustness2 = capabilities.robustness2.unwrap_or_default();
+    robustness2.robust_buffer_access2 &= physical_device_features.robust_buffer_access;
+    if capabilities.robustness2.is_some() {
+        device_create_info = device_create_info.push_next(&mut robustness2);
+    }
+    let mut portability_subset = capabilities.portability_subset.unwrap_or_default();
+    if capabilities.portability_subset.is_some() {
+        device_create_info = device_create_info.push_next(&mut portability_subset);
+    }
 
-    let logical_device =
-        unsafe { instance.create_device(physical_device, &device_create_info, None)? };
+    let logical_device = unsafe {
+        instance.create_device(physical_device, &device_create_info, allocation_callbacks)?
+    };
     let graphics_queue =
         unsafe { logical_device.get_device_queue(queue_families.graphics_q_index.unwrap(), 0) };
     let transfer_queue =
@@ -133,59 +335,226 @@ pub fn init_device_and_queues(
     ))
 }
 
+pub fn instance_extension_supported(
+    entry: &Entry,
+    name: &std::ffi::CStr,
+) -> Result<bool, ash::vk::Result> {
+    entry
+        .enumerate_instance_extension_properties(None)
+        .map(|extensions| {
+            extensions
+                .iter()
+                .any(|ext| unsafe { std::ffi::CStr::from_ptr(ext.extension_name.as_ptr()) } == name)
+        })
+}
+
+pub fn instance_layer_supported(
+    entry: &Entry,
+    name: &std::ffi::CStr,
+) -> Result<bool, ash::vk::Result> {
+    entry.enumerate_instance_layer_properties().map(|layers| {
+        layers
+            .iter()
+            .any(|layer| unsafe { std::ffi::CStr::from_ptr(layer.layer_name.as_ptr()) } == name)
+    })
+}
+
+pub fn device_extension_supported(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    name: &std::ffi::CStr,
+) -> Result<bool> {
+    let extensions = unsafe { instance.enumerate_device_extension_properties(physical_device)? };
+
+    Ok(extensions
+        .iter()
+        .any(|ext| unsafe { std::ffi::CStr::from_ptr(ext.extension_name.as_ptr()) } == name))
+}
+
+/// Picks the adapter at `adapter` when given, otherwise a discrete GPU, then an
+/// integrated one, then whatever comes first. `prefer_software` puts CPU devices ahead
+/// of all of them.
 pub fn init_physical_device_and_properties(
     instance: &ash::Instance,
+    adapter: Option<usize>,
+    prefer_software: bool,
 ) -> Result<(
     vk::PhysicalDevice,
     vk::PhysicalDeviceProperties,
     vk::PhysicalDeviceFeatures,
 )> {
     let phys_devs = unsafe { instance.enumerate_physical_devices()? };
-    let mut chosen = None;
-    for p in phys_devs {
-        let properties = unsafe { instance.get_physical_device_properties(p) };
-        let features = unsafe { instance.get_physical_device_features(p) };
-        if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-            chosen = Some((p, properties, features));
-        }
-    }
+    let rank = |device_type| match device_type {
+        vk::PhysicalDeviceType::CPU if prefer_software => 0,
+        vk::PhysicalDeviceType::DISCRETE_GPU => 1,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 2,
+        _ => 3,
+    };
+    let chosen = match adapter {
+        Some(index) => match phys_devs.get(index) {
+            Some(p) => *p,
+            None => anyhow::bail!(
+                "Adapter {} requested, but only {} are available.",
+                index,
+                phys_devs.len()
+            ),
+        },
+        None => match phys_devs.iter().min_by_key(|p| {
+            rank(unsafe { instance.get_physical_device_properties(**p) }.device_type)
+        }) {
+            Some(p) => *p,
+            None => anyhow::bail!("No Vulkan adapter found."),
+        },
+    };
+    let properties = unsafe { instance.get_physical_device_properties(chosen) };
+    let features = unsafe { instance.get_physical_device_features(chosen) };
+
+    Ok((chosen, properties, features))
+}
 
-    Ok(chosen.unwrap())
+/// Depth format for the swapchain's depth buffer, preferring ones with a stencil aspect.
+pub fn choose_depth_format(instance: &Instance, physical_device: vk::PhysicalDevice) -> vk::Format {
+    [
+        vk::Format::D32_SFLOAT_S8_UINT,
+        vk::Format::D24_UNORM_S8_UINT,
+    ]
+    .into_iter()
+    .find(|format| {
+        let properties =
+            unsafe { instance.get_physical_device_format_properties(physical_device, *format) };
+        properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    })
+    .unwrap_or(vk::Format::D32_SFLOAT)
+}
+
+pub fn format_has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D32_SFLOAT_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::S8_UINT
+    )
 }
 
 pub fn init_renderpass(
     logical_device: &ash::Device,
     physical_device: vk::PhysicalDevice,
     surface: &Surface,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
 ) -> Result<vk::RenderPass> {
-    let attachments = [
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+    let surface_format = surface.choose_format(physical_device)?;
+    let hdr = hdr::OutputColourSpace::from_colour_space(surface_format.color_space).is_hdr();
+    let colour_format = if hdr {
+        hdr::SCENE_FORMAT
+    } else {
+        surface_format.format
+    };
+    let surface_format = surface_format.format;
+    let stencil_load_op = if format_has_stencil(depth_format) {
+        vk::AttachmentLoadOp::CLEAR
+    } else {
+        vk::AttachmentLoadOp::DONT_CARE
+    };
+
+    /* With MSAA, attachment 0 is multisampled and resolves into attachment 4.
+    With HDR output, the single-sampled scene is encoded into the swapchain image,
+    attached last, by subpass 3. */
+    let single_sampled_final_layout = if hdr {
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    } else {
+        vk::ImageLayout::PRESENT_SRC_KHR
+    };
+    let single_sampled_store_op = if hdr {
+        vk::AttachmentStoreOp::DONT_CARE
+    } else {
+        vk::AttachmentStoreOp::STORE
+    };
+    let mut attachments = vec![
         vk::AttachmentDescription::builder()
-            .format(
-                surface
-                    .get_formats(physical_device)?
-                    .first()
-                    .unwrap()
-                    .format,
-            )
+            .format(colour_format)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(if multisampled {
+                vk::AttachmentStoreOp::DONT_CARE
+            } else {
+                single_sampled_store_op
+            })
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(if multisampled {
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            } else {
+                single_sampled_final_layout
+            })
+            .samples(samples)
+            .build(),
+        vk::AttachmentDescription::builder()
+            .format(depth_format)
             .load_op(vk::AttachmentLoadOp::CLEAR)
+            // Kept for `Krakatoa::read_attachment`.
             .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(stencil_load_op)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .samples(samples)
+            .build(),
+        vk::AttachmentDescription::builder()
+            .format(oit::ACCUMULATION_FORMAT)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .samples(samples)
             .build(),
         vk::AttachmentDescription::builder()
-            .format(vk::Format::D32_SFLOAT)
+            .format(oit::REVEALAGE_FORMAT)
             .load_op(vk::AttachmentLoadOp::CLEAR)
             .store_op(vk::AttachmentStoreOp::DONT_CARE)
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .samples(samples)
             .build(),
     ];
+    if multisampled {
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(colour_format)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(single_sampled_store_op)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(single_sampled_final_layout)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        );
+    }
+    let scene_attachment = if multisampled { 4 } else { 0 };
+    let encode_attachment = attachments.len() as u32;
+    if hdr {
+        attachments.push(
+            vk::AttachmentDescription::builder()
+                .format(surface_format)
+                .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        );
+    }
 
     let color_attachment_refs = [vk::AttachmentReference {
         attachment: 0,
@@ -193,24 +562,133 @@ pub fn init_renderpass(
     }];
     let depth_attachment_refs = vk::AttachmentReference {
         attachment: 1,
-        layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+        // Valid for depth-only formats too, without `separateDepthStencilLayouts`.
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
+
+    /* Subpass 1 accumulates weighted-blended transparency, subpass 2 composites it */
+    let oit_attachment_refs = [
+        vk::AttachmentReference {
+            attachment: 2,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        },
+        vk::AttachmentReference {
+            attachment: 3,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        },
+    ];
+    let read_only_depth_attachment_refs = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
     };
+    let oit_input_attachment_refs = [
+        vk::AttachmentReference {
+            attachment: 2,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        },
+        vk::AttachmentReference {
+            attachment: 3,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        },
+    ];
 
-    let subpasses = [vk::SubpassDescription::builder()
+    let resolve_attachment_refs = [vk::AttachmentReference {
+        attachment: 4,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    }];
+    let mut composite_subpass = vk::SubpassDescription::builder()
+        .input_attachments(&oit_input_attachment_refs)
         .color_attachments(&color_attachment_refs)
-        .depth_stencil_attachment(&depth_attachment_refs)
-        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .build()];
-
-    let subspass_dependencies = [vk::SubpassDependency::builder()
-        .src_subpass(vk::SUBPASS_EXTERNAL)
-        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_subpass(0)
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(
-            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-        )
-        .build()];
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+    if multisampled {
+        composite_subpass = composite_subpass.resolve_attachments(&resolve_attachment_refs);
+    }
+
+    let scene_input_attachment_refs = [vk::AttachmentReference {
+        attachment: scene_attachment,
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    }];
+    let encode_attachment_refs = [vk::AttachmentReference {
+        attachment: encode_attachment,
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+    }];
+
+    let mut subpasses = vec![
+        vk::SubpassDescription::builder()
+            .color_attachments(&color_attachment_refs)
+            .depth_stencil_attachment(&depth_attachment_refs)
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .build(),
+        vk::SubpassDescription::builder()
+            .color_attachments(&oit_attachment_refs)
+            .depth_stencil_attachment(&read_only_depth_attachment_refs)
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .build(),
+        composite_subpass.build(),
+    ];
+    if hdr {
+        subpasses.push(
+            vk::SubpassDescription::builder()
+                .input_attachments(&scene_input_attachment_refs)
+                .color_attachments(&encode_attachment_refs)
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .build(),
+        );
+    }
+
+    let mut subspass_dependencies = vec![
+        vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_subpass(0)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            )
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_subpass(1)
+            .dst_stage_mask(vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+            .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ)
+            .dependency_flags(vk::DependencyFlags::BY_REGION)
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(1)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_subpass(2)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
+            .dependency_flags(vk::DependencyFlags::BY_REGION)
+            .build(),
+        vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_subpass(2)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            )
+            .dependency_flags(vk::DependencyFlags::BY_REGION)
+            .build(),
+    ];
+    if hdr {
+        subspass_dependencies.push(
+            vk::SubpassDependency::builder()
+                .src_subpass(2)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_subpass(3)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
+                .dependency_flags(vk::DependencyFlags::BY_REGION)
+                .build(),
+        );
+    }
 
     let renderpass_info = vk::RenderPassCreateInfo::builder()
         .attachments(&attachments)
@@ -234,6 +712,61 @@ pub fn create_command_buffers(
     Ok(unsafe { logical_device.allocate_command_buffers(&command_buffer_allocate_info)? })
 }
 
+pub fn init_descriptor_sets(
+    logical_device: &ash::Device,
+    pipeline: &Pipeline,
+    uniform_buffer: &Buffer,
+    light_buffer: &Buffer,
+    amount: usize,
+) -> Result<(vk::DescriptorPool, Vec<vk::DescriptorSet>)> {
+    let pool_sizes = [vk::DescriptorPoolSize {
+        ty: vk::DescriptorType::UNIFORM_BUFFER,
+        descriptor_count: 2 * amount as u32,
+    }];
+    let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+        .max_sets(amount as u32)
+        .pool_sizes(&pool_sizes);
+    let descriptor_pool =
+        unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+
+    let desc_layouts = vec![pipeline.descriptor_set_layouts[0]; amount];
+    let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(descriptor_pool)
+        .set_layouts(&desc_layouts);
+    let descriptor_sets =
+        unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?;
+
+    descriptor_sets.iter().for_each(|descset| {
+        let buffer_infos = [vk::DescriptorBufferInfo {
+            buffer: uniform_buffer.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let light_buffer_infos = [vk::DescriptorBufferInfo {
+            buffer: light_buffer.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let desc_sets_write = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(*descset)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&buffer_infos)
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(*descset)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(&light_buffer_infos)
+                .build(),
+        ];
+        unsafe { logical_device.update_descriptor_sets(&desc_sets_write, &[]) };
+    });
+
+    Ok((descriptor_pool, descriptor_sets))
+}
+
 pub fn find_memorytype_index(
     memory_req: &vk::MemoryRequirements,
     memory_prop: &vk::PhysicalDeviceMemoryProperties,
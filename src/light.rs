@@ -0,0 +1,39 @@
+use nalgebra::Vector3;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirectionalLight {
+    /// World-space direction towards the light (the world is y-down).
+    pub direction: Vector3<f32>,
+    pub colour: [f32; 3],
+    pub intensity: f32,
+    /// Light every surface receives regardless of its orientation.
+    pub ambient: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vector3::new(-1.0, -1.0, 0.0).normalize(),
+            colour: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            ambient: 0.5,
+        }
+    }
+}
+
+impl DirectionalLight {
+    /// std140 layout of the fragment shader's light block.
+    pub fn to_uniform(&self) -> [[f32; 4]; 2] {
+        let direction = self.direction.normalize();
+        [
+            [direction.x, direction.y, direction.z, self.ambient],
+            [
+                self.colour[0] * self.intensity,
+                self.colour[1] * self.intensity,
+                self.colour[2] * self.intensity,
+                0.0,
+            ],
+        ]
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+use nalgebra::Vector4;
+
+use crate::camera::Camera;
+use crate::light::DirectionalLight;
+
+/// Samples [`LightShafts::sample_count`] is clamped to; must match `shaders/light_shafts.frag`.
+pub const MAX_LIGHT_SHAFT_SAMPLES: u32 = 64;
+
+/// Shafts of the directional light through the air around what covers it. Every pixel
+/// marches through the depth buffer towards the light on the screen, gathering light
+/// where nothing was drawn, and adds it to the frame scattered by a Henyey-Greenstein
+/// phase function. Drawn as a [`crate::post::PostProcess`] effect after the depth of
+/// field and before the lens flare and the vignette and grain; needs single-sampled depth.
+pub struct LightShafts {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// Fraction of the way to the light each pixel marches, from 0 to 1; thicker air
+    /// carries the shafts further from what casts them.
+    pub density: f32,
+    /// Brightness of the scattered light relative to the light's colour and intensity;
+    /// 0 turns the shafts off.
+    pub scattering: f32,
+    /// Henyey-Greenstein asymmetry in (-1, 1); positive values keep the shafts bright
+    /// looking towards the light.
+    pub anisotropy: f32,
+    /// Weight of each sample relative to the one before it, so light gathered far along
+    /// the march counts for less.
+    pub decay: f32,
+    /// Depth buffer samples per pixel, up to [`MAX_LIGHT_SHAFT_SAMPLES`].
+    pub sample_count: u32,
+    /// Where the light is in the framebuffer, in pixels, then its direction in view space;
+    /// `None` while it is behind the camera.
+    light: Option<([f32; 2], [f32; 3])>,
+    /// Offset and size of the viewport in pixels, then what the projection divides view
+    /// space x and y by at unit depth.
+    viewport_and_projection: [f32; 6],
+    colour: [f32; 3],
+}
+
+impl LightShafts {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/oit_composite.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/light_shafts.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // Added onto the frame, which the swapchain blends in linear light.
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::ONE)
+            .dst_color_blend_factor(vk::BlendFactor::ONE)
+            .color_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        /* Pipeline */
+        let descriptor_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: 80,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&descriptor_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colourblend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(LightShafts {
+            pipeline,
+            layout,
+            density: 0.8,
+            scattering: 0.3,
+            anisotropy: 0.6,
+            decay: 0.97,
+            sample_count: 48,
+            light: None,
+            viewport_and_projection: [0.0, 0.0, 1.0, 1.0, 1.0, 1.0],
+            colour: [0.0; 3],
+        })
+    }
+
+    /// Places `light` on the screen as `camera` sees it framed in `viewport`; called for
+    /// every frame it renders.
+    pub fn set_light(&mut self, camera: &Camera, light: &DirectionalLight, viewport: vk::Rect2D) {
+        let direction = light.direction.normalize();
+        let towards_light = Vector4::new(direction.x, direction.y, direction.z, 0.0);
+        // A point at infinity, moved by the view's rotation alone.
+        let view = camera.view_matrix * towards_light;
+        let clip = camera.projection_matrix * view;
+        let width = viewport.extent.width as f32;
+        let height = viewport.extent.height as f32;
+        let left = viewport.offset.x as f32;
+        let top = viewport.offset.y as f32;
+        self.light = (clip.w > f32::EPSILON).then(|| {
+            (
+                [
+                    left + (clip.x / clip.w * 0.5 + 0.5) * width,
+                    top + (clip.y / clip.w * 0.5 + 0.5) * height,
+                ],
+                view.xyz().normalize().into(),
+            )
+        });
+        self.viewport_and_projection = [
+            left,
+            top,
+            width,
+            height,
+            1.0 / camera.projection_matrix[(0, 0)],
+            1.0 / camera.projection_matrix[(1, 1)],
+        ];
+        self.colour = light.colour.map(|channel| channel * light.intensity);
+    }
+
+    /// Draws into the [`crate::post::PostProcess`] render pass. The light shines through
+    /// where the depth buffer still holds `clear_depth`.
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        clear_depth: f32,
+    ) {
+        let Some((position, direction)) = self.light else {
+            return;
+        };
+        if self.scattering <= 0.0 || self.density <= 0.0 {
+            return;
+        }
+        let scattering = self.scattering;
+        let [left, top, width, height, scale_x, scale_y] = self.viewport_and_projection;
+        let parameters = [
+            [
+                position[0],
+                position[1],
+                clear_depth,
+                self.sample_count.clamp(1, MAX_LIGHT_SHAFT_SAMPLES) as f32,
+            ],
+            [
+                self.colour[0] * scattering,
+                self.colour[1] * scattering,
+                self.colour[2] * scattering,
+                self.anisotropy.clamp(-0.99, 0.99),
+            ],
+            [
+                self.density.min(1.0),
+                self.decay.clamp(0.0, 1.0),
+                scale_x,
+                scale_y,
+            ],
+            [direction[0], direction[1], direction[2], 0.0],
+            [left, top, width, height],
+        ];
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                parameters.as_ptr() as *const u8,
+                std::mem::size_of_val(&parameters),
+            )
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                bytes,
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::buffer::Buffer;
+use crate::model::{Mesh, VertexData};
+use crate::ray_query::{begin_one_time, end_one_time};
+
+/// The twelve cell edges as corner pairs; corner `i` sits at
+/// `(i & 1, i >> 1 & 1, i >> 2 & 1)` in the cell.
+const EDGES: [[u32; 2]; 12] = [
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+/// The cell's faces, each with its corners counter-clockwise seen from outside the cell.
+const FACES: [[u32; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+/// Entries per case in the table: a triangle count and up to five triangles of edges.
+const CASE_STRIDE: usize = 16;
+
+/// Cells along each axis of a workgroup in `shaders/marching_cubes.comp`.
+const WORKGROUP_SIZE: u32 = 4;
+
+/// [`EDGES`] followed by the triangles of each of the 256 inside/outside corner cases.
+///
+/// The surface crosses every edge whose corners disagree. On each face, the crossings
+/// are joined so the inside corners lie to the right seen from outside, pairing each
+/// crossing into the inside with the next one out, so diagonal inside corners stay apart
+/// and neighbouring cells always agree. Chained together, the segments form loops around
+/// the surface's outward side, which are fanned into triangles.
+fn case_table() -> Vec<u32> {
+    let edge_index = |a: u32, b: u32| {
+        EDGES
+            .iter()
+            .position(|&edge| edge == [a.min(b), a.max(b)])
+            .unwrap()
+    };
+
+    let mut table: Vec<u32> = EDGES.iter().flatten().copied().collect();
+    for case in 0..256u32 {
+        let inside = |corner: u32| case & (1 << corner) != 0;
+        let mut next = [None; 12];
+        for face in FACES {
+            let crossings: Vec<(usize, bool)> = (0..4)
+                .map(|k| (face[k], face[(k + 1) % 4]))
+                .filter(|&(a, b)| inside(a) != inside(b))
+                .map(|(a, b)| (edge_index(a, b), inside(b)))
+                .collect();
+            for (k, &(edge, entering)) in crossings.iter().enumerate() {
+                if entering {
+                    next[edge] = Some(crossings[(k + 1) % crossings.len()].0);
+                }
+            }
+        }
+
+        let mut triangles = vec![];
+        let mut visited = [false; 12];
+        for start in 0..12 {
+            if visited[start] || next[start].is_none() {
+                continue;
+            }
+            let mut polygon = vec![];
+            let mut edge = start;
+            while !visited[edge] {
+                visited[edge] = true;
+                polygon.push(edge as u32);
+                edge = next[edge].expect("crossings form closed loops");
+            }
+            for k in 1..polygon.len() - 1 {
+                triangles.extend_from_slice(&[polygon[0], polygon[k], polygon[k + 1]]);
+            }
+        }
+
+        let mut row = vec![0; CASE_STRIDE];
+        row[0] = triangles.len() as u32 / 3;
+        row[1..=triangles.len()].copy_from_slice(&triangles);
+        table.extend(row);
+    }
+    table
+}
+
+/// Where the samples of a density field sit: `samples` per axis, `spacing` apart,
+/// starting at `origin`.
+#[derive(Clone, Copy, Debug)]
+pub struct DensityGrid {
+    pub samples: [u32; 3],
+    pub origin: [f32; 3],
+    pub spacing: f32,
+    /// The density of the surface; lower densities are inside. 0 for signed distance
+    /// fields.
+    pub iso_level: f32,
+}
+
+impl DensityGrid {
+    pub fn sample_count(&self) -> usize {
+        self.samples.iter().map(|&n| n as usize).product()
+    }
+
+    /// The world-space position of the sample at `index`.
+    pub fn position(&self, index: [u32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|axis| self.origin[axis] + index[axis] as f32 * self.spacing)
+    }
+
+    /// Evaluates `density` at every sample, x fastest, then y, then z.
+    pub fn sample(&self, density: impl Fn([f32; 3]) -> f32) -> Vec<f32> {
+        let [x_samples, y_samples, z_samples] = self.samples;
+        (0..z_samples)
+            .flat_map(|z| (0..y_samples).flat_map(move |y| (0..x_samples).map(move |x| [x, y, z])))
+            .map(|index| density(self.position(index)))
+            .collect()
+    }
+
+    /// A storage buffer holding `values`, laid out as [`DensityGrid::sample`] returns them.
+    pub fn upload(
+        &self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        values: &[f32],
+    ) -> Result<Buffer> {
+        if values.len() != self.sample_count() {
+            bail!(
+                "A {:?} density grid needs {} values, not {}.",
+                self.samples,
+                self.sample_count(),
+                values.len()
+            );
+        }
+        let mut buffer = Buffer::init(
+            std::mem::size_of_val(values),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        buffer.fill(logical_device, values, memory_properties)?;
+        Ok(buffer)
+    }
+}
+
+/// `Grid` in `shaders/marching_cubes.comp`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GridConstants {
+    origin_and_spacing: [f32; 4],
+    samples: [u32; 3],
+    iso_level: f32,
+    vertex_capacity: u32,
+}
+
+/// Turns density fields living in storage buffers into meshes with a compute shader. A
+/// first dispatch counts the vertices and a second writes them straight into the
+/// mesh's vertex buffer, so nothing but the count passes through the host on the way.
+pub struct MarchingCubes {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// The cell edges and the triangles of each corner case.
+    pub tables: Buffer,
+    /// Vertices emitted by the last dispatch.
+    pub counter: Buffer,
+}
+
+impl MarchingCubes {
+    pub fn init(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self> {
+        /* Descriptors */
+        let descriptor_set_layout_bindings = [0, 1, 2, 3].map(|binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        });
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 4,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let table = case_table();
+        let mut tables = Buffer::init(
+            std::mem::size_of_val(&table[..]),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        tables.fill(logical_device, &table, memory_properties)?;
+        let counter = Buffer::init(
+            4,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+
+        /* Pipeline */
+        let compute_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/marching_cubes.comp", kind: comp));
+        let compute_module = unsafe { logical_device.create_shader_module(&compute_info, None) }?;
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(compute_module)
+            .name(&main_function_name);
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(std::mem::size_of::<GridConstants>() as u32)
+            .build()];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage.build())
+            .layout(layout);
+        let pipeline = unsafe {
+            logical_device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+                .map_err(|(_, e)| e)?
+        }[0];
+        unsafe { logical_device.destroy_shader_module(compute_module, None) };
+
+        Ok(MarchingCubes {
+            pipeline,
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            tables,
+            counter,
+        })
+    }
+
+    /// A triangle list of the surface where `density`, sampled on `grid`, crosses the
+    /// iso level. The triangles wind counter-clockwise around normals taken from the
+    /// density gradient, pointing towards higher densities. The mesh comes back uploaded,
+    /// its vertices written by the GPU and read back into `vertex_data`.
+    pub fn generate(
+        &self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        density: &Buffer,
+        grid: &DensityGrid,
+    ) -> Result<Mesh<VertexData>> {
+        if grid.samples.iter().any(|&n| n < 2) {
+            bail!("A density grid needs at least 2 samples along each axis.");
+        }
+        if density.size_in_bytes < grid.sample_count() * 4 {
+            bail!(
+                "A {:?} density grid needs {} bytes of density, not {}.",
+                grid.samples,
+                grid.sample_count() * 4,
+                density.size_in_bytes
+            );
+        }
+        let vertex_bytes = std::mem::size_of::<VertexData>();
+
+        // Nothing is written while counting, but the binding still needs a buffer.
+        let placeholder = Buffer::init(
+            vertex_bytes,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        let counted = self.dispatch(
+            logical_device,
+            (command_pool, queue),
+            density,
+            &placeholder,
+            grid,
+        );
+        placeholder.cleanup(logical_device);
+        let vertex_count = counted?;
+
+        let vertices = Buffer::init(
+            vertex_count.max(1) as usize * vertex_bytes,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        let vertex_data = if vertex_count == 0 {
+            vec![]
+        } else {
+            let written = self
+                .dispatch(
+                    logical_device,
+                    (command_pool, queue),
+                    density,
+                    &vertices,
+                    grid,
+                )
+                .and_then(|_| {
+                    let mut vertex_data = vertices.download::<VertexData>(logical_device)?;
+                    vertex_data.truncate(vertex_count as usize);
+                    Ok(vertex_data)
+                });
+            match written {
+                std::result::Result::Ok(vertex_data) => vertex_data,
+                Err(e) => {
+                    vertices.cleanup(logical_device);
+                    return Err(e);
+                }
+            }
+        };
+
+        let mut mesh = Mesh::new(vertex_data, (0..vertex_count).collect());
+        mesh.vertex_buffer = Some(vertices);
+        mesh.update_index_buffer(logical_device, memory_properties)?;
+        Ok(mesh)
+    }
+
+    /// Runs the shader over every cell, writing as many vertices as fit in `vertices`,
+    /// and returns how many the surface has.
+    fn dispatch(
+        &self,
+        logical_device: &ash::Device,
+        (command_pool, queue): (vk::CommandPool, vk::Queue),
+        density: &Buffer,
+        vertices: &Buffer,
+        grid: &DensityGrid,
+    ) -> Result<u32> {
+        unsafe {
+            let data = logical_device.map_memory(
+                self.counter.memory,
+                0,
+                4,
+                vk::MemoryMapFlags::empty(),
+            )?;
+            *(data as *mut u32) = 0;
+            logical_device.unmap_memory(self.counter.memory);
+        }
+
+        let buffer_infos = [density, &self.tables, vertices, &self.counter].map(|buffer| {
+            [vk::DescriptorBufferInfo {
+                buffer: buffer.buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }]
+        });
+        let writes: Vec<vk::WriteDescriptorSet> = (0..)
+            .zip(&buffer_infos)
+            .map(|(binding, buffer_info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_set)
+                    .dst_binding(binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(buffer_info)
+                    .build()
+            })
+            .collect();
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+
+        let constants = GridConstants {
+            origin_and_spacing: [grid.origin[0], grid.origin[1], grid.origin[2], grid.spacing],
+            samples: grid.samples,
+            iso_level: grid.iso_level,
+            vertex_capacity: (vertices.size_in_bytes / std::mem::size_of::<VertexData>()) as u32,
+        };
+        let command_buffer = begin_one_time(logical_device, command_pool)?;
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    &constants as *const GridConstants as *const u8,
+                    std::mem::size_of::<GridConstants>(),
+                ),
+            );
+            let [x, y, z] = grid.samples.map(|n| (n - 1).div_ceil(WORKGROUP_SIZE));
+            logical_device.cmd_dispatch(command_buffer, x, y, z);
+            let barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ);
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[barrier.build()],
+                &[],
+                &[],
+            );
+        }
+        end_one_time(logical_device, command_pool, queue, command_buffer)?;
+
+        Ok(self.counter.download::<u32>(logical_device)?[0])
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        self.tables.cleanup(logical_device);
+        self.counter.cleanup(logical_device);
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3};
+
+/// Converts from another math library's vector, such as a `glam::Vec3` with glam's `mint`
+/// feature, or any other type converting into `mint`.
+pub fn vector3(vector: impl Into<mint::Vector3<f32>>) -> Vector3<f32> {
+    Vector3::from(vector.into())
+}
+
+/// Converts from another math library's column-major matrix, such as a `glam::Mat4`.
+pub fn matrix4(matrix: impl Into<mint::ColumnMatrix4<f32>>) -> Matrix4<f32> {
+    Matrix4::from(matrix.into())
+}
+
+/// Converts from another math library's rotation, normalising it.
+pub fn rotation(quaternion: impl Into<mint::Quaternion<f32>>) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_quaternion(Quaternion::from(quaternion.into()))
+}
+
+/// Converts into another math library's vector: `to_vector3::<glam::Vec3>(position)`.
+pub fn to_vector3<V: From<mint::Vector3<f32>>>(vector: Vector3<f32>) -> V {
+    V::from(vector.into())
+}
+
+/// Converts into another math library's column-major matrix.
+pub fn to_matrix4<M: From<mint::ColumnMatrix4<f32>>>(matrix: Matrix4<f32>) -> M {
+    M::from(matrix.into())
+}
+
+/// Converts into another math library's rotation.
+pub fn to_rotation<Q: From<mint::Quaternion<f32>>>(rotation: UnitQuaternion<f32>) -> Q {
+    Q::from(rotation.into())
+}
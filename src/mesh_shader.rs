@@ -0,0 +1,423 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::buffer::Buffer;
+use crate::model::{Instance, InstanceData, Model, VertexData};
+use crate::multisample::Multisampling;
+use crate::pipeline::Topology;
+
+/// Storage buffers of the shaders' set 0, after the camera and light uniforms.
+const BINDING_VERTICES: u32 = 2;
+const BINDING_MESHLETS: u32 = 3;
+const BINDING_MESHLET_VERTICES: u32 = 4;
+const BINDING_MESHLET_TRIANGLES: u32 = 5;
+const BINDING_INSTANCES: u32 = 6;
+
+/// Meshlets handed to one task shader workgroup, its `local_size_x`.
+const MESHLETS_PER_TASK: u32 = 32;
+
+/// Draws models split into meshlets with task and mesh shaders (`VK_EXT_mesh_shader`),
+/// culling meshlets against the frustum on the GPU before any vertex work. Opaque
+/// instances only, shaded like the main pipeline.
+pub struct MeshShaderPass {
+    pub loader: ash::extensions::ext::MeshShader,
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub models: Vec<MeshletModel>,
+}
+
+/// A model's meshlets and visible instances, uploaded for [`MeshShaderPass`].
+pub struct MeshletModel {
+    pub vertices: Buffer,
+    pub meshlets: Buffer,
+    pub meshlet_vertices: Buffer,
+    pub meshlet_triangles: Buffer,
+    pub instances: Buffer,
+    pub meshlet_count: u32,
+    pub instance_count: u32,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl MeshShaderPass {
+    pub fn init(
+        instance: &ash::Instance,
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        multisampling: &Multisampling,
+    ) -> Result<Self> {
+        /* Shaders */
+        let task_info =
+            vk::ShaderModuleCreateInfo::builder().code(vk_shader_macros::include_glsl!(
+                "shaders/meshlet.task",
+                kind: task,
+                target: vulkan1_2
+            ));
+        let task_module = unsafe { logical_device.create_shader_module(&task_info, None) }?;
+
+        let mesh_info =
+            vk::ShaderModuleCreateInfo::builder().code(vk_shader_macros::include_glsl!(
+                "shaders/meshlet.mesh",
+                kind: mesh,
+                target: vulkan1_2
+            ));
+        let mesh_module = unsafe { logical_device.create_shader_module(&mesh_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::TASK_EXT)
+                .module(task_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::MESH_EXT)
+                .module(mesh_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        // No vertex input or input assembly: the mesh shader emits the triangles itself.
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = multisampling.state_info();
+        let colour_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colour_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colour_blend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Descriptors */
+        let storage_binding = |binding, stage_flags| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(stage_flags)
+                .build()
+        };
+        let task_and_mesh = vk::ShaderStageFlags::TASK_EXT | vk::ShaderStageFlags::MESH_EXT;
+        let descriptor_set_layout_bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(task_and_mesh)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            storage_binding(BINDING_VERTICES, vk::ShaderStageFlags::MESH_EXT),
+            storage_binding(BINDING_MESHLETS, task_and_mesh),
+            storage_binding(BINDING_MESHLET_VERTICES, vk::ShaderStageFlags::MESH_EXT),
+            storage_binding(BINDING_MESHLET_TRIANGLES, vk::ShaderStageFlags::MESH_EXT),
+            storage_binding(BINDING_INSTANCES, task_and_mesh),
+        ];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        /* Pipeline */
+        let set_layouts = [descriptor_set_layout];
+        let pipeline_layout_info =
+            vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colour_blend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(mesh_module, None);
+            logical_device.destroy_shader_module(task_module, None);
+        }
+
+        Ok(MeshShaderPass {
+            loader: ash::extensions::ext::MeshShader::new(instance, logical_device),
+            pipeline,
+            layout,
+            descriptor_set_layout,
+            models: vec![],
+        })
+    }
+
+    /// Builds and uploads the model's meshlets along with its visible instances, which
+    /// [`MeshShaderPass::update_instances`] refreshes later. Returns the index into `models`.
+    pub fn add_model<I: Instance>(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        model: &Model<VertexData, I>,
+        uniform_buffer: &Buffer,
+        light_buffer: &Buffer,
+    ) -> Result<usize> {
+        if model.mesh.topology != Topology::TriangleList {
+            bail!("Only triangle list models can be split into meshlets.");
+        }
+        let meshlets = model.mesh.meshlets();
+        let storage_buffer = |bytes: usize| {
+            Buffer::init(
+                bytes.max(16),
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                memory_properties,
+                logical_device,
+            )
+        };
+        let mut vertices = storage_buffer(std::mem::size_of_val(&model.mesh.vertex_data[..]))?;
+        vertices.fill(logical_device, &model.mesh.vertex_data, memory_properties)?;
+        let mut meshlet_buffer = storage_buffer(std::mem::size_of_val(&meshlets.meshlets[..]))?;
+        meshlet_buffer.fill(logical_device, &meshlets.meshlets, memory_properties)?;
+        let mut meshlet_vertices =
+            storage_buffer(std::mem::size_of_val(&meshlets.vertex_indices[..]))?;
+        meshlet_vertices.fill(logical_device, &meshlets.vertex_indices, memory_properties)?;
+        let mut meshlet_triangles = storage_buffer(std::mem::size_of_val(&meshlets.triangles[..]))?;
+        meshlet_triangles.fill(logical_device, &meshlets.triangles, memory_properties)?;
+        // The shaders read only the engine's fields, in their own layout.
+        let visible: Vec<InstanceData> = model
+            .instances
+            .visible()
+            .iter()
+            .map(|instance| *instance.base())
+            .collect();
+        let mut instances = storage_buffer(std::mem::size_of_val(&visible[..]))?;
+        instances.fill(logical_device, &visible, memory_properties)?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 2,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 5,
+            },
+        ];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [self.descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let meshlet_model = MeshletModel {
+            vertices,
+            meshlets: meshlet_buffer,
+            meshlet_vertices,
+            meshlet_triangles,
+            instances,
+            meshlet_count: meshlets.meshlets.len() as u32,
+            instance_count: visible.len() as u32,
+            descriptor_pool,
+            descriptor_set,
+        };
+        let buffers = [
+            (0, vk::DescriptorType::UNIFORM_BUFFER, uniform_buffer),
+            (1, vk::DescriptorType::UNIFORM_BUFFER, light_buffer),
+            (
+                BINDING_VERTICES,
+                vk::DescriptorType::STORAGE_BUFFER,
+                &meshlet_model.vertices,
+            ),
+            (
+                BINDING_MESHLETS,
+                vk::DescriptorType::STORAGE_BUFFER,
+                &meshlet_model.meshlets,
+            ),
+            (
+                BINDING_MESHLET_VERTICES,
+                vk::DescriptorType::STORAGE_BUFFER,
+                &meshlet_model.meshlet_vertices,
+            ),
+            (
+                BINDING_MESHLET_TRIANGLES,
+                vk::DescriptorType::STORAGE_BUFFER,
+                &meshlet_model.meshlet_triangles,
+            ),
+            (
+                BINDING_INSTANCES,
+                vk::DescriptorType::STORAGE_BUFFER,
+                &meshlet_model.instances,
+            ),
+        ];
+        for (binding, descriptor_type, buffer) in buffers {
+            meshlet_model.write_descriptor(logical_device, binding, descriptor_type, buffer);
+        }
+
+        self.models.push(meshlet_model);
+        Ok(self.models.len() - 1)
+    }
+
+    /// Uploads the visible instances of the model added at `index` again.
+    pub fn update_instances<I: Instance>(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        index: usize,
+        model: &Model<VertexData, I>,
+    ) -> Result<()> {
+        let meshlet_model = &mut self.models[index];
+        // The shaders read only the engine's fields, in their own layout.
+        let visible: Vec<InstanceData> = model
+            .instances
+            .visible()
+            .iter()
+            .map(|instance| *instance.base())
+            .collect();
+        let grows = std::mem::size_of_val(&visible[..]) > meshlet_model.instances.size_in_bytes;
+        if grows {
+            // The buffer is replaced, and frames in flight may still read the old one.
+            unsafe { logical_device.device_wait_idle() }?;
+        }
+        meshlet_model
+            .instances
+            .fill(logical_device, &visible, memory_properties)?;
+        if grows {
+            meshlet_model.write_descriptor(
+                logical_device,
+                BINDING_INSTANCES,
+                vk::DescriptorType::STORAGE_BUFFER,
+                &meshlet_model.instances,
+            );
+        }
+        meshlet_model.instance_count = visible.len() as u32;
+        Ok(())
+    }
+
+    /// Records the meshlet models into subpass 0.
+    pub fn draw(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            for model in &self.models {
+                if model.instance_count == 0 || model.meshlet_count == 0 {
+                    continue;
+                }
+                logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.layout,
+                    0,
+                    &[model.descriptor_set],
+                    &[],
+                );
+                self.loader.cmd_draw_mesh_tasks(
+                    command_buffer,
+                    model.meshlet_count.div_ceil(MESHLETS_PER_TASK),
+                    model.instance_count,
+                    1,
+                );
+            }
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            for model in &self.models {
+                model.cleanup(logical_device);
+            }
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+impl MeshletModel {
+    fn write_descriptor(
+        &self,
+        logical_device: &ash::Device,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: &Buffer,
+    ) {
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer: buffer.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(binding)
+            .descriptor_type(descriptor_type)
+            .buffer_info(&buffer_info)
+            .build()];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    ///# Safety
+    ///
+    /// The model must not be in use by the device.
+    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        for buffer in [
+            &self.vertices,
+            &self.meshlets,
+            &self.meshlet_vertices,
+            &self.meshlet_triangles,
+            &self.instances,
+        ] {
+            buffer.cleanup(logical_device);
+        }
+        logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+    }
+}
@@ -0,0 +1,69 @@
+use nalgebra::{Matrix4, Point3, Vector3};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a [f32; 3]>) -> Option<Aabb> {
+        let mut points = points.into_iter();
+        let first = Vector3::from(*points.next()?);
+        Some(points.fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |aabb, p| {
+                let p = Vector3::from(*p);
+                Aabb {
+                    min: aabb.min.inf(&p),
+                    max: aabb.max.sup(&p),
+                }
+            },
+        ))
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn contains(&self, point: &Vector3<f32>) -> bool {
+        (0..3).all(|i| self.min[i] <= point[i] && point[i] <= self.max[i])
+    }
+
+    /// Bounds of this box after transforming all eight corners.
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> Aabb {
+        let corners: Vec<[f32; 3]> = (0..8)
+            .map(|i| {
+                let corner = Point3::new(
+                    if i & 1 == 0 { self.min.x } else { self.max.x },
+                    if i & 2 == 0 { self.min.y } else { self.max.y },
+                    if i & 4 == 0 { self.min.z } else { self.max.z },
+                );
+                matrix.transform_point(&corner).coords.into()
+            })
+            .collect();
+        Aabb::from_points(&corners).unwrap()
+    }
+
+    /// Distance along `direction` (not necessarily normalized) at which the ray enters the box.
+    pub fn ray_intersection(&self, origin: &Vector3<f32>, direction: &Vector3<f32>) -> Option<f32> {
+        let mut t_min = 0.0_f32;
+        let mut t_max = f32::INFINITY;
+        for i in 0..3 {
+            if direction[i].abs() < f32::EPSILON {
+                if origin[i] < self.min[i] || origin[i] > self.max[i] {
+                    return None;
+                }
+            } else {
+                let t1 = (self.min[i] - origin[i]) / direction[i];
+                let t2 = (self.max[i] - origin[i]) / direction[i];
+                t_min = t_min.max(t1.min(t2));
+                t_max = t_max.min(t1.max(t2));
+            }
+        }
+        (t_min <= t_max).then_some(t_min)
+    }
+}
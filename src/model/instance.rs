@@ -1,19 +1,122 @@
 use nalgebra::Matrix4;
 
+use crate::vertex_layout::VertexLayout;
+
 #[repr(C)]
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InstanceData {
     pub model_matrix: [[f32; 4]; 4],
     pub inverse_model_matrix: [[f32; 4]; 4],
     pub colour: [f32; 3],
+    /// 1 is opaque; anything lower is drawn in the sorted transparent pass.
+    pub opacity: f32,
+    /// Above 0, the instance is a cutout, such as a leaf card or a fence, drawn by the
+    /// [`crate::cutout::CutoutPass`] alone: where its opacity times the alpha of its mask
+    /// falls below the cutoff, the surface is cut away.
+    pub alpha_cutoff: f32,
+    /// Layer of the [`crate::cutout::CutoutPass`] masks a cutout is cut by.
+    pub cutout_layer: u32,
+    /// Rounds the size up to 16 bytes, as the meshlet shaders' std430 instance array has it.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _padding: [f32; 2],
+}
+
+crate::assert_std_layout!(
+    Std430,
+    InstanceData {
+        model_matrix: Mat4,
+        inverse_model_matrix: Mat4,
+        colour: Vec3,
+        opacity: Scalar,
+        alpha_cutoff: Scalar,
+        cutout_layer: Scalar,
+    }
+);
+
+/// Per-instance data the engine's passes can draw. The fields of [`InstanceData`] are what
+/// the built-in shaders read; a custom type embeds it first and appends its own fields,
+/// which the pipeline's vertex input picks up in the following locations:
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy)]
+/// struct Sprite { base: InstanceData, uv_offset: [f32; 2], phase: f32 }
+/// krakatoa::vertex_layout!(Sprite { ..base, uv_offset, phase });
+/// ```
+///
+/// `From<InstanceData>` fills in the custom fields for instances the engine makes itself.
+pub trait Instance: VertexLayout + From<InstanceData> + Send + 'static {
+    fn base(&self) -> &InstanceData;
+
+    /// Instances with the same material, such as a texture index, are kept together by
+    /// [`crate::model::Model::sort_for_camera`].
+    fn material(&self) -> u32 {
+        0
+    }
+}
+
+impl Instance for InstanceData {
+    fn base(&self) -> &InstanceData {
+        self
+    }
 }
 
+crate::vertex_layout!(InstanceData {
+    model_matrix,
+    inverse_model_matrix,
+    colour,
+    opacity,
+    alpha_cutoff,
+    cutout_layer,
+});
+
 impl InstanceData {
     pub fn from_matrix_and_colour(model_matrix: Matrix4<f32>, colour: [f32; 3]) -> InstanceData {
         InstanceData {
             model_matrix: model_matrix.into(),
             inverse_model_matrix: model_matrix.try_inverse().unwrap().into(),
             colour,
+            opacity: 1.0,
+            alpha_cutoff: 0.0,
+            cutout_layer: 0,
+            _padding: [0.0; 2],
         }
     }
+
+    /// [`InstanceData::from_matrix_and_colour`] with another math library's matrix, such as
+    /// a `glam::Mat4`; see [`crate::math`].
+    #[cfg(feature = "mint")]
+    pub fn from_mint_matrix_and_colour(
+        model_matrix: impl Into<mint::ColumnMatrix4<f32>>,
+        colour: [f32; 3],
+    ) -> InstanceData {
+        Self::from_matrix_and_colour(crate::math::matrix4(model_matrix), colour)
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    /// Makes the instance a cutout at `threshold` by the mask in `layer`, see
+    /// [`InstanceData::alpha_cutoff`].
+    pub fn with_alpha_cutoff(mut self, threshold: f32, layer: u32) -> Self {
+        self.alpha_cutoff = threshold;
+        self.cutout_layer = layer;
+        self
+    }
+
+    pub fn is_cutout(&self) -> bool {
+        self.alpha_cutoff > 0.0
+    }
+
+    pub fn is_translucent(&self) -> bool {
+        self.opacity < 1.0 && !self.is_cutout()
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        let [x, y, z, _] = self.model_matrix[3];
+        [x, y, z]
+    }
 }
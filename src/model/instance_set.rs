@@ -0,0 +1,346 @@
+use std::ops::Range;
+
+use ash::vk;
+use nalgebra::Vector3;
+
+use crate::buffer::Buffer;
+
+use super::{instance::Instance, InvalidHandle};
+
+/// Instances addressed by stable handles, visible ones first, and the buffer the visible
+/// ones are uploaded to. Drawn with a [`super::Mesh`], either inside a [`super::Model`] or
+/// sharing a mesh from a [`super::MeshLibrary`].
+pub struct InstanceSet<I: Copy> {
+    pub handle_to_index: std::collections::HashMap<usize, usize>,
+    pub handles: Vec<usize>,
+    pub instances: Vec<I>,
+    pub first_invisible: usize,
+    pub next_handle: usize,
+    pub buffer: Option<Buffer>,
+}
+
+impl<I: Copy> Default for InstanceSet<I> {
+    fn default() -> Self {
+        InstanceSet {
+            handle_to_index: std::collections::HashMap::new(),
+            handles: Vec::new(),
+            instances: Vec::new(),
+            first_invisible: 0,
+            next_handle: 0,
+            buffer: None,
+        }
+    }
+}
+
+impl<I: Copy> InstanceSet<I> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The instances that are drawn, in the order they are uploaded.
+    pub fn visible(&self) -> &[I] {
+        &self.instances[..self.first_invisible]
+    }
+
+    pub fn get(&self, handle: usize) -> Option<&I> {
+        if let Some(&index) = self.handle_to_index.get(&handle) {
+            self.instances.get(index)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: usize) -> Option<&mut I> {
+        if let Some(&index) = self.handle_to_index.get(&handle) {
+            self.instances.get_mut(index)
+        } else {
+            None
+        }
+    }
+
+    /// Every instance with its handle, visible ones first.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &I)> {
+        self.handles.iter().copied().zip(self.instances.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (usize, &mut I)> {
+        self.handles.iter().copied().zip(self.instances.iter_mut())
+    }
+
+    pub fn iter_visible(&self) -> impl Iterator<Item = (usize, &I)> {
+        self.iter().take(self.first_invisible)
+    }
+
+    /// The visible instances with their handles, for animating what is drawn. Upload the
+    /// instance buffer again afterwards.
+    pub fn iter_visible_mut(&mut self) -> impl Iterator<Item = (usize, &mut I)> {
+        let first_invisible = self.first_invisible;
+        self.iter_mut().take(first_invisible)
+    }
+
+    /// Handles of every instance, visible ones first.
+    pub fn handles(&self) -> impl Iterator<Item = usize> + '_ {
+        self.handles.iter().copied()
+    }
+
+    pub fn swap_by_handle(&mut self, handle1: usize, handle2: usize) -> Result<(), InvalidHandle> {
+        if handle1 == handle2 {
+            return Ok(());
+        }
+        if let (Some(&index1), Some(&index2)) = (
+            self.handle_to_index.get(&handle1),
+            self.handle_to_index.get(&handle2),
+        ) {
+            self.handles.swap(index1, index2);
+            self.instances.swap(index1, index2);
+
+            self.handle_to_index.insert(index1, handle1);
+            self.handle_to_index.insert(index2, handle2);
+
+            Ok(())
+        } else {
+            Err(InvalidHandle)
+        }
+    }
+
+    pub fn swap_by_index(&mut self, index1: usize, index2: usize) {
+        if index1 == index2 {
+            return;
+        }
+        let handle1 = self.handles[index1];
+        let handle2 = self.handles[index2];
+
+        self.handles.swap(index1, index2);
+        self.instances.swap(index1, index2);
+
+        self.handle_to_index.insert(index1, handle2);
+        self.handle_to_index.insert(index2, handle1);
+    }
+
+    pub fn in_visible(&self, handle: usize) -> Result<bool, InvalidHandle> {
+        if let Some(index) = self.handle_to_index.get(&handle) {
+            Ok(index < &self.first_invisible)
+        } else {
+            Err(InvalidHandle)
+        }
+    }
+
+    pub fn make_visible(&mut self, handle: usize) -> Result<(), InvalidHandle> {
+        if let Some(&index) = self.handle_to_index.get(&handle) {
+            if index < self.first_invisible {
+                return Ok(());
+            }
+
+            self.swap_by_index(index, self.first_invisible);
+            self.first_invisible += 1;
+            Ok(())
+        } else {
+            Err(InvalidHandle)
+        }
+    }
+
+    pub fn make_invisible(&mut self, handle: usize) -> Result<(), InvalidHandle> {
+        if let Some(&index) = self.handle_to_index.get(&handle) {
+            if index >= self.first_invisible {
+                return Ok(());
+            }
+
+            self.swap_by_index(index, self.first_invisible - 1);
+            self.first_invisible -= 1;
+            Ok(())
+        } else {
+            Err(InvalidHandle)
+        }
+    }
+
+    pub fn insert(&mut self, element: I) -> usize {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+
+        let index = self.instances.len();
+        self.instances.push(element);
+        self.handles.push(handle);
+        self.handle_to_index.insert(handle, index);
+
+        handle
+    }
+
+    pub fn insert_visibly(&mut self, element: I) -> usize {
+        let new_handle = self.insert(element);
+        self.make_visible(new_handle).ok();
+
+        new_handle
+    }
+
+    /// Makes room for `additional` more instances without reallocating one at a time.
+    pub fn reserve(&mut self, additional: usize) {
+        self.instances.reserve(additional);
+        self.handles.reserve(additional);
+        self.handle_to_index.reserve(additional);
+    }
+
+    /// Inserts every element invisibly; returns the range of their handles.
+    pub fn insert_many(&mut self, elements: impl IntoIterator<Item = I>) -> Range<usize> {
+        let elements = elements.into_iter();
+        self.reserve(elements.size_hint().0);
+        let first_handle = self.next_handle;
+        for element in elements {
+            self.insert(element);
+        }
+        first_handle..self.next_handle
+    }
+
+    /// Inserts every element visibly; returns the range of their handles.
+    pub fn insert_visibly_many(&mut self, elements: impl IntoIterator<Item = I>) -> Range<usize> {
+        let previous_len = self.instances.len();
+        let handles = self.insert_many(elements);
+        let inserted = self.instances.len() - previous_len;
+        // Move the new instances in front of the invisible ones in one go.
+        self.instances[self.first_invisible..].rotate_right(inserted);
+        self.handles[self.first_invisible..].rotate_right(inserted);
+        for index in self.first_invisible..self.instances.len() {
+            self.handle_to_index.insert(self.handles[index], index);
+        }
+        self.first_invisible += inserted;
+        handles
+    }
+
+    pub fn remove(&mut self, handle: usize) -> Result<I, InvalidHandle> {
+        if let Some(&index) = self.handle_to_index.get(&handle) {
+            if index < self.first_invisible {
+                self.swap_by_index(index, self.first_invisible - 1);
+                self.first_invisible -= 1;
+            }
+            self.swap_by_index(self.first_invisible, self.instances.len() - 1);
+            self.handles.pop();
+            self.handle_to_index.remove(&handle);
+
+            Ok(self.instances.pop().unwrap())
+        } else {
+            Err(InvalidHandle)
+        }
+    }
+
+    /// Reorders the visible instances by `compare`; handles keep pointing at the same
+    /// instances. Upload the instance buffer again afterwards.
+    pub fn sort_visible_by(&mut self, mut compare: impl FnMut(&I, &I) -> std::cmp::Ordering) {
+        let mut order: Vec<usize> = (0..self.first_invisible).collect();
+        order.sort_by(|&a, &b| compare(&self.instances[a], &self.instances[b]));
+        let sorted: Vec<(I, usize)> = order
+            .iter()
+            .map(|&index| (self.instances[index], self.handles[index]))
+            .collect();
+        for (index, (instance, handle)) in sorted.into_iter().enumerate() {
+            self.instances[index] = instance;
+            self.handles[index] = handle;
+            self.handle_to_index.insert(handle, index);
+        }
+    }
+
+    pub fn update_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> anyhow::Result<()> {
+        if let Some(buffer) = &mut self.buffer {
+            buffer.fill(
+                logical_device,
+                &self.instances[0..self.first_invisible],
+                memory_properties,
+            )?;
+            Ok(())
+        } else {
+            // Vulkan has no empty buffers, and sets start out with nothing visible.
+            let bytes = self.first_invisible.max(1) * std::mem::size_of::<I>();
+            // Occlusion culling reads it as a storage buffer.
+            let mut buffer = Buffer::init(
+                bytes,
+                ash::vk::BufferUsageFlags::VERTEX_BUFFER
+                    | ash::vk::BufferUsageFlags::STORAGE_BUFFER,
+                memory_properties,
+                logical_device,
+            )?;
+            buffer.fill(
+                logical_device,
+                &self.instances[0..self.first_invisible],
+                memory_properties,
+            )?;
+            self.buffer = Some(buffer);
+            Ok(())
+        }
+    }
+
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        if let Some(buffer) = self.buffer.take() {
+            buffer.cleanup(logical_device);
+        }
+    }
+}
+
+impl<I: Instance> InstanceSet<I> {
+    /// Orders the visible instances for drawing from `camera_position`: opaque ones first,
+    /// grouped by [`Instance::material`] and front to back within a group to cut overdraw,
+    /// then translucent ones back to front.
+    pub fn sort_for_camera(&mut self, camera_position: &Vector3<f32>) {
+        let key = |instance: &I| {
+            let base = instance.base();
+            let distance = (Vector3::from(base.position()) - camera_position).norm_squared();
+            let translucent = base.is_translucent();
+            // Blending order beats grouping for translucent instances.
+            if translucent {
+                (true, 0, -distance)
+            } else {
+                (false, instance.material(), distance)
+            }
+        };
+        self.sort_visible_by(|a, b| {
+            let (a, b) = (key(a), key(b));
+            a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.total_cmp(&b.2))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every handle finds its own element, and the handles of the visible ones.
+    fn visible_handles(set: &InstanceSet<u32>) -> Vec<usize> {
+        for (index, &handle) in set.handles.iter().enumerate() {
+            assert_eq!(set.handle_to_index[&handle], index);
+        }
+        let mut handles: Vec<usize> = set.iter_visible().map(|(handle, _)| handle).collect();
+        handles.sort_unstable();
+        handles
+    }
+
+    #[test]
+    fn inserting_many_visibly_keeps_the_invisible_ones_hidden() {
+        let mut set = InstanceSet::new();
+        let shown = set.insert_visibly(10);
+        let hidden = set.insert_many([20, 21, 22]);
+        let inserted = set.insert_visibly_many([30, 31, 32, 33]);
+        assert_eq!(inserted, 4..8);
+
+        assert_eq!(set.first_invisible, 5);
+        assert_eq!(visible_handles(&set), [shown, 4, 5, 6, 7]);
+        for handle in hidden {
+            assert!(!set.in_visible(handle).unwrap());
+        }
+        for (handle, element) in inserted.zip([30, 31, 32, 33]) {
+            assert_eq!(set.get(handle), Some(&element));
+        }
+        let mut visible = set.visible().to_vec();
+        visible.sort_unstable();
+        assert_eq!(visible, [10, 30, 31, 32, 33]);
+    }
+
+    #[test]
+    fn inserting_many_visibly_into_an_empty_set() {
+        let mut set = InstanceSet::new();
+        assert_eq!(set.insert_visibly_many(std::iter::empty()), 0..0);
+        assert_eq!(set.insert_visibly_many([1, 2]), 0..2);
+        assert_eq!(set.visible(), [1, 2]);
+        assert_eq!(visible_handles(&set), [0, 1]);
+    }
+}
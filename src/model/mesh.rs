@@ -0,0 +1,515 @@
+use ash::vk;
+
+use crate::buffer::Buffer;
+use crate::pipeline::Topology;
+
+use super::{aabb::Aabb, instance_set::InstanceSet, vertex::normalize, VertexData};
+
+/// Geometry and its GPU buffers, uploaded once and drawn with any number of
+/// [`InstanceSet`]s.
+pub struct Mesh<V: Copy> {
+    pub vertex_data: Vec<V>,
+    pub index_data: Vec<u32>,
+    pub vertex_buffer: Option<Buffer>,
+    pub index_buffer: Option<Buffer>,
+    /// Anything but a triangle list is drawn only by the main opaque pipeline; the
+    /// transparent, outline, ray query and meshlet paths skip such meshes.
+    pub topology: Topology,
+    /// Lets a `u32::MAX` index start a new strip; ignored for lists.
+    pub primitive_restart: bool,
+}
+
+impl<V: Copy> Mesh<V> {
+    /// A triangle list of `vertex_data`, not uploaded yet.
+    pub fn new(vertex_data: Vec<V>, index_data: Vec<u32>) -> Self {
+        Mesh {
+            vertex_data,
+            index_data,
+            vertex_buffer: None,
+            index_buffer: None,
+            topology: Topology::TriangleList,
+            primitive_restart: false,
+        }
+    }
+
+    /// Declares how `index_data` forms primitives.
+    pub fn with_topology(mut self, topology: Topology, primitive_restart: bool) -> Self {
+        self.topology = topology;
+        self.primitive_restart = primitive_restart;
+        self
+    }
+
+    pub fn update_vertex_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> anyhow::Result<()> {
+        if let Some(buffer) = &mut self.vertex_buffer {
+            buffer.fill(logical_device, &self.vertex_data, memory_properties)?;
+            anyhow::Ok(())
+        } else {
+            let bytes = self.vertex_data.len() * std::mem::size_of::<V>();
+            let mut buffer = Buffer::init(
+                bytes,
+                ash::vk::BufferUsageFlags::VERTEX_BUFFER,
+                memory_properties,
+                logical_device,
+            )?;
+            buffer.fill(logical_device, &self.vertex_data, memory_properties)?;
+            self.vertex_buffer = Some(buffer);
+
+            Ok(())
+        }
+    }
+
+    pub fn update_index_buffer(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> anyhow::Result<()> {
+        if let Some(buffer) = &mut self.index_buffer {
+            buffer.fill(logical_device, &self.index_data, memory_properties)?;
+            Ok(())
+        } else {
+            let bytes = self.index_data.len() * std::mem::size_of::<u32>();
+            let mut buffer = Buffer::init(
+                bytes,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                memory_properties,
+                logical_device,
+            )?;
+            buffer.fill(logical_device, &self.index_data, memory_properties)?;
+            self.index_buffer = Some(buffer);
+
+            Ok(())
+        }
+    }
+
+    /// Uploads the vertices and indices.
+    pub fn upload(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> anyhow::Result<()> {
+        self.update_vertex_buffer(logical_device, memory_properties)?;
+        self.update_index_buffer(logical_device, memory_properties)
+    }
+
+    /// Releases the GPU buffers; the mesh can be uploaded again afterwards.
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        for buffer in [self.vertex_buffer.take(), self.index_buffer.take()]
+            .into_iter()
+            .flatten()
+        {
+            buffer.cleanup(logical_device);
+        }
+    }
+
+    /// Draws the visible instances of `instances`.
+    pub fn draw<I: Copy>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instances: &InstanceSet<I>,
+    ) {
+        if instances.first_invisible > 0 {
+            self.draw_range(
+                logical_device,
+                command_buffer,
+                instances,
+                0,
+                instances.first_invisible as u32,
+            );
+        }
+    }
+
+    /// Draws the visible instance at `index` in the instance list.
+    pub fn draw_instance_at<I: Copy>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instances: &InstanceSet<I>,
+        index: usize,
+    ) {
+        if index < instances.first_invisible {
+            self.draw_range(logical_device, command_buffer, instances, index as u32, 1);
+        }
+    }
+
+    /// Draws with the instances in `instance_buffer` and the instance count and other
+    /// parameters in the `VkDrawIndexedIndirectCommand` at the start of `indirect_buffer`.
+    pub fn draw_indexed_indirect(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instance_buffer: vk::Buffer,
+        indirect_buffer: vk::Buffer,
+    ) {
+        if self.bind_indirect(logical_device, command_buffer, instance_buffer) {
+            unsafe {
+                logical_device.cmd_draw_indexed_indirect(
+                    command_buffer,
+                    indirect_buffer,
+                    0,
+                    1,
+                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                );
+            }
+        }
+    }
+
+    /// Like [`Mesh::draw_indexed_indirect`], but draws as many of the commands packed at
+    /// the start of `indirect_buffer` as the `u32` at `count_offset` in `count_buffer`
+    /// says, up to `max_draw_count`, so a culling pass can also decide how many draws
+    /// there are. Needs `VK_KHR_draw_indirect_count`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed_indirect_count(
+        &self,
+        logical_device: &ash::Device,
+        draw_indirect_count: &ash::extensions::khr::DrawIndirectCount,
+        command_buffer: vk::CommandBuffer,
+        instance_buffer: vk::Buffer,
+        indirect_buffer: vk::Buffer,
+        count_buffer: vk::Buffer,
+        count_offset: vk::DeviceSize,
+        max_draw_count: u32,
+    ) {
+        if self.bind_indirect(logical_device, command_buffer, instance_buffer) {
+            unsafe {
+                draw_indirect_count.cmd_draw_indexed_indirect_count(
+                    command_buffer,
+                    indirect_buffer,
+                    0,
+                    count_buffer,
+                    count_offset,
+                    max_draw_count,
+                    std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                );
+            }
+        }
+    }
+
+    /// Binds the vertex and index buffers with `instance_buffer`, if the mesh is uploaded.
+    fn bind_indirect(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instance_buffer: vk::Buffer,
+    ) -> bool {
+        let (Some(vertex_buffer), Some(index_buffer)) = (&self.vertex_buffer, &self.index_buffer)
+        else {
+            return false;
+        };
+        unsafe {
+            logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[vertex_buffer.buffer, instance_buffer],
+                &[0, 0],
+            );
+            logical_device.cmd_bind_index_buffer(
+                command_buffer,
+                index_buffer.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+        true
+    }
+
+    fn draw_range<I: Copy>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        instances: &InstanceSet<I>,
+        first_instance: u32,
+        instance_count: u32,
+    ) {
+        if let (Some(vertex_buffer), Some(index_buffer), Some(instance_buffer)) =
+            (&self.vertex_buffer, &self.index_buffer, &instances.buffer)
+        {
+            unsafe {
+                logical_device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[vertex_buffer.buffer, instance_buffer.buffer],
+                    &[0, 0],
+                );
+                logical_device.cmd_bind_index_buffer(
+                    command_buffer,
+                    index_buffer.buffer,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                logical_device.cmd_draw_indexed(
+                    command_buffer,
+                    self.index_data.len() as u32,
+                    instance_count,
+                    0,
+                    0,
+                    first_instance,
+                );
+            }
+        }
+    }
+}
+
+impl Mesh<VertexData> {
+    /// Bounds of the mesh in model space.
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(self.vertex_data.iter().map(|v| &v.position))
+    }
+
+    pub fn cube() -> Self {
+        let lbf = VertexData {
+            position: [-1.0, 1.0, 0.0],
+            normal: [-1.0, 1.0, 0.0],
+        };
+        let lbb = VertexData {
+            position: [-1.0, 1.0, 1.0],
+            normal: [-1.0, 1.0, 1.0],
+        };
+        let ltf = VertexData {
+            position: [-1.0, -1.0, 0.0],
+            normal: [-1.0, -1.0, 0.0],
+        };
+        let ltb = VertexData {
+            position: [-1.0, -1.0, 1.0],
+            normal: [-1.0, -1.0, 1.0],
+        };
+        let rbf = VertexData {
+            position: [1.0, 1.0, 0.0],
+            normal: [1.0, 1.0, 0.0],
+        };
+        let rbb = VertexData {
+            position: [1.0, 1.0, 1.0],
+            normal: [1.0, 1.0, 1.0],
+        };
+        let rtf = VertexData {
+            position: [1.0, -1.0, 0.0],
+            normal: [1.0, -1.0, 0.0],
+        };
+        let rtb = VertexData {
+            position: [1.0, -1.0, 1.0],
+            normal: [1.0, -1.0, 1.0],
+        };
+
+        Mesh {
+            vertex_data: vec![lbf, lbb, ltf, ltb, rbf, rbb, rtf, rtb],
+            index_data: vec![
+                0, 1, 5, 0, 5, 4, //bottom
+                2, 7, 3, 2, 6, 7, //top
+                0, 6, 2, 0, 4, 6, //front
+                1, 3, 7, 1, 7, 5, //back
+                0, 2, 1, 1, 2, 3, //left
+                4, 5, 6, 5, 7, 6, //right
+            ],
+            vertex_buffer: None,
+            index_buffer: None,
+            topology: Topology::TriangleList,
+            primitive_restart: false,
+        }
+    }
+
+    pub fn sphere(refinements: u32) -> Self {
+        let mut mesh = Mesh::icosahedron();
+        for _ in 0..refinements {
+            mesh.refine();
+        }
+        for v in &mut mesh.vertex_data {
+            v.position = normalize(v.position);
+        }
+
+        mesh
+    }
+
+    pub fn icosahedron() -> Self {
+        let phi = (1.0 + 5.0_f32.sqrt()) / 2.0;
+        let darkgreen_front_top = VertexData {
+            position: [phi, -1.0, 0.0],
+            normal: normalize([phi, -1.0, 0.0]),
+        }; //0
+        let darkgreen_front_bottom = VertexData {
+            position: [phi, 1.0, 0.0],
+            normal: normalize([phi, 1.0, 0.0]),
+        }; //1
+        let darkgreen_back_top = VertexData {
+            position: [-phi, -1.0, 0.0],
+            normal: normalize([-phi, -1.0, 0.0]),
+        }; //2
+        let darkgreen_back_bottom = VertexData {
+            position: [-phi, 1.0, 0.0],
+            normal: normalize([-phi, 1.0, 0.0]),
+        }; //3
+        let lightgreen_front_right = VertexData {
+            position: [1.0, 0.0, -phi],
+            normal: normalize([1.0, 0.0, -phi]),
+        }; //4
+        let lightgreen_front_left = VertexData {
+            position: [-1.0, 0.0, -phi],
+            normal: normalize([-1.0, 0.0, -phi]),
+        }; //5
+        let lightgreen_back_right = VertexData {
+            position: [1.0, 0.0, phi],
+            normal: normalize([1.0, 0.0, phi]),
+        }; //6
+        let lightgreen_back_left = VertexData {
+            position: [-1.0, 0.0, phi],
+            normal: normalize([-1.0, 0.0, phi]),
+        }; //7
+        let purple_top_left = VertexData {
+            position: [0.0, -phi, -1.0],
+            normal: normalize([0.0, -phi, -1.0]),
+        }; //8
+        let purple_top_right = VertexData {
+            position: [0.0, -phi, 1.0],
+            normal: normalize([0.0, -phi, 1.0]),
+        }; //9
+        let purple_bottom_left = VertexData {
+            position: [0.0, phi, -1.0],
+            normal: normalize([0.0, phi, -1.0]),
+        }; //10
+        let purple_bottom_right = VertexData {
+            position: [0.0, phi, 1.0],
+            normal: normalize([0.0, phi, 1.0]),
+        }; //11
+
+        Mesh {
+            vertex_data: vec![
+                darkgreen_front_top,
+                darkgreen_front_bottom,
+                darkgreen_back_top,
+                darkgreen_back_bottom,
+                lightgreen_front_right,
+                lightgreen_front_left,
+                lightgreen_back_right,
+                lightgreen_back_left,
+                purple_top_left,
+                purple_top_right,
+                purple_bottom_left,
+                purple_bottom_right,
+            ],
+            index_data: vec![
+                0, 9, 8, //
+                0, 8, 4, //
+                0, 4, 1, //
+                0, 1, 6, //
+                0, 6, 9, //
+                8, 9, 2, //
+                8, 2, 5, //
+                8, 5, 4, //
+                4, 5, 10, //
+                4, 10, 1, //
+                1, 10, 11, //
+                1, 11, 6, //
+                2, 3, 5, //
+                2, 7, 3, //
+                2, 9, 7, //
+                5, 3, 10, //
+                3, 11, 10, //
+                3, 7, 11, //
+                6, 7, 9, //
+                6, 11, 7, //
+            ],
+            vertex_buffer: None,
+            index_buffer: None,
+            topology: Topology::TriangleList,
+            primitive_restart: false,
+        }
+    }
+
+    /// Splits every triangle into four at its edge midpoints without moving anything; see
+    /// [`Mesh::subdivide`] for smoothing.
+    pub fn refine(&mut self) {
+        let mut new_indices = vec![];
+        let mut midpoints = std::collections::HashMap::<(u32, u32), u32>::new();
+        for triangle in self.index_data.chunks(3) {
+            let a = triangle[0];
+            let b = triangle[1];
+            let c = triangle[2];
+            let vertex_a = self.vertex_data[a as usize];
+            let vertex_b = self.vertex_data[b as usize];
+            let vertex_c = self.vertex_data[c as usize];
+            let mab = if let Some(ab) = midpoints.get(&(a, b)) {
+                *ab
+            } else {
+                let vertex_ab = VertexData::midpoint(&vertex_a, &vertex_b);
+                let mab = self.vertex_data.len() as u32;
+                self.vertex_data.push(vertex_ab);
+                midpoints.insert((a, b), mab);
+                midpoints.insert((b, a), mab);
+                mab
+            };
+            let mbc = if let Some(bc) = midpoints.get(&(b, c)) {
+                *bc
+            } else {
+                let vertex_bc = VertexData::midpoint(&vertex_b, &vertex_c);
+                let mbc = self.vertex_data.len() as u32;
+                midpoints.insert((b, c), mbc);
+                midpoints.insert((c, b), mbc);
+                self.vertex_data.push(vertex_bc);
+                mbc
+            };
+            let mca = if let Some(ca) = midpoints.get(&(c, a)) {
+                *ca
+            } else {
+                let vertex_ca = VertexData::midpoint(&vertex_c, &vertex_a);
+                let mca = self.vertex_data.len() as u32;
+                midpoints.insert((c, a), mca);
+                midpoints.insert((a, c), mca);
+                self.vertex_data.push(vertex_ca);
+                mca
+            };
+            new_indices.extend_from_slice(&[mca, a, mab, mab, b, mbc, mbc, c, mca, mab, mbc, mca]);
+        }
+        self.index_data = new_indices;
+    }
+}
+
+/// Refers to a mesh in a [`MeshLibrary`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub usize);
+
+/// Meshes shared between instance sets, addressed by [`MeshHandle`]s that stay valid
+/// until the mesh is removed.
+pub struct MeshLibrary<V: Copy> {
+    pub meshes: Vec<Option<Mesh<V>>>,
+}
+
+impl<V: Copy> Default for MeshLibrary<V> {
+    fn default() -> Self {
+        MeshLibrary { meshes: Vec::new() }
+    }
+}
+
+impl<V: Copy> MeshLibrary<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, mesh: Mesh<V>) -> MeshHandle {
+        self.meshes.push(Some(mesh));
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    pub fn get(&self, handle: MeshHandle) -> Option<&Mesh<V>> {
+        self.meshes.get(handle.0).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, handle: MeshHandle) -> Option<&mut Mesh<V>> {
+        self.meshes.get_mut(handle.0).and_then(Option::as_mut)
+    }
+
+    /// Takes the mesh out; its handle is not reused. Its buffers are still alive, so clean
+    /// it up once no frame in flight draws it.
+    pub fn remove(&mut self, handle: MeshHandle) -> Option<Mesh<V>> {
+        self.meshes.get_mut(handle.0).and_then(Option::take)
+    }
+
+    /// Destroys every mesh's buffers. The device must be idle.
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        for mesh in self.meshes.iter_mut().flatten() {
+            mesh.cleanup(logical_device);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use super::{Mesh, VertexData};
+
+/// Meshlet size limits, matching the `max_vertices` and `max_primitives` of
+/// `shaders/meshlet.mesh`; 64 and 124 suit most mesh shading hardware.
+pub const MAX_MESHLET_VERTICES: usize = 64;
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+
+/// A bounded chunk of a mesh, as read by `shaders/meshlet.task` and `shaders/meshlet.mesh`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Meshlet {
+    /// First entry of [`Meshlets::vertex_indices`] used by this meshlet.
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    /// First entry of [`Meshlets::triangles`] used by this meshlet.
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+    /// Bounding sphere in model space, centre then radius, for culling whole meshlets.
+    pub bounds: [f32; 4],
+}
+
+/// A triangle list split into meshlets of at most [`MAX_MESHLET_VERTICES`] vertices and
+/// [`MAX_MESHLET_TRIANGLES`] triangles.
+#[derive(Clone, Debug, Default)]
+pub struct Meshlets {
+    pub meshlets: Vec<Meshlet>,
+    /// Indices into the model's vertices, a run per meshlet.
+    pub vertex_indices: Vec<u32>,
+    /// Three indices into the meshlet's run of `vertex_indices` per triangle, packed into
+    /// the low three bytes.
+    pub triangles: Vec<u32>,
+}
+
+impl Meshlets {
+    /// Fills meshlets greedily in index order, so meshes with good vertex locality share
+    /// most vertices within a meshlet.
+    pub fn build(vertices: &[VertexData], indices: &[u32]) -> Self {
+        let mut meshlets = Meshlets::default();
+        let mut local: Vec<u32> = Vec::with_capacity(MAX_MESHLET_VERTICES);
+        let mut triangle_offset = 0;
+        for triangle in indices.chunks_exact(3) {
+            let new_vertices = triangle
+                .iter()
+                .enumerate()
+                .filter(|&(i, index)| !local.contains(index) && !triangle[..i].contains(index))
+                .count();
+            if local.len() + new_vertices > MAX_MESHLET_VERTICES
+                || meshlets.triangles.len() - triangle_offset == MAX_MESHLET_TRIANGLES
+            {
+                meshlets.push(vertices, &local, triangle_offset);
+                local.clear();
+                triangle_offset = meshlets.triangles.len();
+            }
+            let mut packed = 0;
+            for (corner, &index) in triangle.iter().enumerate() {
+                let slot = local.iter().position(|&v| v == index).unwrap_or_else(|| {
+                    local.push(index);
+                    local.len() - 1
+                });
+                packed |= (slot as u32) << (8 * corner);
+            }
+            meshlets.triangles.push(packed);
+        }
+        if meshlets.triangles.len() > triangle_offset {
+            meshlets.push(vertices, &local, triangle_offset);
+        }
+        meshlets
+    }
+
+    fn push(&mut self, vertices: &[VertexData], local: &[u32], triangle_offset: usize) {
+        let positions = || local.iter().map(|&v| vertices[v as usize].position);
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in positions() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        let centre = [0, 1, 2].map(|axis| 0.5 * (min[axis] + max[axis]));
+        let radius = positions()
+            .map(|p| {
+                ((p[0] - centre[0]).powi(2)
+                    + (p[1] - centre[1]).powi(2)
+                    + (p[2] - centre[2]).powi(2))
+                .sqrt()
+            })
+            .fold(0.0, f32::max);
+
+        self.meshlets.push(Meshlet {
+            vertex_offset: self.vertex_indices.len() as u32,
+            vertex_count: local.len() as u32,
+            triangle_offset: triangle_offset as u32,
+            triangle_count: (self.triangles.len() - triangle_offset) as u32,
+            bounds: [centre[0], centre[1], centre[2], radius],
+        });
+        self.vertex_indices.extend_from_slice(local);
+    }
+}
+
+impl Mesh<VertexData> {
+    /// Splits the mesh's triangles into meshlets for [`crate::mesh_shader::MeshShaderPass`].
+    pub fn meshlets(&self) -> Meshlets {
+        Meshlets::build(&self.vertex_data, &self.index_data)
+    }
+}
@@ -1,9 +1,28 @@
+mod aabb;
 mod instance;
+mod instance_set;
+mod mesh;
+mod meshlet;
 mod model;
+mod optimize;
+mod simplify;
+mod subdivide;
+mod terrain;
+mod terrain_streamer;
+#[cfg(feature = "text")]
+mod text;
 mod vertex;
 
-pub use instance::InstanceData;
+pub use aabb::Aabb;
+pub use instance::{Instance, InstanceData};
+pub use instance_set::InstanceSet;
+pub use mesh::{Mesh, MeshHandle, MeshLibrary};
+pub use meshlet::{Meshlet, Meshlets, MAX_MESHLET_TRIANGLES, MAX_MESHLET_VERTICES};
 pub use model::Model;
+pub use terrain::NoiseTerrain;
+pub use terrain_streamer::{TerrainChunk, TerrainStreamer};
+#[cfg(feature = "text")]
+pub use text::Font;
 pub use vertex::VertexData;
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use crate::pipeline::Topology;
+
+use super::{vertex::normalize, Mesh, VertexData};
+
+/// Vertices the post-transform cache is modelled to hold.
+const CACHE_SIZE: usize = 32;
+
+/// Forsyth's score for a vertex at `position` in the modelled cache with `uses` triangles
+/// left to emit.
+fn vertex_score(position: Option<usize>, uses: usize) -> f32 {
+    if uses == 0 {
+        return -1.0;
+    }
+    let cache_score = match position {
+        None => 0.0,
+        // The last triangle's vertices are in the cache whichever comes next.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+    };
+    cache_score + 2.0 / (uses as f32).sqrt()
+}
+
+impl Mesh<VertexData> {
+    /// Merges vertices closer than `epsilon` to each other, averaging their normals, and
+    /// drops triangles that collapse in the process. Meshes with flat shading lose their
+    /// hard edges, as those are split vertices with the same position.
+    pub fn weld(&mut self, epsilon: f32) {
+        let epsilon = epsilon.max(f32::EPSILON);
+        let cell = |position: [f32; 3]| position.map(|axis| (axis / epsilon).floor() as i64);
+
+        let mut cells = HashMap::<[i64; 3], Vec<u32>>::new();
+        let mut remap = Vec::with_capacity(self.vertex_data.len());
+        let mut vertex_data: Vec<VertexData> = vec![];
+        let mut normals: Vec<[f32; 3]> = vec![];
+        for vertex in &self.vertex_data {
+            let [x, y, z] = cell(vertex.position);
+            // Neighbouring cells too, so points straddling a cell border still meet.
+            let existing = (-1..=1)
+                .flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [dx, dy, dz])))
+                .filter_map(|[dx, dy, dz]| cells.get(&[x + dx, y + dy, z + dz]))
+                .flatten()
+                .copied()
+                .find(|&index| {
+                    let other = vertex_data[index as usize].position;
+                    (0..3).all(|axis| (other[axis] - vertex.position[axis]).abs() <= epsilon)
+                });
+            let index = existing.unwrap_or_else(|| {
+                let index = vertex_data.len() as u32;
+                vertex_data.push(*vertex);
+                normals.push([0.0; 3]);
+                cells.entry([x, y, z]).or_default().push(index);
+                index
+            });
+            for (sum, axis) in normals[index as usize].iter_mut().zip(vertex.normal) {
+                *sum += axis;
+            }
+            remap.push(index);
+        }
+        for (vertex, normal) in vertex_data.iter_mut().zip(normals) {
+            if normal != [0.0; 3] {
+                vertex.normal = normalize(normal);
+            }
+        }
+
+        let remapped = self
+            .index_data
+            .iter()
+            .map(|&index| remap.get(index as usize).copied().unwrap_or(index));
+        self.index_data = if self.topology == Topology::TriangleList {
+            let indices: Vec<u32> = remapped.collect();
+            indices
+                .chunks_exact(3)
+                .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+                .flatten()
+                .copied()
+                .collect()
+        } else {
+            remapped.collect()
+        };
+        self.vertex_data = vertex_data;
+    }
+
+    /// Reorders the triangles so consecutive ones share vertices, using Tom Forsyth's linear
+    /// speed vertex cache optimisation, then renumbers the vertices in first-use order so
+    /// fetches walk the vertex buffer forwards. Only triangle lists are reordered.
+    pub fn optimize_vertex_cache(&mut self) {
+        if self.topology != Topology::TriangleList {
+            return;
+        }
+        let triangles: Vec<[u32; 3]> = self
+            .index_data
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        let vertex_count = self.vertex_data.len();
+
+        let mut vertex_triangles = vec![vec![]; vertex_count];
+        for (index, triangle) in triangles.iter().enumerate() {
+            for &v in triangle {
+                vertex_triangles[v as usize].push(index);
+            }
+        }
+        let mut uses: Vec<usize> = vertex_triangles.iter().map(Vec::len).collect();
+        let mut scores: Vec<f32> = uses.iter().map(|&uses| vertex_score(None, uses)).collect();
+        let triangle_score = |triangle: &[u32; 3], scores: &[f32]| {
+            triangle.iter().map(|&v| scores[v as usize]).sum()
+        };
+        let mut triangle_scores: Vec<f32> = triangles
+            .iter()
+            .map(|triangle| triangle_score(triangle, &scores))
+            .collect();
+        let mut emitted = vec![false; triangles.len()];
+        let mut cache: Vec<u32> = vec![];
+        let mut order = Vec::with_capacity(triangles.len());
+        let mut next_unemitted = 0;
+
+        while order.len() < triangles.len() {
+            // The best triangle touching the cache, or the next unemitted one if none does.
+            let best = cache
+                .iter()
+                .flat_map(|&v| &vertex_triangles[v as usize])
+                .copied()
+                .filter(|&t| !emitted[t])
+                .max_by(|&a, &b| triangle_scores[a].total_cmp(&triangle_scores[b]))
+                .unwrap_or_else(|| {
+                    while emitted[next_unemitted] {
+                        next_unemitted += 1;
+                    }
+                    next_unemitted
+                });
+            emitted[best] = true;
+            order.push(best);
+
+            let triangle = triangles[best];
+            for &v in &triangle {
+                uses[v as usize] -= 1;
+                vertex_triangles[v as usize].retain(|&t| t != best);
+            }
+            let mut next_cache: Vec<u32> = triangle.to_vec();
+            next_cache.extend(cache.iter().filter(|v| !triangle.contains(v)));
+            // Vertices pushed out of the cache still need their scores lowered.
+            let touched = next_cache.clone();
+            next_cache.truncate(CACHE_SIZE);
+            for &v in &touched {
+                let position = next_cache.iter().position(|&cached| cached == v);
+                scores[v as usize] = vertex_score(position, uses[v as usize]);
+            }
+            for &v in &touched {
+                for &t in &vertex_triangles[v as usize] {
+                    triangle_scores[t] = triangle_score(&triangles[t], &scores);
+                }
+            }
+            cache = next_cache;
+        }
+
+        let mut remap = vec![u32::MAX; vertex_count];
+        let mut vertex_data = Vec::with_capacity(vertex_count);
+        let mut index_data = Vec::with_capacity(self.index_data.len());
+        for t in order {
+            for v in triangles[t] {
+                if remap[v as usize] == u32::MAX {
+                    remap[v as usize] = vertex_data.len() as u32;
+                    vertex_data.push(self.vertex_data[v as usize]);
+                }
+                index_data.push(remap[v as usize]);
+            }
+        }
+        self.vertex_data = vertex_data;
+        self.index_data = index_data;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `mesh` with a vertex of its own for every index, as flat-shaded exports come.
+    fn unindexed(mesh: &Mesh<VertexData>) -> Mesh<VertexData> {
+        let vertex_data = mesh
+            .index_data
+            .iter()
+            .map(|&index| mesh.vertex_data[index as usize])
+            .collect();
+        Mesh::new(vertex_data, (0..mesh.index_data.len() as u32).collect())
+    }
+
+    fn corners(mesh: &Mesh<VertexData>) -> Vec<[f32; 3]> {
+        mesh.index_data
+            .iter()
+            .map(|&index| mesh.vertex_data[index as usize].position)
+            .collect()
+    }
+
+    #[test]
+    fn welding_merges_duplicate_vertices() {
+        let cube = Mesh::cube();
+        let mut welded = unindexed(&cube);
+        assert_eq!(welded.vertex_data.len(), 36);
+        welded.weld(1e-4);
+        assert_eq!(welded.vertex_data.len(), 8);
+        assert_eq!(corners(&welded), corners(&cube));
+    }
+
+    #[test]
+    fn welding_drops_collapsed_triangles() {
+        let vertex = |x| VertexData {
+            position: [x, 0.0, 0.0],
+            normal: [0.0, -1.0, 0.0],
+        };
+        let mut mesh = Mesh::new(
+            vec![
+                vertex(0.0),
+                vertex(1e-5),
+                vertex(1.0),
+                vertex(0.0),
+                vertex(1.0),
+            ],
+            vec![0, 1, 2, 3, 4, 2],
+        );
+        mesh.weld(1e-3);
+        assert_eq!(mesh.vertex_data.len(), 2);
+        assert!(mesh.index_data.is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::pipeline::Topology;
+
+use super::{vertex::normalize, Mesh, VertexData};
+
+/// Weight of the planes that pin open boundaries in place, relative to the surface's.
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+/// An edge collapse waiting in the queue, stale once either vertex has changed since.
+struct Collapse {
+    cost: f64,
+    kept: u32,
+    removed: u32,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Reversed, so the max-heap yields the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn plane_quadric(normal: Vector3<f64>, point: Vector3<f64>, weight: f64) -> Matrix4<f64> {
+    let plane = Vector4::new(normal.x, normal.y, normal.z, -normal.dot(&point));
+    plane * plane.transpose() * weight
+}
+
+fn quadric_error(quadric: &Matrix4<f64>, position: &Vector3<f64>) -> f64 {
+    let p = position.push(1.0);
+    (p.transpose() * quadric * p)[0]
+}
+
+fn position(vertex: &VertexData) -> Vector3<f64> {
+    Vector3::from(vertex.position).cast()
+}
+
+impl Mesh<VertexData> {
+    /// A reduced copy keeping about `target_ratio` of the triangles, made by collapsing the
+    /// edges whose removal changes the surface least under quadric error metrics (Garland
+    /// and Heckbert). Open boundaries are held in place and collapses that would flip a
+    /// triangle are skipped, so the result may keep more triangles than asked for.
+    /// Anything but a triangle list is copied unchanged.
+    pub fn simplify(&self, target_ratio: f32) -> Mesh<VertexData> {
+        let unchanged = || {
+            Mesh::new(self.vertex_data.clone(), self.index_data.clone())
+                .with_topology(self.topology, self.primitive_restart)
+        };
+        if self.topology != Topology::TriangleList {
+            return unchanged();
+        }
+        let mut triangles: Vec<[u32; 3]> = self
+            .index_data
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+        let target = (triangles.len() as f32 * target_ratio.clamp(0.0, 1.0)).ceil() as usize;
+        if target >= triangles.len() {
+            return unchanged();
+        }
+
+        let mut vertices = self.vertex_data.clone();
+        let mut quadrics = vec![Matrix4::<f64>::zeros(); vertices.len()];
+        let mut vertex_triangles = vec![vec![]; vertices.len()];
+        let mut edge_uses = HashMap::<(u32, u32), u32>::new();
+        for (index, triangle) in triangles.iter().enumerate() {
+            let [a, b, c] = triangle.map(|v| position(&vertices[v as usize]));
+            let normal = (b - a).cross(&(c - a));
+            let area = normal.norm();
+            if area > 0.0 {
+                let quadric = plane_quadric(normal / area, a, area);
+                for &v in triangle {
+                    quadrics[v as usize] += quadric;
+                }
+            }
+            for corner in 0..3 {
+                vertex_triangles[triangle[corner] as usize].push(index);
+                let (u, v) = (triangle[corner], triangle[(corner + 1) % 3]);
+                *edge_uses.entry((u.min(v), u.max(v))).or_default() += 1;
+            }
+        }
+        // A plane through each boundary edge, perpendicular to its triangle, keeps the
+        // outline from shrinking.
+        for triangle in &triangles {
+            let [a, b, c] = triangle.map(|v| position(&vertices[v as usize]));
+            let normal = (b - a).cross(&(c - a));
+            for corner in 0..3 {
+                let (u, v) = (triangle[corner], triangle[(corner + 1) % 3]);
+                if edge_uses[&(u.min(v), u.max(v))] != 1 {
+                    continue;
+                }
+                let (pu, pv) = (
+                    position(&vertices[u as usize]),
+                    position(&vertices[v as usize]),
+                );
+                let edge = pv - pu;
+                let Some(side) = edge.cross(&normal).try_normalize(f64::EPSILON) else {
+                    continue;
+                };
+                let quadric = plane_quadric(side, pu, BOUNDARY_WEIGHT * edge.norm_squared());
+                quadrics[u as usize] += quadric;
+                quadrics[v as usize] += quadric;
+            }
+        }
+
+        let mut versions = vec![0u32; vertices.len()];
+        let mut removed = vec![false; vertices.len()];
+        let mut alive = vec![true; triangles.len()];
+        let mut alive_count = triangles.len();
+
+        let candidate =
+            |kept: u32, other: u32, vertices: &[VertexData], quadrics: &[Matrix4<f64>]| {
+                let quadric = quadrics[kept as usize] + quadrics[other as usize];
+                let (pk, po) = (
+                    position(&vertices[kept as usize]),
+                    position(&vertices[other as usize]),
+                );
+                [pk, po, (pk + po) * 0.5]
+                    .into_iter()
+                    .map(|p| (quadric_error(&quadric, &p), p))
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .unwrap()
+            };
+        let mut queue = BinaryHeap::new();
+        for &(u, v) in edge_uses.keys() {
+            queue.push(Collapse {
+                cost: candidate(u, v, &vertices, &quadrics).0,
+                kept: u,
+                removed: v,
+                versions: (0, 0),
+            });
+        }
+
+        while alive_count > target {
+            let Some(collapse) = queue.pop() else {
+                break;
+            };
+            let (kept, gone) = (collapse.kept as usize, collapse.removed as usize);
+            if removed[kept]
+                || removed[gone]
+                || collapse.versions != (versions[kept], versions[gone])
+            {
+                continue;
+            }
+            let (_, new_position) =
+                candidate(collapse.kept, collapse.removed, &vertices, &quadrics);
+
+            // Skip collapses that would turn a surviving triangle over.
+            let flips = vertex_triangles[kept]
+                .iter()
+                .chain(&vertex_triangles[gone])
+                .filter(|&&t| alive[t])
+                .any(|&t| {
+                    let triangle = triangles[t];
+                    if triangle.contains(&collapse.kept) && triangle.contains(&collapse.removed) {
+                        return false;
+                    }
+                    let corners = triangle.map(|v| position(&vertices[v as usize]));
+                    let moved = triangle.map(|v| {
+                        if v as usize == kept || v as usize == gone {
+                            new_position
+                        } else {
+                            position(&vertices[v as usize])
+                        }
+                    });
+                    let before = (corners[1] - corners[0]).cross(&(corners[2] - corners[0]));
+                    let after = (moved[1] - moved[0]).cross(&(moved[2] - moved[0]));
+                    before.dot(&after) <= 0.0
+                });
+            if flips {
+                continue;
+            }
+
+            let normal =
+                [0, 1, 2].map(|axis| vertices[kept].normal[axis] + vertices[gone].normal[axis]);
+            vertices[kept].position = new_position.cast::<f32>().into();
+            if normal != [0.0; 3] {
+                vertices[kept].normal = normalize(normal);
+            }
+            let gone_quadric = quadrics[gone];
+            quadrics[kept] += gone_quadric;
+            removed[gone] = true;
+            versions[kept] += 1;
+
+            for t in std::mem::take(&mut vertex_triangles[gone]) {
+                if !alive[t] {
+                    continue;
+                }
+                for v in &mut triangles[t] {
+                    if *v == collapse.removed {
+                        *v = collapse.kept;
+                    }
+                }
+                let [a, b, c] = triangles[t];
+                if a == b || b == c || c == a {
+                    alive[t] = false;
+                    alive_count -= 1;
+                } else {
+                    vertex_triangles[kept].push(t);
+                }
+            }
+            vertex_triangles[kept].retain(|&t| alive[t]);
+            vertex_triangles[kept].dedup();
+
+            let mut neighbours: Vec<u32> = vertex_triangles[kept]
+                .iter()
+                .flat_map(|&t| triangles[t])
+                .filter(|&v| v != collapse.kept)
+                .collect();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            for neighbour in neighbours {
+                queue.push(Collapse {
+                    cost: candidate(collapse.kept, neighbour, &vertices, &quadrics).0,
+                    kept: collapse.kept,
+                    removed: neighbour,
+                    versions: (versions[kept], versions[neighbour as usize]),
+                });
+            }
+        }
+
+        // Keep only the vertices the surviving triangles use, in first-use order.
+        let mut remap = vec![u32::MAX; vertices.len()];
+        let mut vertex_data = vec![];
+        let mut index_data = Vec::with_capacity(alive_count * 3);
+        for (triangle, _) in triangles.iter().zip(&alive).filter(|(_, &alive)| alive) {
+            for &v in triangle {
+                if remap[v as usize] == u32::MAX {
+                    remap[v as usize] = vertex_data.len() as u32;
+                    vertex_data.push(vertices[v as usize]);
+                }
+                index_data.push(remap[v as usize]);
+            }
+        }
+        Mesh::new(vertex_data, index_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit square in the xz plane, as two triangles.
+    fn quad() -> Mesh<VertexData> {
+        let vertex = |x, z| VertexData {
+            position: [x, 0.0, z],
+            normal: [0.0, -1.0, 0.0],
+        };
+        Mesh::new(
+            vec![
+                vertex(0.0, 0.0),
+                vertex(1.0, 0.0),
+                vertex(1.0, 1.0),
+                vertex(0.0, 1.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        )
+    }
+
+    #[test]
+    fn halving_a_quad_leaves_one_triangle() {
+        let simplified = quad().simplify(0.5);
+        assert_eq!(simplified.index_data.len(), 3);
+        assert_eq!(simplified.vertex_data.len(), 3);
+        assert!(simplified
+            .index_data
+            .iter()
+            .all(|&index| (index as usize) < simplified.vertex_data.len()));
+    }
+
+    #[test]
+    fn a_full_ratio_keeps_the_quad() {
+        let simplified = quad().simplify(1.0);
+        assert_eq!(simplified.index_data, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(simplified.vertex_data.len(), 4);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use crate::pipeline::Topology;
+
+use super::{vertex::normalize, Mesh, VertexData};
+
+/// `a * weight_a + sum(others) * weight_other` on both position and normal.
+fn blend(a: &VertexData, weight_a: f32, others: &[&VertexData], weight_other: f32) -> VertexData {
+    let mut vertex = VertexData {
+        position: a.position.map(|axis| axis * weight_a),
+        normal: a.normal.map(|axis| axis * weight_a),
+    };
+    for other in others {
+        for axis in 0..3 {
+            vertex.position[axis] += other.position[axis] * weight_other;
+            vertex.normal[axis] += other.normal[axis] * weight_other;
+        }
+    }
+    vertex
+}
+
+impl Mesh<VertexData> {
+    /// One step of Loop subdivision: every triangle splits into four and the vertices move
+    /// towards the limit surface, so repeated calls smooth out low-poly meshes. Open and
+    /// non-manifold edges are kept as creases. Unlike [`Mesh::refine`], which only splits,
+    /// the shape changes; anything but a triangle list is left alone.
+    pub fn subdivide(&mut self) {
+        if self.topology != Topology::TriangleList {
+            return;
+        }
+        let triangles: Vec<[u32; 3]> = self
+            .index_data
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+
+        // The corners opposite each edge, one per triangle using it.
+        let mut opposite = HashMap::<(u32, u32), Vec<u32>>::new();
+        for triangle in &triangles {
+            for corner in 0..3 {
+                let (u, v) = (triangle[corner], triangle[(corner + 1) % 3]);
+                opposite
+                    .entry((u.min(v), u.max(v)))
+                    .or_default()
+                    .push(triangle[(corner + 2) % 3]);
+            }
+        }
+
+        let mut neighbours = vec![vec![]; self.vertex_data.len()];
+        let mut crease_neighbours = vec![vec![]; self.vertex_data.len()];
+        for (&(u, v), corners) in &opposite {
+            neighbours[u as usize].push(v);
+            neighbours[v as usize].push(u);
+            if corners.len() != 2 {
+                crease_neighbours[u as usize].push(v);
+                crease_neighbours[v as usize].push(u);
+            }
+        }
+
+        let old = &self.vertex_data;
+        let mut vertex_data: Vec<VertexData> = old
+            .iter()
+            .enumerate()
+            .map(|(index, vertex)| {
+                let creases = &crease_neighbours[index];
+                if creases.len() == 2 {
+                    let ends = [&old[creases[0] as usize], &old[creases[1] as usize]];
+                    return blend(vertex, 0.75, &ends, 0.125);
+                }
+                // Corners where creases meet, and lone vertices, stay put.
+                if !creases.is_empty() || neighbours[index].is_empty() {
+                    return *vertex;
+                }
+                let n = neighbours[index].len();
+                let beta = if n == 3 {
+                    3.0 / 16.0
+                } else {
+                    3.0 / (8.0 * n as f32)
+                };
+                let ring: Vec<&VertexData> = neighbours[index]
+                    .iter()
+                    .map(|&v| &old[v as usize])
+                    .collect();
+                blend(vertex, 1.0 - n as f32 * beta, &ring, beta)
+            })
+            .collect();
+
+        let mut edge_points = HashMap::<(u32, u32), u32>::new();
+        for (&(u, v), corners) in &opposite {
+            let (a, b) = (&old[u as usize], &old[v as usize]);
+            let point = if let [c, d] = corners[..] {
+                let ends = blend(a, 0.375, &[b], 0.375);
+                blend(&ends, 1.0, &[&old[c as usize], &old[d as usize]], 0.125)
+            } else {
+                VertexData::midpoint(a, b)
+            };
+            edge_points.insert((u, v), vertex_data.len() as u32);
+            vertex_data.push(point);
+        }
+        for vertex in &mut vertex_data {
+            if vertex.normal != [0.0; 3] {
+                vertex.normal = normalize(vertex.normal);
+            }
+        }
+
+        let edge_point = |u: u32, v: u32| edge_points[&(u.min(v), u.max(v))];
+        let mut index_data = Vec::with_capacity(triangles.len() * 12);
+        for [a, b, c] in triangles {
+            let (mab, mbc, mca) = (edge_point(a, b), edge_point(b, c), edge_point(c, a));
+            index_data.extend_from_slice(&[a, mab, mca, mab, b, mbc, mbc, c, mca, mab, mbc, mca]);
+        }
+        self.vertex_data = vertex_data;
+        self.index_data = index_data;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_level_quadruples_the_triangles() {
+        let mut mesh = Mesh::icosahedron();
+        assert_eq!(mesh.index_data.len(), 20 * 3);
+        mesh.subdivide();
+        assert_eq!(mesh.index_data.len(), 80 * 3);
+        // A vertex for every old vertex and every edge.
+        assert_eq!(mesh.vertex_data.len(), 12 + 30);
+        assert!(mesh
+            .index_data
+            .iter()
+            .all(|&index| (index as usize) < mesh.vertex_data.len()));
+    }
+
+    #[test]
+    fn only_triangle_lists_are_subdivided() {
+        let mut mesh = Mesh::icosahedron().with_topology(Topology::LineList, false);
+        mesh.subdivide();
+        assert_eq!(mesh.index_data.len(), 20 * 3);
+    }
+}
@@ -0,0 +1,266 @@
+use nalgebra::Vector3;
+
+use crate::noise::Perlin;
+
+use super::{mesh::Mesh, vertex::normalize, VertexData};
+
+impl Mesh<VertexData> {
+    /// Grid mesh centred on the origin from `width * depth` heights laid out row by row.
+    /// `scale` is the spacing between samples along x and z and the height multiplier
+    /// along y; heights grow upwards, i.e. towards negative y.
+    pub fn terrain_from_heights(
+        width: usize,
+        depth: usize,
+        heights: &[f32],
+        scale: Vector3<f32>,
+    ) -> Self {
+        assert!(
+            width >= 2 && depth >= 2,
+            "a terrain needs at least 2x2 samples"
+        );
+        assert_eq!(heights.len(), width * depth, "one height per sample");
+
+        let height = |x: usize, z: usize| heights[z * width + x] * scale.y;
+        let x_offset = 0.5 * (width - 1) as f32 * scale.x;
+        let z_offset = 0.5 * (depth - 1) as f32 * scale.z;
+
+        let mut vertex_data = Vec::with_capacity(width * depth);
+        for z in 0..depth {
+            for x in 0..width {
+                let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (front, back) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+                let slope_x =
+                    (height(right, z) - height(left, z)) / ((right - left) as f32 * scale.x);
+                let slope_z =
+                    (height(x, back) - height(x, front)) / ((back - front) as f32 * scale.z);
+
+                vertex_data.push(VertexData {
+                    position: [
+                        x as f32 * scale.x - x_offset,
+                        -height(x, z),
+                        z as f32 * scale.z - z_offset,
+                    ],
+                    normal: normalize([-slope_x, -1.0, -slope_z]),
+                });
+            }
+        }
+
+        let mut index_data = Vec::with_capacity((width - 1) * (depth - 1) * 6);
+        for z in 0..depth - 1 {
+            for x in 0..width - 1 {
+                let front_left = (z * width + x) as u32;
+                let front_right = front_left + 1;
+                let back_left = front_left + width as u32;
+                let back_right = back_left + 1;
+                index_data.extend_from_slice(&[
+                    front_left,
+                    back_right,
+                    back_left,
+                    front_left,
+                    front_right,
+                    back_right,
+                ]);
+            }
+        }
+
+        Mesh::new(vertex_data, index_data)
+    }
+
+    /// Terrain with one vertex per pixel; black is height 0 and white is height `scale.y`.
+    #[cfg(feature = "image")]
+    pub fn terrain_from_heightmap(image: &image::GrayImage, scale: Vector3<f32>) -> Self {
+        let heights: Vec<f32> = image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / 255.0)
+            .collect();
+        Self::terrain_from_heights(
+            image.width() as usize,
+            image.height() as usize,
+            &heights,
+            scale,
+        )
+    }
+}
+
+/// Endless terrain from layered Perlin noise, generated in square chunks that tile
+/// seamlessly because every chunk samples the same world-space noise field.
+pub struct NoiseTerrain {
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+    /// Noise frequency of the first octave, in cycles per world unit.
+    pub frequency: f32,
+    /// Height of the tallest possible peak above 0.
+    pub amplitude: f32,
+    /// Quads along each side of a chunk.
+    pub chunk_resolution: usize,
+    /// World-space side length of a chunk.
+    pub chunk_size: f32,
+    noise: Perlin,
+}
+
+impl Default for NoiseTerrain {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl NoiseTerrain {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            octaves: 5,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            frequency: 0.02,
+            amplitude: 10.0,
+            chunk_resolution: 32,
+            chunk_size: 32.0,
+            noise: Perlin::new(seed),
+        }
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.noise = Perlin::new(seed);
+        self
+    }
+
+    pub fn octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn lacunarity(mut self, lacunarity: f32) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn persistence(mut self, persistence: f32) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    pub fn frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    pub fn chunk_resolution(mut self, chunk_resolution: usize) -> Self {
+        self.chunk_resolution = chunk_resolution.max(1);
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: f32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Terrain height (upwards, i.e. along negative y) at a world-space position.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let noise = self.noise.fbm(
+            x * self.frequency,
+            z * self.frequency,
+            self.octaves,
+            self.lacunarity,
+            self.persistence,
+        );
+        self.amplitude * noise
+    }
+
+    /// RGBA8 splat map of `resolution * resolution` weights over the square from `origin`
+    /// spanning `size` along x and z, for a [`crate::splat_terrain::SplatMaterial`]. Red
+    /// covers the low, gentle ground, green the steep slopes, blue the peaks and alpha the
+    /// lowlands below height 0, so layers read e.g. grass, rock, snow and sand.
+    pub fn splat_map(&self, origin: [f32; 2], size: f32, resolution: usize) -> Vec<u8> {
+        let spacing = size / resolution.max(1) as f32;
+        let amplitude = self.amplitude.abs().max(f32::EPSILON);
+        let smoothstep = |low: f32, high: f32, value: f32| {
+            let t = ((value - low) / (high - low)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+
+        let mut weights = Vec::with_capacity(resolution * resolution * 4);
+        for z in 0..resolution {
+            for x in 0..resolution {
+                let world_x = origin[0] + (x as f32 + 0.5) * spacing;
+                let world_z = origin[1] + (z as f32 + 0.5) * spacing;
+                let height = self.height_at(world_x, world_z) / amplitude;
+                let slope_x = (self.height_at(world_x + spacing, world_z)
+                    - self.height_at(world_x - spacing, world_z))
+                    / (2.0 * spacing);
+                let slope_z = (self.height_at(world_x, world_z + spacing)
+                    - self.height_at(world_x, world_z - spacing))
+                    / (2.0 * spacing);
+                let slope = (slope_x * slope_x + slope_z * slope_z).sqrt();
+
+                let rock = smoothstep(0.6, 1.0, slope);
+                let snow = smoothstep(0.4, 0.6, height) * (1.0 - rock);
+                let sand = (1.0 - smoothstep(-0.1, 0.0, height)) * (1.0 - rock);
+                let grass = (1.0 - rock - snow - sand).max(0.0);
+                weights.extend([grass, rock, snow, sand].map(|w| (w * 255.0).round() as u8));
+            }
+        }
+        weights
+    }
+
+    /// Mesh of the chunk at integer chunk coordinates, in world space.
+    pub fn chunk(&self, chunk_x: i32, chunk_z: i32) -> Mesh<VertexData> {
+        self.chunk_lod(chunk_x, chunk_z, 0)
+    }
+
+    /// Like [`NoiseTerrain::chunk`], with the resolution halved `lod` times.
+    pub fn chunk_lod(&self, chunk_x: i32, chunk_z: i32, lod: u32) -> Mesh<VertexData> {
+        let resolution = (self.chunk_resolution >> lod.min(usize::BITS - 1)).max(1);
+        let samples = resolution + 1;
+        let spacing = self.chunk_size / resolution as f32;
+        let centre_x = (chunk_x as f32 + 0.5) * self.chunk_size;
+        let centre_z = (chunk_z as f32 + 0.5) * self.chunk_size;
+
+        let mut heights = Vec::with_capacity(samples * samples);
+        for z in 0..samples {
+            for x in 0..samples {
+                heights.push(self.height_at(
+                    chunk_x as f32 * self.chunk_size + x as f32 * spacing,
+                    chunk_z as f32 * self.chunk_size + z as f32 * spacing,
+                ));
+            }
+        }
+
+        let mut mesh = Mesh::terrain_from_heights(
+            samples,
+            samples,
+            &heights,
+            Vector3::new(spacing, 1.0, spacing),
+        );
+        for vertex in &mut mesh.vertex_data {
+            vertex.position[0] += centre_x;
+            vertex.position[2] += centre_z;
+        }
+        mesh
+    }
+
+    /// All chunks within `radius` chunks of the chunk containing `centre`.
+    pub fn chunks_around(
+        &self,
+        centre: &Vector3<f32>,
+        radius: i32,
+    ) -> Vec<((i32, i32), Mesh<VertexData>)> {
+        let (centre_x, centre_z) = self.chunk_coordinates(centre);
+        (-radius..=radius)
+            .flat_map(|dz| (-radius..=radius).map(move |dx| (centre_x + dx, centre_z + dz)))
+            .map(|(x, z)| ((x, z), self.chunk(x, z)))
+            .collect()
+    }
+
+    /// Coordinates of the chunk containing a world-space position.
+    pub fn chunk_coordinates(&self, position: &Vector3<f32>) -> (i32, i32) {
+        (
+            (position.x / self.chunk_size).floor() as i32,
+            (position.z / self.chunk_size).floor() as i32,
+        )
+    }
+}
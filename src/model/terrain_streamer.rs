@@ -0,0 +1,171 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+
+use anyhow::{Ok, Result};
+use ash::vk;
+use nalgebra::{Matrix4, Vector3};
+
+use super::{terrain::NoiseTerrain, Instance, InstanceData, Mesh, Model, VertexData};
+
+type ChunkCoordinates = (i32, i32);
+
+pub struct TerrainChunk<I: Instance = InstanceData> {
+    pub lod: u32,
+    pub model: Model<VertexData, I>,
+}
+
+/// Keeps the chunks of a [`NoiseTerrain`] loaded around the camera.
+/// Meshes are generated on a worker thread; finished ones are uploaded by `update`,
+/// and chunks that fall out of range are destroyed once no frame in flight uses them.
+pub struct TerrainStreamer<I: Instance = InstanceData> {
+    /// Chunks loaded in every direction around the camera's chunk.
+    pub radius: i32,
+    /// Chunk rings per level of detail; each level halves the chunk resolution.
+    pub lod_distance: i32,
+    pub max_lod: u32,
+    pub colour: [f32; 3],
+    pub chunks: HashMap<ChunkCoordinates, TerrainChunk<I>>,
+    pub chunk_size: f32,
+    frames_in_flight: usize,
+    pending: HashSet<(ChunkCoordinates, u32)>,
+    requests: Option<Sender<(ChunkCoordinates, u32)>>,
+    results: Receiver<(ChunkCoordinates, u32, Mesh<VertexData>)>,
+    retired: Vec<(Model<VertexData, I>, usize)>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<I: Instance> TerrainStreamer<I> {
+    pub fn new(terrain: NoiseTerrain, frames_in_flight: usize) -> Self {
+        let chunk_size = terrain.chunk_size;
+        let (requests, worker_requests) = channel::<(ChunkCoordinates, u32)>();
+        let (worker_results, results) = channel();
+        let worker = std::thread::spawn(move || {
+            for ((x, z), lod) in worker_requests {
+                let mesh = terrain.chunk_lod(x, z, lod);
+                if worker_results.send(((x, z), lod, mesh)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            radius: 4,
+            lod_distance: 2,
+            max_lod: 3,
+            colour: [0.3, 0.45, 0.2],
+            chunks: HashMap::new(),
+            chunk_size,
+            frames_in_flight,
+            pending: HashSet::new(),
+            requests: Some(requests),
+            results,
+            retired: Vec::new(),
+            worker: Some(worker),
+        }
+    }
+
+    fn desired_lod(&self, centre: ChunkCoordinates, chunk: ChunkCoordinates) -> Option<u32> {
+        let distance = (chunk.0 - centre.0).abs().max((chunk.1 - centre.1).abs());
+        if distance > self.radius {
+            None
+        } else {
+            Some(((distance / self.lod_distance.max(1)) as u32).min(self.max_lod))
+        }
+    }
+
+    /// Requests missing chunks, uploads finished ones and retires those out of range.
+    /// Call once per frame, before recording the command buffer.
+    pub fn update(
+        &mut self,
+        camera_position: &Vector3<f32>,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<()> {
+        let centre = (
+            (camera_position.x / self.chunk_size).floor() as i32,
+            (camera_position.z / self.chunk_size).floor() as i32,
+        );
+
+        for dz in -self.radius..=self.radius {
+            for dx in -self.radius..=self.radius {
+                let coordinates = (centre.0 + dx, centre.1 + dz);
+                let lod = self.desired_lod(centre, coordinates).unwrap();
+                let loaded = self.chunks.get(&coordinates).map(|chunk| chunk.lod);
+                if loaded != Some(lod) && self.pending.insert((coordinates, lod)) {
+                    if let Some(requests) = &self.requests {
+                        requests.send((coordinates, lod))?;
+                    }
+                }
+            }
+        }
+
+        while let std::result::Result::Ok((coordinates, lod, mesh)) = self.results.try_recv() {
+            self.pending.remove(&(coordinates, lod));
+            if self.desired_lod(centre, coordinates) != Some(lod) {
+                continue;
+            }
+            let mut model = Model::new(mesh);
+            model
+                .instances
+                .insert_visibly(I::from(InstanceData::from_matrix_and_colour(
+                    Matrix4::identity(),
+                    self.colour,
+                )));
+            model.upload(logical_device, memory_properties)?;
+            if let Some(previous) = self.chunks.insert(coordinates, TerrainChunk { lod, model }) {
+                self.retired.push((previous.model, self.frames_in_flight));
+            }
+        }
+
+        let out_of_range: Vec<ChunkCoordinates> = self
+            .chunks
+            .keys()
+            .filter(|coordinates| self.desired_lod(centre, **coordinates).is_none())
+            .copied()
+            .collect();
+        for coordinates in out_of_range {
+            if let Some(chunk) = self.chunks.remove(&coordinates) {
+                self.retired.push((chunk.model, self.frames_in_flight));
+            }
+        }
+
+        self.retired.retain_mut(|(model, frames_left)| {
+            if *frames_left == 0 {
+                model.cleanup(logical_device);
+                false
+            } else {
+                *frames_left -= 1;
+                true
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn draw(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        self.chunks
+            .values()
+            .for_each(|chunk| chunk.model.draw(logical_device, command_buffer));
+    }
+
+    /// Destroys every chunk's buffers. The device must be idle.
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        for (_, mut chunk) in self.chunks.drain() {
+            chunk.model.cleanup(logical_device);
+        }
+        for (mut model, _) in self.retired.drain(..) {
+            model.cleanup(logical_device);
+        }
+    }
+}
+
+impl<I: Instance> Drop for TerrainStreamer<I> {
+    fn drop(&mut self) {
+        // Closing the request channel ends the worker's loop.
+        self.requests = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Result};
+use lyon_tessellation::{
+    math::{point, Point},
+    path::{iterator::PathIterator, path::Builder, Event, Path},
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, VertexBuffers,
+};
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+use super::{vertex::normalize, Mesh, VertexData};
+
+/// How far flattened curves may stray from the glyph outline, in ems.
+const TOLERANCE: f32 = 0.005;
+
+/// A TrueType or OpenType font to build text meshes from.
+pub struct Font {
+    data: Vec<u8>,
+    index: u32,
+}
+
+impl Font {
+    /// The first face of a `.ttf`, `.otf` or `.ttc` file's contents.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        Self::from_collection(data, 0)
+    }
+
+    /// Face `index` of a font collection.
+    pub fn from_collection(data: Vec<u8>, index: u32) -> Result<Self> {
+        Face::parse(&data, index).map_err(|e| anyhow!("Invalid font: {e}"))?;
+        Ok(Font { data, index })
+    }
+
+    fn face(&self) -> Face<'_> {
+        Face::parse(&self.data, self.index).expect("font was parsed when created")
+    }
+}
+
+/// Collects glyph outlines into one path, in ems with y up, placed at the pen position.
+struct Outline {
+    builder: Builder,
+    scale: f32,
+    pen: (f32, f32),
+    open: bool,
+}
+
+impl Outline {
+    fn at(&self, x: f32, y: f32) -> Point {
+        point(self.pen.0 + x * self.scale, self.pen.1 + y * self.scale)
+    }
+
+    fn end_contour(&mut self) {
+        if self.open {
+            self.builder.end(true);
+            self.open = false;
+        }
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.end_contour();
+        self.builder.begin(self.at(x, y));
+        self.open = true;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.builder.line_to(self.at(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.builder
+            .quadratic_bezier_to(self.at(x1, y1), self.at(x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.builder
+            .cubic_bezier_to(self.at(x1, y1), self.at(x2, y2), self.at(x, y));
+    }
+
+    fn close(&mut self) {
+        self.end_contour();
+    }
+}
+
+/// Glyph space has y up; the scene's is down.
+fn world(p: Point, z: f32) -> [f32; 3] {
+    [p.x, -p.y, z]
+}
+
+/// Pushes `triangle`, wound counter-clockwise around `normal`.
+fn push_facing(mesh: &mut Mesh<VertexData>, [a, b, c]: [u32; 3], normal: [f32; 3]) {
+    let [pa, pb, pc] = [a, b, c].map(|v| mesh.vertex_data[v as usize].position);
+    let ab = [0, 1, 2].map(|axis| pb[axis] - pa[axis]);
+    let ac = [0, 1, 2].map(|axis| pc[axis] - pa[axis]);
+    let cross = [
+        ab[1] * ac[2] - ab[2] * ac[1],
+        ab[2] * ac[0] - ab[0] * ac[2],
+        ab[0] * ac[1] - ab[1] * ac[0],
+    ];
+    let facing: f32 = (0..3).map(|axis| cross[axis] * normal[axis]).sum();
+    if facing < 0.0 {
+        mesh.index_data.extend_from_slice(&[a, c, b]);
+    } else {
+        mesh.index_data.extend_from_slice(&[a, b, c]);
+    }
+}
+
+impl Mesh<VertexData> {
+    /// `text` set in `font` at one unit per em and extruded `extrude_depth` along +z. The
+    /// first baseline runs along +x from the origin with glyphs rising towards -y, the
+    /// front face sits at z = 0 facing -z, and each `\n` starts a new line below. An
+    /// `extrude_depth` of zero gives flat, single-sided text.
+    pub fn text_3d(font: &Font, text: &str, extrude_depth: f32) -> Result<Mesh<VertexData>> {
+        let face = font.face();
+        let scale = 1.0 / face.units_per_em() as f32;
+        let line_height = (face.ascender() - face.descender() + face.line_gap()) as f32 * scale;
+
+        let mut outline = Outline {
+            builder: Path::builder(),
+            scale,
+            pen: (0.0, 0.0),
+            open: false,
+        };
+        for line in text.lines() {
+            outline.pen.0 = 0.0;
+            for character in line.chars() {
+                // Missing characters fall back to the font's .notdef box.
+                let glyph = face.glyph_index(character).unwrap_or(GlyphId(0));
+                face.outline_glyph(glyph, &mut outline);
+                outline.end_contour();
+                outline.pen.0 += face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale;
+            }
+            outline.pen.1 -= line_height;
+        }
+        let path = outline.builder.build();
+
+        let mut cap: VertexBuffers<Point, u32> = VertexBuffers::new();
+        FillTessellator::new()
+            .tessellate_path(
+                &path,
+                &FillOptions::tolerance(TOLERANCE).with_fill_rule(FillRule::NonZero),
+                &mut BuffersBuilder::new(&mut cap, |vertex: FillVertex| vertex.position()),
+            )
+            .map_err(|e| anyhow!("Failed to triangulate text: {e:?}"))?;
+
+        let depth = extrude_depth.max(0.0);
+        let mut caps = vec![(0.0, [0.0, 0.0, -1.0])];
+        if depth > 0.0 {
+            caps.push((depth, [0.0, 0.0, 1.0]));
+        }
+        let mut mesh = Mesh::new(vec![], vec![]);
+        for (z, normal) in caps {
+            let first = mesh.vertex_data.len() as u32;
+            mesh.vertex_data
+                .extend(cap.vertices.iter().map(|&p| VertexData {
+                    position: world(p, z),
+                    normal,
+                }));
+            for triangle in cap.indices.chunks_exact(3) {
+                let triangle = [triangle[0], triangle[1], triangle[2]].map(|v| v + first);
+                push_facing(&mut mesh, triangle, normal);
+            }
+        }
+        if depth == 0.0 {
+            return Ok(mesh);
+        }
+
+        let mut contours: Vec<Vec<Point>> = vec![];
+        for event in path.iter().flattened(TOLERANCE) {
+            match event {
+                Event::Begin { at } => contours.push(vec![at]),
+                Event::Line { to, .. } => contours.last_mut().unwrap().push(to),
+                _ => {}
+            }
+        }
+        // TrueType outlines wind clockwise and CFF ones counter-clockwise; the sign of the
+        // total area tells which side of each edge is solid.
+        let area: f32 = contours
+            .iter()
+            .flat_map(|contour| {
+                contour
+                    .iter()
+                    .zip(contour.iter().cycle().skip(1))
+                    .map(|(a, b)| a.x * b.y - b.x * a.y)
+            })
+            .sum();
+        let outward_sign = if area < 0.0 { 1.0 } else { -1.0 };
+
+        for contour in &contours {
+            for (&a, &b) in contour.iter().zip(contour.iter().cycle().skip(1)) {
+                let edge = b - a;
+                if edge.square_length() == 0.0 {
+                    continue;
+                }
+                let outward = [-edge.y * outward_sign, edge.x * outward_sign];
+                let normal = normalize([outward[0], -outward[1], 0.0]);
+                let first = mesh.vertex_data.len() as u32;
+                mesh.vertex_data
+                    .extend([(a, 0.0), (b, 0.0), (b, depth), (a, depth)].map(|(p, z)| {
+                        VertexData {
+                            position: world(p, z),
+                            normal,
+                        }
+                    }));
+                push_facing(&mut mesh, [first, first + 1, first + 2], normal);
+                push_facing(&mut mesh, [first, first + 2, first + 3], normal);
+            }
+        }
+        Ok(mesh)
+    }
+}
@@ -1,10 +1,13 @@
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct VertexData {
     pub position: [f32; 3],
     pub normal: [f32; 3],
 }
 
+crate::vertex_layout!(VertexData { position, normal });
+
 impl VertexData {
     pub fn midpoint(a: &VertexData, b: &VertexData) -> VertexData {
         VertexData {
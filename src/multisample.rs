@@ -0,0 +1,69 @@
+use ash::vk;
+
+/// Multisampling shared by the render pass and every pipeline drawn in it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Multisampling {
+    pub samples: vk::SampleCountFlags,
+    /// Fraction of the samples shaded individually, from 0 to 1; `None` shades once per pixel.
+    pub min_sample_shading: Option<f32>,
+}
+
+impl Default for Multisampling {
+    fn default() -> Self {
+        Self {
+            samples: vk::SampleCountFlags::TYPE_1,
+            min_sample_shading: None,
+        }
+    }
+}
+
+impl Multisampling {
+    pub fn is_enabled(&self) -> bool {
+        self.samples != vk::SampleCountFlags::TYPE_1
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.samples.as_raw()
+    }
+
+    /// Lowers the request to what the device supports for colour and depth attachments
+    /// together, and drops sample shading without the `sampleRateShading` feature.
+    pub fn supported(
+        self,
+        properties: &vk::PhysicalDeviceProperties,
+        features: &vk::PhysicalDeviceFeatures,
+    ) -> Self {
+        let supported_counts = properties.limits.framebuffer_color_sample_counts
+            & properties.limits.framebuffer_depth_sample_counts
+            & properties.limits.framebuffer_stencil_sample_counts;
+        let mut samples = self.samples.as_raw().max(1);
+        while samples > 1 && !supported_counts.contains(vk::SampleCountFlags::from_raw(samples)) {
+            samples >>= 1;
+        }
+
+        Self {
+            samples: vk::SampleCountFlags::from_raw(samples),
+            min_sample_shading: self
+                .min_sample_shading
+                .filter(|_| features.sample_rate_shading == vk::TRUE && samples > 1)
+                .map(|fraction| fraction.clamp(0.0, 1.0)),
+        }
+    }
+
+    pub fn state_info(&self) -> vk::PipelineMultisampleStateCreateInfo {
+        vk::PipelineMultisampleStateCreateInfo::builder()
+            .rasterization_samples(self.samples)
+            .sample_shading_enable(self.min_sample_shading.is_some())
+            .min_sample_shading(self.min_sample_shading.unwrap_or(0.0))
+            .build()
+    }
+
+    /// [`Multisampling::state_info`] for [`crate::cutout::CutoutPass`], which turns the
+    /// alpha of cutouts into coverage when there are samples to cover.
+    pub fn cutout_state_info(&self) -> vk::PipelineMultisampleStateCreateInfo {
+        vk::PipelineMultisampleStateCreateInfo {
+            alpha_to_coverage_enable: self.is_enabled().into(),
+            ..self.state_info()
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector3;
+
+/// Seeded 2D Perlin noise.
+pub struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed: u32) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        // Fisher-Yates shuffle driven by a xorshift generator.
+        let mut state = seed.wrapping_mul(0x9e37_79b9) | 1;
+        for i in (1..256).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            table.swap(i, state as usize % (i + 1));
+        }
+
+        Self {
+            permutation: std::array::from_fn(|i| table[i % 256]),
+        }
+    }
+
+    /// Noise value in roughly [-1, 1]; 0 at every integer lattice point.
+    pub fn get(&self, x: f32, y: f32) -> f32 {
+        let (x_floor, y_floor) = (x.floor(), y.floor());
+        let (xi, yi) = (x_floor as i32 as usize & 255, y_floor as i32 as usize & 255);
+        let (xf, yf) = (x - x_floor, y - y_floor);
+        let (u, v) = (fade(xf), fade(yf));
+
+        let p = &self.permutation;
+        let corner = |dx: usize, dy: usize| p[p[xi + dx] as usize + yi + dy];
+        let a = lerp(
+            gradient(corner(0, 0), xf, yf),
+            gradient(corner(1, 0), xf - 1.0, yf),
+            u,
+        );
+        let b = lerp(
+            gradient(corner(0, 1), xf, yf - 1.0),
+            gradient(corner(1, 1), xf - 1.0, yf - 1.0),
+            u,
+        );
+        lerp(a, b, v)
+    }
+
+    /// Fractal Brownian motion: `octaves` layers of noise, each `lacunarity` times the
+    /// frequency and `persistence` times the amplitude of the previous one.
+    /// The result is normalized back to roughly [-1, 1].
+    pub fn fbm(&self, x: f32, y: f32, octaves: u32, lacunarity: f32, persistence: f32) -> f32 {
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        for octave in 0..octaves {
+            // Offset each octave so lattice zeros don't line up.
+            let offset = octave as f32 * 17.31;
+            sum += amplitude * self.get(x * frequency + offset, y * frequency + offset);
+            total_amplitude += amplitude;
+            frequency *= lacunarity;
+            amplitude *= persistence;
+        }
+        if total_amplitude > 0.0 {
+            sum / total_amplitude
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Xorshift, the same generator [`Perlin`] shuffles with, for seeded scattering.
+pub(crate) struct XorShift(u32);
+
+impl XorShift {
+    pub(crate) fn new(seed: u32) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9) | 1)
+    }
+
+    /// Uniform in [0, 1).
+    pub(crate) fn unit(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub(crate) fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.unit()
+    }
+
+    pub(crate) fn in_unit_sphere(&mut self) -> Vector3<f32> {
+        loop {
+            let point = Vector3::new(
+                self.range(-1.0, 1.0),
+                self.range(-1.0, 1.0),
+                self.range(-1.0, 1.0),
+            );
+            if point.norm_squared() <= 1.0 {
+                return point;
+            }
+        }
+    }
+
+    /// A unit vector within `angle` radians of `axis`, uniform over the spherical cap.
+    pub(crate) fn in_cone(&mut self, axis: &Vector3<f32>, angle: f32) -> Vector3<f32> {
+        let axis = axis.try_normalize(1.0e-6).unwrap_or(-Vector3::y());
+        let cos_theta = 1.0 - self.unit() * (1.0 - angle.cos());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = self.range(0.0, 2.0 * PI);
+        let helper = if axis.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        let tangent = axis.cross(&helper).normalize();
+        let bitangent = axis.cross(&tangent);
+        (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta + axis * cos_theta
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => x - y,
+        2 => -x + y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_is_zero_on_the_lattice_and_bounded_between() {
+        let perlin = Perlin::new(7);
+        for (x, y) in [(0.0, 0.0), (3.0, -5.0), (-12.0, 40.0)] {
+            assert_eq!(perlin.get(x, y), 0.0);
+        }
+        for i in 0..1000 {
+            let (x, y) = (i as f32 * 0.137 - 50.0, i as f32 * 0.291 - 80.0);
+            let value = perlin.get(x, y);
+            assert!((-1.0..=1.0).contains(&value), "{value} at ({x}, {y})");
+            assert!((-1.0..=1.0).contains(&perlin.fbm(x, y, 5, 2.0, 0.5)));
+        }
+    }
+
+    #[test]
+    fn perlin_depends_on_the_seed_alone() {
+        let sample = |perlin: &Perlin| {
+            (0..64)
+                .map(|i| perlin.get(i as f32 * 0.41, 0.7))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sample(&Perlin::new(3)), sample(&Perlin::new(3)));
+        assert_ne!(sample(&Perlin::new(3)), sample(&Perlin::new(4)));
+    }
+
+    #[test]
+    fn xorshift_stays_in_its_ranges() {
+        let mut random = XorShift::new(11);
+        let axis = Vector3::new(0.0, -1.0, 0.0);
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&random.unit()));
+            assert!(random.in_unit_sphere().norm() <= 1.0);
+            let direction = random.in_cone(&axis, 0.5);
+            assert!((direction.norm() - 1.0).abs() < 1e-4);
+            assert!(direction.dot(&axis) >= 0.5f32.cos() - 1e-4);
+        }
+    }
+}
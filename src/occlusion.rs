@@ -0,0 +1,998 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+use nalgebra::Matrix4;
+
+use crate::allocations;
+use crate::buffer::Buffer;
+use crate::camera::Camera;
+use crate::conditional_rendering::ConditionalRendering;
+use crate::find_memorytype_index;
+use crate::format_has_stencil;
+use crate::model::{Instance, Mesh, Model, VertexData};
+use crate::pipeline::Topology;
+use crate::swapchain::Swapchain;
+
+/// Invocations per workgroup of `shaders/hiz_downsample.comp`, along each axis.
+const DOWNSAMPLE_WORKGROUP_SIZE: u32 = 8;
+/// Invocations per workgroup of `shaders/hiz_cull.comp`.
+const CULL_WORKGROUP_SIZE: u32 = 64;
+
+/// `Cull` in `shaders/hiz_cull.comp`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CullUniforms {
+    view_projection: [[f32; 4]; 4],
+    previous_view_projection: [[f32; 4]; 4],
+    pyramid: [f32; 4],
+    viewport: [f32; 4],
+}
+
+/// `Bounds` in `shaders/hiz_cull.comp`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CullBounds {
+    aabb_min: [f32; 3],
+    count: u32,
+    aabb_max: [f32; 3],
+}
+
+/// Bytes from the start of [`CulledModel::command`] to its draw count.
+const DRAW_COUNT_OFFSET: usize = std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+
+/// The instances of one model that survived culling, and the indirect draw of them.
+pub struct CulledModel {
+    pub visible: Buffer,
+    /// A `VkDrawIndexedIndirectCommand` whose instance count the culling pass fills in,
+    /// followed by a draw count it sets to 1 once any instance survives.
+    pub command: Buffer,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// The model's instance buffer the descriptor set reads.
+    source: vk::Buffer,
+    capacity: usize,
+    count: u32,
+    index_count: u32,
+    bounds: CullBounds,
+}
+
+/// Culls the instances of triangle-list models on the GPU before they are drawn, against
+/// the frustum and against a hierarchical depth pyramid: each level holds the farthest
+/// depth of the texels below it, so an instance whose bounds are nearer than nothing in
+/// the texels it covers is hidden. The pyramid is built from the depth buffer at the end
+/// of each frame and tested against in the next with that frame's camera, so hidden
+/// geometry appears a frame late when it comes into view. Survivors are compacted into
+/// a buffer of their own and drawn with `vkCmdDrawIndexedIndirect`, or with
+/// `vkCmdDrawIndexedIndirectCount` where available, so models with every instance culled
+/// issue no draw at all. Failing that, the draw is predicated on the draw count through
+/// [`ConditionalRendering`] where that is available.
+pub struct OcclusionCulling {
+    pub pyramid: vk::Image,
+    pub pyramid_memory: vk::DeviceMemory,
+    /// Every level, for sampling.
+    pub pyramid_view: vk::ImageView,
+    /// One view per level, for writing.
+    pub level_views: Vec<vk::ImageView>,
+    pub pyramid_extent: vk::Extent2D,
+    depth_image: vk::Image,
+    depth_view: vk::ImageView,
+    depth_format: vk::Format,
+    depth_extent: vk::Extent2D,
+    sampler: vk::Sampler,
+    downsample_pipeline: vk::Pipeline,
+    downsample_layout: vk::PipelineLayout,
+    downsample_set_layout: vk::DescriptorSetLayout,
+    downsample_pool: vk::DescriptorPool,
+    downsample_sets: Vec<vk::DescriptorSet>,
+    cull_pipeline: vk::Pipeline,
+    cull_layout: vk::PipelineLayout,
+    cull_set_layout: vk::DescriptorSetLayout,
+    uniforms: Buffer,
+    /// By index into the engine's models; `None` for models that are drawn as usual.
+    pub models: Vec<Option<CulledModel>>,
+    /// Present when the device has `VK_KHR_draw_indirect_count`.
+    pub draw_indirect_count: Option<ash::extensions::khr::DrawIndirectCount>,
+    /// Present when the device has `VK_EXT_conditional_rendering`; the draw counts can
+    /// then predicate draws, see [`OcclusionCulling::draw_predicate`].
+    pub conditional_rendering: Option<ConditionalRendering>,
+    instance_stride: usize,
+    view_projection: Matrix4<f32>,
+    /// The pyramid holds a frame's depth.
+    ready: bool,
+}
+
+impl OcclusionCulling {
+    /// Draws with the indirect count when given `draw_indirect_count`, otherwise
+    /// predicated on it when given `conditional_rendering`.
+    pub fn init<I: Instance>(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        swapchain: &Swapchain,
+        samples: vk::SampleCountFlags,
+        draw_indirect_count: Option<ash::extensions::khr::DrawIndirectCount>,
+        conditional_rendering: Option<ConditionalRendering>,
+    ) -> Result<Self> {
+        if samples != vk::SampleCountFlags::TYPE_1 {
+            bail!("Occlusion culling reads the depth buffer, which needs multisampling off.");
+        }
+        let instance_stride = std::mem::size_of::<I>();
+        if !instance_stride.is_multiple_of(4) {
+            bail!(
+                "Instances of {} bytes cannot be read from a storage buffer as floats.",
+                instance_stride
+            );
+        }
+
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .min_lod(0.0)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        /* Downsampling */
+        let downsample_bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build(),
+        ];
+        let downsample_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&downsample_bindings),
+                None,
+            )
+        }?;
+        let (downsample_pipeline, downsample_layout) = init_compute_pipeline(
+            logical_device,
+            vk_shader_macros::include_glsl!("shaders/hiz_downsample.comp", kind: comp),
+            downsample_set_layout,
+            16,
+            None,
+        )?;
+
+        /* Culling */
+        let cull_bindings = [
+            (0, vk::DescriptorType::STORAGE_BUFFER),
+            (1, vk::DescriptorType::STORAGE_BUFFER),
+            (2, vk::DescriptorType::STORAGE_BUFFER),
+            (3, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (4, vk::DescriptorType::UNIFORM_BUFFER),
+        ]
+        .map(|(binding, descriptor_type)| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(descriptor_type)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        });
+        let cull_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&cull_bindings),
+                None,
+            )
+        }?;
+        let specialization_data = ((instance_stride / 4) as u32).to_ne_bytes();
+        let (cull_pipeline, cull_layout) = init_compute_pipeline(
+            logical_device,
+            vk_shader_macros::include_glsl!("shaders/hiz_cull.comp", kind: comp),
+            cull_set_layout,
+            std::mem::size_of::<CullBounds>() as u32,
+            Some(&specialization_data),
+        )?;
+
+        let uniforms = Buffer::init(
+            std::mem::size_of::<CullUniforms>(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        uniforms.set_name(logical_device, "occlusion culling uniforms");
+
+        let mut occlusion_culling = OcclusionCulling {
+            pyramid: vk::Image::null(),
+            pyramid_memory: vk::DeviceMemory::null(),
+            pyramid_view: vk::ImageView::null(),
+            level_views: vec![],
+            pyramid_extent: vk::Extent2D::default(),
+            depth_image: swapchain.depth_image,
+            depth_view: vk::ImageView::null(),
+            depth_format: swapchain.depth_format,
+            depth_extent: swapchain.extent,
+            sampler,
+            downsample_pipeline,
+            downsample_layout,
+            downsample_set_layout,
+            downsample_pool: vk::DescriptorPool::null(),
+            downsample_sets: vec![],
+            cull_pipeline,
+            cull_layout,
+            cull_set_layout,
+            uniforms,
+            models: vec![],
+            draw_indirect_count,
+            conditional_rendering,
+            instance_stride,
+            view_projection: Matrix4::identity(),
+            ready: false,
+        };
+        occlusion_culling.init_pyramid(logical_device, memory_properties, swapchain)?;
+        Ok(occlusion_culling)
+    }
+
+    /// Rebuilds the pyramid for a new swapchain; culling tests only the frustum until it
+    /// holds a frame again.
+    pub fn resize(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        swapchain: &Swapchain,
+    ) -> Result<()> {
+        unsafe { self.cleanup_pyramid(logical_device) };
+        self.init_pyramid(logical_device, memory_properties, swapchain)
+    }
+
+    fn init_pyramid(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        swapchain: &Swapchain,
+    ) -> Result<()> {
+        // Half the depth buffer's resolution, halving down to a single texel.
+        let extent = vk::Extent2D {
+            width: swapchain.extent.width.div_ceil(2).max(1),
+            height: swapchain.extent.height.div_ceil(2).max(1),
+        };
+        let levels = 32 - extent.width.max(extent.height).leading_zeros();
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(vk::Format::R32_SFLOAT)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(levels)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let pyramid = unsafe { logical_device.create_image(&image_info, None) }?;
+        let memory_requirements = unsafe { logical_device.get_image_memory_requirements(pyramid) };
+        allocations::track(
+            logical_device,
+            pyramid,
+            memory_requirements.size,
+            "depth pyramid",
+        );
+        let memory_index = find_memorytype_index(
+            &memory_requirements,
+            &memory_properties,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+        .expect("Unable to find suitable memory index for the depth pyramid.");
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(memory_index);
+        let pyramid_memory = unsafe { logical_device.allocate_memory(&allocate_info, None) }?;
+        allocations::track(
+            logical_device,
+            pyramid_memory,
+            memory_requirements.size,
+            "memory of the depth pyramid",
+        );
+        unsafe { logical_device.bind_image_memory(pyramid, pyramid_memory, 0) }?;
+
+        let view = |base_mip_level, level_count| {
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .image(pyramid)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(vk::Format::R32_SFLOAT)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level,
+                    level_count,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+            unsafe { logical_device.create_image_view(&view_info, None) }
+        };
+        let pyramid_view = view(0, levels)?;
+        let level_views = (0..levels)
+            .map(|level| view(level, 1))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let depth_view_info = vk::ImageViewCreateInfo::builder()
+            .image(swapchain.depth_image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(swapchain.depth_format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        let depth_view = unsafe { logical_device.create_image_view(&depth_view_info, None) }?;
+
+        /* One set per level, reading the level above it or the depth buffer */
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: levels,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_IMAGE,
+                descriptor_count: levels,
+            },
+        ];
+        let downsample_pool = unsafe {
+            logical_device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::builder()
+                    .max_sets(levels)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }?;
+        let set_layouts = vec![self.downsample_set_layout; levels as usize];
+        let downsample_sets = unsafe {
+            logical_device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::builder()
+                    .descriptor_pool(downsample_pool)
+                    .set_layouts(&set_layouts),
+            )
+        }?;
+        for (level, set) in downsample_sets.iter().enumerate() {
+            let source_info = [if level == 0 {
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: depth_view,
+                    image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                }
+            } else {
+                vk::DescriptorImageInfo {
+                    sampler: self.sampler,
+                    image_view: level_views[level - 1],
+                    image_layout: vk::ImageLayout::GENERAL,
+                }
+            }];
+            let destination_info = [vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: level_views[level],
+                image_layout: vk::ImageLayout::GENERAL,
+            }];
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&source_info)
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(*set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                    .image_info(&destination_info)
+                    .build(),
+            ];
+            unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        self.pyramid = pyramid;
+        self.pyramid_memory = pyramid_memory;
+        self.pyramid_view = pyramid_view;
+        self.level_views = level_views;
+        self.pyramid_extent = extent;
+        self.depth_image = swapchain.depth_image;
+        self.depth_view = depth_view;
+        self.depth_format = swapchain.depth_format;
+        self.depth_extent = swapchain.extent;
+        self.downsample_pool = downsample_pool;
+        self.downsample_sets = downsample_sets;
+        self.ready = false;
+        // The culling sets read the old pyramid view.
+        for culled in self.models.iter_mut().filter_map(Option::take) {
+            culled.cleanup(logical_device);
+        }
+        Ok(())
+    }
+
+    /// Writes the cameras to cull with and makes sure every triangle-list model in
+    /// `models` has its culling resources, rebuilding them where its instance buffer
+    /// has changed or outgrown them.
+    pub fn update<I: Instance>(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        camera: &Camera,
+        viewport: vk::Rect2D,
+        models: &[Model<VertexData, I>],
+    ) -> Result<()> {
+        let view_projection = camera.projection_matrix * camera.view_matrix;
+        let (width, height) = (
+            self.depth_extent.width as f32,
+            self.depth_extent.height as f32,
+        );
+        let uniforms = CullUniforms {
+            view_projection: view_projection.into(),
+            previous_view_projection: self.view_projection.into(),
+            pyramid: [
+                self.pyramid_extent.width as f32,
+                self.pyramid_extent.height as f32,
+                self.level_views.len() as f32,
+                if self.ready { 1.0 } else { 0.0 },
+            ],
+            viewport: [
+                viewport.offset.x as f32 / width,
+                viewport.offset.y as f32 / height,
+                viewport.extent.width as f32 / width,
+                viewport.extent.height as f32 / height,
+            ],
+        };
+        self.uniforms
+            .fill(logical_device, &[uniforms], memory_properties)?;
+        self.view_projection = view_projection;
+        // The frame being recorded builds the pyramid the next one tests against.
+        self.ready = true;
+
+        self.models.resize_with(models.len(), || None);
+        for (index, model) in models.iter().enumerate() {
+            let source = model
+                .instances
+                .buffer
+                .as_ref()
+                .filter(|_| model.mesh.topology == Topology::TriangleList);
+            let (Some(source), Some(aabb)) = (source, model.mesh.aabb()) else {
+                if let Some(culled) = self.models[index].take() {
+                    unsafe { logical_device.device_wait_idle() }?;
+                    culled.cleanup(logical_device);
+                }
+                continue;
+            };
+            let count = model.instances.first_invisible;
+            let current = self.models[index]
+                .as_ref()
+                .is_some_and(|culled| culled.source == source.buffer && culled.capacity >= count);
+            if !current {
+                if let Some(culled) = self.models[index].take() {
+                    unsafe { logical_device.device_wait_idle() }?;
+                    culled.cleanup(logical_device);
+                }
+                self.models[index] = Some(self.init_culled_model(
+                    logical_device,
+                    memory_properties,
+                    source,
+                    count.max(1),
+                )?);
+            }
+            let culled = self.models[index].as_mut().unwrap();
+            culled.count = count as u32;
+            culled.index_count = model.mesh.index_data.len() as u32;
+            culled.bounds = CullBounds {
+                aabb_min: aabb.min.into(),
+                count: count as u32,
+                aabb_max: aabb.max.into(),
+            };
+        }
+        Ok(())
+    }
+
+    fn init_culled_model(
+        &self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        source: &Buffer,
+        capacity: usize,
+    ) -> Result<CulledModel> {
+        let visible = Buffer::init(
+            capacity * self.instance_stride,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::VERTEX_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        let predicate_usage = if self.conditional_rendering.is_some() {
+            vk::BufferUsageFlags::CONDITIONAL_RENDERING_EXT
+        } else {
+            vk::BufferUsageFlags::empty()
+        };
+        let command = Buffer::init(
+            DRAW_COUNT_OFFSET + 4,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST
+                | predicate_usage,
+            memory_properties,
+            logical_device,
+        )?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 3,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+            },
+        ];
+        let descriptor_pool = unsafe {
+            logical_device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::builder()
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }?;
+        let set_layouts = [self.cull_set_layout];
+        let descriptor_set = unsafe {
+            logical_device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::builder()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&set_layouts),
+            )
+        }?[0];
+
+        let buffer_infos = [source, &visible, &command, &self.uniforms].map(|buffer| {
+            [vk::DescriptorBufferInfo {
+                buffer: buffer.buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }]
+        });
+        let pyramid_info = [vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.pyramid_view,
+            image_layout: vk::ImageLayout::GENERAL,
+        }];
+        let buffer_write = |binding: u32, descriptor_type, info: &[vk::DescriptorBufferInfo]| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding)
+                .descriptor_type(descriptor_type)
+                .buffer_info(info)
+                .build()
+        };
+        let writes = [
+            buffer_write(0, vk::DescriptorType::STORAGE_BUFFER, &buffer_infos[0]),
+            buffer_write(1, vk::DescriptorType::STORAGE_BUFFER, &buffer_infos[1]),
+            buffer_write(2, vk::DescriptorType::STORAGE_BUFFER, &buffer_infos[2]),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(3)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&pyramid_info)
+                .build(),
+            buffer_write(4, vk::DescriptorType::UNIFORM_BUFFER, &buffer_infos[3]),
+        ];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+
+        Ok(CulledModel {
+            visible,
+            command,
+            descriptor_pool,
+            descriptor_set,
+            source: source.buffer,
+            capacity,
+            count: 0,
+            index_count: 0,
+            bounds: CullBounds {
+                aabb_min: [0.0; 3],
+                count: 0,
+                aabb_max: [0.0; 3],
+            },
+        })
+    }
+
+    /// Culls every model's instances into its visible buffer and indirect command.
+    ///# Safety
+    ///
+    /// Must be recorded outside a render pass, before the draws it feeds.
+    pub unsafe fn cull(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let culled_models: Vec<&CulledModel> = self.models.iter().flatten().collect();
+        if culled_models.is_empty() {
+            return;
+        }
+        for culled in &culled_models {
+            // The command's fields, all zero but the index count, then the draw count.
+            let command: [u32; 6] = [culled.index_count, 0, 0, 0, 0, 0];
+            logical_device.cmd_update_buffer(
+                command_buffer,
+                culled.command.buffer,
+                0,
+                std::slice::from_raw_parts(
+                    command.as_ptr() as *const u8,
+                    std::mem::size_of_val(&command),
+                ),
+            );
+        }
+        // The resets, and the previous frame's pyramid and draws, come first.
+        let before = vk::MemoryBarrier::builder()
+            .src_access_mask(
+                vk::AccessFlags::TRANSFER_WRITE
+                    | vk::AccessFlags::SHADER_WRITE
+                    | vk::AccessFlags::INDIRECT_COMMAND_READ
+                    | vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            )
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER
+                | vk::PipelineStageFlags::COMPUTE_SHADER
+                | vk::PipelineStageFlags::DRAW_INDIRECT
+                | vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[before.build()],
+            &[],
+            &[],
+        );
+
+        logical_device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.cull_pipeline,
+        );
+        for culled in &culled_models {
+            if culled.count == 0 {
+                continue;
+            }
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.cull_layout,
+                0,
+                &[culled.descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.cull_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    &culled.bounds as *const CullBounds as *const u8,
+                    std::mem::size_of::<CullBounds>(),
+                ),
+            );
+            logical_device.cmd_dispatch(
+                command_buffer,
+                culled.count.div_ceil(CULL_WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+
+        let (predicate_access, predicate_stage) = if self.conditional_rendering.is_some() {
+            (
+                vk::AccessFlags::CONDITIONAL_RENDERING_READ_EXT,
+                vk::PipelineStageFlags::CONDITIONAL_RENDERING_EXT,
+            )
+        } else {
+            (vk::AccessFlags::empty(), vk::PipelineStageFlags::empty())
+        };
+        let after = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::INDIRECT_COMMAND_READ
+                    | vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | predicate_access,
+            );
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::DRAW_INDIRECT
+                | vk::PipelineStageFlags::VERTEX_INPUT
+                | predicate_stage,
+            vk::DependencyFlags::empty(),
+            &[after.build()],
+            &[],
+            &[],
+        );
+    }
+
+    /// Draws the instances of model `index` that survived [`OcclusionCulling::cull`] with
+    /// the bound pipeline. Returns false, drawing nothing, for models it does not cull.
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+        mesh: &Mesh<VertexData>,
+    ) -> bool {
+        let Some(Some(culled)) = self.models.get(index) else {
+            return false;
+        };
+        let draw = || {
+            mesh.draw_indexed_indirect(
+                logical_device,
+                command_buffer,
+                culled.visible.buffer,
+                culled.command.buffer,
+            )
+        };
+        match (&self.draw_indirect_count, &self.conditional_rendering) {
+            (Some(draw_indirect_count), _) => mesh.draw_indexed_indirect_count(
+                logical_device,
+                draw_indirect_count,
+                command_buffer,
+                culled.visible.buffer,
+                culled.command.buffer,
+                culled.command.buffer,
+                DRAW_COUNT_OFFSET as vk::DeviceSize,
+                1,
+            ),
+            (None, Some(conditional_rendering)) => unsafe {
+                conditional_rendering.predicated(
+                    command_buffer,
+                    culled.command.buffer,
+                    DRAW_COUNT_OFFSET as vk::DeviceSize,
+                    false,
+                    draw,
+                )
+            },
+            (None, None) => draw(),
+        }
+        true
+    }
+
+    /// Where model `index`'s draw count lies once [`OcclusionCulling::cull`] has run: 1
+    /// when any of its instances survived, 0 when none did. With conditional rendering
+    /// enabled, work that only matters for visible models, such as their decals or
+    /// effects, can be predicated on it through [`ConditionalRendering::begin`].
+    pub fn draw_predicate(&self, index: usize) -> Option<(vk::Buffer, vk::DeviceSize)> {
+        let culled = self.models.get(index)?.as_ref()?;
+        self.conditional_rendering
+            .as_ref()
+            .map(|_| (culled.command.buffer, DRAW_COUNT_OFFSET as vk::DeviceSize))
+    }
+
+    /// Downsamples the frame's depth buffer into the pyramid the next frame culls with.
+    ///# Safety
+    ///
+    /// Must be recorded outside a render pass, after the last one writing depth, with the
+    /// depth buffer in `DEPTH_STENCIL_ATTACHMENT_OPTIMAL`, which it is left in.
+    pub unsafe fn build_pyramid(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let depth_range = vk::ImageSubresourceRange {
+            aspect_mask: if format_has_stencil(self.depth_format) {
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+            } else {
+                vk::ImageAspectFlags::DEPTH
+            },
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let pyramid_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: vk::REMAINING_MIP_LEVELS,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let to_read = [
+            vk::ImageMemoryBarrier::builder()
+                .image(self.depth_image)
+                .old_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .new_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(depth_range)
+                .build(),
+            // The previous contents were culled with already; this frame's cull has run.
+            vk::ImageMemoryBarrier::builder()
+                .image(self.pyramid)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_access_mask(vk::AccessFlags::SHADER_READ)
+                .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(pyramid_range)
+                .build(),
+        ];
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::FRAGMENT_SHADER
+                | vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &to_read,
+        );
+
+        logical_device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.downsample_pipeline,
+        );
+        let mut source = self.depth_extent;
+        for (level, set) in self.downsample_sets.iter().enumerate() {
+            let destination = vk::Extent2D {
+                width: (self.pyramid_extent.width >> level).max(1),
+                height: (self.pyramid_extent.height >> level).max(1),
+            };
+            let sizes = [
+                source.width,
+                source.height,
+                destination.width,
+                destination.height,
+            ];
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.downsample_layout,
+                0,
+                &[*set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.downsample_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    sizes.as_ptr() as *const u8,
+                    std::mem::size_of_val(&sizes),
+                ),
+            );
+            logical_device.cmd_dispatch(
+                command_buffer,
+                destination.width.div_ceil(DOWNSAMPLE_WORKGROUP_SIZE),
+                destination.height.div_ceil(DOWNSAMPLE_WORKGROUP_SIZE),
+                1,
+            );
+            // The next level reads this one.
+            let written = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ);
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[written.build()],
+                &[],
+                &[],
+            );
+            source = destination;
+        }
+
+        let to_attachment = vk::ImageMemoryBarrier::builder()
+            .image(self.depth_image)
+            .old_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(depth_range)
+            .build();
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_attachment],
+        );
+    }
+
+    unsafe fn cleanup_pyramid(&self, logical_device: &ash::Device) {
+        logical_device.destroy_descriptor_pool(self.downsample_pool, None);
+        logical_device.destroy_image_view(self.depth_view, None);
+        for view in &self.level_views {
+            logical_device.destroy_image_view(*view, None);
+        }
+        logical_device.destroy_image_view(self.pyramid_view, None);
+        allocations::untrack(logical_device, self.pyramid);
+        allocations::untrack(logical_device, self.pyramid_memory);
+        logical_device.destroy_image(self.pyramid, None);
+        logical_device.free_memory(self.pyramid_memory, None);
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        for culled in self.models.iter().flatten() {
+            culled.cleanup(logical_device);
+        }
+        self.uniforms.cleanup(logical_device);
+        unsafe {
+            self.cleanup_pyramid(logical_device);
+            logical_device.destroy_pipeline(self.cull_pipeline, None);
+            logical_device.destroy_pipeline_layout(self.cull_layout, None);
+            logical_device.destroy_descriptor_set_layout(self.cull_set_layout, None);
+            logical_device.destroy_pipeline(self.downsample_pipeline, None);
+            logical_device.destroy_pipeline_layout(self.downsample_layout, None);
+            logical_device.destroy_descriptor_set_layout(self.downsample_set_layout, None);
+            logical_device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+impl CulledModel {
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        self.visible.cleanup(logical_device);
+        self.command.cleanup(logical_device);
+        unsafe { logical_device.destroy_descriptor_pool(self.descriptor_pool, None) };
+    }
+}
+
+/// A compute pipeline running `code` with one descriptor set and `push_constant_size`
+/// bytes of push constants, its constant 0 specialized to `specialization` when given.
+pub(crate) fn init_compute_pipeline(
+    logical_device: &ash::Device,
+    code: &[u32],
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    push_constant_size: u32,
+    specialization: Option<&[u8]>,
+) -> Result<(vk::Pipeline, vk::PipelineLayout)> {
+    let compute_info = vk::ShaderModuleCreateInfo::builder().code(code);
+    let compute_module = unsafe { logical_device.create_shader_module(&compute_info, None) }?;
+    let main_function_name = std::ffi::CString::new("main").unwrap();
+    let specialization_entries = [vk::SpecializationMapEntry {
+        constant_id: 0,
+        offset: 0,
+        size: std::mem::size_of::<u32>(),
+    }];
+    let specialization_info = vk::SpecializationInfo::builder()
+        .map_entries(&specialization_entries)
+        .data(specialization.unwrap_or(&[]));
+    let mut stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(compute_module)
+        .name(&main_function_name);
+    if specialization.is_some() {
+        stage = stage.specialization_info(&specialization_info);
+    }
+
+    let set_layouts = [descriptor_set_layout];
+    let push_constant_ranges = [vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(push_constant_size)
+        .build()];
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(&set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+    let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+    let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage.build())
+        .layout(layout);
+    let pipeline = unsafe {
+        logical_device
+            .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)?
+    }[0];
+    unsafe { logical_device.destroy_shader_module(compute_module, None) };
+    Ok((pipeline, layout))
+}
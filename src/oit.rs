@@ -0,0 +1,359 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::model::{Instance, Model, VertexData};
+use crate::multisample::Multisampling;
+use crate::pipeline::{Pipeline, Topology};
+use crate::swapchain::Swapchain;
+
+pub const ACCUMULATION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+pub const REVEALAGE_FORMAT: vk::Format = vk::Format::R16_SFLOAT;
+
+/// Weighted-blended order-independent transparency (McGuire and Bavoil).
+/// Translucent instances accumulate premultiplied, depth-weighted colour and revealage
+/// in subpass 1 without any sorting; subpass 2 resolves them over the opaque image.
+pub struct WeightedBlendedOit {
+    pub accumulation_pipeline: vk::Pipeline,
+    pub composite_pipeline: vk::Pipeline,
+    pub composite_layout: vk::PipelineLayout,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl WeightedBlendedOit {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+        swapchain: &Swapchain,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let composite_vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/oit_composite.vert", kind: vert));
+        let composite_vertex_module =
+            unsafe { logical_device.create_shader_module(&composite_vertex_info, None) }?;
+
+        let composite_fragment_code: &[u32] = if pipeline.multisampling.is_enabled() {
+            vk_shader_macros::include_glsl!("shaders/oit_composite_ms.frag", kind: frag)
+        } else {
+            vk_shader_macros::include_glsl!("shaders/oit_composite.frag", kind: frag)
+        };
+        let composite_fragment_info =
+            vk::ShaderModuleCreateInfo::builder().code(composite_fragment_code);
+        let composite_fragment_module =
+            unsafe { logical_device.create_shader_module(&composite_fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let enabled = vk::TRUE.to_ne_bytes();
+        let vertex_specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<vk::Bool32>(),
+        }];
+        let vertex_specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&vertex_specialization_entries)
+            .data(&enabled);
+        let fragment_specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 1,
+            offset: 0,
+            size: std::mem::size_of::<vk::Bool32>(),
+        }];
+        let fragment_specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&fragment_specialization_entries)
+            .data(&enabled);
+        let sample_count = (pipeline.multisampling.sample_count() as i32).to_ne_bytes();
+        let composite_specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<i32>(),
+        }];
+        let composite_specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&composite_specialization_entries)
+            .data(&sample_count);
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .specialization_info(&vertex_specialization_info)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .specialization_info(&fragment_specialization_info)
+                .build(),
+        ];
+        let composite_shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(composite_vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(composite_fragment_module)
+                .name(&main_function_name)
+                .specialization_info(&composite_specialization_info)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = pipeline.vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = pipeline.multisampling.state_info();
+
+        let all_components = vk::ColorComponentFlags::R
+            | vk::ColorComponentFlags::G
+            | vk::ColorComponentFlags::B
+            | vk::ColorComponentFlags::A;
+
+        /* Accumulation: colour adds up, revealage multiplies by (1 - alpha) */
+        let accumulation_blend_attachments = [
+            vk::PipelineColorBlendAttachmentState::builder()
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::ONE)
+                .dst_color_blend_factor(vk::BlendFactor::ONE)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+                .alpha_blend_op(vk::BlendOp::ADD)
+                .color_write_mask(all_components)
+                .build(),
+            vk::PipelineColorBlendAttachmentState::builder()
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::ZERO)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_COLOR)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+                .alpha_blend_op(vk::BlendOp::ADD)
+                .color_write_mask(vk::ColorComponentFlags::R)
+                .build(),
+        ];
+        let accumulation_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&accumulation_blend_attachments);
+        let accumulation_depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Composite: the average colour over the opaque image by the total coverage */
+        let composite_vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+        let composite_multisampler_info = Multisampling {
+            min_sample_shading: None,
+            ..pipeline.multisampling
+        }
+        .state_info();
+        let composite_rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let composite_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(all_components)
+            .build()];
+        let composite_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&composite_blend_attachments);
+        let composite_depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        /* Descriptors */
+        let descriptor_set_layout_bindings = [0, 1].map(|binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        });
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 2,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let composite_layout_info =
+            vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+        let composite_layout =
+            unsafe { logical_device.create_pipeline_layout(&composite_layout_info, None) }?;
+
+        /* Pipelines */
+        let pipeline_infos = [
+            vk::GraphicsPipelineCreateInfo::builder()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&accumulation_depth_stencil_info)
+                .color_blend_state(&accumulation_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline.layout)
+                .render_pass(*renderpass)
+                .subpass(1)
+                .build(),
+            vk::GraphicsPipelineCreateInfo::builder()
+                .stages(&composite_shader_stages)
+                .vertex_input_state(&composite_vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&composite_rasterizer_info)
+                .multisample_state(&composite_multisampler_info)
+                .depth_stencil_state(&composite_depth_stencil_info)
+                .color_blend_state(&composite_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(composite_layout)
+                .render_pass(*renderpass)
+                .subpass(2)
+                .build(),
+        ];
+        let pipelines = unsafe {
+            logical_device
+                .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_infos, None)
+                .map_err(|(_, e)| e)?
+        };
+
+        unsafe {
+            logical_device.destroy_shader_module(composite_fragment_module, None);
+            logical_device.destroy_shader_module(composite_vertex_module, None);
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        let oit = WeightedBlendedOit {
+            accumulation_pipeline: pipelines[0],
+            composite_pipeline: pipelines[1],
+            composite_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+        };
+        oit.update_descriptor_set(logical_device, swapchain);
+
+        Ok(oit)
+    }
+
+    /// Points the composite at the swapchain's current targets; call after recreating it.
+    pub fn update_descriptor_set(&self, logical_device: &ash::Device, swapchain: &Swapchain) {
+        let accumulation_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: swapchain.accumulation.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let revealage_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: swapchain.revealage.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let writes = [
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                .image_info(&accumulation_info)
+                .build(),
+            vk::WriteDescriptorSet::builder()
+                .dst_set(self.descriptor_set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                .image_info(&revealage_info)
+                .build(),
+        ];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Records subpass 1; expects the main pipeline's descriptor sets to be bound already.
+    pub fn draw_accumulation<I: Instance>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        models: &[Model<VertexData, I>],
+    ) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.accumulation_pipeline,
+            );
+        }
+        models
+            .iter()
+            .filter(|m| m.mesh.topology == Topology::TriangleList)
+            .for_each(|m| m.draw(logical_device, command_buffer));
+    }
+
+    /// Records subpass 2.
+    pub fn draw_composite(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.composite_pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.composite_layout,
+                0,
+                &[self.descriptor_set],
+                &[],
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.accumulation_pipeline, None);
+            logical_device.destroy_pipeline(self.composite_pipeline, None);
+            logical_device.destroy_pipeline_layout(self.composite_layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::model::{Instance, Model, VertexData};
+use crate::multisample::Multisampling;
+use crate::pipeline::Topology;
+use crate::vertex_layout::VertexInput;
+
+/// Highlights selected instances with a silhouette outline drawn on top of the scene.
+/// The selected instances are first marked in the stencil buffer, then drawn again
+/// dilated along their normals wherever the stencil is not marked.
+pub struct Outline {
+    pub mark_pipeline: vk::Pipeline,
+    pub outline_pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub colour: [f32; 4],
+    /// Outline thickness in pixels.
+    pub width: f32,
+}
+
+impl Outline {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        multisampling: &Multisampling,
+        vertex_input: &VertexInput,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/outline.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/outline.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = multisampling.state_info();
+
+        /* The mark pass only touches the stencil buffer */
+        let mark_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(vk::ColorComponentFlags::empty())
+            .build()];
+        let mark_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&mark_blend_attachments);
+        let mark_stencil = vk::StencilOpState {
+            fail_op: vk::StencilOp::REPLACE,
+            pass_op: vk::StencilOp::REPLACE,
+            depth_fail_op: vk::StencilOp::REPLACE,
+            compare_op: vk::CompareOp::ALWAYS,
+            compare_mask: 0xff,
+            write_mask: 0xff,
+            reference: 1,
+        };
+        let mark_depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false)
+            .stencil_test_enable(true)
+            .front(mark_stencil)
+            .back(mark_stencil);
+
+        /* The outline pass draws wherever the mark pass did not */
+        let outline_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let outline_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
+            .attachments(&outline_blend_attachments);
+        let outline_stencil = vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::KEEP,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op: vk::CompareOp::NOT_EQUAL,
+            compare_mask: 0xff,
+            write_mask: 0,
+            reference: 1,
+        };
+        let outline_depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(false)
+            .depth_write_enable(false)
+            .stencil_test_enable(true)
+            .front(outline_stencil)
+            .back(outline_stencil);
+
+        /* Pipelines */
+        let descriptor_layouts = [descriptor_set_layout];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: 32,
+        }];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&descriptor_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_infos = [
+            vk::GraphicsPipelineCreateInfo::builder()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&mark_depth_stencil_info)
+                .color_blend_state(&mark_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(layout)
+                .render_pass(*renderpass)
+                .subpass(0)
+                .build(),
+            vk::GraphicsPipelineCreateInfo::builder()
+                .stages(&shader_stages)
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info)
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&outline_depth_stencil_info)
+                .color_blend_state(&outline_blend_info)
+                .dynamic_state(&dynamic_state_info)
+                .layout(layout)
+                .render_pass(*renderpass)
+                .subpass(0)
+                .build(),
+        ];
+        let pipelines = unsafe {
+            logical_device
+                .create_graphics_pipelines(vk::PipelineCache::null(), &pipeline_infos, None)
+                .map_err(|(_, e)| e)?
+        };
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(Outline {
+            mark_pipeline: pipelines[0],
+            outline_pipeline: pipelines[1],
+            layout,
+            colour: [1.0, 0.6, 0.1, 1.0],
+            width: 3.0,
+        })
+    }
+
+    /// Outlines `selected`, given as (index into `models`, instance handle) pairs.
+    pub fn draw<I: Instance>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        extent: vk::Extent2D,
+        models: &[Model<VertexData, I>],
+        selected: &[(usize, usize)],
+    ) {
+        let draw_selected = |pipeline: vk::Pipeline, width: f32| {
+            let parameters = [
+                self.colour[0],
+                self.colour[1],
+                self.colour[2],
+                self.colour[3],
+                width,
+                extent.width as f32,
+                extent.height as f32,
+                0.0,
+            ];
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    parameters.as_ptr() as *const u8,
+                    std::mem::size_of_val(&parameters),
+                )
+            };
+            unsafe {
+                logical_device.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.layout,
+                    0,
+                    &[descriptor_set],
+                    &[],
+                );
+                logical_device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    bytes,
+                );
+            }
+            for (model, handle) in selected {
+                if let Some(model) = models
+                    .get(*model)
+                    .filter(|m| m.mesh.topology == Topology::TriangleList)
+                {
+                    model.draw_instance(logical_device, command_buffer, *handle);
+                }
+            }
+        };
+
+        draw_selected(self.mark_pipeline, 0.0);
+        draw_selected(self.outline_pipeline, self.width);
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.mark_pipeline, None);
+            logical_device.destroy_pipeline(self.outline_pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
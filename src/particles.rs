@@ -0,0 +1,345 @@
+use std::f32::consts::PI;
+
+use nalgebra::{Matrix4, Vector3};
+
+use crate::model::{InstanceData, InstanceSet};
+use crate::noise::XorShift;
+
+/// Where new particles appear, relative to the emitter's position.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EmitterShape {
+    Point,
+    /// Anywhere inside the sphere.
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_extents: Vector3<f32>,
+    },
+    /// On a disc of `radius` in the xz plane, opening `angle` radians around up (-y).
+    Cone {
+        radius: f32,
+        angle: f32,
+    },
+}
+
+/// How fast, and in which direction, new particles start moving.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VelocityDistribution {
+    /// Around `direction`, deviating by up to `spread` radians.
+    Directional {
+        direction: Vector3<f32>,
+        spread: f32,
+        min_speed: f32,
+        max_speed: f32,
+    },
+    /// Away from the emitter's position; with a [`EmitterShape::Cone`], along the cone.
+    Radial { min_speed: f32, max_speed: f32 },
+}
+
+/// Values a [`Curve`] can blend between.
+pub trait Interpolate: Copy {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl<const N: usize> Interpolate for [f32; N] {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        std::array::from_fn(|i| self[i] + (other[i] - self[i]) * t)
+    }
+}
+
+/// Piecewise-linear keys over a particle's normalized age, 0 at birth and 1 at death.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Curve<T> {
+    pub keys: Vec<(f32, T)>,
+}
+
+impl<T: Interpolate> Curve<T> {
+    pub fn constant(value: T) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    pub fn linear(from: T, to: T) -> Self {
+        Self {
+            keys: vec![(0.0, from), (1.0, to)],
+        }
+    }
+
+    /// Inserts the key keeping the list ordered by time.
+    pub fn with_key(mut self, time: f32, value: T) -> Self {
+        let index = self.keys.partition_point(|(other, _)| *other <= time);
+        self.keys.insert(index, (time, value));
+        self
+    }
+
+    /// The first key's value before it, the last key's after it. Panics without keys.
+    pub fn sample(&self, time: f32) -> T {
+        let i = self.keys.partition_point(|(key_time, _)| *key_time <= time);
+        if i == 0 {
+            return self.keys[0].1;
+        }
+        if i == self.keys.len() {
+            return self.keys[i - 1].1;
+        }
+        let (t1, v1) = self.keys[i - 1];
+        let (t2, v2) = self.keys[i];
+        v1.interpolate(v2, (time - t1) / (t2 - t1))
+    }
+
+    /// `samples` evenly spaced values from birth to death, for a lookup table on the GPU.
+    pub fn bake(&self, samples: usize) -> Vec<T> {
+        let last = samples.saturating_sub(1).max(1) as f32;
+        (0..samples).map(|i| self.sample(i as f32 / last)).collect()
+    }
+}
+
+/// Everything that defines an effect, independent of where it is simulated.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmitterDesc {
+    pub shape: EmitterShape,
+    pub velocity: VelocityDistribution,
+    /// Particles per second.
+    pub spawn_rate: f32,
+    /// Particles emitted at once when the emitter starts.
+    pub burst: u32,
+    pub min_lifetime: f32,
+    pub max_lifetime: f32,
+    pub max_particles: usize,
+    /// Acceleration in world units per second squared; y is down.
+    pub gravity: Vector3<f32>,
+    /// Fraction of the velocity lost per second.
+    pub drag: f32,
+    pub colour: Curve<[f32; 3]>,
+    /// Below 1, particles are drawn in the sorted transparent pass.
+    pub opacity: Curve<f32>,
+    /// Scale of the particle mesh.
+    pub size: Curve<f32>,
+}
+
+impl Default for EmitterDesc {
+    fn default() -> Self {
+        Self {
+            shape: EmitterShape::Point,
+            velocity: VelocityDistribution::Directional {
+                direction: -Vector3::y(),
+                spread: 0.3,
+                min_speed: 1.0,
+                max_speed: 2.0,
+            },
+            spawn_rate: 20.0,
+            burst: 0,
+            min_lifetime: 1.0,
+            max_lifetime: 2.0,
+            max_particles: 1000,
+            gravity: Vector3::new(0.0, 9.81, 0.0),
+            drag: 0.0,
+            colour: Curve::constant([1.0, 1.0, 1.0]),
+            opacity: Curve::constant(1.0),
+            size: Curve::linear(0.1, 0.0),
+        }
+    }
+}
+
+/// A particle as it is spawned and simulated. Laid out for std430, so a compute backend
+/// can take the same records the emitter produces.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub age: f32,
+    pub velocity: [f32; 3],
+    pub lifetime: f32,
+}
+
+impl Particle {
+    /// Age from 0 at birth to 1 at death, where the curves are sampled.
+    pub fn normalized_age(&self) -> f32 {
+        (self.age / self.lifetime).min(1.0)
+    }
+}
+
+/// Turns an [`EmitterDesc`] into new particles over time. Both the CPU simulation in
+/// [`CpuParticles`] and a GPU one start from what [`Emitter::emit`] returns.
+pub struct Emitter {
+    pub desc: EmitterDesc,
+    pub position: Vector3<f32>,
+    pub emitting: bool,
+    pending: f32,
+    burst_done: bool,
+    rng: XorShift,
+}
+
+impl Emitter {
+    pub fn new(desc: EmitterDesc, position: Vector3<f32>, seed: u32) -> Self {
+        Self {
+            desc,
+            position,
+            emitting: true,
+            pending: 0.0,
+            burst_done: false,
+            rng: XorShift::new(seed),
+        }
+    }
+
+    /// Emits the burst again on the next [`Emitter::emit`].
+    pub fn restart(&mut self) {
+        self.emitting = true;
+        self.pending = 0.0;
+        self.burst_done = false;
+    }
+
+    /// The particles due after `delta_time` seconds, with at most `room` of them.
+    pub fn emit(&mut self, delta_time: f32, room: usize) -> Vec<Particle> {
+        if !self.emitting {
+            return vec![];
+        }
+        let mut count = 0;
+        if !self.burst_done {
+            self.burst_done = true;
+            count += self.desc.burst as usize;
+        }
+        self.pending += self.desc.spawn_rate * delta_time;
+        let due = self.pending.floor();
+        self.pending -= due;
+        count = (count + due as usize).min(room);
+        (0..count).map(|_| self.spawn()).collect()
+    }
+
+    fn spawn(&mut self) -> Particle {
+        let rng = &mut self.rng;
+        let offset = match self.desc.shape {
+            EmitterShape::Point => Vector3::zeros(),
+            EmitterShape::Sphere { radius } => rng.in_unit_sphere() * radius,
+            EmitterShape::Box { half_extents } => Vector3::new(
+                rng.range(-1.0, 1.0),
+                rng.range(-1.0, 1.0),
+                rng.range(-1.0, 1.0),
+            )
+            .component_mul(&half_extents),
+            EmitterShape::Cone { radius, .. } => {
+                let angle = rng.range(0.0, 2.0 * PI);
+                let distance = radius * rng.unit().sqrt();
+                Vector3::new(angle.cos(), 0.0, angle.sin()) * distance
+            }
+        };
+        let velocity = match self.desc.velocity {
+            VelocityDistribution::Directional {
+                direction,
+                spread,
+                min_speed,
+                max_speed,
+            } => rng.in_cone(&direction, spread) * rng.range(min_speed, max_speed),
+            VelocityDistribution::Radial {
+                min_speed,
+                max_speed,
+            } => {
+                let direction = match self.desc.shape {
+                    EmitterShape::Cone { radius, angle } if radius > 0.0 => {
+                        let outward = offset / radius * angle.sin();
+                        Vector3::new(outward.x, -angle.cos(), outward.z)
+                    }
+                    EmitterShape::Cone { angle, .. } => rng.in_cone(&-Vector3::y(), angle),
+                    _ => offset
+                        .try_normalize(1.0e-6)
+                        .unwrap_or_else(|| rng.in_unit_sphere().normalize()),
+                };
+                direction.normalize() * rng.range(min_speed, max_speed)
+            }
+        };
+
+        Particle {
+            position: (self.position + offset).into(),
+            age: 0.0,
+            velocity: velocity.into(),
+            lifetime: rng.range(self.desc.min_lifetime, self.desc.max_lifetime),
+        }
+    }
+}
+
+/// An emitter simulated on the CPU and written out as instances of a small mesh.
+pub struct CpuParticles {
+    pub emitter: Emitter,
+    pub particles: Vec<Particle>,
+    handles: Vec<usize>,
+}
+
+impl CpuParticles {
+    pub fn new(emitter: Emitter) -> Self {
+        Self {
+            emitter,
+            particles: Vec::new(),
+            handles: Vec::new(),
+        }
+    }
+
+    /// Ages and moves the live particles, drops the dead ones and spawns new ones.
+    pub fn update(&mut self, delta_time: f32) {
+        let desc = &self.emitter.desc;
+        let damping = (1.0 - desc.drag * delta_time).max(0.0);
+        self.particles.retain_mut(|particle| {
+            particle.age += delta_time;
+            if particle.age >= particle.lifetime {
+                return false;
+            }
+            let velocity = (Vector3::from(particle.velocity) + desc.gravity * delta_time) * damping;
+            particle.velocity = velocity.into();
+            particle.position = (Vector3::from(particle.position) + velocity * delta_time).into();
+            true
+        });
+        let room = desc.max_particles.saturating_sub(self.particles.len());
+        let spawned = self.emitter.emit(delta_time, room);
+        self.particles.extend(spawned);
+    }
+
+    /// The emitter has stopped and every particle has died.
+    pub fn is_finished(&self) -> bool {
+        !self.emitter.emitting && self.particles.is_empty()
+    }
+
+    /// Writes one visible instance per live particle into `instances`, reusing the ones
+    /// written last time. Upload the instance buffer afterwards.
+    pub fn write_instances<I>(&mut self, instances: &mut InstanceSet<I>)
+    where
+        I: Copy + From<InstanceData>,
+    {
+        let desc = &self.emitter.desc;
+        let data = self.particles.iter().map(|particle| {
+            let t = particle.normalized_age();
+            let size = desc.size.sample(t).max(1.0e-6);
+            let matrix =
+                Matrix4::new_translation(&particle.position.into()) * Matrix4::new_scaling(size);
+            I::from(
+                InstanceData::from_matrix_and_colour(matrix, desc.colour.sample(t))
+                    .with_opacity(desc.opacity.sample(t)),
+            )
+        });
+
+        let mut written = 0;
+        for instance in data {
+            match self.handles.get(written) {
+                Some(&handle) => {
+                    if let Some(slot) = instances.get_mut(handle) {
+                        *slot = instance;
+                    }
+                }
+                None => self.handles.push(instances.insert_visibly(instance)),
+            }
+            written += 1;
+        }
+        for handle in self.handles.drain(written..) {
+            instances.remove(handle).ok();
+        }
+    }
+}
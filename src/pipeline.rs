@@ -1,237 +1,710 @@
-use crate::swapchain::Swapchain;
-use anyhow::{Ok, Result};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::{bail, Ok, Result};
 use ash::vk;
 
+use crate::layout_cache::LayoutCache;
+use crate::multisample::Multisampling;
+use crate::vertex_layout::{VertexInput, VertexLayout};
+
+/// Formats of the inputs `shaders/shader.vert` declares, by location.
+const SHADER_INPUTS: [vk::Format; 14] = [
+    vk::Format::R32G32B32_SFLOAT,
+    vk::Format::R32G32B32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32A32_SFLOAT,
+    vk::Format::R32G32B32_SFLOAT,
+    vk::Format::R32_SFLOAT,
+    vk::Format::R32_SFLOAT,
+    vk::Format::R32_UINT,
+];
+
 pub struct Pipeline {
     pub pipeline: vk::Pipeline,
     pub layout: vk::PipelineLayout,
     pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub multisampling: Multisampling,
+    /// Vertex input of the models the pipeline draws, shared by the passes drawing them.
+    pub vertex_input: VertexInput,
+    /// Variants of `pipeline` for other topologies, with their primitive restart setting.
+    pub topology_pipelines: Vec<(Topology, bool, vk::Pipeline)>,
+    /// The main pipeline without depth testing, so [`crate::render_mode::RenderMode::Overdraw`]
+    /// counts hidden fragments too.
+    pub overdraw_pipeline: vk::Pipeline,
+    /// Winding of front faces, which are kept while back faces are culled.
+    pub front_face: vk::FrontFace,
+    /// Used instead of `shaders/shader.vert` and `shaders/shader.frag` when set.
+    pub shaders: Option<PipelineShaders>,
+    /// Parts variants are linked from, once [`Pipeline::enable_library`] made them.
+    pub library: Option<Arc<PipelineLibrary>>,
+    /// Changed with [`Pipeline::set_blend_mode`].
+    pub blend_mode: BlendMode,
+}
+
+/// SPIR-V to shade a [`Pipeline`] with instead of the built-in shaders, such as HLSL
+/// compiled with DXC or `ShaderCompiler::compile_hlsl`. They read the same vertex
+/// inputs and descriptor set 0 as the built-in ones.
+#[derive(Clone, Debug)]
+pub struct PipelineShaders {
+    pub vertex: Vec<u32>,
+    pub vertex_entry_point: String,
+    pub fragment: Vec<u32>,
+    pub fragment_entry_point: String,
+}
+
+/// Separately compiled parts of a [`Pipeline`] (`VK_EXT_graphics_pipeline_library`):
+/// vertex input, pre-rasterization (the vertex shader), fragment shader and fragment
+/// output. A variant only needs the parts it changes made, then links them.
+pub struct PipelineLibrary {
+    /// Vertex input parts by topology and primitive restart, made as first needed from
+    /// whichever thread links a variant.
+    pub vertex_inputs: Mutex<Vec<(Topology, bool, vk::Pipeline)>>,
+    pub pre_rasterization: vk::Pipeline,
+    /// Fragment shader parts without and with depth testing.
+    pub fragment_shaders: [vk::Pipeline; 2],
+    pub fragment_output: vk::Pipeline,
+}
+
+impl PipelineLibrary {
+    fn vertex_inputs(&self) -> MutexGuard<'_, Vec<(Topology, bool, vk::Pipeline)>> {
+        // A panic while holding the lock leaves the list itself intact.
+        self.vertex_inputs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The vertex input part for `topology`, made with `create` if there is none yet.
+    fn vertex_input(
+        &self,
+        topology: Topology,
+        primitive_restart: bool,
+        create: impl FnOnce() -> Result<vk::Pipeline>,
+    ) -> Result<vk::Pipeline> {
+        let mut vertex_inputs = self.vertex_inputs();
+        if let Some((_, _, pipeline)) = vertex_inputs
+            .iter()
+            .find(|(t, r, _)| *t == topology && *r == primitive_restart)
+        {
+            return Ok(*pipeline);
+        }
+        let pipeline = create()?;
+        vertex_inputs.push((topology, primitive_restart, pipeline));
+        Ok(pipeline)
+    }
+
+    fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            for (_, _, pipeline) in self.vertex_inputs().iter() {
+                logical_device.destroy_pipeline(*pipeline, None);
+            }
+            logical_device.destroy_pipeline(self.pre_rasterization, None);
+            for pipeline in self.fragment_shaders {
+                logical_device.destroy_pipeline(pipeline, None);
+            }
+            logical_device.destroy_pipeline(self.fragment_output, None);
+        }
+    }
+}
+
+/// How the colour a pipeline draws is combined with the colour already in the target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlendMode {
+    /// Replaces the target.
+    Opaque,
+    /// Mixes by the drawn alpha.
+    #[default]
+    AlphaBlend,
+    /// Adds the drawn colour, scaled by its alpha, as for glows and sparks.
+    Additive,
+    /// Alpha blending for colours already multiplied by their alpha.
+    Premultiplied,
+    /// Multiplies the target by the drawn colour, as for tints and decal shadows.
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        // Colour source and destination, then alpha source and destination.
+        let factors = match self {
+            BlendMode::Opaque => None,
+            BlendMode::AlphaBlend => Some((
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            )),
+            BlendMode::Additive => Some((
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+            )),
+            BlendMode::Premultiplied => Some((
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            )),
+            BlendMode::Multiply => Some((
+                vk::BlendFactor::DST_COLOR,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+            )),
+        };
+        let state = vk::PipelineColorBlendAttachmentState::builder().color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        );
+        match factors {
+            None => state.blend_enable(false).build(),
+            Some((src_colour, dst_colour, src_alpha, dst_alpha)) => state
+                .blend_enable(true)
+                .src_color_blend_factor(src_colour)
+                .dst_color_blend_factor(dst_colour)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(src_alpha)
+                .dst_alpha_blend_factor(dst_alpha)
+                .alpha_blend_op(vk::BlendOp::ADD)
+                .build(),
+        }
+    }
+}
+
+/// How a model's indices are assembled into primitives.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Topology {
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    LineList,
+    LineStrip,
+    PointList,
+}
+
+impl Topology {
+    pub fn vk(self) -> vk::PrimitiveTopology {
+        match self {
+            Topology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
+            Topology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
+            Topology::LineList => vk::PrimitiveTopology::LINE_LIST,
+            Topology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
+            Topology::PointList => vk::PrimitiveTopology::POINT_LIST,
+        }
+    }
+
+    /// Only strips can be cut with primitive restart; lists would need an extension.
+    pub fn is_strip(self) -> bool {
+        matches!(self, Topology::TriangleStrip | Topology::LineStrip)
+    }
 }
 
 impl Pipeline {
-    pub fn init(
+    /// Builds the pipeline for drawing `Model<V, I>`.
+    pub fn init<V: VertexLayout, I: VertexLayout>(
         logical_device: &ash::Device,
-        swapchain: &Swapchain,
         renderpass: &vk::RenderPass,
+        multisampling: Multisampling,
+        layout_cache: &mut LayoutCache,
     ) -> Result<Self> {
+        Self::init_with_front_face::<V, I>(
+            logical_device,
+            renderpass,
+            multisampling,
+            layout_cache,
+            vk::FrontFace::COUNTER_CLOCKWISE,
+            None,
+        )
+    }
+
+    /// [`Pipeline::init`] shading with `shaders`.
+    pub fn init_with_shaders<V: VertexLayout, I: VertexLayout>(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        multisampling: Multisampling,
+        layout_cache: &mut LayoutCache,
+        shaders: PipelineShaders,
+    ) -> Result<Self> {
+        Self::init_with_front_face::<V, I>(
+            logical_device,
+            renderpass,
+            multisampling,
+            layout_cache,
+            vk::FrontFace::COUNTER_CLOCKWISE,
+            Some(shaders),
+        )
+    }
+
+    /// [`Pipeline::init`] for views seen through a mirror, such as reflections, whose
+    /// triangles wind the other way.
+    pub fn init_mirrored<V: VertexLayout, I: VertexLayout>(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        multisampling: Multisampling,
+        layout_cache: &mut LayoutCache,
+    ) -> Result<Self> {
+        Self::init_with_front_face::<V, I>(
+            logical_device,
+            renderpass,
+            multisampling,
+            layout_cache,
+            vk::FrontFace::CLOCKWISE,
+            None,
+        )
+    }
+
+    fn init_with_front_face<V: VertexLayout, I: VertexLayout>(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        multisampling: Multisampling,
+        layout_cache: &mut LayoutCache,
+        front_face: vk::FrontFace,
+        shaders: Option<PipelineShaders>,
+    ) -> Result<Self> {
+        let vertex_input = VertexInput::of::<V, I>();
+        vertex_input.validate()?;
+        // Locations past the shader's inputs hold custom instance fields, which it ignores.
+        let formats: Vec<_> = vertex_input.attributes.iter().map(|a| a.format).collect();
+        if !formats.starts_with(&SHADER_INPUTS) {
+            bail!(
+                "The vertex and instance layouts give the locations {:?}, but shaders/shader.vert reads {:?}.",
+                formats,
+                SHADER_INPUTS
+            );
+        }
+
+        /* Descriptor Set Layout */
+        let descriptorset_layout_binding_descs = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        ];
+        let descriptorset_layout = layout_cache.descriptor_set_layout(
+            logical_device,
+            vk::DescriptorSetLayoutCreateFlags::empty(),
+            &descriptorset_layout_binding_descs,
+        )?;
+        let descriptor_layouts = vec![descriptorset_layout];
+
+        /* Pipeline */
+        let pipeline_layout =
+            layout_cache.pipeline_layout(logical_device, &descriptor_layouts, &[])?;
+
+        let mut pipeline = Pipeline {
+            pipeline: vk::Pipeline::null(),
+            layout: pipeline_layout,
+            descriptor_set_layouts: descriptor_layouts,
+            multisampling,
+            vertex_input,
+            topology_pipelines: vec![],
+            overdraw_pipeline: vk::Pipeline::null(),
+            front_face,
+            shaders,
+            library: None,
+            blend_mode: BlendMode::default(),
+        };
+        pipeline.pipeline = pipeline.create(
+            logical_device,
+            renderpass,
+            Topology::TriangleList,
+            false,
+            true,
+        )?;
+        pipeline.overdraw_pipeline = pipeline.create(
+            logical_device,
+            renderpass,
+            Topology::TriangleList,
+            false,
+            false,
+        )?;
+        Ok(pipeline)
+    }
+
+    /// The pipeline for models with another topology than a triangle list, made by
+    /// [`Pipeline::create_topology_pipeline`].
+    pub fn topology_pipeline(
+        &self,
+        topology: Topology,
+        primitive_restart: bool,
+    ) -> Option<vk::Pipeline> {
+        let primitive_restart = primitive_restart && topology.is_strip();
+        self.topology_pipelines
+            .iter()
+            .find(|(t, r, _)| *t == topology && *r == primitive_restart)
+            .map(|(_, _, pipeline)| *pipeline)
+    }
+
+    /// Same shading and layout as the main pipeline, assembling `topology` instead.
+    /// Made once per topology and kept until cleanup.
+    pub fn create_topology_pipeline(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        topology: Topology,
+        primitive_restart: bool,
+    ) -> Result<vk::Pipeline> {
+        let primitive_restart = primitive_restart && topology.is_strip();
+        if topology == Topology::TriangleList && !primitive_restart {
+            return Ok(self.pipeline);
+        }
+        if let Some(pipeline) = self.topology_pipeline(topology, primitive_restart) {
+            return Ok(pipeline);
+        }
+        let pipeline = self.create(
+            logical_device,
+            renderpass,
+            topology,
+            primitive_restart,
+            true,
+        )?;
+        self.topology_pipelines
+            .push((topology, primitive_restart, pipeline));
+        Ok(pipeline)
+    }
+
+    /// Compiles what [`Pipeline::create_topology_pipeline`] would, for a
+    /// [`crate::pipeline_registry::PipelineRegistry`] to run off the render thread. The
+    /// job shares this pipeline's layout and library, so it must finish before cleanup.
+    pub fn topology_pipeline_job(
+        &self,
+        logical_device: &ash::Device,
+        renderpass: vk::RenderPass,
+        topology: Topology,
+        primitive_restart: bool,
+    ) -> impl FnOnce() -> Result<vk::Pipeline> + Send + 'static {
+        let primitive_restart = primitive_restart && topology.is_strip();
+        // Only what creating a variant reads; the handles stay owned by `self`.
+        let template = Pipeline {
+            pipeline: vk::Pipeline::null(),
+            layout: self.layout,
+            descriptor_set_layouts: vec![],
+            multisampling: self.multisampling,
+            vertex_input: self.vertex_input.clone(),
+            topology_pipelines: vec![],
+            overdraw_pipeline: vk::Pipeline::null(),
+            front_face: self.front_face,
+            shaders: self.shaders.clone(),
+            library: self.library.clone(),
+            blend_mode: self.blend_mode,
+        };
+        let logical_device = logical_device.clone();
+        move || {
+            template.create(
+                &logical_device,
+                &renderpass,
+                topology,
+                primitive_restart,
+                true,
+            )
+        }
+    }
+
+    /// Compiles the parts [`Pipeline::create_topology_pipeline`] links its variants from,
+    /// which is much faster than compiling each variant whole. Needs
+    /// `capabilities.graphics_pipeline_library`; without it, variants keep being compiled
+    /// in one piece.
+    pub fn enable_library(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+    ) -> Result<()> {
+        if self.library.is_some() {
+            return Ok(());
+        }
+        let part = |parts, depth_tested| {
+            self.create_part(
+                logical_device,
+                renderpass,
+                Topology::TriangleList,
+                false,
+                depth_tested,
+                parts,
+            )
+        };
+        let library = PipelineLibrary {
+            vertex_inputs: Mutex::new(vec![(
+                Topology::TriangleList,
+                false,
+                part(
+                    vk::GraphicsPipelineLibraryFlagsEXT::VERTEX_INPUT_INTERFACE,
+                    true,
+                )?,
+            )]),
+            pre_rasterization: part(
+                vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS,
+                true,
+            )?,
+            fragment_shaders: [
+                part(vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER, false)?,
+                part(vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER, true)?,
+            ],
+            fragment_output: part(
+                vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_OUTPUT_INTERFACE,
+                true,
+            )?,
+        };
+        self.library = Some(Arc::new(library));
+        Ok(())
+    }
+
+    /// Remakes the main pipeline and its variants blending with `blend_mode`. Nothing may
+    /// still use the old ones, including jobs from [`Pipeline::topology_pipeline_job`].
+    pub fn set_blend_mode(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        blend_mode: BlendMode,
+    ) -> Result<()> {
+        if blend_mode == self.blend_mode {
+            return Ok(());
+        }
+        self.blend_mode = blend_mode;
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline(self.overdraw_pipeline, None);
+            for (_, _, pipeline) in self.topology_pipelines.drain(..) {
+                logical_device.destroy_pipeline(pipeline, None);
+            }
+        }
+        // Blending is part of the fragment output the library holds.
+        if let Some(library) = self.library.take() {
+            library.cleanup(logical_device);
+            self.enable_library(logical_device, renderpass)?;
+        }
+        self.pipeline = self.create(
+            logical_device,
+            renderpass,
+            Topology::TriangleList,
+            false,
+            true,
+        )?;
+        self.overdraw_pipeline = self.create(
+            logical_device,
+            renderpass,
+            Topology::TriangleList,
+            false,
+            false,
+        )?;
+        Ok(())
+    }
+
+    fn create(
+        &self,
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        topology: Topology,
+        primitive_restart: bool,
+        depth_tested: bool,
+    ) -> Result<vk::Pipeline> {
+        let Some(library) = &self.library else {
+            return self.create_part(
+                logical_device,
+                renderpass,
+                topology,
+                primitive_restart,
+                depth_tested,
+                vk::GraphicsPipelineLibraryFlagsEXT::empty(),
+            );
+        };
+
+        /* Only the vertex input differs between variants of one depth test */
+        let vertex_input = library.vertex_input(topology, primitive_restart, || {
+            self.create_part(
+                logical_device,
+                renderpass,
+                topology,
+                primitive_restart,
+                depth_tested,
+                vk::GraphicsPipelineLibraryFlagsEXT::VERTEX_INPUT_INTERFACE,
+            )
+        })?;
+        let libraries = [
+            vertex_input,
+            library.pre_rasterization,
+            library.fragment_shaders[depth_tested as usize],
+            library.fragment_output,
+        ];
+        let mut library_info = vk::PipelineLibraryCreateInfoKHR::builder().libraries(&libraries);
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .push_next(&mut library_info)
+            .layout(self.layout);
+        let graphics_pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+        Ok(graphics_pipeline)
+    }
+
+    /// The whole pipeline when `parts` is empty, otherwise a library of just those parts.
+    fn create_part(
+        &self,
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        topology: Topology,
+        primitive_restart: bool,
+        depth_tested: bool,
+        parts: vk::GraphicsPipelineLibraryFlagsEXT,
+    ) -> Result<vk::Pipeline> {
+        let has = |part| parts.is_empty() || parts.contains(part);
+
         /* Shaders */
-        let vertex_info = vk::ShaderModuleCreateInfo::builder()
-            .code(vk_shader_macros::include_glsl!("shaders/shader.vert", kind: vert));
+        let (vertex_code, fragment_code, vertex_entry_point, fragment_entry_point): (
+            &[u32],
+            &[u32],
+            &str,
+            &str,
+        ) = match &self.shaders {
+            Some(shaders) => (
+                shaders.vertex.as_slice(),
+                shaders.fragment.as_slice(),
+                shaders.vertex_entry_point.as_str(),
+                shaders.fragment_entry_point.as_str(),
+            ),
+            None => (
+                vk_shader_macros::include_glsl!("shaders/shader.vert", kind: vert),
+                vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag),
+                "main",
+                "main",
+            ),
+        };
+        let vertex_info = vk::ShaderModuleCreateInfo::builder().code(vertex_code);
         let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
 
-        let fragment_info = vk::ShaderModuleCreateInfo::builder()
-            .code(vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag));
+        let fragment_info = vk::ShaderModuleCreateInfo::builder().code(fragment_code);
         let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
 
-        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let vertex_function_name = std::ffi::CString::new(vertex_entry_point)?;
+        let fragment_function_name = std::ffi::CString::new(fragment_entry_point)?;
         let vertex_stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::VERTEX)
             .module(vertex_module)
-            .name(&main_function_name);
+            .name(&vertex_function_name);
         let fragment_stage = vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::FRAGMENT)
             .module(fragment_module)
-            .name(&main_function_name);
-        let shader_stages = vec![vertex_stage.build(), fragment_stage.build()];
-
-        let vertex_attrib_descs = [
-            vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 0,
-                offset: 0,
-                format: vk::Format::R32G32B32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 1,
-                offset: 12,
-                format: vk::Format::R32G32B32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 2,
-                offset: 0,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 3,
-                offset: 16,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 4,
-                offset: 32,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 5,
-                offset: 48,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 6,
-                offset: 64,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 7,
-                offset: 80,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 8,
-                offset: 96,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 9,
-                offset: 112,
-                format: vk::Format::R32G32B32A32_SFLOAT,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 1,
-                location: 10,
-                offset: 128,
-                format: vk::Format::R32G32B32_SFLOAT,
-            },
-        ];
-        let vertex_binding_descs = [
-            vk::VertexInputBindingDescription {
-                binding: 0,
-                stride: 24,
-                input_rate: vk::VertexInputRate::VERTEX,
-            },
-            vk::VertexInputBindingDescription {
-                binding: 1,
-                stride: 140,
-                input_rate: vk::VertexInputRate::INSTANCE,
-            },
-        ];
-        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
-            .vertex_attribute_descriptions(&vertex_attrib_descs)
-            .vertex_binding_descriptions(&vertex_binding_descs);
+            .name(&fragment_function_name);
+        let mut shader_stages = vec![];
+        if has(vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS) {
+            shader_stages.push(vertex_stage.build());
+        }
+        if has(vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER) {
+            shader_stages.push(fragment_stage.build());
+        }
+
+        let vertex_input_info = self.vertex_input.state_info();
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+            .topology(topology.vk())
+            .primitive_restart_enable(primitive_restart);
 
         /* Rasterization */
 
-        let viewports = [vk::Viewport {
-            x: 0.,
-            y: 0.,
-            width: swapchain.extent.width as f32,
-            height: swapchain.extent.height as f32,
-            min_depth: 0.,
-            max_depth: 1.,
-        }];
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swapchain.extent,
-        }];
+        /* Viewport and scissor are set per frame so resizes don't need a new pipeline. */
         let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
 
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
             .line_width(1.0)
-            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .front_face(self.front_face)
             .cull_mode(vk::CullModeFlags::BACK)
             .polygon_mode(vk::PolygonMode::FILL);
 
-        let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
-            .blend_enable(true)
-            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .color_blend_op(vk::BlendOp::ADD)
-            .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
-            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-            .alpha_blend_op(vk::BlendOp::ADD)
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            )
-            .build()];
+        let multisampler_info = self.multisampling.state_info();
+
+        let colourblend_attachments = [self.blend_mode.attachment_state()];
         let colourblend_info =
             vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
         let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
+            .depth_test_enable(depth_tested)
+            .depth_write_enable(depth_tested)
             .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
 
-        /* Descriptor Set Layout */
-        let descriptorset_layout_binding_descs = [vk::DescriptorSetLayoutBinding::builder()
-            .binding(0)
-            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-            .descriptor_count(1)
-            .stage_flags(vk::ShaderStageFlags::VERTEX)
-            .build()];
-        let descriptorset_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
-            .bindings(&descriptorset_layout_binding_descs);
-        let descriptorset_layout = unsafe {
-            logical_device.create_descriptor_set_layout(&descriptorset_layout_info, None)
-        }?;
-        let descriptor_layouts = vec![descriptorset_layout];
-        let _pipeline_layout_info =
-            vk::PipelineLayoutCreateInfo::builder().set_layouts(&descriptor_layouts);
-
-        /* Pipeline */
-        let pipeline_layout_info =
-            vk::PipelineLayoutCreateInfo::builder().set_layouts(&descriptor_layouts);
-        let pipeline_layout =
-            unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
-
-        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        let mut library_info = vk::GraphicsPipelineLibraryCreateInfoEXT::builder().flags(parts);
+        let mut pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(&shader_stages)
-            .vertex_input_state(&vertex_input_info)
-            .input_assembly_state(&input_assembly_info)
-            .viewport_state(&viewport_info)
-            .rasterization_state(&rasterizer_info)
-            .multisample_state(&multisampler_info)
-            .depth_stencil_state(&depth_stencil_info)
-            .color_blend_state(&colourblend_info)
-            .layout(pipeline_layout)
+            .layout(self.layout)
             .render_pass(*renderpass)
             .subpass(0);
-        let graphics_pipeline = unsafe {
-            logical_device
-                .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
-                    &[pipeline_info.build()],
-                    None,
-                )
-                .expect("A problem with the pipeline creation")
-        }[0];
+        if !parts.is_empty() {
+            pipeline_info = pipeline_info
+                .flags(vk::PipelineCreateFlags::LIBRARY_KHR)
+                .push_next(&mut library_info);
+        }
+        if has(vk::GraphicsPipelineLibraryFlagsEXT::VERTEX_INPUT_INTERFACE) {
+            pipeline_info = pipeline_info
+                .vertex_input_state(&vertex_input_info)
+                .input_assembly_state(&input_assembly_info);
+        }
+        if has(vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS) {
+            pipeline_info = pipeline_info
+                .viewport_state(&viewport_info)
+                .rasterization_state(&rasterizer_info)
+                .dynamic_state(&dynamic_state_info);
+        }
+        if has(vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER) {
+            pipeline_info = pipeline_info
+                .multisample_state(&multisampler_info)
+                .depth_stencil_state(&depth_stencil_info);
+        }
+        if has(vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_OUTPUT_INTERFACE) {
+            pipeline_info = pipeline_info
+                .multisample_state(&multisampler_info)
+                .color_blend_state(&colourblend_info);
+        }
+        let graphics_pipelines = unsafe {
+            logical_device.create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &[pipeline_info.build()],
+                None,
+            )
+        };
 
         unsafe {
             logical_device.destroy_shader_module(fragment_module, None);
             logical_device.destroy_shader_module(vertex_module, None)
         }
 
-        Ok(Pipeline {
-            pipeline: graphics_pipeline,
-            layout: pipeline_layout,
-            descriptor_set_layouts: descriptor_layouts,
-        })
+        Ok(graphics_pipelines.map_err(|(_, e)| e)?[0])
     }
 
+    /// Destroys the pipelines; the layouts stay with the [`LayoutCache`] they came from.
     pub fn cleanup(&self, logical_device: &ash::Device) {
         unsafe {
-            for dsl in &self.descriptor_set_layouts {
-                logical_device.destroy_descriptor_set_layout(*dsl, None);
-            }
             logical_device.destroy_pipeline(self.pipeline, None);
-            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_pipeline(self.overdraw_pipeline, None);
+            for (_, _, pipeline) in &self.topology_pipelines {
+                logical_device.destroy_pipeline(*pipeline, None);
+            }
+            if let Some(library) = &self.library {
+                library.cleanup(logical_device);
+            }
         }
     }
 }
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use anyhow::{Ok, Result};
+use ash::vk;
+
+type CompileJob = Box<dyn FnOnce() -> Result<vk::Pipeline> + Send>;
+
+/// How far a pipeline requested from a [`PipelineRegistry`] has got.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PipelineStatus {
+    Compiling,
+    Ready(vk::Pipeline),
+    /// The compile job's error, which is not retried.
+    Failed(String),
+}
+
+/// Pipelines compiled one after another on a worker thread, so the first draw needing a
+/// new variant doesn't stall the frame. Lookups never wait: draws are skipped until
+/// their pipeline is ready. Requests and lookups only take `&self`, so the registry can
+/// be shared between threads.
+pub struct PipelineRegistry<K> {
+    pipelines: Arc<Mutex<HashMap<K, PipelineStatus>>>,
+    requests: Option<Sender<(K, CompileJob)>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<K: Clone + Eq + Hash + Send + 'static> PipelineRegistry<K> {
+    pub fn new() -> Self {
+        let pipelines = Arc::new(Mutex::new(HashMap::new()));
+        let (requests, worker_requests) = channel::<(K, CompileJob)>();
+        let worker_pipelines = pipelines.clone();
+        let worker = std::thread::spawn(move || {
+            for (key, compile) in worker_requests {
+                let status = match compile() {
+                    std::result::Result::Ok(pipeline) => PipelineStatus::Ready(pipeline),
+                    Err(error) => PipelineStatus::Failed(error.to_string()),
+                };
+                lock(&worker_pipelines).insert(key, status);
+            }
+        });
+        Self {
+            pipelines,
+            requests: Some(requests),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues `compile` unless `key` was requested before.
+    pub fn request(
+        &self,
+        key: K,
+        compile: impl FnOnce() -> Result<vk::Pipeline> + Send + 'static,
+    ) -> Result<()> {
+        let mut pipelines = lock(&self.pipelines);
+        if pipelines.contains_key(&key) {
+            return Ok(());
+        }
+        pipelines.insert(key.clone(), PipelineStatus::Compiling);
+        if let Some(requests) = &self.requests {
+            requests
+                .send((key, Box::new(compile)))
+                .map_err(|_| anyhow::anyhow!("The pipeline compiler thread has stopped."))?;
+        }
+        Ok(())
+    }
+
+    pub fn status(&self, key: &K) -> Option<PipelineStatus> {
+        lock(&self.pipelines).get(key).cloned()
+    }
+
+    /// The pipeline for `key` once it is compiled.
+    pub fn get(&self, key: &K) -> Option<vk::Pipeline> {
+        match self.status(key) {
+            Some(PipelineStatus::Ready(pipeline)) => Some(pipeline),
+            _ => None,
+        }
+    }
+
+    /// The pipeline for `key`, or `fallback` while it is compiling.
+    pub fn get_or(&self, key: &K, fallback: vk::Pipeline) -> vk::Pipeline {
+        self.get(key).unwrap_or(fallback)
+    }
+
+    /// Whether every requested pipeline has finished compiling, successfully or not.
+    pub fn is_idle(&self) -> bool {
+        !lock(&self.pipelines)
+            .values()
+            .any(|status| *status == PipelineStatus::Compiling)
+    }
+
+    /// Waits for queued jobs to finish, then destroys every compiled pipeline.
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        self.requests = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        for (_, status) in lock(&self.pipelines).drain() {
+            if let PipelineStatus::Ready(pipeline) = status {
+                unsafe { logical_device.destroy_pipeline(pipeline, None) };
+            }
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash + Send + 'static> Default for PipelineRegistry<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lock<K>(
+    pipelines: &Mutex<HashMap<K, PipelineStatus>>,
+) -> MutexGuard<'_, HashMap<K, PipelineStatus>> {
+    // A panic while holding the lock leaves the map itself intact.
+    pipelines
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,619 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::format_has_stencil;
+use crate::swapchain::{Attachment, Swapchain};
+
+/// Vignette and film grain, applied by [`PostProcess`] after every other effect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PostSettings {
+    /// Where the vignette starts darkening, as a fraction of the distance from the centre
+    /// of the viewport to its corners.
+    pub vignette_radius: f32,
+    /// Distance over which the vignette reaches full strength, in the same units.
+    pub vignette_softness: f32,
+    /// How dark the vignette gets, from 0 (off) to 1 (black corners).
+    pub vignette_intensity: f32,
+    /// Strength of the film grain, redrawn every frame; 0 is off.
+    pub grain_intensity: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            vignette_radius: 0.6,
+            vignette_softness: 0.5,
+            vignette_intensity: 0.0,
+            grain_intensity: 0.0,
+        }
+    }
+}
+
+impl PostSettings {
+    /// Whether drawing them changes the frame.
+    pub fn is_active(&self) -> bool {
+        self.vignette_intensity > 0.0 || self.grain_intensity > 0.0
+    }
+}
+
+/// Fullscreen effects drawn over the finished frame, after the main render pass. The frame
+/// is copied aside so effects can sample around each pixel, then drawn back into the
+/// swapchain image by a render pass of its own, which keeps what the copy left there.
+///
+/// Effects bind `descriptor_set` as set 0: the copy of the frame at binding 0, sampled
+/// linearly with clamped edges, and the depth buffer at binding 1, for `texelFetch`, when
+/// the scene is single-sampled. The HDR outputs are copied as encoded for the display.
+pub struct PostProcess {
+    pub renderpass: vk::RenderPass,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    /// Copy of the frame, recreated with the swapchain.
+    pub source: Attachment,
+    /// Depth aspect alone, for sampling; null with MSAA.
+    pub depth_view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    /// Draws `settings` over the frame, multiplying it.
+    pub finish_pipeline: vk::Pipeline,
+    pub finish_layout: vk::PipelineLayout,
+    pub settings: PostSettings,
+    /// Counts the frames drawn, animating the grain.
+    pub frame: u32,
+}
+
+impl PostProcess {
+    pub fn init(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        swapchain: &Swapchain,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self> {
+        if !swapchain
+            .image_usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            bail!("Post-processing needs swapchain images that can be copied from.");
+        }
+
+        /* Render Pass */
+        let attachments = [vk::AttachmentDescription::builder()
+            .format(swapchain.surface_format.format)
+            .load_op(vk::AttachmentLoadOp::LOAD)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .build()];
+        let colour_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let subpasses = [vk::SubpassDescription::builder()
+            .color_attachments(&colour_attachment_refs)
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .build()];
+        let subpass_dependencies = [vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::TRANSFER)
+            .dst_subpass(0)
+            .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            )
+            .build()];
+        let renderpass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+        let renderpass = unsafe { logical_device.create_render_pass(&renderpass_info, None) }?;
+
+        /* Sampler */
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        /* Descriptors */
+        let descriptor_set_layout_bindings = [0, 1].map(|binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        });
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 2,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let (source, depth_view, framebuffers) = init_targets(
+            logical_device,
+            memory_properties,
+            swapchain,
+            renderpass,
+            samples,
+        )?;
+        let (finish_pipeline, finish_layout) = init_finish_pipeline(logical_device, renderpass)?;
+        let post = PostProcess {
+            renderpass,
+            framebuffers,
+            source,
+            depth_view,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            finish_pipeline,
+            finish_layout,
+            settings: PostSettings::default(),
+            frame: 0,
+        };
+        post.update_descriptor_set(logical_device);
+
+        Ok(post)
+    }
+
+    /// Recreates the copy of the frame and the framebuffers for a new swapchain.
+    pub fn resize(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        swapchain: &Swapchain,
+        samples: vk::SampleCountFlags,
+    ) -> Result<()> {
+        unsafe { self.cleanup_targets(logical_device) };
+        (self.source, self.depth_view, self.framebuffers) = init_targets(
+            logical_device,
+            memory_properties,
+            swapchain,
+            self.renderpass,
+            samples,
+        )?;
+        self.update_descriptor_set(logical_device);
+        Ok(())
+    }
+
+    fn update_descriptor_set(&self, logical_device: &ash::Device) {
+        let source_info = [vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.source.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let depth_info = [vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.depth_view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        }];
+        let mut writes = vec![vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&source_info)
+            .build()];
+        if self.depth_view != vk::ImageView::null() {
+            writes.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(self.descriptor_set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&depth_info)
+                    .build(),
+            );
+        }
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Copies the finished frame in swapchain image `index` aside and begins the render
+    /// pass drawing back into it. Recorded after the main render pass.
+    ///# Safety
+    ///
+    /// `command_buffer` must be recording, outside a render pass.
+    pub unsafe fn begin(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        swapchain: &Swapchain,
+        index: usize,
+    ) {
+        let image = swapchain.images[index];
+        let colour_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let barrier = |image, range, old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .image(image)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(range)
+                .build()
+        };
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[
+                barrier(
+                    image,
+                    colour_range,
+                    vk::ImageLayout::PRESENT_SRC_KHR,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                ),
+                barrier(
+                    self.source.image,
+                    colour_range,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::AccessFlags::empty(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                ),
+            ],
+        );
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        logical_device.cmd_copy_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            self.source.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[vk::ImageCopy {
+                src_subresource: subresource,
+                src_offset: vk::Offset3D::default(),
+                dst_subresource: subresource,
+                dst_offset: vk::Offset3D::default(),
+                extent: vk::Extent3D {
+                    width: swapchain.extent.width,
+                    height: swapchain.extent.height,
+                    depth: 1,
+                },
+            }],
+        );
+
+        let mut barriers = vec![barrier(
+            self.source.image,
+            colour_range,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        )];
+        if self.depth_view != vk::ImageView::null() {
+            barriers.push(barrier(
+                swapchain.depth_image,
+                depth_range(swapchain.depth_format),
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            ));
+        }
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers,
+        );
+
+        let renderpass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.renderpass)
+            .framebuffer(self.framebuffers[index])
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: swapchain.extent,
+            });
+        logical_device.cmd_begin_render_pass(
+            command_buffer,
+            &renderpass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+    }
+
+    /// Draws the vignette and grain of `settings` over the effects before it, centred in
+    /// `viewport`. Does nothing while they are off.
+    pub fn draw_finish(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        viewport: vk::Rect2D,
+    ) {
+        if !self.settings.is_active() {
+            return;
+        }
+        let parameters = [
+            viewport.offset.x as f32,
+            viewport.offset.y as f32,
+            viewport.extent.width as f32,
+            viewport.extent.height as f32,
+            self.settings.vignette_radius,
+            self.settings.vignette_softness,
+            self.settings.vignette_intensity.clamp(0.0, 1.0),
+            self.settings.grain_intensity.max(0.0),
+        ]
+        .map(f32::to_bits);
+        let mut words = [0; 9];
+        words[..8].copy_from_slice(&parameters);
+        words[8] = self.frame;
+        let bytes = unsafe {
+            std::slice::from_raw_parts(words.as_ptr() as *const u8, std::mem::size_of_val(&words))
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.finish_pipeline,
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.finish_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                bytes,
+            );
+            logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    /// Ends the render pass and hands the depth buffer back for the next frame.
+    ///# Safety
+    ///
+    /// Must follow [`PostProcess::begin`] in the same command buffer.
+    pub unsafe fn end(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        swapchain: &Swapchain,
+    ) {
+        logical_device.cmd_end_render_pass(command_buffer);
+        if self.depth_view == vk::ImageView::null() {
+            return;
+        }
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .image(swapchain.depth_image)
+            .old_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(depth_range(swapchain.depth_format))
+            .build();
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    }
+
+    unsafe fn cleanup_targets(&self, logical_device: &ash::Device) {
+        for framebuffer in &self.framebuffers {
+            logical_device.destroy_framebuffer(*framebuffer, None);
+        }
+        logical_device.destroy_image_view(self.depth_view, None);
+        self.source.cleanup(logical_device);
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            self.cleanup_targets(logical_device);
+            logical_device.destroy_pipeline(self.finish_pipeline, None);
+            logical_device.destroy_pipeline_layout(self.finish_layout, None);
+            logical_device.destroy_render_pass(self.renderpass, None);
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+/// Both aspects of a depth/stencil image, which change layout together.
+fn depth_range(depth_format: vk::Format) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: if format_has_stencil(depth_format) {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        } else {
+            vk::ImageAspectFlags::DEPTH
+        },
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// The pipeline drawing [`PostSettings`], which multiplies the frame by twice what it
+/// outputs.
+fn init_finish_pipeline(
+    logical_device: &ash::Device,
+    renderpass: vk::RenderPass,
+) -> Result<(vk::Pipeline, vk::PipelineLayout)> {
+    /* Shaders */
+    let vertex_info = vk::ShaderModuleCreateInfo::builder()
+        .code(vk_shader_macros::include_glsl!("shaders/oit_composite.vert", kind: vert));
+    let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+    let fragment_info = vk::ShaderModuleCreateInfo::builder()
+        .code(vk_shader_macros::include_glsl!("shaders/post_finish.frag", kind: frag));
+    let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+    let main_function_name = std::ffi::CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vertex_module)
+            .name(&main_function_name)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(fragment_module)
+            .name(&main_function_name)
+            .build(),
+    ];
+
+    /* Fixed Functions */
+    let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+    let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .polygon_mode(vk::PolygonMode::FILL);
+    let multisampler_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+    // colour * destination + destination * colour, since fixed-point attachments clamp
+    // the output to [0, 1] and it could otherwise only darken.
+    let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::DST_COLOR)
+        .dst_color_blend_factor(vk::BlendFactor::SRC_COLOR)
+        .color_blend_op(vk::BlendOp::ADD)
+        .color_write_mask(
+            vk::ColorComponentFlags::R | vk::ColorComponentFlags::G | vk::ColorComponentFlags::B,
+        )
+        .build()];
+    let colourblend_info =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false)
+        .depth_write_enable(false);
+
+    /* Pipeline */
+    let push_constant_ranges = [vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        offset: 0,
+        size: 36,
+    }];
+    let pipeline_layout_info =
+        vk::PipelineLayoutCreateInfo::builder().push_constant_ranges(&push_constant_ranges);
+    let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly_info)
+        .viewport_state(&viewport_info)
+        .rasterization_state(&rasterizer_info)
+        .multisample_state(&multisampler_info)
+        .depth_stencil_state(&depth_stencil_info)
+        .color_blend_state(&colourblend_info)
+        .dynamic_state(&dynamic_state_info)
+        .layout(layout)
+        .render_pass(renderpass)
+        .subpass(0);
+    let pipeline = unsafe {
+        logical_device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)?
+    }[0];
+
+    unsafe {
+        logical_device.destroy_shader_module(fragment_module, None);
+        logical_device.destroy_shader_module(vertex_module, None)
+    }
+
+    Ok((pipeline, layout))
+}
+
+fn init_targets(
+    logical_device: &ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    swapchain: &Swapchain,
+    renderpass: vk::RenderPass,
+    samples: vk::SampleCountFlags,
+) -> Result<(Attachment, vk::ImageView, Vec<vk::Framebuffer>)> {
+    let source = Attachment::init(
+        logical_device,
+        memory_properties,
+        swapchain.extent,
+        swapchain.surface_format.format,
+        vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        vk::ImageAspectFlags::COLOR,
+        vk::SampleCountFlags::TYPE_1,
+    )?;
+
+    let depth_view = if samples == vk::SampleCountFlags::TYPE_1 {
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(swapchain.depth_image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(swapchain.depth_format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                ..depth_range(swapchain.depth_format)
+            });
+        unsafe { logical_device.create_image_view(&view_info, None) }?
+    } else {
+        vk::ImageView::null()
+    };
+
+    let mut framebuffers = vec![];
+    for image_view in &swapchain.image_views {
+        let attachments = [*image_view];
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(renderpass)
+            .attachments(&attachments)
+            .width(swapchain.extent.width)
+            .height(swapchain.extent.height)
+            .layers(1);
+        framebuffers.push(unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?);
+    }
+
+    Ok((source, depth_view, framebuffers))
+}
@@ -0,0 +1,66 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+/// Writes descriptors straight into a command buffer (`VK_KHR_push_descriptor`), for
+/// bindings that change every draw or pass, without allocating descriptor sets for them.
+pub struct PushDescriptors {
+    pub loader: ash::extensions::khr::PushDescriptor,
+    /// Most descriptors a push set layout may hold on this device.
+    pub max_push_descriptors: u32,
+}
+
+impl PushDescriptors {
+    pub fn init(
+        instance: &ash::Instance,
+        logical_device: &ash::Device,
+        physical_device: vk::PhysicalDevice,
+    ) -> Self {
+        let mut push_descriptor_properties =
+            vk::PhysicalDevicePushDescriptorPropertiesKHR::default();
+        let mut properties =
+            vk::PhysicalDeviceProperties2::builder().push_next(&mut push_descriptor_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+        PushDescriptors {
+            loader: ash::extensions::khr::PushDescriptor::new(instance, logical_device),
+            max_push_descriptors: push_descriptor_properties.max_push_descriptors,
+        }
+    }
+
+    /// Creates a set layout whose descriptors are pushed rather than allocated. A pipeline
+    /// layout may contain at most one of these.
+    pub fn create_set_layout(
+        &self,
+        logical_device: &ash::Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<vk::DescriptorSetLayout> {
+        let descriptor_count: u32 = bindings.iter().map(|b| b.descriptor_count).sum();
+        if descriptor_count > self.max_push_descriptors {
+            bail!(
+                "{} push descriptors requested, the device allows {}.",
+                descriptor_count,
+                self.max_push_descriptors
+            );
+        }
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+            .bindings(bindings);
+        let layout = unsafe { logical_device.create_descriptor_set_layout(&layout_info, None) }?;
+        Ok(layout)
+    }
+
+    /// Records `writes` into set number `set` of `layout`, which must have been made with
+    /// [`PushDescriptors::create_set_layout`]. The writes' `dst_set` is ignored.
+    pub fn push(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        set: u32,
+        writes: &[vk::WriteDescriptorSet],
+    ) {
+        unsafe {
+            self.loader
+                .cmd_push_descriptor_set(command_buffer, bind_point, layout, set, writes)
+        };
+    }
+}
@@ -0,0 +1,21 @@
+use ash::vk;
+
+use crate::capabilities::DeviceCapabilities;
+use crate::queue::{QueueFamilies, Queues};
+
+/// Vulkan objects an application already owns, for [`crate::krakatoa::Krakatoa::from_raw`].
+/// The instance needs the surface extensions for the window's platform, and the device
+/// `VK_KHR_swapchain`; both stay alive until the engine is dropped, which leaves them be.
+pub struct RawContext {
+    pub entry: ash::Entry,
+    pub instance: ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: ash::Device,
+    /// The graphics family must be able to present to the window.
+    pub queue_families: QueueFamilies,
+    pub queues: Queues,
+    /// Features the device was created with; the engine relies on none beyond them.
+    pub features: vk::PhysicalDeviceFeatures,
+    /// Optional features the device was created with.
+    pub capabilities: DeviceCapabilities,
+}
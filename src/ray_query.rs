@@ -0,0 +1,577 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+use nalgebra::Vector3;
+
+use crate::buffer::Buffer;
+use crate::model::{Instance, Model, VertexData};
+use crate::pipeline::Topology;
+
+/// Instance mask bits in the TLAS, so shaders can leave translucent instances out of
+/// shadow and occlusion rays. Picking traces both.
+pub const MASK_OPAQUE: u8 = 0x01;
+pub const MASK_TRANSLUCENT: u8 = 0x02;
+
+/// An acceleration structure and the buffer it lives in.
+pub struct AccelerationStructure {
+    pub handle: vk::AccelerationStructureKHR,
+    pub buffer: Buffer,
+    pub address: vk::DeviceAddress,
+}
+
+/// Ray-traceable copy of the scene for shaders using `GL_EXT_ray_query`: a bottom-level
+/// acceleration structure per model and a top-level one over their visible instances.
+///
+/// Binding 0 of `descriptor_set` holds the TLAS, visible to fragment and compute stages;
+/// bind it with a pipeline layout made from `descriptor_set_layout` to trace shadow or
+/// ambient occlusion rays. Instance custom indices point into `instances`.
+pub struct RayQueryScene {
+    pub loader: ash::extensions::khr::AccelerationStructure,
+    /// Indexed like the models the scene was updated with; `None` for models without
+    /// triangles or with another topology than a triangle list.
+    pub blases: Vec<Option<AccelerationStructure>>,
+    pub tlas: Option<AccelerationStructure>,
+    /// (index into models, instance handle) of each TLAS instance.
+    pub instances: Vec<(usize, usize)>,
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub pick_pipeline: vk::Pipeline,
+    pub pick_layout: vk::PipelineLayout,
+    pub pick_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pick_descriptor_set: vk::DescriptorSet,
+    /// Written by `shaders/ray_pick.comp`: instance custom index, then distance.
+    pub hit_buffer: Buffer,
+}
+
+impl RayQueryScene {
+    pub fn init(
+        instance: &ash::Instance,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+    ) -> Result<Self> {
+        /* Descriptors */
+        let scene_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE)
+            .build()];
+        let scene_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&scene_bindings);
+        let descriptor_set_layout =
+            unsafe { logical_device.create_descriptor_set_layout(&scene_layout_info, None) }?;
+        let hit_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build()];
+        let hit_layout_info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&hit_bindings);
+        let pick_descriptor_set_layout =
+            unsafe { logical_device.create_descriptor_set_layout(&hit_layout_info, None) }?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+            },
+        ];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(2)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout, pick_descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?;
+
+        let hit_buffer = Buffer::init(
+            8,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer: hit_buffer.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_sets[1])
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_info)
+            .build()];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+
+        /* Pipeline */
+        let compute_info = vk::ShaderModuleCreateInfo::builder().code(
+            vk_shader_macros::include_glsl!("shaders/ray_pick.comp", kind: comp, target: vulkan1_2),
+        );
+        let compute_module = unsafe { logical_device.create_shader_module(&compute_info, None) }?;
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(compute_module)
+            .name(&main_function_name);
+
+        let push_constant_ranges = [vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .offset(0)
+            .size(32)
+            .build()];
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pick_layout =
+            unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage.build())
+            .layout(pick_layout);
+        let pick_pipeline = unsafe {
+            logical_device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+                .map_err(|(_, e)| e)?
+        }[0];
+        unsafe { logical_device.destroy_shader_module(compute_module, None) };
+
+        Ok(RayQueryScene {
+            loader: ash::extensions::khr::AccelerationStructure::new(instance, logical_device),
+            blases: vec![],
+            tlas: None,
+            instances: vec![],
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set: descriptor_sets[0],
+            pick_pipeline,
+            pick_layout,
+            pick_descriptor_set_layout,
+            pick_descriptor_set: descriptor_sets[1],
+            hit_buffer,
+        })
+    }
+
+    /// Builds the BLAS of models it has not seen yet and rebuilds the TLAS from the
+    /// visible instances. Call after adding models or moving instances; waits for the
+    /// device, since frames in flight may still be tracing the old TLAS.
+    pub fn update<I: Instance>(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        models: &[Model<VertexData, I>],
+    ) -> Result<()> {
+        unsafe { logical_device.device_wait_idle() }?;
+        // Geometry and scratch buffers, released once the builds have finished.
+        let mut temporaries = vec![];
+        let command_buffer = begin_one_time(logical_device, command_pool)?;
+
+        while self.blases.len() < models.len() {
+            let model = &models[self.blases.len()];
+            let blas = if model.mesh.index_data.is_empty()
+                || model.mesh.topology != Topology::TriangleList
+            {
+                None
+            } else {
+                Some(self.build_blas(
+                    logical_device,
+                    memory_properties,
+                    command_buffer,
+                    model,
+                    &mut temporaries,
+                )?)
+            };
+            self.blases.push(blas);
+        }
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR)
+            .dst_access_mask(vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR);
+        unsafe {
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
+                vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
+                vk::DependencyFlags::empty(),
+                &[barrier.build()],
+                &[],
+                &[],
+            )
+        };
+
+        self.instances.clear();
+        let mut tlas_instances = vec![];
+        for (model_index, (model, blas)) in models.iter().zip(&self.blases).enumerate() {
+            let Some(blas) = blas else {
+                continue;
+            };
+            for (index, instance) in model.instances.visible().iter().enumerate() {
+                let m = instance.base().model_matrix;
+                let mask = if instance.base().is_translucent() {
+                    MASK_TRANSLUCENT
+                } else {
+                    MASK_OPAQUE
+                };
+                tlas_instances.push(vk::AccelerationStructureInstanceKHR {
+                    // Row-major 3x4, from the column-major model matrix.
+                    transform: vk::TransformMatrixKHR {
+                        matrix: [
+                            m[0][0], m[1][0], m[2][0], m[3][0], m[0][1], m[1][1], m[2][1], m[3][1],
+                            m[0][2], m[1][2], m[2][2], m[3][2],
+                        ],
+                    },
+                    instance_custom_index_and_mask: vk::Packed24_8::new(
+                        self.instances.len() as u32,
+                        mask,
+                    ),
+                    instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                        0,
+                        vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8,
+                    ),
+                    acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                        device_handle: blas.address,
+                    },
+                });
+                self.instances
+                    .push((model_index, model.instances.handles[index]));
+            }
+        }
+        let mut instance_buffer = Buffer::init(
+            std::mem::size_of_val(&tlas_instances[..]).max(16),
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            memory_properties,
+            logical_device,
+        )?;
+        instance_buffer.fill(logical_device, &tlas_instances, memory_properties)?;
+        let instances_data = vk::AccelerationStructureGeometryInstancesDataKHR::builder()
+            .array_of_pointers(false)
+            .data(vk::DeviceOrHostAddressConstKHR {
+                device_address: instance_buffer.device_address(logical_device),
+            })
+            .build();
+        let geometry = vk::AccelerationStructureGeometryKHR::builder()
+            .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR {
+                instances: instances_data,
+            })
+            .build();
+        temporaries.push(instance_buffer);
+        let tlas = self.build(
+            logical_device,
+            memory_properties,
+            command_buffer,
+            geometry,
+            tlas_instances.len() as u32,
+            &mut temporaries,
+        )?;
+
+        let submitted = end_one_time(logical_device, command_pool, queue, command_buffer);
+        for buffer in temporaries {
+            buffer.cleanup(logical_device);
+        }
+        submitted?;
+
+        if let Some(old) = self.tlas.replace(tlas) {
+            self.destroy(logical_device, &old);
+        }
+        let acceleration_structures = [self.tlas.as_ref().unwrap().handle];
+        let mut acceleration_structure_info =
+            vk::WriteDescriptorSetAccelerationStructureKHR::builder()
+                .acceleration_structures(&acceleration_structures);
+        let mut write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR)
+            .push_next(&mut acceleration_structure_info)
+            .build();
+        // Not set by the builder, since the count lives in the chained struct.
+        write.descriptor_count = 1;
+        unsafe { logical_device.update_descriptor_sets(&[write], &[]) };
+        Ok(())
+    }
+
+    fn build_blas<I: Instance>(
+        &self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_buffer: vk::CommandBuffer,
+        model: &Model<VertexData, I>,
+        temporaries: &mut Vec<Buffer>,
+    ) -> Result<AccelerationStructure> {
+        let input_usage = vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS;
+        let mut vertex_buffer = Buffer::init(
+            std::mem::size_of_val(&model.mesh.vertex_data[..]),
+            input_usage,
+            memory_properties,
+            logical_device,
+        )?;
+        vertex_buffer.fill(logical_device, &model.mesh.vertex_data, memory_properties)?;
+        let mut index_buffer = Buffer::init(
+            std::mem::size_of_val(&model.mesh.index_data[..]),
+            input_usage,
+            memory_properties,
+            logical_device,
+        )?;
+        index_buffer.fill(logical_device, &model.mesh.index_data, memory_properties)?;
+
+        let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
+            .vertex_format(vk::Format::R32G32B32_SFLOAT)
+            .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                device_address: vertex_buffer.device_address(logical_device),
+            })
+            .vertex_stride(std::mem::size_of::<VertexData>() as u64)
+            .max_vertex(model.mesh.vertex_data.len().saturating_sub(1) as u32)
+            .index_type(vk::IndexType::UINT32)
+            .index_data(vk::DeviceOrHostAddressConstKHR {
+                device_address: index_buffer.device_address(logical_device),
+            })
+            .build();
+        let geometry = vk::AccelerationStructureGeometryKHR::builder()
+            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR { triangles })
+            .flags(vk::GeometryFlagsKHR::OPAQUE)
+            .build();
+        temporaries.push(vertex_buffer);
+        temporaries.push(index_buffer);
+        self.build(
+            logical_device,
+            memory_properties,
+            command_buffer,
+            geometry,
+            (model.mesh.index_data.len() / 3) as u32,
+            temporaries,
+        )
+    }
+
+    /// Records the build of a BLAS, or of the TLAS for instance geometry.
+    fn build(
+        &self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_buffer: vk::CommandBuffer,
+        geometry: vk::AccelerationStructureGeometryKHR,
+        primitive_count: u32,
+        temporaries: &mut Vec<Buffer>,
+    ) -> Result<AccelerationStructure> {
+        let ty = if geometry.geometry_type == vk::GeometryTypeKHR::INSTANCES {
+            vk::AccelerationStructureTypeKHR::TOP_LEVEL
+        } else {
+            vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL
+        };
+        let geometries = [geometry];
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
+            .ty(ty)
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .geometries(&geometries);
+        let sizes = unsafe {
+            self.loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[primitive_count],
+            )
+        };
+
+        let buffer = Buffer::init(
+            sizes.acceleration_structure_size as usize,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            memory_properties,
+            logical_device,
+        )?;
+        let create_info = vk::AccelerationStructureCreateInfoKHR::builder()
+            .buffer(buffer.buffer)
+            .size(sizes.acceleration_structure_size)
+            .ty(ty);
+        let handle = unsafe {
+            self.loader
+                .create_acceleration_structure(&create_info, None)
+        }?;
+        let scratch = Buffer::init(
+            sizes.build_scratch_size as usize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            memory_properties,
+            logical_device,
+        )?;
+
+        build_info = build_info.dst_acceleration_structure(handle).scratch_data(
+            vk::DeviceOrHostAddressKHR {
+                device_address: scratch.device_address(logical_device),
+            },
+        );
+        let range = vk::AccelerationStructureBuildRangeInfoKHR::builder()
+            .primitive_count(primitive_count)
+            .build();
+        unsafe {
+            self.loader.cmd_build_acceleration_structures(
+                command_buffer,
+                &[build_info.build()],
+                &[&[range]],
+            )
+        };
+        temporaries.push(scratch);
+
+        let address_info =
+            vk::AccelerationStructureDeviceAddressInfoKHR::builder().acceleration_structure(handle);
+        let address = unsafe {
+            self.loader
+                .get_acceleration_structure_device_address(&address_info)
+        };
+        Ok(AccelerationStructure {
+            handle,
+            buffer,
+            address,
+        })
+    }
+
+    /// Traces a ray against the TLAS on the GPU and returns the (index into models,
+    /// instance handle) it hits first, with the distance along the normalized `direction`.
+    pub fn pick(
+        &self,
+        logical_device: &ash::Device,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        origin: &Vector3<f32>,
+        direction: &Vector3<f32>,
+        max_distance: f32,
+    ) -> Result<Option<(usize, usize, f32)>> {
+        if self.tlas.is_none() {
+            return Ok(None);
+        }
+        let direction = direction.normalize();
+        let push_constants = [
+            origin.x,
+            origin.y,
+            origin.z,
+            max_distance,
+            direction.x,
+            direction.y,
+            direction.z,
+            0.0,
+        ];
+        let command_buffer = begin_one_time(logical_device, command_pool)?;
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pick_pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pick_layout,
+                0,
+                &[self.descriptor_set, self.pick_descriptor_set],
+                &[],
+            );
+            logical_device.cmd_push_constants(
+                command_buffer,
+                self.pick_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(push_constants.as_ptr() as *const u8, 32),
+            );
+            logical_device.cmd_dispatch(command_buffer, 1, 1, 1);
+            let barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ);
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[barrier.build()],
+                &[],
+                &[],
+            );
+        }
+        end_one_time(logical_device, command_pool, queue, command_buffer)?;
+
+        let (custom_index, distance) = unsafe {
+            let data = logical_device.map_memory(
+                self.hit_buffer.memory,
+                0,
+                8,
+                vk::MemoryMapFlags::empty(),
+            )? as *const u32;
+            let hit = (*data, f32::from_bits(*data.add(1)));
+            logical_device.unmap_memory(self.hit_buffer.memory);
+            hit
+        };
+        Ok(self
+            .instances
+            .get(custom_index as usize)
+            .map(|&(model, handle)| (model, handle, distance)))
+    }
+
+    fn destroy(
+        &self,
+        logical_device: &ash::Device,
+        acceleration_structure: &AccelerationStructure,
+    ) {
+        unsafe {
+            self.loader
+                .destroy_acceleration_structure(acceleration_structure.handle, None)
+        };
+        acceleration_structure.buffer.cleanup(logical_device);
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        for acceleration_structure in self.blases.iter().chain([&self.tlas]).flatten() {
+            self.destroy(logical_device, acceleration_structure);
+        }
+        self.hit_buffer.cleanup(logical_device);
+        unsafe {
+            logical_device.destroy_pipeline(self.pick_pipeline, None);
+            logical_device.destroy_pipeline_layout(self.pick_layout, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            logical_device.destroy_descriptor_set_layout(self.pick_descriptor_set_layout, None);
+        }
+    }
+}
+
+pub(crate) fn begin_one_time(
+    logical_device: &ash::Device,
+    command_pool: vk::CommandPool,
+) -> Result<vk::CommandBuffer> {
+    let allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(command_pool)
+        .command_buffer_count(1);
+    let command_buffer = unsafe { logical_device.allocate_command_buffers(&allocate_info) }?[0];
+    let begin_info =
+        vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe { logical_device.begin_command_buffer(command_buffer, &begin_info) }?;
+    Ok(command_buffer)
+}
+
+/// Submits the command buffer, waits for it and frees it.
+pub(crate) fn end_one_time(
+    logical_device: &ash::Device,
+    command_pool: vk::CommandPool,
+    queue: vk::Queue,
+    command_buffer: vk::CommandBuffer,
+) -> Result<()> {
+    let command_buffers = [command_buffer];
+    let submit_info = vk::SubmitInfo::builder().command_buffers(&command_buffers);
+    unsafe {
+        logical_device.end_command_buffer(command_buffer)?;
+        let fence = logical_device.create_fence(&vk::FenceCreateInfo::default(), None)?;
+        let waited = logical_device
+            .queue_submit(queue, &[submit_info.build()], fence)
+            .and_then(|_| logical_device.wait_for_fences(&[fence], true, u64::MAX));
+        logical_device.destroy_fence(fence, None);
+        logical_device.free_command_buffers(command_pool, &command_buffers);
+        waited?;
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+/// An attachment of the main render pass that [`crate::krakatoa::Krakatoa::read_attachment`]
+/// can copy to the host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttachmentId {
+    /// The swapchain image of the latest frame, as presented: RGBA, 8 bits per channel.
+    /// HDR output comes back encoded for the display, with the precision cut to 8 bits.
+    Colour,
+    /// The depth buffer of the latest frame, one native-endian `f32` per pixel in [0, 1].
+    /// Not available with MSAA.
+    Depth,
+}
+
+/// Bytes per texel of `format` in a buffer copied from the aspect `attachment` reads.
+pub(crate) fn texel_size(attachment: AttachmentId, format: vk::Format) -> Result<u64> {
+    Ok(match (attachment, format) {
+        (
+            AttachmentId::Colour,
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A2B10G10R10_UNORM_PACK32
+            | vk::Format::A2R10G10B10_UNORM_PACK32,
+        ) => 4,
+        (AttachmentId::Depth, vk::Format::D16_UNORM | vk::Format::D16_UNORM_S8_UINT) => 2,
+        (
+            AttachmentId::Depth,
+            vk::Format::D32_SFLOAT
+            | vk::Format::D32_SFLOAT_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::X8_D24_UNORM_PACK32,
+        ) => 4,
+        _ => bail!(
+            "{:?} attachments in {:?} cannot be read back",
+            attachment,
+            format
+        ),
+    })
+}
+
+/// Converts texels copied from an attachment in `format` into the layout documented on
+/// [`AttachmentId`].
+pub(crate) fn convert(attachment: AttachmentId, format: vk::Format, texels: &[u8]) -> Vec<u8> {
+    let packed = || {
+        texels
+            .chunks_exact(4)
+            .map(|texel| u32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]))
+    };
+    match (attachment, format) {
+        (AttachmentId::Colour, vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB) => texels
+            .chunks_exact(4)
+            .flat_map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+            .collect(),
+        (AttachmentId::Colour, vk::Format::A2B10G10R10_UNORM_PACK32) => packed()
+            .flat_map(|texel| {
+                [
+                    (texel >> 2) as u8,
+                    (texel >> 12) as u8,
+                    (texel >> 22) as u8,
+                    ((texel >> 30) * 85) as u8,
+                ]
+            })
+            .collect(),
+        (AttachmentId::Colour, vk::Format::A2R10G10B10_UNORM_PACK32) => packed()
+            .flat_map(|texel| {
+                [
+                    (texel >> 22) as u8,
+                    (texel >> 12) as u8,
+                    (texel >> 2) as u8,
+                    ((texel >> 30) * 85) as u8,
+                ]
+            })
+            .collect(),
+        (AttachmentId::Depth, vk::Format::D16_UNORM | vk::Format::D16_UNORM_S8_UINT) => texels
+            .chunks_exact(2)
+            .flat_map(|texel| {
+                (u16::from_ne_bytes([texel[0], texel[1]]) as f32 / u16::MAX as f32).to_ne_bytes()
+            })
+            .collect(),
+        // The depth aspect of these is copied with the top 8 bits undefined.
+        (AttachmentId::Depth, vk::Format::D24_UNORM_S8_UINT | vk::Format::X8_D24_UNORM_PACK32) => {
+            packed()
+                .flat_map(|texel| ((texel & 0xff_ffff) as f32 / 0xff_ffff as f32).to_ne_bytes())
+                .collect()
+        }
+        _ => texels.to_vec(),
+    }
+}
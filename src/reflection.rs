@@ -0,0 +1,1035 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+use nalgebra::{Matrix3, Matrix4, Unit, Vector3, Vector4};
+
+use crate::allocations;
+use crate::buffer::Buffer;
+use crate::camera::Camera;
+use crate::find_memorytype_index;
+use crate::format_has_stencil;
+use crate::init_descriptor_sets;
+use crate::layout_cache::LayoutCache;
+use crate::model::{Instance, VertexData};
+use crate::multisample::Multisampling;
+use crate::pipeline::Pipeline;
+use crate::ray_query::{begin_one_time, end_one_time};
+use crate::swapchain::Attachment;
+
+/// Probes the lit shader blends between; must match `shaders/shader.frag`.
+pub const MAX_REFLECTION_PROBES: usize = 8;
+
+/// Format of the planar reflection and the probe cubemaps.
+pub const REFLECTION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// A flat mirror: the points `p` with `normal.dot(p) + distance == 0`. Surfaces lying in
+/// it with a matching normal show the planar reflection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReflectionPlane {
+    pub normal: Unit<Vector3<f32>>,
+    pub distance: f32,
+}
+
+impl ReflectionPlane {
+    /// The plane through `point` facing `normal`.
+    pub fn new(point: Vector3<f32>, normal: Vector3<f32>) -> Self {
+        let normal = Unit::new_normalize(normal);
+        ReflectionPlane {
+            normal,
+            distance: -normal.dot(&point),
+        }
+    }
+
+    /// Mirrors world-space points through the plane.
+    pub fn reflection_matrix(&self) -> Matrix4<f32> {
+        let n = self.normal.into_inner();
+        let mut matrix = (Matrix3::identity() - 2.0 * n * n.transpose()).to_homogeneous();
+        matrix
+            .fixed_view_mut::<3, 1>(0, 3)
+            .copy_from(&(-2.0 * self.distance * n));
+        matrix
+    }
+
+    /// The view and projection of `camera` seen in the mirror. The near plane is moved
+    /// onto the mirror, so nothing on the far side of it is reflected.
+    pub fn mirror(&self, camera: &Camera) -> (Matrix4<f32>, Matrix4<f32>) {
+        let view = camera.view_matrix * self.reflection_matrix();
+        let camera_position = camera
+            .view_matrix
+            .try_inverse()
+            .map_or(camera.position, |inverse| {
+                inverse.fixed_view::<3, 1>(0, 3).into_owned()
+            });
+        // What is reflected lies on the camera's side of the plane.
+        let side = (self.normal.dot(&camera_position) + self.distance).signum();
+        let plane = side * Vector4::new(self.normal.x, self.normal.y, self.normal.z, self.distance);
+        let projection = match view.try_inverse() {
+            Some(inverse) => {
+                oblique_projection(&camera.projection_matrix, inverse.transpose() * plane)
+            }
+            None => camera.projection_matrix,
+        };
+        (view, projection)
+    }
+}
+
+/// `projection` with its near plane replaced by `clip_plane`, given in view space with
+/// the visible side positive, after Lengyel. The far plane is tilted as little as keeps
+/// the whole frustum in front of it.
+pub fn oblique_projection(projection: &Matrix4<f32>, clip_plane: Vector4<f32>) -> Matrix4<f32> {
+    let Some(inverse) = projection.try_inverse() else {
+        return *projection;
+    };
+    let corner = inverse * Vector4::new(clip_plane.x.signum(), clip_plane.y.signum(), 1.0, 1.0);
+    let facing = clip_plane.dot(&corner);
+    if facing.abs() < f32::EPSILON {
+        return *projection;
+    }
+    let w_row = projection.row(3).transpose();
+    let scale = w_row.dot(&corner) / facing;
+    let mut oblique = *projection;
+    oblique.set_row(2, &(scale * clip_plane).transpose());
+    oblique
+}
+
+/// The volume a [`ReflectionProbe`] stands for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProbeShape {
+    Sphere {
+        radius: f32,
+    },
+    /// An axis-aligned box.
+    Box {
+        half_extents: Vector3<f32>,
+    },
+}
+
+/// A cubemap of the scene captured at `position` and reflected by what lies inside its
+/// volume, corrected for the volume's walls so nearby geometry is reflected in place.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReflectionProbe {
+    pub position: Vector3<f32>,
+    pub shape: ProbeShape,
+    /// Distance inside the volume over which the probe fades out, so neighbours blend.
+    pub blend_distance: f32,
+    /// Clip distances of the capture.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl ReflectionProbe {
+    pub fn sphere(position: Vector3<f32>, radius: f32) -> Self {
+        ReflectionProbe {
+            position,
+            shape: ProbeShape::Sphere { radius },
+            blend_distance: 0.1 * radius,
+            near: 0.05,
+            far: 100.0,
+        }
+    }
+
+    pub fn cuboid(position: Vector3<f32>, half_extents: Vector3<f32>) -> Self {
+        ReflectionProbe {
+            position,
+            shape: ProbeShape::Box { half_extents },
+            blend_distance: 0.1 * half_extents.min(),
+            near: 0.05,
+            far: 100.0,
+        }
+    }
+
+    /// View matrices of the cube faces in layer order, +x, -x, +y, -y, +z, -z, oriented
+    /// as Vulkan samples cubemaps. Each is a mirror image of an ordinary camera.
+    pub fn face_views(&self) -> [Matrix4<f32>; 6] {
+        let x = Vector3::x();
+        let y = Vector3::y();
+        let z = Vector3::z();
+        // (right, down, forward) of every face.
+        let faces = [
+            (-z, -y, x),
+            (z, -y, -x),
+            (x, z, y),
+            (x, -z, -y),
+            (x, -y, z),
+            (-x, -y, -z),
+        ];
+        faces.map(|(right, down, forward)| {
+            let p = self.position;
+            Matrix4::new(
+                right.x,
+                right.y,
+                right.z,
+                -right.dot(&p), //
+                down.x,
+                down.y,
+                down.z,
+                -down.dot(&p), //
+                forward.x,
+                forward.y,
+                forward.z,
+                -forward.dot(&p), //
+                0.0,
+                0.0,
+                0.0,
+                1.0,
+            )
+        })
+    }
+
+    /// The 90 degree projection of every face.
+    pub fn face_projection(&self) -> Matrix4<f32> {
+        let far = self.far.max(self.near * 2.0);
+        let depth_scale = far / (far - self.near);
+        Matrix4::new(
+            1.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            depth_scale,
+            -self.near * depth_scale,
+            0.0,
+            0.0,
+            1.0,
+            0.0,
+        )
+    }
+
+    fn to_uniform(self) -> [[f32; 4]; 2] {
+        let p = self.position;
+        let (shape, extents) = match self.shape {
+            ProbeShape::Sphere { radius } => (0.0, Vector3::repeat(radius)),
+            ProbeShape::Box { half_extents } => (1.0, half_extents),
+        };
+        [
+            [p.x, p.y, p.z, shape],
+            [extents.x, extents.y, extents.z, self.blend_distance],
+        ]
+    }
+}
+
+/// Contents of the lit shader's reflection block, see `shaders/shader.frag`.
+type ReflectionUniform = [[f32; 4]; 3 + 2 * MAX_REFLECTION_PROBES];
+
+/// Planar reflections and reflection probes. The scene is drawn into the planar target
+/// from the mirrored camera every frame, and into the probes' cubemaps when captured;
+/// the opaque triangle lists are then shaded by a variant of the main pipeline that
+/// blends in what they reflect.
+pub struct Reflections {
+    /// Draws into the reflection targets, which it leaves ready for sampling.
+    pub renderpass: vk::RenderPass,
+    /// The main pipeline made for `renderpass`, with the winding flipped.
+    pub capture_pipeline: Pipeline,
+    /// View and projection of whichever capture is drawn next.
+    pub camera_buffer: Buffer,
+    pub capture_descriptor_pool: vk::DescriptorPool,
+    pub capture_descriptor_set: vk::DescriptorSet,
+    /// The main pipeline's shading plus reflections, for the main render pass.
+    pub lit_pipeline: vk::Pipeline,
+    pub lit_layout: vk::PipelineLayout,
+    /// Set 1 of `lit_layout`: the parameters, the probe cubemaps and the planar target.
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub parameters: Buffer,
+    pub sampler: vk::Sampler,
+    pub planar_extent: vk::Extent2D,
+    pub planar_colour: Attachment,
+    pub planar_depth: Attachment,
+    pub planar_framebuffer: vk::Framebuffer,
+    /// Width and height of every cube face.
+    pub probe_resolution: u32,
+    /// `MAX_REFLECTION_PROBES` cubemaps, one after the other.
+    pub probe_image: vk::Image,
+    pub probe_memory: vk::DeviceMemory,
+    /// A `CUBE_ARRAY` view over every probe.
+    pub probe_view: vk::ImageView,
+    /// One 2D view and framebuffer per face, six per probe.
+    pub face_views: Vec<vk::ImageView>,
+    pub face_framebuffers: Vec<vk::Framebuffer>,
+    pub probe_depth: Attachment,
+    /// Drawn every frame while set.
+    pub plane: Option<ReflectionPlane>,
+    /// Only the first `MAX_REFLECTION_PROBES` are used; capture them again after changing
+    /// them with [`crate::krakatoa::Krakatoa::capture_reflection_probes`].
+    pub probes: Vec<ReflectionProbe>,
+    /// Reflectance at normal incidence of surfaces reflecting the probes, from 0 to 1.
+    pub reflectivity: f32,
+    /// Reflectance at normal incidence of surfaces on `plane`.
+    pub planar_reflectivity: f32,
+}
+
+impl Reflections {
+    #[allow(clippy::too_many_arguments)]
+    pub fn init<I: Instance>(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+        layout_cache: &mut LayoutCache,
+        light_buffer: &Buffer,
+        depth_format: vk::Format,
+        probe_resolution: u32,
+        planar_extent: vk::Extent2D,
+    ) -> Result<Self> {
+        if probe_resolution == 0 || planar_extent.width == 0 || planar_extent.height == 0 {
+            bail!("Reflection targets need at least one pixel.");
+        }
+        let depth_aspect = if format_has_stencil(depth_format) {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        } else {
+            vk::ImageAspectFlags::DEPTH
+        };
+
+        /* Capture Render Pass */
+        let stencil_load_op = if format_has_stencil(depth_format) {
+            vk::AttachmentLoadOp::CLEAR
+        } else {
+            vk::AttachmentLoadOp::DONT_CARE
+        };
+        let attachments = [
+            vk::AttachmentDescription::builder()
+                .format(REFLECTION_FORMAT)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+            vk::AttachmentDescription::builder()
+                .format(depth_format)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(stencil_load_op)
+                .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .build(),
+        ];
+        let colour_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let depth_attachment_ref = vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        let subpasses = [vk::SubpassDescription::builder()
+            .color_attachments(&colour_attachment_refs)
+            .depth_stencil_attachment(&depth_attachment_ref)
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .build()];
+        // The previous frame's shading must be done reading the target before it is drawn
+        // over, and this frame's shading must wait for it to be drawn.
+        let subpass_dependencies = [
+            vk::SubpassDependency::builder()
+                .src_subpass(vk::SUBPASS_EXTERNAL)
+                .src_stage_mask(
+                    vk::PipelineStageFlags::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                )
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_subpass(0)
+                .dst_stage_mask(
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                )
+                .dst_access_mask(
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )
+                .build(),
+            vk::SubpassDependency::builder()
+                .src_subpass(0)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_subpass(vk::SUBPASS_EXTERNAL)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .build(),
+        ];
+        let renderpass_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&subpass_dependencies);
+        let capture_renderpass =
+            unsafe { logical_device.create_render_pass(&renderpass_info, None) }?;
+
+        let capture_pipeline = Pipeline::init_mirrored::<VertexData, I>(
+            logical_device,
+            &capture_renderpass,
+            Multisampling::default(),
+            layout_cache,
+        )?;
+        let mut camera_buffer = Buffer::init(
+            128,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        let camera_transforms: [[[f32; 4]; 4]; 2] =
+            [Matrix4::identity().into(), Matrix4::identity().into()];
+        camera_buffer.fill(logical_device, &camera_transforms, memory_properties)?;
+        camera_buffer.set_name(logical_device, "reflection camera uniforms");
+        let (capture_descriptor_pool, capture_descriptor_sets) = init_descriptor_sets(
+            logical_device,
+            &capture_pipeline,
+            &camera_buffer,
+            light_buffer,
+            1,
+        )?;
+
+        /* Targets */
+        let target_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | vk::ImageUsageFlags::SAMPLED
+            | vk::ImageUsageFlags::TRANSFER_DST;
+        let planar_colour = Attachment::init(
+            logical_device,
+            memory_properties,
+            planar_extent,
+            REFLECTION_FORMAT,
+            target_usage,
+            vk::ImageAspectFlags::COLOR,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+        let planar_depth = Attachment::init(
+            logical_device,
+            memory_properties,
+            planar_extent,
+            depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            depth_aspect,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+        let planar_framebuffer = init_framebuffer(
+            logical_device,
+            capture_renderpass,
+            planar_colour.view,
+            planar_depth.view,
+            planar_extent,
+        )?;
+
+        let face_extent = vk::Extent2D {
+            width: probe_resolution,
+            height: probe_resolution,
+        };
+        let layer_count = 6 * MAX_REFLECTION_PROBES as u32;
+        let probe_info = vk::ImageCreateInfo::builder()
+            .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(REFLECTION_FORMAT)
+            .extent(vk::Extent3D {
+                width: probe_resolution,
+                height: probe_resolution,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(layer_count)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(target_usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let probe_image = unsafe { logical_device.create_image(&probe_info, None) }?;
+        let memory_requirements =
+            unsafe { logical_device.get_image_memory_requirements(probe_image) };
+        allocations::track(
+            logical_device,
+            probe_image,
+            memory_requirements.size,
+            "reflection probe cubemaps",
+        );
+        let memory_index = find_memorytype_index(
+            &memory_requirements,
+            &memory_properties,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+        .expect("Unable to find suitable memory index for the reflection probes.");
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(memory_index);
+        let probe_memory = unsafe { logical_device.allocate_memory(&allocate_info, None) }?;
+        allocations::track(
+            logical_device,
+            probe_memory,
+            memory_requirements.size,
+            "memory of the reflection probe cubemaps",
+        );
+        unsafe { logical_device.bind_image_memory(probe_image, probe_memory, 0) }?;
+
+        let probe_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count,
+        };
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(probe_image)
+            .view_type(vk::ImageViewType::CUBE_ARRAY)
+            .format(REFLECTION_FORMAT)
+            .subresource_range(probe_range);
+        let probe_view = unsafe { logical_device.create_image_view(&view_info, None) }?;
+        let probe_depth = Attachment::init(
+            logical_device,
+            memory_properties,
+            face_extent,
+            depth_format,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            depth_aspect,
+            vk::SampleCountFlags::TYPE_1,
+        )?;
+        let mut face_views = vec![];
+        let mut face_framebuffers = vec![];
+        for layer in 0..layer_count {
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .image(probe_image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(REFLECTION_FORMAT)
+                .subresource_range(vk::ImageSubresourceRange {
+                    base_array_layer: layer,
+                    layer_count: 1,
+                    ..probe_range
+                });
+            let view = unsafe { logical_device.create_image_view(&view_info, None) }?;
+            face_views.push(view);
+            face_framebuffers.push(init_framebuffer(
+                logical_device,
+                capture_renderpass,
+                view,
+                probe_depth.view,
+                face_extent,
+            )?);
+        }
+
+        // Black until something is drawn into them, so sampling is defined from the start.
+        let command_buffer = begin_one_time(logical_device, command_pool)?;
+        unsafe {
+            let colour_range = vk::ImageSubresourceRange {
+                layer_count: 1,
+                ..probe_range
+            };
+            let barrier =
+                |image, range, old_layout, new_layout, src_access_mask, dst_access_mask| {
+                    vk::ImageMemoryBarrier::builder()
+                        .image(image)
+                        .old_layout(old_layout)
+                        .new_layout(new_layout)
+                        .src_access_mask(src_access_mask)
+                        .dst_access_mask(dst_access_mask)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .subresource_range(range)
+                        .build()
+                };
+            let targets = [
+                (planar_colour.image, colour_range),
+                (probe_image, probe_range),
+            ];
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &targets.map(|(image, range)| {
+                    barrier(
+                        image,
+                        range,
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::empty(),
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    )
+                }),
+            );
+            for (image, range) in targets {
+                logical_device.cmd_clear_color_image(
+                    command_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &vk::ClearColorValue {
+                        float32: [0.0, 0.0, 0.0, 1.0],
+                    },
+                    &[range],
+                );
+            }
+            logical_device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &targets.map(|(image, range)| {
+                    barrier(
+                        image,
+                        range,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::SHADER_READ,
+                    )
+                }),
+            );
+        }
+        end_one_time(logical_device, command_pool, queue, command_buffer)?;
+
+        /* Sampler */
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+        /* Descriptors */
+        let sampled = |binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        };
+        let descriptor_set_layout_bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            sampled(1),
+            sampled(2),
+        ];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 2,
+            },
+        ];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let mut parameters = Buffer::init(
+            std::mem::size_of::<ReflectionUniform>(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        parameters.fill(
+            logical_device,
+            &[[[0.0f32; 4]; 3 + 2 * MAX_REFLECTION_PROBES]],
+            memory_properties,
+        )?;
+        parameters.set_name(logical_device, "reflection uniforms");
+
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer: parameters.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let image_infos = [probe_view, planar_colour.view].map(|image_view| {
+            [vk::DescriptorImageInfo {
+                sampler,
+                image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }]
+        });
+        let mut writes = vec![vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(&buffer_info)
+            .build()];
+        for (binding, image_info) in (1..).zip(&image_infos) {
+            writes.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+                    .build(),
+            );
+        }
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+
+        let (lit_pipeline, lit_layout) =
+            init_lit_pipeline(logical_device, renderpass, pipeline, descriptor_set_layout)?;
+
+        Ok(Reflections {
+            renderpass: capture_renderpass,
+            capture_pipeline,
+            camera_buffer,
+            capture_descriptor_pool,
+            capture_descriptor_set: capture_descriptor_sets[0],
+            lit_pipeline,
+            lit_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            parameters,
+            sampler,
+            planar_extent,
+            planar_colour,
+            planar_depth,
+            planar_framebuffer,
+            probe_resolution,
+            probe_image,
+            probe_memory,
+            probe_view,
+            face_views,
+            face_framebuffers,
+            probe_depth,
+            plane: None,
+            probes: vec![],
+            reflectivity: 0.04,
+            planar_reflectivity: 0.5,
+        })
+    }
+
+    /// Uploads the mirrored `camera` for the planar reflection and the parameters the lit
+    /// shader reads; called for every frame before it is recorded. `viewport` is the part
+    /// of the swapchain the scene is framed in.
+    pub fn update(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        camera: &Camera,
+        viewport: vk::Rect2D,
+    ) -> Result<()> {
+        let mut uniform: ReflectionUniform = [[0.0; 4]; 3 + 2 * MAX_REFLECTION_PROBES];
+        if let Some(plane) = &self.plane {
+            let (view, projection) = plane.mirror(camera);
+            let camera_transforms: [[[f32; 4]; 4]; 2] = [view.into(), projection.into()];
+            self.camera_buffer
+                .fill(logical_device, &camera_transforms, memory_properties)?;
+            uniform[0] = [
+                plane.normal.x,
+                plane.normal.y,
+                plane.normal.z,
+                plane.distance,
+            ];
+        }
+        uniform[1] = [
+            viewport.offset.x as f32,
+            viewport.offset.y as f32,
+            viewport.extent.width as f32,
+            viewport.extent.height as f32,
+        ];
+        let probe_count = self.probes.len().min(MAX_REFLECTION_PROBES);
+        uniform[2] = [
+            self.reflectivity.clamp(0.0, 1.0),
+            self.planar_reflectivity.clamp(0.0, 1.0),
+            probe_count as f32,
+            if self.plane.is_some() { 1.0 } else { 0.0 },
+        ];
+        for (index, probe) in self.probes.iter().take(probe_count).enumerate() {
+            let [position, extents] = probe.to_uniform();
+            uniform[3 + 2 * index] = position;
+            uniform[4 + 2 * index] = extents;
+        }
+        self.parameters
+            .fill(logical_device, &[uniform], memory_properties)
+    }
+
+    /// Uploads the view and projection the next capture is drawn with. The device must
+    /// not be using the camera buffer.
+    pub fn set_capture_camera(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        view: &Matrix4<f32>,
+        projection: &Matrix4<f32>,
+    ) -> Result<()> {
+        let camera_transforms: [[[f32; 4]; 4]; 2] = [(*view).into(), (*projection).into()];
+        self.camera_buffer
+            .fill(logical_device, &camera_transforms, memory_properties)
+    }
+
+    /// The framebuffer of face `face` of probe `probe`.
+    pub fn face_framebuffer(&self, probe: usize, face: usize) -> vk::Framebuffer {
+        self.face_framebuffers[6 * probe + face]
+    }
+
+    /// Begins drawing into `framebuffer`, one of the reflection targets, with the capture
+    /// pipeline and its camera bound. Triangle-list meshes can be drawn until
+    /// [`Reflections::end`].
+    ///# Safety
+    ///
+    /// `command_buffer` must be recording, outside a render pass.
+    pub unsafe fn begin(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        clear_colour: [f32; 4],
+    ) {
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: clear_colour,
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ];
+        let area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        let renderpass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.renderpass)
+            .framebuffer(framebuffer)
+            .render_area(area)
+            .clear_values(&clear_values);
+        logical_device.cmd_begin_render_pass(
+            command_buffer,
+            &renderpass_begin_info,
+            vk::SubpassContents::INLINE,
+        );
+        logical_device.cmd_set_viewport(
+            command_buffer,
+            0,
+            &[vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: extent.width as f32,
+                height: extent.height as f32,
+                min_depth: 0.,
+                max_depth: 1.,
+            }],
+        );
+        logical_device.cmd_set_scissor(command_buffer, 0, &[area]);
+        logical_device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.capture_pipeline.pipeline,
+        );
+        logical_device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.capture_pipeline.layout,
+            0,
+            &[self.capture_descriptor_set],
+            &[],
+        );
+    }
+
+    ///# Safety
+    ///
+    /// Must follow [`Reflections::begin`] in the same command buffer.
+    pub unsafe fn end(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        logical_device.cmd_end_render_pass(command_buffer);
+    }
+
+    /// Binds the lit pipeline and set 1 in subpass 0 of the main render pass, after the
+    /// main pipeline's set 0.
+    pub fn bind_lit(&self, logical_device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.lit_pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.lit_layout,
+                1,
+                &[self.descriptor_set],
+                &[],
+            );
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            for framebuffer in &self.face_framebuffers {
+                logical_device.destroy_framebuffer(*framebuffer, None);
+            }
+            for view in &self.face_views {
+                logical_device.destroy_image_view(*view, None);
+            }
+            logical_device.destroy_framebuffer(self.planar_framebuffer, None);
+            logical_device.destroy_image_view(self.probe_view, None);
+            allocations::untrack(logical_device, self.probe_image);
+            allocations::untrack(logical_device, self.probe_memory);
+            logical_device.destroy_image(self.probe_image, None);
+            logical_device.free_memory(self.probe_memory, None);
+            self.probe_depth.cleanup(logical_device);
+            self.planar_colour.cleanup(logical_device);
+            self.planar_depth.cleanup(logical_device);
+            self.parameters.cleanup(logical_device);
+            self.camera_buffer.cleanup(logical_device);
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_descriptor_pool(self.capture_descriptor_pool, None);
+            logical_device.destroy_pipeline(self.lit_pipeline, None);
+            logical_device.destroy_pipeline_layout(self.lit_layout, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.capture_pipeline.cleanup(logical_device);
+            logical_device.destroy_render_pass(self.renderpass, None);
+        }
+    }
+}
+
+pub(crate) fn init_framebuffer(
+    logical_device: &ash::Device,
+    renderpass: vk::RenderPass,
+    colour: vk::ImageView,
+    depth: vk::ImageView,
+    extent: vk::Extent2D,
+) -> Result<vk::Framebuffer> {
+    let attachments = [colour, depth];
+    let framebuffer_info = vk::FramebufferCreateInfo::builder()
+        .render_pass(renderpass)
+        .attachments(&attachments)
+        .width(extent.width)
+        .height(extent.height)
+        .layers(1);
+    Ok(unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?)
+}
+
+/// The main pipeline's shading with `shaders/shader.frag` built with `REFLECTIONS`,
+/// reading `descriptor_set_layout` as set 1.
+fn init_lit_pipeline(
+    logical_device: &ash::Device,
+    renderpass: &vk::RenderPass,
+    pipeline: &Pipeline,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+) -> Result<(vk::Pipeline, vk::PipelineLayout)> {
+    /* Shaders */
+    let vertex_info = vk::ShaderModuleCreateInfo::builder()
+        .code(vk_shader_macros::include_glsl!("shaders/shader.vert", kind: vert));
+    let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+    let fragment_info =
+        vk::ShaderModuleCreateInfo::builder().code(vk_shader_macros::include_glsl!(
+            "shaders/shader.frag",
+            kind: frag,
+            define: REFLECTIONS,
+        ));
+    let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+    let main_function_name = std::ffi::CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(vertex_module)
+            .name(&main_function_name)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(fragment_module)
+            .name(&main_function_name)
+            .build(),
+    ];
+
+    /* Fixed Functions */
+    let vertex_input_info = pipeline.vertex_input.state_info();
+    let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+    let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+    let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .line_width(1.0)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .cull_mode(vk::CullModeFlags::BACK)
+        .polygon_mode(vk::PolygonMode::FILL);
+    let multisampler_info = pipeline.multisampling.state_info();
+    let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .build()];
+    let colourblend_info =
+        vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+    let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+    /* Pipeline */
+    let set_layouts = [pipeline.descriptor_set_layouts[0], descriptor_set_layout];
+    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+    let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+    let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_info)
+        .input_assembly_state(&input_assembly_info)
+        .viewport_state(&viewport_info)
+        .rasterization_state(&rasterizer_info)
+        .multisample_state(&multisampler_info)
+        .depth_stencil_state(&depth_stencil_info)
+        .color_blend_state(&colourblend_info)
+        .dynamic_state(&dynamic_state_info)
+        .layout(layout)
+        .render_pass(*renderpass)
+        .subpass(0);
+    let graphics_pipeline = unsafe {
+        logical_device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .map_err(|(_, e)| e)?
+    }[0];
+
+    unsafe {
+        logical_device.destroy_shader_module(fragment_module, None);
+        logical_device.destroy_shader_module(vertex_module, None);
+    }
+
+    Ok((graphics_pipeline, layout))
+}
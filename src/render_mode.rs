@@ -0,0 +1,42 @@
+/// What the scene's fragment shaders output, for diagnosing shading issues. Every mode
+/// but `Lit` skips lighting and fog.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderMode {
+    #[default]
+    Lit,
+    /// The unlit surface colour.
+    Albedo,
+    /// World-space normals, mapped from [-1, 1] to [0, 1].
+    Normals,
+    /// Distance from the camera, black up close and white at the depth range.
+    Depth,
+    /// Brighter where more fragments land on a pixel. The main pipeline's triangle
+    /// lists are drawn without depth testing to count every layer.
+    Overdraw,
+    /// A colour hashed from the instance index within each draw.
+    InstanceId,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 6] = [
+        RenderMode::Lit,
+        RenderMode::Albedo,
+        RenderMode::Normals,
+        RenderMode::Depth,
+        RenderMode::Overdraw,
+        RenderMode::InstanceId,
+    ];
+
+    /// The mode after this one, wrapping back to `Lit`.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// std140 layout of the render mode member of the fragment shader's light block:
+    /// the mode, then the distance `Depth` shows as white.
+    pub fn to_uniform(self, depth_range: f32) -> [f32; 4] {
+        [self as u32 as f32, depth_range.max(f32::EPSILON), 0.0, 0.0]
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::swapchain::Attachment;
+
+/// Everything an attachment is made from, plus the role it plays so two targets that
+/// happen to match, such as the OIT accumulation and an HDR scene, stay apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AttachmentKey {
+    pub role: &'static str,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub aspect_mask: vk::ImageAspectFlags,
+    pub samples: vk::SampleCountFlags,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FramebufferKey {
+    pub renderpass: vk::RenderPass,
+    pub attachments: Vec<vk::ImageView>,
+    pub extent: vk::Extent2D,
+}
+
+/// Attachments and framebuffers kept from one swapchain to the next, so recreating it
+/// only makes the targets whose extent, format or usage changed. Whatever the new
+/// swapchain did not ask for is destroyed by [`RenderTargetCache::evict_unused`].
+#[derive(Default)]
+pub struct RenderTargetCache {
+    attachments: HashMap<AttachmentKey, Attachment>,
+    framebuffers: HashMap<FramebufferKey, vk::Framebuffer>,
+    used_attachments: HashSet<AttachmentKey>,
+    used_framebuffers: HashSet<FramebufferKey>,
+}
+
+impl RenderTargetCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The attachment described by `key`, made if no earlier one matches.
+    pub fn attachment(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        key: AttachmentKey,
+    ) -> Result<Attachment> {
+        self.used_attachments.insert(key);
+        if let Some(attachment) = self.attachments.get(&key) {
+            return Ok(*attachment);
+        }
+        let attachment = Attachment::init(
+            logical_device,
+            memory_properties,
+            key.extent,
+            key.format,
+            key.usage,
+            key.aspect_mask,
+            key.samples,
+        )?;
+        self.attachments.insert(key, attachment);
+        Ok(attachment)
+    }
+
+    /// A framebuffer of `renderpass` over `attachments`, made if no earlier one matches.
+    pub fn framebuffer(
+        &mut self,
+        logical_device: &ash::Device,
+        renderpass: vk::RenderPass,
+        attachments: &[vk::ImageView],
+        extent: vk::Extent2D,
+    ) -> Result<vk::Framebuffer> {
+        let key = FramebufferKey {
+            renderpass,
+            attachments: attachments.to_vec(),
+            extent,
+        };
+        self.used_framebuffers.insert(key.clone());
+        if let Some(framebuffer) = self.framebuffers.get(&key) {
+            return Ok(*framebuffer);
+        }
+        let framebuffer_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(renderpass)
+            .attachments(attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?;
+        self.framebuffers.insert(key, framebuffer);
+        Ok(framebuffer)
+    }
+
+    /// Destroys the framebuffers attached to any of `views`, before the views themselves
+    /// go; a later view could otherwise reuse a handle and find a stale framebuffer.
+    pub fn forget_views(&mut self, logical_device: &ash::Device, views: &[vk::ImageView]) {
+        self.framebuffers.retain(|key, framebuffer| {
+            let stale = key.attachments.iter().any(|view| views.contains(view));
+            if stale {
+                unsafe { logical_device.destroy_framebuffer(*framebuffer, None) };
+            }
+            !stale
+        });
+    }
+
+    /// Destroys the targets not asked for since the last eviction, and starts counting
+    /// anew. The device must be done with them.
+    pub fn evict_unused(&mut self, logical_device: &ash::Device) {
+        let used_framebuffers = std::mem::take(&mut self.used_framebuffers);
+        self.framebuffers.retain(|key, framebuffer| {
+            let used = used_framebuffers.contains(key);
+            if !used {
+                unsafe { logical_device.destroy_framebuffer(*framebuffer, None) };
+            }
+            used
+        });
+        let used_attachments = std::mem::take(&mut self.used_attachments);
+        self.attachments.retain(|key, attachment| {
+            let used = used_attachments.contains(key);
+            if !used {
+                unsafe { attachment.cleanup(logical_device) };
+            }
+            used
+        });
+    }
+
+    /// Attachments and framebuffers held.
+    pub fn counts(&self) -> (usize, usize) {
+        (self.attachments.len(), self.framebuffers.len())
+    }
+
+    pub fn cleanup(&mut self, logical_device: &ash::Device) {
+        self.used_framebuffers.clear();
+        self.used_attachments.clear();
+        self.evict_unused(logical_device);
+    }
+}
@@ -0,0 +1,21 @@
+use anyhow::Result;
+use ash::vk;
+
+/// Trilinear, repeating sampler. Anisotropic filtering is enabled when `max_anisotropy`
+/// is above 1, which requires the `samplerAnisotropy` device feature.
+pub fn create_sampler(logical_device: &ash::Device, max_anisotropy: f32) -> Result<vk::Sampler> {
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::REPEAT)
+        .address_mode_v(vk::SamplerAddressMode::REPEAT)
+        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .anisotropy_enable(max_anisotropy > 1.0)
+        .max_anisotropy(max_anisotropy)
+        .min_lod(0.0)
+        .max_lod(vk::LOD_CLAMP_NONE);
+    let sampler = unsafe { logical_device.create_sampler(&sampler_info, None) }?;
+
+    Ok(sampler)
+}
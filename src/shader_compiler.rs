@@ -0,0 +1,277 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Ok, Result};
+use ash::vk;
+
+/// The pipeline stage a shader is compiled for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+    Task,
+    Mesh,
+}
+
+impl ShaderStage {
+    pub fn flags(&self) -> vk::ShaderStageFlags {
+        match self {
+            ShaderStage::Vertex => vk::ShaderStageFlags::VERTEX,
+            ShaderStage::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
+            ShaderStage::Task => vk::ShaderStageFlags::TASK_EXT,
+            ShaderStage::Mesh => vk::ShaderStageFlags::MESH_EXT,
+        }
+    }
+
+    fn kind(&self) -> shaderc::ShaderKind {
+        match self {
+            ShaderStage::Vertex => shaderc::ShaderKind::Vertex,
+            ShaderStage::Fragment => shaderc::ShaderKind::Fragment,
+            ShaderStage::Compute => shaderc::ShaderKind::Compute,
+            ShaderStage::Task => shaderc::ShaderKind::Task,
+            ShaderStage::Mesh => shaderc::ShaderKind::Mesh,
+        }
+    }
+}
+
+/// Compiles GLSL to SPIR-V at runtime, for shaders `include_glsl!` cannot know about at
+/// build time. `#include "..."` looks next to the including file first and then in the
+/// include directories, `#include <...>` only in the include directories, so shared
+/// lighting and utility code can live in common `.glsl` files. Defines given here apply
+/// to every shader; those passed to a compile call are added on top.
+pub struct ShaderCompiler {
+    compiler: shaderc::Compiler,
+    pub include_directories: Vec<PathBuf>,
+    pub defines: Vec<(String, Option<String>)>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            compiler: shaderc::Compiler::new()?,
+            include_directories: vec![],
+            defines: vec![],
+        })
+    }
+
+    pub fn include_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.include_directories.push(directory.into());
+        self
+    }
+
+    /// `#define name value` in every shader, or just `#define name` without a value.
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines
+            .push((name.to_owned(), value.map(ToOwned::to_owned)));
+        self
+    }
+
+    /// SPIR-V for `source`, named `file_name` in messages and as the place relative
+    /// includes are looked up from.
+    pub fn compile_glsl(
+        &self,
+        source: &str,
+        file_name: &str,
+        stage: ShaderStage,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<Vec<u32>> {
+        self.compile(
+            source,
+            file_name,
+            stage,
+            "main",
+            shaderc::SourceLanguage::GLSL,
+            defines,
+        )
+    }
+
+    /// Reads and compiles the GLSL file at `path`.
+    pub fn compile_glsl_file(
+        &self,
+        path: impl AsRef<Path>,
+        stage: ShaderStage,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<Vec<u32>> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading {} failed.", path.display()))?;
+        self.compile_glsl(&source, &path.to_string_lossy(), stage, defines)
+    }
+
+    /// SPIR-V for the HLSL `source`, starting at `entry_point`. Vertex inputs get their
+    /// locations from `semantics` (see [`map_semantics`]), so [`ENGINE_SEMANTICS`] lets
+    /// an HLSL vertex shader read the engine's vertex and instance data. `register`s
+    /// become bindings, and anything left unassigned is numbered automatically.
+    pub fn compile_hlsl(
+        &self,
+        source: &str,
+        file_name: &str,
+        stage: ShaderStage,
+        entry_point: &str,
+        semantics: &[(&str, u32)],
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<Vec<u32>> {
+        self.compile(
+            &map_semantics(source, semantics),
+            file_name,
+            stage,
+            entry_point,
+            shaderc::SourceLanguage::HLSL,
+            defines,
+        )
+    }
+
+    /// Reads and compiles the HLSL file at `path`.
+    pub fn compile_hlsl_file(
+        &self,
+        path: impl AsRef<Path>,
+        stage: ShaderStage,
+        entry_point: &str,
+        semantics: &[(&str, u32)],
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<Vec<u32>> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading {} failed.", path.display()))?;
+        self.compile_hlsl(
+            &source,
+            &path.to_string_lossy(),
+            stage,
+            entry_point,
+            semantics,
+            defines,
+        )
+    }
+
+    fn compile(
+        &self,
+        source: &str,
+        file_name: &str,
+        stage: ShaderStage,
+        entry_point: &str,
+        language: shaderc::SourceLanguage,
+        defines: &[(&str, Option<&str>)],
+    ) -> Result<Vec<u32>> {
+        let mut options = shaderc::CompileOptions::new()?;
+        options.set_source_language(language);
+        if language == shaderc::SourceLanguage::HLSL {
+            options.set_hlsl_io_mapping(true);
+            options.set_hlsl_offsets(true);
+            options.set_auto_map_locations(true);
+            options.set_auto_bind_uniforms(true);
+        }
+        options.set_target_env(
+            shaderc::TargetEnv::Vulkan,
+            shaderc::EnvVersion::Vulkan1_2 as u32,
+        );
+        options.set_generate_debug_info();
+        for (name, value) in &self.defines {
+            options.add_macro_definition(name, value.as_deref());
+        }
+        for (name, value) in defines {
+            options.add_macro_definition(name, *value);
+        }
+        options.set_include_callback(|requested, include_type, requesting, _depth| {
+            self.resolve_include(requested, include_type, requesting)
+        });
+
+        let artifact = self
+            .compiler
+            .compile_into_spirv(source, stage.kind(), file_name, entry_point, Some(&options))
+            .with_context(|| format!("Compiling {} failed.", file_name))?;
+        if artifact.get_num_warnings() > 0 {
+            eprintln!("{}", artifact.get_warning_messages());
+        }
+        Ok(artifact.as_binary().to_vec())
+    }
+
+    fn resolve_include(
+        &self,
+        requested: &str,
+        include_type: shaderc::IncludeType,
+        requesting: &str,
+    ) -> shaderc::IncludeCallbackResult {
+        let beside_requesting = Path::new(requesting)
+            .parent()
+            .filter(|_| include_type == shaderc::IncludeType::Relative)
+            .map(|directory| directory.join(requested));
+        let candidates = beside_requesting.into_iter().chain(
+            self.include_directories
+                .iter()
+                .map(|directory| directory.join(requested)),
+        );
+        for candidate in candidates {
+            if let std::result::Result::Ok(content) = std::fs::read_to_string(&candidate) {
+                return std::result::Result::Ok(shaderc::ResolvedInclude {
+                    resolved_name: candidate.to_string_lossy().into_owned(),
+                    content,
+                });
+            }
+        }
+        Err(format!(
+            "{} is not beside {} or in an include directory.",
+            requested, requesting
+        ))
+    }
+}
+
+/// A module of `code`, such as [`ShaderCompiler::compile_glsl`] returns.
+pub fn create_shader_module(
+    logical_device: &ash::Device,
+    code: &[u32],
+) -> Result<vk::ShaderModule> {
+    let module_info = vk::ShaderModuleCreateInfo::builder().code(code);
+    Ok(unsafe { logical_device.create_shader_module(&module_info, None) }?)
+}
+
+/// The vertex input locations of the engine's shaders by HLSL semantic, the instance
+/// matrices taking four locations each.
+pub const ENGINE_SEMANTICS: [(&str, u32); 6] = [
+    ("POSITION", 0),
+    ("NORMAL", 1),
+    ("MODEL", 2),
+    ("INVERSE_MODEL", 6),
+    ("COLOR", 10),
+    ("OPACITY", 11),
+];
+
+/// `source` with `[[vk::location(n)]]` in front of every declaration whose semantic is
+/// in `semantics` (compared ignoring case), so HLSL input structs can keep plain
+/// semantics and still line up with Vulkan vertex attributes. Only declarations on a
+/// line of their own ending in `;`, such as struct members, are mapped, and lines
+/// already carrying a `vk::` attribute are left alone.
+pub fn map_semantics(source: &str, semantics: &[(&str, u32)]) -> String {
+    source
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            if !trimmed.ends_with(';') || trimmed.contains("::") {
+                return line.to_owned();
+            }
+            let location = trimmed.split_once(':').and_then(|(_, semantic)| {
+                let semantic = semantic
+                    .trim_start()
+                    .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .next()?;
+                semantics
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(semantic))
+                    .map(|(_, location)| *location)
+            });
+            match location {
+                Some(location) => {
+                    let indent = line.len() - line.trim_start().len();
+                    format!(
+                        "{}[[vk::location({})]] {}",
+                        &line[..indent],
+                        location,
+                        &line[indent..]
+                    )
+                }
+                None => line.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
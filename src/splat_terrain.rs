@@ -0,0 +1,522 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::buffer::Buffer;
+use crate::model::{Mesh, VertexData};
+use crate::pipeline::Pipeline;
+use crate::texture::Texture;
+use crate::vertex_layout::VertexInput;
+
+/// Layers a [`SplatMaterial`] can blend, one per splat map channel.
+pub const MAX_SPLAT_LAYERS: usize = 4;
+
+/// One tiled texture set of a [`SplatMaterial`], weighted by one channel of the splat map.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SplatLayer {
+    /// RGBA8 sRGB colours, `layer_extent` in size.
+    pub albedo: Vec<u8>,
+    /// RGBA8 tangent-space normals, `layer_extent` in size, with u along world +x and v
+    /// along +z. `None` keeps the mesh normal.
+    pub normal_map: Option<Vec<u8>>,
+    /// World units one repetition of the textures covers.
+    pub tile_size: f32,
+    /// How far the normal map bends the surface; 1 applies it as stored.
+    pub normal_strength: f32,
+}
+
+/// Up to four tiled layers blended by the RGBA weights of a splat map stretched over a
+/// rectangle of the xz plane; outside the rectangle the splat map repeats. The weights
+/// are normalised, so they need not sum to 255.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SplatMaterial {
+    /// RGBA8 weights, `splat_extent` in size, with rows running along +x and advancing
+    /// along +z. Channel `i` weights `layers[i]`.
+    pub splat_map: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "Extent2DDef"))]
+    pub splat_extent: vk::Extent2D,
+    /// World-space x and z of the splat map's first corner.
+    pub origin: [f32; 2],
+    /// World-space extent the splat map covers along x and z.
+    pub size: [f32; 2],
+    /// Size of every layer's textures.
+    #[cfg_attr(feature = "serde", serde(with = "Extent2DDef"))]
+    pub layer_extent: vk::Extent2D,
+    pub layers: Vec<SplatLayer>,
+}
+
+/// Serialises `vk::Extent2D` fields, which ash does not.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(remote = "vk::Extent2D")]
+struct Extent2DDef {
+    width: u32,
+    height: u32,
+}
+
+impl SplatMaterial {
+    pub fn new(
+        splat_map: Vec<u8>,
+        splat_extent: vk::Extent2D,
+        origin: [f32; 2],
+        size: [f32; 2],
+        layer_extent: vk::Extent2D,
+    ) -> Self {
+        SplatMaterial {
+            splat_map,
+            splat_extent,
+            origin,
+            size,
+            layer_extent,
+            layers: vec![],
+        }
+    }
+
+    /// Adds the layer weighted by the next splat map channel.
+    pub fn layer(mut self, albedo: Vec<u8>, normal_map: Option<Vec<u8>>, tile_size: f32) -> Self {
+        self.layers.push(SplatLayer {
+            albedo,
+            normal_map,
+            tile_size,
+            normal_strength: 1.0,
+        });
+        self
+    }
+
+    fn parameters(&self) -> SplatParameters {
+        let mut tile_sizes = [1.0; MAX_SPLAT_LAYERS];
+        let mut normal_strengths = [0.0; MAX_SPLAT_LAYERS];
+        for (index, layer) in self.layers.iter().enumerate() {
+            tile_sizes[index] = layer.tile_size;
+            if layer.normal_map.is_some() {
+                normal_strengths[index] = layer.normal_strength;
+            }
+        }
+        SplatParameters {
+            origin_and_size: [self.origin[0], self.origin[1], self.size[0], self.size[1]],
+            tile_sizes,
+            normal_strengths,
+            layer_count: self.layers.len() as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// `SplatParameters` in `shaders/terrain_splat.frag`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SplatParameters {
+    origin_and_size: [f32; 4],
+    tile_sizes: [f32; MAX_SPLAT_LAYERS],
+    normal_strengths: [f32; MAX_SPLAT_LAYERS],
+    layer_count: u32,
+    _padding: [u32; 3],
+}
+
+/// A [`SplatMaterial`]'s textures on the device.
+pub struct SplatTextures {
+    /// One layer of weights.
+    pub splat_map: Texture,
+    /// One layer per material layer, in sRGB.
+    pub albedo: Texture,
+    /// One layer per material layer; layers without a normal map are flat.
+    pub normal_maps: Texture,
+}
+
+impl SplatTextures {
+    /// Uploads the material's textures with `command_pool`, which must belong to the
+    /// graphics `queue` as the mip chains are blitted.
+    pub fn upload(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        material: &SplatMaterial,
+    ) -> Result<Self> {
+        if material.layers.is_empty() || material.layers.len() > MAX_SPLAT_LAYERS {
+            bail!(
+                "A splat material blends 1 to {} layers, not {}.",
+                MAX_SPLAT_LAYERS,
+                material.layers.len()
+            );
+        }
+        let flat_normals = [128, 128, 255, 255]
+            .repeat(material.layer_extent.width as usize * material.layer_extent.height as usize);
+        let albedo_layers: Vec<&[u8]> = material
+            .layers
+            .iter()
+            .map(|layer| &layer.albedo[..])
+            .collect();
+        let normal_layers: Vec<&[u8]> = material
+            .layers
+            .iter()
+            .map(|layer| layer.normal_map.as_deref().unwrap_or(&flat_normals))
+            .collect();
+        let texture = |extent, format, layers: &[&[u8]]| {
+            Texture::init(
+                logical_device,
+                memory_properties,
+                command_pool,
+                queue,
+                extent,
+                format,
+                layers,
+            )
+        };
+
+        let splat_map = texture(
+            material.splat_extent,
+            vk::Format::R8G8B8A8_UNORM,
+            &[&material.splat_map],
+        )?;
+        let albedo = match texture(
+            material.layer_extent,
+            vk::Format::R8G8B8A8_SRGB,
+            &albedo_layers,
+        ) {
+            std::result::Result::Ok(albedo) => albedo,
+            Err(e) => {
+                unsafe { splat_map.cleanup(logical_device) };
+                return Err(e);
+            }
+        };
+        let normal_maps = match texture(
+            material.layer_extent,
+            vk::Format::R8G8B8A8_UNORM,
+            &normal_layers,
+        ) {
+            std::result::Result::Ok(normal_maps) => normal_maps,
+            Err(e) => {
+                unsafe {
+                    splat_map.cleanup(logical_device);
+                    albedo.cleanup(logical_device);
+                }
+                return Err(e);
+            }
+        };
+
+        Ok(SplatTextures {
+            splat_map,
+            albedo,
+            normal_maps,
+        })
+    }
+
+    ///# Safety
+    ///
+    /// The textures must not be in use by the device.
+    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        for texture in [&self.splat_map, &self.albedo, &self.normal_maps] {
+            texture.cleanup(logical_device);
+        }
+    }
+}
+
+/// Draws terrain meshes with a [`SplatMaterial`] instead of a flat colour, lit and fogged
+/// like the main pipeline. The meshes must be in world space, as terrain chunks are.
+pub struct SplatTerrainPass {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// Set 1, holding the material. Set 0 is the main pipeline's.
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+    pub parameters: Buffer,
+    pub textures: SplatTextures,
+    /// Repeating, so the layers tile and the splat map wraps around.
+    pub sampler: vk::Sampler,
+}
+
+impl SplatTerrainPass {
+    /// Builds the pass around uploaded `textures` and `sampler`, both of which it owns from
+    /// then on.
+    pub fn init(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+        textures: SplatTextures,
+        sampler: vk::Sampler,
+        material: &SplatMaterial,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/terrain_splat.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/terrain_splat.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        // Only the vertex binding of the main pipeline; terrain has no instance transform.
+        let vertex_input = VertexInput {
+            bindings: pipeline
+                .vertex_input
+                .bindings
+                .iter()
+                .filter(|b| b.binding == 0)
+                .copied()
+                .collect(),
+            attributes: pipeline
+                .vertex_input
+                .attributes
+                .iter()
+                .filter(|a| a.binding == 0)
+                .copied()
+                .collect(),
+        };
+        let vertex_input_info = vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = pipeline.multisampling.state_info();
+        let colour_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colour_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colour_blend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Descriptors */
+        let sampled = |binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build()
+        };
+        let descriptor_set_layout_bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            sampled(1),
+            sampled(2),
+            sampled(3),
+        ];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 3,
+            },
+        ];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let mut parameters = Buffer::init(
+            std::mem::size_of::<SplatParameters>(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            memory_properties,
+            logical_device,
+        )?;
+        parameters.fill(logical_device, &[material.parameters()], memory_properties)?;
+
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer: parameters.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let image_infos =
+            [&textures.splat_map, &textures.albedo, &textures.normal_maps].map(|texture| {
+                [vk::DescriptorImageInfo {
+                    sampler,
+                    image_view: texture.view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                }]
+            });
+        let mut writes = vec![vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(&buffer_info)
+            .build()];
+        for (binding, image_info) in (1..).zip(&image_infos) {
+            writes.push(
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+                    .build(),
+            );
+        }
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+
+        /* Pipeline */
+        let set_layouts = [pipeline.descriptor_set_layouts[0], descriptor_set_layout];
+        let pipeline_layout_info =
+            vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colour_blend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let graphics_pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None);
+        }
+
+        Ok(SplatTerrainPass {
+            pipeline: graphics_pipeline,
+            layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            parameters,
+            textures,
+            sampler,
+        })
+    }
+
+    /// Uploads the material's origin, size, tiling and normal strengths again. Its
+    /// textures stay as they were uploaded; the device must not be using the pass.
+    pub fn update_parameters(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        material: &SplatMaterial,
+    ) -> Result<()> {
+        self.parameters
+            .fill(logical_device, &[material.parameters()], memory_properties)
+    }
+
+    /// Records the uploaded triangle list `meshes` into subpass 0, with the main
+    /// pipeline's set 0 `descriptor_set`.
+    pub fn draw<'a>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        meshes: impl Iterator<Item = &'a Mesh<VertexData>>,
+    ) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set, self.descriptor_set],
+                &[],
+            );
+            for mesh in meshes {
+                let (Some(vertex_buffer), Some(index_buffer)) =
+                    (&mesh.vertex_buffer, &mesh.index_buffer)
+                else {
+                    continue;
+                };
+                logical_device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[vertex_buffer.buffer],
+                    &[0],
+                );
+                logical_device.cmd_bind_index_buffer(
+                    command_buffer,
+                    index_buffer.buffer,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                logical_device.cmd_draw_indexed(
+                    command_buffer,
+                    mesh.index_data.len() as u32,
+                    1,
+                    0,
+                    0,
+                    0,
+                );
+            }
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            self.textures.cleanup(logical_device);
+            self.parameters.cleanup(logical_device);
+            logical_device.destroy_sampler(self.sampler, None);
+            logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+/// How GLSL lays out the members of a uniform or storage block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Uniform blocks: array elements and structs are aligned to 16 bytes.
+    Std140,
+    /// Storage blocks and push constants: everything is aligned to its own base alignment.
+    Std430,
+}
+
+/// A block member, as far as its layout goes. `Scalar` is any of `float`, `int`, `uint`
+/// and `bool`; matrices are column-major, each column laid out as an array element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlslType {
+    Scalar,
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat2,
+    Mat3,
+    Mat4,
+    Array(&'static GlslType, usize),
+    Struct(&'static [GlslType]),
+}
+
+impl GlslType {
+    /// The base alignment in bytes.
+    pub const fn align(&self, layout: Layout) -> usize {
+        match self {
+            GlslType::Scalar => 4,
+            GlslType::Vec2 => 8,
+            GlslType::Vec3 | GlslType::Vec4 => 16,
+            GlslType::Mat2 => GlslType::Array(&GlslType::Vec2, 2).align(layout),
+            GlslType::Mat3 => GlslType::Array(&GlslType::Vec3, 3).align(layout),
+            GlslType::Mat4 => GlslType::Array(&GlslType::Vec4, 4).align(layout),
+            GlslType::Array(element, _) => match layout {
+                Layout::Std140 => round_up(element.align(layout), 16),
+                Layout::Std430 => element.align(layout),
+            },
+            GlslType::Struct(members) => members_layout(members, layout).1,
+        }
+    }
+
+    /// The bytes it takes up, including trailing padding of arrays and structs.
+    pub const fn size(&self, layout: Layout) -> usize {
+        match self {
+            GlslType::Scalar => 4,
+            GlslType::Vec2 => 8,
+            GlslType::Vec3 => 12,
+            GlslType::Vec4 => 16,
+            GlslType::Mat2 => GlslType::Array(&GlslType::Vec2, 2).size(layout),
+            GlslType::Mat3 => GlslType::Array(&GlslType::Vec3, 3).size(layout),
+            GlslType::Mat4 => GlslType::Array(&GlslType::Vec4, 4).size(layout),
+            GlslType::Array(element, length) => {
+                round_up(element.size(layout), self.align(layout)) * *length
+            }
+            GlslType::Struct(members) => {
+                let (end, align) = members_layout(members, layout);
+                round_up(end, align)
+            }
+        }
+    }
+}
+
+/// Where each of `members` starts in a block.
+pub const fn offsets<const N: usize>(members: &[GlslType; N], layout: Layout) -> [usize; N] {
+    let mut offsets = [0; N];
+    let mut end = 0;
+    let mut i = 0;
+    while i < N {
+        offsets[i] = round_up(end, members[i].align(layout));
+        end = offsets[i] + members[i].size(layout);
+        i += 1;
+    }
+    offsets
+}
+
+/// The bytes a block of `members` takes up, padded as a struct of them would be.
+pub const fn block_size(members: &[GlslType], layout: Layout) -> usize {
+    let (end, align) = members_layout(members, layout);
+    round_up(end, align)
+}
+
+/// The end of the last of `members` and the alignment of a struct of them.
+const fn members_layout(members: &[GlslType], layout: Layout) -> (usize, usize) {
+    let mut end = 0;
+    let mut align = match layout {
+        Layout::Std140 => 16,
+        Layout::Std430 => 1,
+    };
+    let mut i = 0;
+    while i < members.len() {
+        let member_align = members[i].align(layout);
+        if member_align > align {
+            align = member_align;
+        }
+        end = round_up(end, member_align) + members[i].size(layout);
+        i += 1;
+    }
+    (end, align)
+}
+
+const fn round_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+/// A value padded out to 16 bytes and aligned to them, as std140 lays out the elements of
+/// arrays of scalars and vectors: `[Aligned16<f32>; 8]` matches `float values[8]`.
+#[repr(C, align(16))]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aligned16<T>(pub T);
+
+impl<T> From<T> for Aligned16<T> {
+    fn from(value: T) -> Self {
+        Aligned16(value)
+    }
+}
+
+/// Fails to compile unless the fields of a `#[repr(C)]` struct sit where the given GLSL
+/// layout puts the block members they are uploaded to, and the struct is as large as the
+/// block. Members are [`GlslType`]s, written without the enum's path:
+///
+/// ```ignore
+/// krakatoa::assert_std_layout!(Std140, Camera { view: Mat4, projection: Mat4, time: Vec4 });
+/// ```
+#[macro_export]
+macro_rules! assert_std_layout {
+    ($layout:ident, $ty:ty { $($field:ident: $glsl:expr),* $(,)? }) => {
+        #[allow(unused_assignments)]
+        const _: () = {
+            use $crate::std_layout::GlslType::*;
+            let members = [$($glsl),*];
+            let offsets =
+                $crate::std_layout::offsets(&members, $crate::std_layout::Layout::$layout);
+            let mut member = 0;
+            $(
+                assert!(
+                    std::mem::offset_of!($ty, $field) == offsets[member],
+                    concat!(
+                        "`", stringify!($ty), "::", stringify!($field),
+                        "` is not at its ", stringify!($layout), " offset"
+                    ),
+                );
+                member += 1;
+            )*
+            assert!(
+                std::mem::size_of::<$ty>()
+                    == $crate::std_layout::block_size(
+                        &members,
+                        $crate::std_layout::Layout::$layout,
+                    ),
+                concat!(
+                    "`", stringify!($ty), "` is not the size of its ", stringify!($layout),
+                    " block"
+                ),
+            );
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GlslType::*;
+    use super::*;
+
+    /// `struct S { vec2 x; float y; }`
+    const S: GlslType = Struct(&[Vec2, Scalar]);
+
+    #[test]
+    fn matrices_are_arrays_of_columns() {
+        assert_eq!(
+            (Mat3.align(Layout::Std140), Mat3.size(Layout::Std140)),
+            (16, 48)
+        );
+        assert_eq!(
+            (Mat3.align(Layout::Std430), Mat3.size(Layout::Std430)),
+            (16, 48)
+        );
+        assert_eq!(
+            (Mat2.align(Layout::Std140), Mat2.size(Layout::Std140)),
+            (16, 32)
+        );
+        assert_eq!(
+            (Mat2.align(Layout::Std430), Mat2.size(Layout::Std430)),
+            (8, 16)
+        );
+    }
+
+    #[test]
+    fn array_strides_round_up_only_under_std140() {
+        let vec3s = Array(&Vec3, 4);
+        assert_eq!(vec3s.size(Layout::Std140), 64);
+        assert_eq!(vec3s.size(Layout::Std430), 64);
+        let floats = Array(&Scalar, 4);
+        assert_eq!(
+            (floats.align(Layout::Std140), floats.size(Layout::Std140)),
+            (16, 64)
+        );
+        assert_eq!(
+            (floats.align(Layout::Std430), floats.size(Layout::Std430)),
+            (4, 16)
+        );
+    }
+
+    #[test]
+    fn block_offsets_follow_the_spec() {
+        // float a; vec2 b; vec3 c; float d; mat3 e; vec3 f[2]; S s; float g; float h[3];
+        let members = [
+            Scalar,
+            Vec2,
+            Vec3,
+            Scalar,
+            Mat3,
+            Array(&Vec3, 2),
+            S,
+            Scalar,
+            Array(&Scalar, 3),
+        ];
+        assert_eq!(
+            offsets(&members, Layout::Std140),
+            [0, 8, 16, 28, 32, 80, 112, 128, 144]
+        );
+        assert_eq!(block_size(&members, Layout::Std140), 192);
+        assert_eq!(
+            offsets(&members, Layout::Std430),
+            [0, 8, 16, 28, 32, 80, 112, 128, 132]
+        );
+        assert_eq!(block_size(&members, Layout::Std430), 144);
+    }
+
+    #[test]
+    fn nested_structs_pad_to_their_alignment() {
+        // struct T { float a; S s[2]; }
+        let t = Struct(&[Scalar, Array(&S, 2)]);
+        assert_eq!((S.align(Layout::Std140), S.size(Layout::Std140)), (16, 16));
+        assert_eq!((S.align(Layout::Std430), S.size(Layout::Std430)), (8, 16));
+        assert_eq!(offsets(&[Scalar, Array(&S, 2)], Layout::Std140), [0, 16]);
+        assert_eq!(offsets(&[Scalar, Array(&S, 2)], Layout::Std430), [0, 8]);
+        assert_eq!(t.size(Layout::Std140), 48);
+        assert_eq!(t.size(Layout::Std430), 40);
+        // A scalar after a struct starts past its padding.
+        assert_eq!(offsets(&[S, Scalar], Layout::Std430), [0, 16]);
+    }
+}
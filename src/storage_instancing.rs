@@ -0,0 +1,385 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::buffer::Buffer;
+use crate::model::{Instance, Model, VertexData};
+use crate::pipeline::{Pipeline, Topology};
+use crate::vertex_layout::VertexInput;
+
+/// Draws models whose instances live in a storage buffer, read in the vertex shader at
+/// `gl_InstanceIndex`, instead of in per-instance vertex attributes. Instances are
+/// uploaded as they are, so a custom [`Instance`] type can carry as large a payload as it
+/// likes without spending vertex input locations. Opaque instances only, shaded like the
+/// main pipeline.
+///
+/// `shaders/storage_instanced.vert` reads the [`crate::model::InstanceData`] fields at the
+/// start of each instance; pass a vertex shader of your own to use the rest.
+pub struct StorageInstancedPass {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    /// Set 1, holding a model's instance buffer. Set 0 is the main pipeline's.
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    /// Bytes per instance the pipeline was made for.
+    pub instance_stride: usize,
+    pub models: Vec<StorageInstancedModel>,
+}
+
+/// A model's geometry and visible instances, uploaded for [`StorageInstancedPass`].
+pub struct StorageInstancedModel {
+    pub vertices: Buffer,
+    pub indices: Buffer,
+    pub instances: Buffer,
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub descriptor_pool: vk::DescriptorPool,
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl StorageInstancedPass {
+    /// Builds the pass for instances of `instance_stride` bytes, a multiple of 4, drawn with
+    /// `vertex_spirv` or `shaders/storage_instanced.vert` when `None`.
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+        instance_stride: usize,
+        vertex_spirv: Option<&[u32]>,
+    ) -> Result<Self> {
+        if !instance_stride.is_multiple_of(4) {
+            bail!(
+                "Instances of {} bytes cannot be read from a storage buffer as floats.",
+                instance_stride
+            );
+        }
+
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder().code(vertex_spirv.unwrap_or(
+            vk_shader_macros::include_glsl!("shaders/storage_instanced.vert", kind: vert),
+        ));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<u32>(),
+        }];
+        let specialization_data = ((instance_stride / 4) as u32).to_ne_bytes();
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&specialization_data);
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .specialization_info(&specialization_info)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        // Only the vertex binding of the main pipeline; instances come from set 1.
+        let vertex_input = VertexInput {
+            bindings: pipeline
+                .vertex_input
+                .bindings
+                .iter()
+                .filter(|b| b.binding == 0)
+                .copied()
+                .collect(),
+            attributes: pipeline
+                .vertex_input
+                .attributes
+                .iter()
+                .filter(|a| a.binding == 0)
+                .copied()
+                .collect(),
+        };
+        let vertex_input_info = vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = pipeline.multisampling.state_info();
+        let colour_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(false)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colour_blend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colour_blend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Descriptors */
+        let descriptor_set_layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .build()];
+        let descriptor_set_layout_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&descriptor_set_layout_bindings);
+        let descriptor_set_layout = unsafe {
+            logical_device.create_descriptor_set_layout(&descriptor_set_layout_info, None)
+        }?;
+
+        /* Pipeline */
+        let set_layouts = [pipeline.descriptor_set_layouts[0], descriptor_set_layout];
+        let pipeline_layout_info =
+            vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colour_blend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let graphics_pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None);
+        }
+
+        Ok(StorageInstancedPass {
+            pipeline: graphics_pipeline,
+            layout,
+            descriptor_set_layout,
+            instance_stride,
+            models: vec![],
+        })
+    }
+
+    /// Uploads the model's geometry and visible instances, which
+    /// [`StorageInstancedPass::update_instances`] refreshes later. Returns the index into
+    /// `models`.
+    pub fn add_model<I: Instance>(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        model: &Model<VertexData, I>,
+    ) -> Result<usize> {
+        if model.mesh.topology != Topology::TriangleList {
+            bail!("Only triangle list models can be drawn with storage buffer instancing.");
+        }
+        if std::mem::size_of::<I>() != self.instance_stride {
+            bail!(
+                "Instances of {} bytes were given to a pass made for {} bytes.",
+                std::mem::size_of::<I>(),
+                self.instance_stride
+            );
+        }
+        let buffer = |bytes: usize, usage| {
+            Buffer::init(bytes.max(16), usage, memory_properties, logical_device)
+        };
+        let mut vertices = buffer(
+            std::mem::size_of_val(&model.mesh.vertex_data[..]),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        )?;
+        vertices.fill(logical_device, &model.mesh.vertex_data, memory_properties)?;
+        let mut indices = buffer(
+            std::mem::size_of_val(&model.mesh.index_data[..]),
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        )?;
+        indices.fill(logical_device, &model.mesh.index_data, memory_properties)?;
+        let visible = model.instances.visible();
+        let mut instances = buffer(
+            std::mem::size_of_val(visible),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+        instances.fill(logical_device, visible, memory_properties)?;
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+        }];
+        let descriptor_pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool =
+            unsafe { logical_device.create_descriptor_pool(&descriptor_pool_info, None) }?;
+        let set_layouts = [self.descriptor_set_layout];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set =
+            unsafe { logical_device.allocate_descriptor_sets(&descriptor_set_allocate_info) }?[0];
+
+        let storage_model = StorageInstancedModel {
+            vertices,
+            indices,
+            instances,
+            index_count: model.mesh.index_data.len() as u32,
+            instance_count: visible.len() as u32,
+            descriptor_pool,
+            descriptor_set,
+        };
+        storage_model.write_descriptor(logical_device);
+
+        self.models.push(storage_model);
+        Ok(self.models.len() - 1)
+    }
+
+    /// Uploads the visible instances of the model added at `index` again.
+    pub fn update_instances<I: Instance>(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        index: usize,
+        model: &Model<VertexData, I>,
+    ) -> Result<()> {
+        let Some(storage_model) = self.models.get_mut(index) else {
+            bail!("No model was added at index {}.", index);
+        };
+        let visible = model.instances.visible();
+        let grows = std::mem::size_of_val(visible) > storage_model.instances.size_in_bytes;
+        if grows {
+            // The buffer is replaced, and frames in flight may still read the old one.
+            unsafe { logical_device.device_wait_idle() }?;
+        }
+        storage_model
+            .instances
+            .fill(logical_device, visible, memory_properties)?;
+        if grows {
+            storage_model.write_descriptor(logical_device);
+        }
+        storage_model.instance_count = visible.len() as u32;
+        Ok(())
+    }
+
+    /// Records the models into subpass 0, with the main pipeline's set 0 `descriptor_set`.
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            for model in &self.models {
+                if model.instance_count == 0 || model.index_count == 0 {
+                    continue;
+                }
+                logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.layout,
+                    1,
+                    &[model.descriptor_set],
+                    &[],
+                );
+                logical_device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[model.vertices.buffer],
+                    &[0],
+                );
+                logical_device.cmd_bind_index_buffer(
+                    command_buffer,
+                    model.indices.buffer,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                logical_device.cmd_draw_indexed(
+                    command_buffer,
+                    model.index_count,
+                    model.instance_count,
+                    0,
+                    0,
+                    0,
+                );
+            }
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            for model in &self.models {
+                model.cleanup(logical_device);
+            }
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+            logical_device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+impl StorageInstancedModel {
+    fn write_descriptor(&self, logical_device: &ash::Device) {
+        let buffer_info = [vk::DescriptorBufferInfo {
+            buffer: self.instances.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let writes = [vk::WriteDescriptorSet::builder()
+            .dst_set(self.descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_info)
+            .build()];
+        unsafe { logical_device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    ///# Safety
+    ///
+    /// The model must not be in use by the device.
+    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        for buffer in [&self.vertices, &self.indices, &self.instances] {
+            buffer.cleanup(logical_device);
+        }
+        logical_device.destroy_descriptor_pool(self.descriptor_pool, None);
+    }
+}
@@ -0,0 +1,92 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector3;
+
+use crate::light::DirectionalLight;
+use crate::sky::Sky;
+use crate::timing::FrameTimer;
+
+/// Drives the directional light (and the sky's sun) from a time of day.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SunCycle {
+    /// Hours in [0, 24); 6 is sunrise, 12 noon, 18 sunset.
+    pub time_of_day: f32,
+    /// Real seconds for a full 24 hour cycle; 0 freezes the time of day.
+    pub day_length: f32,
+    /// Angle between the sun's path and the zenith, in radians.
+    pub tilt: f32,
+    pub sun_intensity: f32,
+    pub moon_intensity: f32,
+}
+
+impl Default for SunCycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SunCycle {
+    pub fn new() -> Self {
+        Self {
+            time_of_day: 9.0,
+            day_length: 120.0,
+            tilt: 0.4,
+            sun_intensity: 1.0,
+            moon_intensity: 0.15,
+        }
+    }
+
+    pub fn advance(&mut self, timer: &FrameTimer) {
+        if self.day_length > 0.0 {
+            self.time_of_day = (self.time_of_day + timer.delta * 24.0 / self.day_length) % 24.0;
+        }
+    }
+
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = 2.0 * PI * (self.time_of_day - 6.0) / 24.0;
+        Vector3::new(
+            angle.cos(),
+            -angle.sin() * self.tilt.cos(),
+            -angle.sin() * self.tilt.sin(),
+        )
+    }
+
+    /// Sine of the sun's elevation above the horizon.
+    pub fn sun_elevation(&self) -> f32 {
+        -self.sun_direction().y
+    }
+
+    pub fn light(&self) -> DirectionalLight {
+        let elevation = self.sun_elevation();
+        if elevation > 0.0 {
+            let day = smoothstep(0.0, 0.4, elevation);
+            let low_sun = [1.0, 0.5, 0.3];
+            let high_sun = [1.0, 0.97, 0.92];
+            DirectionalLight {
+                direction: self.sun_direction(),
+                colour: [0, 1, 2].map(|i| low_sun[i] + (high_sun[i] - low_sun[i]) * day),
+                intensity: self.sun_intensity * smoothstep(0.0, 0.1, elevation),
+                ambient: 0.2 + 0.3 * day,
+            }
+        } else {
+            DirectionalLight {
+                direction: -self.sun_direction(),
+                colour: [0.4, 0.5, 0.8],
+                intensity: self.moon_intensity * smoothstep(0.0, 0.1, -elevation),
+                ambient: 0.15,
+            }
+        }
+    }
+
+    pub fn apply(&self, light: &mut DirectionalLight, sky: Option<&mut Sky>) {
+        *light = self.light();
+        if let Some(sky) = sky {
+            sky.sun_direction = self.sun_direction();
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -2,9 +2,19 @@ use anyhow::{Ok, Result};
 use ash::vk;
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
+use crate::hdr::OutputColourSpace;
+
 pub struct Surface {
     pub surface: vk::SurfaceKHR,
     pub surface_loader: ash::extensions::khr::Surface,
+    /// Requested output; the swapchain falls back to SDR when the display lacks it.
+    pub output: OutputColourSpace,
+    /// Monitor to take exclusively while fullscreen. Only set on Windows when
+    /// `VK_EXT_full_screen_exclusive` was requested and the device supports it.
+    pub full_screen_exclusive: Option<vk::HMONITOR>,
+    /// Swapchain size used when the surface leaves it to the application, as Wayland does.
+    /// Kept at the window's inner size.
+    pub desired_extent: vk::Extent2D,
 }
 
 impl Surface {
@@ -13,20 +23,18 @@ impl Surface {
         entry: &ash::Entry,
         instance: &ash::Instance,
     ) -> Result<Self> {
-        let surface = unsafe {
-            ash_window::create_surface(
-                entry,
-                instance,
-                window.raw_display_handle(),
-                window.raw_window_handle(),
-                None,
-            )?
-        };
+        let surface = create_surface(window, entry, instance)?;
         let surface_loader = ash::extensions::khr::Surface::new(entry, instance);
 
         Ok(Self {
             surface,
             surface_loader,
+            output: OutputColourSpace::default(),
+            full_screen_exclusive: None,
+            desired_extent: vk::Extent2D {
+                width: window.inner_size().width,
+                height: window.inner_size().height,
+            },
         })
     }
 
@@ -59,12 +67,73 @@ impl Surface {
                 .get_physical_device_surface_formats(physical_device, self.surface)
         }
     }
+
+    /// Replaces the surface with one for the window's current native window, e.g. after
+    /// Android handed out a new one on resume.
+    pub fn recreate(
+        &mut self,
+        window: &winit::window::Window,
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+    ) -> Result<()> {
+        self.destroy();
+        self.surface = create_surface(window, entry, instance)?;
+        Ok(())
+    }
+
+    /// Destroys the surface, which must no longer back a swapchain. Safe to call twice.
+    pub fn destroy(&mut self) {
+        if self.surface != vk::SurfaceKHR::null() {
+            unsafe { self.surface_loader.destroy_surface(self.surface, None) };
+            self.surface = vk::SurfaceKHR::null();
+        }
+    }
+
+    /// The surface's current extent, or the desired one clamped to the supported range
+    /// when the current extent is the special value `u32::MAX`.
+    pub fn choose_extent(&self, capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
+        if capabilities.current_extent.width != u32::MAX {
+            return capabilities.current_extent;
+        }
+        vk::Extent2D {
+            width: self.desired_extent.width.clamp(
+                capabilities.min_image_extent.width,
+                capabilities.max_image_extent.width,
+            ),
+            height: self.desired_extent.height.clamp(
+                capabilities.min_image_extent.height,
+                capabilities.max_image_extent.height,
+            ),
+        }
+    }
+
+    pub fn choose_format(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> Result<vk::SurfaceFormatKHR, vk::Result> {
+        self.get_formats(physical_device)
+            .map(|formats| self.output.choose_format(&formats))
+    }
 }
 
 impl Drop for Surface {
     fn drop(&mut self) {
-        unsafe {
-            self.surface_loader.destroy_surface(self.surface, None);
-        }
+        self.destroy();
     }
 }
+
+fn create_surface(
+    window: &winit::window::Window,
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+) -> Result<vk::SurfaceKHR> {
+    Ok(unsafe {
+        ash_window::create_surface(
+            entry,
+            instance,
+            window.raw_display_handle(),
+            window.raw_window_handle(),
+            None,
+        )?
+    })
+}
@@ -2,20 +2,37 @@ use anyhow::Result;
 use ash::vk;
 
 use crate::{
-    find_memorytype_index,
-    queue::{QueueFamilies, Queues},
+    allocations, choose_depth_format, find_memorytype_index, format_has_stencil,
+    hdr::{OutputColourSpace, SCENE_FORMAT},
+    oit::{ACCUMULATION_FORMAT, REVEALAGE_FORMAT},
+    queue::QueueFamilies,
+    render_targets::{AttachmentKey, RenderTargetCache},
     surface::Surface,
 };
 
 pub struct Swapchain {
     pub swapchain_loader: ash::extensions::khr::Swapchain,
     pub swapchain: vk::SwapchainKHR,
+    /// Set while the swapchain holds exclusive fullscreen, released on cleanup.
+    pub full_screen_exclusive: Option<ash::extensions::ext::FullScreenExclusive>,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
     pub depth_image: vk::Image,
+    pub depth_image_memory: vk::DeviceMemory,
     pub depth_imageview: vk::ImageView,
+    pub depth_format: vk::Format,
+    /// Weighted-blended OIT targets, shared by every framebuffer like the depth buffer.
+    pub accumulation: Attachment,
+    pub revealage: Attachment,
+    /// Multisampled colour target resolved into the scene or swapchain image, present with MSAA only.
+    pub msaa_colour: Option<Attachment>,
+    /// Scene image encoded into the swapchain image by the last subpass, present with HDR output only.
+    pub scene: Option<Attachment>,
+    /// One per swapchain image, owned by the [`RenderTargetCache`] like the attachments.
     pub framebuffers: Vec<vk::Framebuffer>,
     pub surface_format: vk::SurfaceFormatKHR,
+    /// Includes `TRANSFER_SRC` where the surface allows it, so frames can be copied out.
+    pub image_usage: vk::ImageUsageFlags,
     pub extent: vk::Extent2D,
     pub image_available: Vec<vk::Semaphore>,
     pub rendering_finished: Vec<vk::Semaphore>,
@@ -25,22 +42,27 @@ pub struct Swapchain {
 }
 
 impl Swapchain {
+    #[allow(clippy::too_many_arguments)]
     pub fn init(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
         logical_device: &ash::Device,
         surface: &Surface,
         queue_families: &QueueFamilies,
-        _queues: &Queues,
+        samples: vk::SampleCountFlags,
         memory_properties: vk::PhysicalDeviceMemoryProperties,
+        render_targets: &mut RenderTargetCache,
     ) -> Result<Self> {
         /* Setup */
         let surface_capabilities = surface.get_capabilities(physical_device)?;
-        let extent = surface_capabilities.current_extent;
+        let extent = surface.choose_extent(&surface_capabilities);
         let _surface_present_modes = surface.get_present_modes(physical_device)?;
-        let surface_format = *surface.get_formats(physical_device)?.first().unwrap();
+        let surface_format = surface.choose_format(physical_device)?;
+        let hdr = OutputColourSpace::from_colour_space(surface_format.color_space).is_hdr();
 
         /* Swapchain */
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (surface_capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
         let queue_families = [queue_families.graphics_q_index.unwrap()];
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(surface.surface)
@@ -52,15 +74,39 @@ impl Swapchain {
             .image_color_space(surface_format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .queue_family_indices(&queue_families)
             .pre_transform(surface_capabilities.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(vk::PresentModeKHR::FIFO);
+        let mut full_screen_exclusive_info = vk::SurfaceFullScreenExclusiveInfoEXT::builder()
+            .full_screen_exclusive(vk::FullScreenExclusiveEXT::APPLICATION_CONTROLLED);
+        let mut full_screen_exclusive_win32_info =
+            vk::SurfaceFullScreenExclusiveWin32InfoEXT::builder().hmonitor(
+                surface
+                    .full_screen_exclusive
+                    .unwrap_or(std::ptr::null_mut()),
+            );
+        let swapchain_create_info = if surface.full_screen_exclusive.is_some() {
+            swapchain_create_info
+                .push_next(&mut full_screen_exclusive_info)
+                .push_next(&mut full_screen_exclusive_win32_info)
+        } else {
+            swapchain_create_info
+        };
         let swapchain_loader = ash::extensions::khr::Swapchain::new(instance, logical_device);
         let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None) }?;
 
+        /* Exclusive Fullscreen */
+        // Acquiring fails while the window is not fullscreen; presentation then stays windowed.
+        let full_screen_exclusive = surface.full_screen_exclusive.and_then(|_| {
+            let loader = ash::extensions::ext::FullScreenExclusive::new(instance, logical_device);
+            unsafe { loader.acquire_full_screen_exclusive_mode(swapchain) }
+                .ok()
+                .map(|_| loader)
+        });
+
         let images = unsafe { swapchain_loader.get_swapchain_images(swapchain) }?;
         let amount_of_images = images.len();
         let mut image_views = Vec::with_capacity(amount_of_images);
@@ -74,7 +120,7 @@ impl Swapchain {
             let imageview_create_info = vk::ImageViewCreateInfo::builder()
                 .image(*image)
                 .view_type(vk::ImageViewType::TYPE_2D)
-                .format(vk::Format::B8G8R8A8_UNORM)
+                .format(surface_format.format)
                 .subresource_range(*subresource_range);
             let image_view = unsafe {
                 logical_device
@@ -85,53 +131,97 @@ impl Swapchain {
         });
 
         /* Depth Buffer */
-        let extent3d = vk::Extent3D {
-            width: extent.width,
-            height: extent.height,
-            depth: 1,
+        let depth_format = choose_depth_format(instance, physical_device);
+        let depth_aspect = if format_has_stencil(depth_format) {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        } else {
+            vk::ImageAspectFlags::DEPTH
         };
-        let depth_image_info = vk::ImageCreateInfo::builder()
-            .image_type(vk::ImageType::TYPE_2D)
-            .format(vk::Format::D32_SFLOAT)
-            .extent(extent3d)
-            .mip_levels(1)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .queue_family_indices(&queue_families);
-        let depth_image = unsafe { logical_device.create_image(&depth_image_info, None) }?;
-
-        /* Bind Depth Memory */
-        let depth_memory_req = unsafe { logical_device.get_image_memory_requirements(depth_image) };
-        let depth_memory_index = find_memorytype_index(
-            &depth_memory_req,
-            &memory_properties,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-        )
-        .expect("Unable to find suitable memory index for depth image.");
+        let target = |role, format, usage, aspect_mask, samples| AttachmentKey {
+            role,
+            extent,
+            format,
+            usage,
+            aspect_mask,
+            samples,
+        };
+        let depth = render_targets.attachment(
+            logical_device,
+            memory_properties,
+            target(
+                "depth",
+                depth_format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::SAMPLED,
+                depth_aspect,
+                samples,
+            ),
+        )?;
 
-        let depth_allocate_info = vk::MemoryAllocateInfo::builder()
-            .allocation_size(depth_memory_req.size)
-            .memory_type_index(depth_memory_index);
-        let depth_memory = unsafe { logical_device.allocate_memory(&depth_allocate_info, None) }?;
-        unsafe { logical_device.bind_image_memory(depth_image, depth_memory, 0) }?;
+        /* Transparency Targets */
+        let oit_usage =
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT;
+        let accumulation = render_targets.attachment(
+            logical_device,
+            memory_properties,
+            target(
+                "accumulation",
+                ACCUMULATION_FORMAT,
+                oit_usage,
+                vk::ImageAspectFlags::COLOR,
+                samples,
+            ),
+        )?;
+        let revealage = render_targets.attachment(
+            logical_device,
+            memory_properties,
+            target(
+                "revealage",
+                REVEALAGE_FORMAT,
+                oit_usage,
+                vk::ImageAspectFlags::COLOR,
+                samples,
+            ),
+        )?;
 
-        /* Depth Image View */
-        let subresource_range = vk::ImageSubresourceRange::builder()
-            .aspect_mask(vk::ImageAspectFlags::DEPTH)
-            .base_mip_level(0)
-            .level_count(1)
-            .base_array_layer(0)
-            .layer_count(1);
-        let imageview_create_info = vk::ImageViewCreateInfo::builder()
-            .image(depth_image)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(vk::Format::D32_SFLOAT)
-            .subresource_range(*subresource_range);
-        let depth_imageview =
-            unsafe { logical_device.create_image_view(&imageview_create_info, None) }?;
+        /* Scene Colour */
+        let colour_format = if hdr {
+            SCENE_FORMAT
+        } else {
+            surface_format.format
+        };
+        let msaa_colour = if samples != vk::SampleCountFlags::TYPE_1 {
+            Some(render_targets.attachment(
+                logical_device,
+                memory_properties,
+                target(
+                    "multisampled colour",
+                    colour_format,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                    vk::ImageAspectFlags::COLOR,
+                    samples,
+                ),
+            )?)
+        } else {
+            None
+        };
+        let scene = if hdr {
+            Some(render_targets.attachment(
+                logical_device,
+                memory_properties,
+                target(
+                    "scene",
+                    SCENE_FORMAT,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::INPUT_ATTACHMENT,
+                    vk::ImageAspectFlags::COLOR,
+                    vk::SampleCountFlags::TYPE_1,
+                ),
+            )?)
+        } else {
+            None
+        };
 
         /* Semaphores & Fences */
         let mut image_available = vec![];
@@ -156,12 +246,20 @@ impl Swapchain {
         Ok(Swapchain {
             swapchain_loader,
             swapchain,
+            full_screen_exclusive,
             images,
             image_views,
-            depth_image,
-            depth_imageview,
+            depth_image: depth.image,
+            depth_image_memory: depth.memory,
+            depth_imageview: depth.view,
+            depth_format,
+            accumulation,
+            revealage,
+            msaa_colour,
+            scene,
             framebuffers: vec![],
             surface_format,
+            image_usage,
             extent,
             amount_of_images,
             current_image: 0,
@@ -171,20 +269,33 @@ impl Swapchain {
         })
     }
 
+    /// Looks up a framebuffer per swapchain image in `render_targets`.
     pub fn create_framebuffers(
         &mut self,
         logical_device: &ash::Device,
         renderpass: vk::RenderPass,
+        render_targets: &mut RenderTargetCache,
     ) -> Result<()> {
+        self.framebuffers.clear();
         for iv in &self.image_views {
-            let iview = [*iv, self.depth_imageview];
-            let framebuffer_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(renderpass)
-                .attachments(&iview)
-                .width(self.extent.width)
-                .height(self.extent.height)
-                .layers(1);
-            let fb = unsafe { logical_device.create_framebuffer(&framebuffer_info, None) }?;
+            // Matches `init_renderpass`: the single-sampled scene is the resolve target
+            // with MSAA, and the swapchain image is attached last when it is encoded into.
+            let single_sampled = self.scene.as_ref().map_or(*iv, |scene| scene.view);
+            let mut iview = vec![
+                self.msaa_colour
+                    .as_ref()
+                    .map_or(single_sampled, |msaa_colour| msaa_colour.view),
+                self.depth_imageview,
+                self.accumulation.view,
+                self.revealage.view,
+            ];
+            if self.msaa_colour.is_some() {
+                iview.push(single_sampled);
+            }
+            if self.scene.is_some() {
+                iview.push(*iv);
+            }
+            let fb = render_targets.framebuffer(logical_device, renderpass, &iview, self.extent)?;
             self.framebuffers.push(fb);
         }
 
@@ -193,16 +304,17 @@ impl Swapchain {
 
     ///# Safety
     ///
-    ///
-    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
-        for fb in &self.framebuffers {
-            logical_device.destroy_framebuffer(*fb, None);
-        }
+    /// Nothing may still be rendering to the swapchain. Its attachments stay in
+    /// `render_targets` for the next swapchain to reuse.
+    pub unsafe fn cleanup(
+        &self,
+        logical_device: &ash::Device,
+        render_targets: &mut RenderTargetCache,
+    ) {
+        render_targets.forget_views(logical_device, &self.image_views);
         for iv in &self.image_views {
             unsafe { logical_device.destroy_image_view(*iv, None) }
         }
-        unsafe { logical_device.destroy_image_view(self.depth_imageview, None) }
-        unsafe { logical_device.destroy_image(self.depth_image, None) }
         for semaphore in &self.image_available {
             logical_device.destroy_semaphore(*semaphore, None);
         }
@@ -213,7 +325,102 @@ impl Swapchain {
             logical_device.destroy_fence(*fence, None);
         }
 
+        if let Some(full_screen_exclusive) = &self.full_screen_exclusive {
+            // Losing exclusivity first is reported as an error here, which is harmless.
+            let _ = full_screen_exclusive.release_full_screen_exclusive_mode(self.swapchain);
+        }
         self.swapchain_loader
             .destroy_swapchain(self.swapchain, None);
     }
 }
+
+/// An image with its own memory and a view over all of it.
+#[derive(Clone, Copy, Debug)]
+pub struct Attachment {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+}
+
+impl Attachment {
+    pub fn init(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_mask: vk::ImageAspectFlags,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self> {
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let image = unsafe { logical_device.create_image(&image_info, None) }?;
+
+        let memory_requirements = unsafe { logical_device.get_image_memory_requirements(image) };
+        allocations::track(
+            logical_device,
+            image,
+            memory_requirements.size,
+            format!("{:?} {:?} attachment", format, usage),
+        );
+        let memory_index = find_memorytype_index(
+            &memory_requirements,
+            &memory_properties,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+        .expect("Unable to find suitable memory index for an attachment.");
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(memory_index);
+        let memory = unsafe { logical_device.allocate_memory(&allocate_info, None) }?;
+        allocations::track(
+            logical_device,
+            memory,
+            memory_requirements.size,
+            format!("memory of a {:?} {:?} attachment", format, usage),
+        );
+        unsafe { logical_device.bind_image_memory(image, memory, 0) }?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(aspect_mask)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1);
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(*subresource_range);
+        let view = unsafe { logical_device.create_image_view(&view_info, None) }?;
+
+        Ok(Self {
+            image,
+            memory,
+            view,
+        })
+    }
+
+    ///# Safety
+    ///
+    /// The attachment must not be in use by the device.
+    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        allocations::untrack(logical_device, self.image);
+        allocations::untrack(logical_device, self.memory);
+        logical_device.destroy_image_view(self.view, None);
+        logical_device.destroy_image(self.image, None);
+        logical_device.free_memory(self.memory, None);
+    }
+}
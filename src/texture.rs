@@ -0,0 +1,309 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+use crate::allocations;
+use crate::buffer::Buffer;
+use crate::find_memorytype_index;
+use crate::ray_query::{begin_one_time, end_one_time};
+
+/// A sampled 2D array image with a full mip chain, uploaded once from RGBA8 pixels.
+pub struct Texture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    /// A `TYPE_2D_ARRAY` view over every layer and mip level.
+    pub view: vk::ImageView,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub layers: u32,
+    pub mip_levels: u32,
+}
+
+impl Texture {
+    /// Uploads `layers`, each `extent.width * extent.height` RGBA8 pixels stored row by row,
+    /// and builds the smaller mip levels with linear blits. `format` must be a four-byte
+    /// RGBA format that supports linear filtering, such as `R8G8B8A8_SRGB` for colours or
+    /// `R8G8B8A8_UNORM` for data. The image ends up in `SHADER_READ_ONLY_OPTIMAL`.
+    pub fn init(
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        queue: vk::Queue,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        layers: &[&[u8]],
+    ) -> Result<Self> {
+        let layer_bytes = extent.width as usize * extent.height as usize * 4;
+        if layers.is_empty() || layer_bytes == 0 {
+            bail!("A texture needs at least one layer of at least one pixel.");
+        }
+        if let Some(layer) = layers.iter().find(|layer| layer.len() != layer_bytes) {
+            bail!(
+                "A {}x{} texture layer has {} bytes, not {}.",
+                extent.width,
+                extent.height,
+                layer.len(),
+                layer_bytes
+            );
+        }
+        let layer_count = layers.len() as u32;
+        let mip_levels = u32::BITS - extent.width.max(extent.height).leading_zeros();
+
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(layer_count)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(
+                vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::SAMPLED,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let image = unsafe { logical_device.create_image(&image_info, None) }?;
+        let memory_requirements = unsafe { logical_device.get_image_memory_requirements(image) };
+        allocations::track(
+            logical_device,
+            image,
+            memory_requirements.size,
+            format!("{:?} texture", format),
+        );
+        let memory_index = find_memorytype_index(
+            &memory_requirements,
+            &memory_properties,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+        .expect("Unable to find suitable memory index for a texture.");
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(memory_index);
+        let memory = unsafe { logical_device.allocate_memory(&allocate_info, None) }?;
+        allocations::track(
+            logical_device,
+            memory,
+            memory_requirements.size,
+            format!("memory of a {:?} texture", format),
+        );
+        unsafe { logical_device.bind_image_memory(image, memory, 0) }?;
+
+        let mut staging = Buffer::init(
+            layer_bytes * layers.len(),
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            memory_properties,
+            logical_device,
+        )?;
+        staging.fill(logical_device, &layers.concat(), memory_properties)?;
+
+        let command_buffer = begin_one_time(logical_device, command_pool)?;
+        unsafe {
+            record_upload(
+                logical_device,
+                command_buffer,
+                image,
+                staging.buffer,
+                extent,
+                layer_count,
+                mip_levels,
+            )
+        };
+        let uploaded = end_one_time(logical_device, command_pool, queue, command_buffer);
+        staging.cleanup(logical_device);
+        uploaded?;
+
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(mip_levels)
+            .base_array_layer(0)
+            .layer_count(layer_count);
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+            .format(format)
+            .subresource_range(*subresource_range);
+        let view = unsafe { logical_device.create_image_view(&view_info, None) }?;
+
+        Ok(Texture {
+            image,
+            memory,
+            view,
+            extent,
+            format,
+            layers: layer_count,
+            mip_levels,
+        })
+    }
+
+    ///# Safety
+    ///
+    /// The texture must not be in use by the device.
+    pub unsafe fn cleanup(&self, logical_device: &ash::Device) {
+        allocations::untrack(logical_device, self.image);
+        allocations::untrack(logical_device, self.memory);
+        logical_device.destroy_image_view(self.view, None);
+        logical_device.destroy_image(self.image, None);
+        logical_device.free_memory(self.memory, None);
+    }
+}
+
+/// Copies the staging buffer into mip 0, blits each level from the one above and leaves
+/// every level ready for sampling.
+unsafe fn record_upload(
+    logical_device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    staging: vk::Buffer,
+    extent: vk::Extent2D,
+    layers: u32,
+    mip_levels: u32,
+) {
+    let levels = |base_mip_level, level_count| vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level,
+        level_count,
+        base_array_layer: 0,
+        layer_count: layers,
+    };
+    let layers_of = |mip_level| vk::ImageSubresourceLayers {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        mip_level,
+        base_array_layer: 0,
+        layer_count: layers,
+    };
+    let barrier = |range, old_layout, new_layout, src_access_mask, dst_access_mask| {
+        vk::ImageMemoryBarrier::builder()
+            .image(image)
+            .subresource_range(range)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .build()
+    };
+    let transfer_barrier = |barrier: vk::ImageMemoryBarrier| {
+        logical_device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    };
+
+    logical_device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TOP_OF_PIPE,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        &[barrier(
+            levels(0, mip_levels),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+        )],
+    );
+    let copy = vk::BufferImageCopy::builder()
+        .image_subresource(layers_of(0))
+        .image_extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        });
+    logical_device.cmd_copy_buffer_to_image(
+        command_buffer,
+        staging,
+        image,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        &[copy.build()],
+    );
+
+    let size = |level: u32| {
+        [
+            (extent.width >> level).max(1) as i32,
+            (extent.height >> level).max(1) as i32,
+        ]
+    };
+    for level in 1..mip_levels {
+        transfer_barrier(barrier(
+            levels(level - 1, 1),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::TRANSFER_READ,
+        ));
+        let ([src_width, src_height], [dst_width, dst_height]) = (size(level - 1), size(level));
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(layers_of(level - 1))
+            .src_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: src_width,
+                    y: src_height,
+                    z: 1,
+                },
+            ])
+            .dst_subresource(layers_of(level))
+            .dst_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: dst_width,
+                    y: dst_height,
+                    z: 1,
+                },
+            ]);
+        logical_device.cmd_blit_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[blit.build()],
+            vk::Filter::LINEAR,
+        );
+    }
+
+    // Every level but the last was left as a blit source.
+    let to_shader_read = [
+        barrier(
+            levels(0, mip_levels - 1),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::AccessFlags::SHADER_READ,
+        ),
+        barrier(
+            levels(mip_levels - 1, 1),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        ),
+    ];
+    let barriers = if mip_levels > 1 {
+        &to_shader_read[..]
+    } else {
+        &to_shader_read[1..]
+    };
+    logical_device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::DependencyFlags::empty(),
+        &[],
+        &[],
+        barriers,
+    );
+}
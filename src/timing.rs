@@ -0,0 +1,163 @@
+use std::time::Instant;
+
+use anyhow::{Ok, Result};
+use ash::vk;
+
+use crate::swapchain::Swapchain;
+
+pub struct FrameTimer {
+    pub start: Instant,
+    pub last_frame: Instant,
+    pub delta: f32,
+    pub elapsed: f32,
+    pub frame_count: u64,
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            last_frame: now,
+            delta: 0.0,
+            elapsed: 0.0,
+            frame_count: 0,
+        }
+    }
+
+    /// Advances to the next frame and returns the seconds since the previous one.
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        self.delta = (now - self.last_frame).as_secs_f32();
+        self.elapsed = (now - self.start).as_secs_f32();
+        self.last_frame = now;
+        self.frame_count += 1;
+        self.delta
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DisplayTimingStats {
+    pub refresh_duration: u64,
+    pub presented_frames: u64,
+    pub missed_vsyncs: u64,
+}
+
+pub struct DisplayTiming {
+    pub handle: vk::Device,
+    pub fp: vk::GoogleDisplayTimingFn,
+    pub refresh_duration: u64,
+    pub next_present_id: u32,
+    pub last_present_id: u32,
+    pub last_actual_present_time: u64,
+    pub presented_frames: u64,
+    pub missed_vsyncs: u64,
+}
+
+impl DisplayTiming {
+    pub fn init(
+        instance: &ash::Instance,
+        logical_device: &ash::Device,
+        swapchain: &Swapchain,
+    ) -> Result<Self> {
+        let handle = logical_device.handle();
+        let fp = vk::GoogleDisplayTimingFn::load(|name| unsafe {
+            std::mem::transmute(instance.get_device_proc_addr(handle, name.as_ptr()))
+        });
+
+        let mut refresh_cycle = vk::RefreshCycleDurationGOOGLE::default();
+        unsafe {
+            (fp.get_refresh_cycle_duration_google)(handle, swapchain.swapchain, &mut refresh_cycle)
+        }
+        .result()?;
+
+        Ok(Self {
+            handle,
+            fp,
+            refresh_duration: refresh_cycle.refresh_duration,
+            next_present_id: 1,
+            last_present_id: 0,
+            last_actual_present_time: 0,
+            presented_frames: 0,
+            missed_vsyncs: 0,
+        })
+    }
+
+    /// Timing to chain into the next `queue_present`. Until the driver reported a
+    /// past presentation the desired time is 0, which means "as soon as possible".
+    pub fn next_present_time(&mut self) -> vk::PresentTimeGOOGLE {
+        let present_id = self.next_present_id;
+        self.next_present_id = self.next_present_id.wrapping_add(1);
+
+        let desired_present_time = if self.last_actual_present_time == 0 {
+            0
+        } else {
+            let frames_ahead = present_id.wrapping_sub(self.last_present_id) as u64;
+            self.last_actual_present_time + frames_ahead * self.refresh_duration
+        };
+
+        vk::PresentTimeGOOGLE {
+            present_id,
+            desired_present_time,
+        }
+    }
+
+    pub fn poll(&mut self, swapchain: &Swapchain) -> Result<()> {
+        let mut count = 0;
+        unsafe {
+            (self.fp.get_past_presentation_timing_google)(
+                self.handle,
+                swapchain.swapchain,
+                &mut count,
+                std::ptr::null_mut(),
+            )
+        }
+        .result()?;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut timings = vec![vk::PastPresentationTimingGOOGLE::default(); count as usize];
+        unsafe {
+            (self.fp.get_past_presentation_timing_google)(
+                self.handle,
+                swapchain.swapchain,
+                &mut count,
+                timings.as_mut_ptr(),
+            )
+        }
+        .result()?;
+        timings.truncate(count as usize);
+
+        for timing in timings {
+            self.presented_frames += 1;
+            if timing.desired_present_time != 0
+                && self.refresh_duration > 0
+                && timing.actual_present_time
+                    > timing.desired_present_time + self.refresh_duration / 2
+            {
+                self.missed_vsyncs += (timing.actual_present_time - timing.desired_present_time
+                    + self.refresh_duration / 2)
+                    / self.refresh_duration;
+            }
+            self.last_present_id = timing.present_id;
+            self.last_actual_present_time = timing.actual_present_time;
+        }
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> DisplayTimingStats {
+        DisplayTimingStats {
+            refresh_duration: self.refresh_duration,
+            presented_frames: self.presented_frames,
+            missed_vsyncs: self.missed_vsyncs,
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::VecDeque;
+
+use anyhow::{Ok, Result};
+use ash::vk;
+use nalgebra::Vector3;
+
+use crate::buffer::Buffer;
+use crate::model::{Instance, Model, VertexData};
+use crate::multisample::Multisampling;
+use crate::particles::Curve;
+use crate::vertex_layout::VertexLayout;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct TrailVertex {
+    pub position: [f32; 3],
+    pub colour: [f32; 4],
+}
+
+crate::vertex_layout!(TrailVertex { position, colour });
+
+/// The recent positions of something moving, drawn as a ribbon that always faces the
+/// camera. Width, colour and opacity are curves over a point's age, 0 where it was just
+/// recorded and 1 when it expires.
+pub struct Trail {
+    /// Newest first, with their age in seconds.
+    pub points: VecDeque<(Vector3<f32>, f32)>,
+    /// Seconds a recorded point stays on the trail.
+    pub lifetime: f32,
+    /// Distance the head has to move before a new point is recorded; until then the
+    /// newest point follows it.
+    pub min_distance: f32,
+    pub width: Curve<f32>,
+    pub colour: Curve<[f32; 3]>,
+    pub opacity: Curve<f32>,
+    /// Model index and instance handle whose position is recorded every frame.
+    pub target: Option<(usize, usize)>,
+}
+
+impl Trail {
+    pub fn new(lifetime: f32, width: f32, colour: [f32; 3]) -> Self {
+        Self {
+            points: VecDeque::new(),
+            lifetime,
+            min_distance: 0.05,
+            width: Curve::linear(width, 0.0),
+            colour: Curve::constant(colour),
+            opacity: Curve::linear(1.0, 0.0),
+            target: None,
+        }
+    }
+
+    /// A trail behind the instance `handle` of model `model`.
+    pub fn following(mut self, model: usize, handle: usize) -> Self {
+        self.target = Some((model, handle));
+        self
+    }
+
+    pub fn record(&mut self, position: Vector3<f32>) {
+        match self.points.get(1) {
+            Some((previous, _)) if (position - previous).norm() < self.min_distance => {
+                self.points[0] = (position, 0.0);
+            }
+            _ => self.points.push_front((position, 0.0)),
+        }
+    }
+
+    /// Ages the points and drops the expired ones.
+    pub fn advance(&mut self, delta_time: f32) {
+        for (_, age) in &mut self.points {
+            *age += delta_time;
+        }
+        while self
+            .points
+            .back()
+            .is_some_and(|(_, age)| *age >= self.lifetime)
+        {
+            self.points.pop_back();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Two vertices per point, spread across the trail as seen from `camera_position`,
+    /// for drawing as a triangle strip.
+    pub fn strip(&self, camera_position: &Vector3<f32>) -> Vec<TrailVertex> {
+        if self.points.len() < 2 {
+            return vec![];
+        }
+        let last = self.points.len() - 1;
+        self.points
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (position, age))| {
+                let ahead = self.points[i.saturating_sub(1)].0;
+                let behind = self.points[(i + 1).min(last)].0;
+                let to_camera = camera_position - position;
+                let side = (ahead - behind)
+                    .cross(&to_camera)
+                    .try_normalize(1.0e-6)
+                    .unwrap_or_else(Vector3::zeros);
+                let t = (age / self.lifetime).min(1.0);
+                let offset = side * 0.5 * self.width.sample(t);
+                let [r, g, b] = self.colour.sample(t);
+                let colour = [r, g, b, self.opacity.sample(t)];
+                [
+                    TrailVertex {
+                        position: (position + offset).into(),
+                        colour,
+                    },
+                    TrailVertex {
+                        position: (position - offset).into(),
+                        colour,
+                    },
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Draws every [`Trail`] from one vertex buffer rebuilt each frame, blended over the
+/// opaque scene without writing depth.
+pub struct Trails {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub trails: Vec<Trail>,
+    vertex_buffer: Option<Buffer>,
+    /// First vertex and vertex count of each trail's strip in the buffer.
+    ranges: Vec<(u32, u32)>,
+}
+
+impl Trails {
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        multisampling: &Multisampling,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/trail.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/trail.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_bindings = [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: TrailVertex::stride(),
+            input_rate: vk::VertexInputRate::VERTEX,
+        }];
+        let vertex_attributes: Vec<vk::VertexInputAttributeDescription> = TrailVertex::attributes()
+            .into_iter()
+            .enumerate()
+            .map(
+                |(location, attribute)| vk::VertexInputAttributeDescription {
+                    binding: 0,
+                    location: location as u32,
+                    offset: attribute.offset,
+                    format: attribute.format,
+                },
+            )
+            .collect();
+        let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(&vertex_bindings)
+            .vertex_attribute_descriptions(&vertex_attributes);
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_STRIP);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = multisampling.state_info();
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Pipeline */
+        let descriptor_layouts = [descriptor_set_layout];
+        let pipeline_layout_info =
+            vk::PipelineLayoutCreateInfo::builder().set_layouts(&descriptor_layouts);
+        let layout = unsafe { logical_device.create_pipeline_layout(&pipeline_layout_info, None) }?;
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colourblend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(Trails {
+            pipeline,
+            layout,
+            trails: vec![],
+            vertex_buffer: None,
+            ranges: vec![],
+        })
+    }
+
+    /// Adds the trail and returns its index in [`Trails::trails`].
+    pub fn add(&mut self, trail: Trail) -> usize {
+        self.trails.push(trail);
+        self.trails.len() - 1
+    }
+
+    /// Ages every trail's points; call once per frame with the frame time.
+    pub fn advance(&mut self, delta_time: f32) {
+        for trail in &mut self.trails {
+            trail.advance(delta_time);
+        }
+    }
+
+    /// Records the position of each followed instance, then rebuilds the strips facing
+    /// `camera_position` and uploads them.
+    pub fn update<I: Instance>(
+        &mut self,
+        logical_device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        camera_position: &Vector3<f32>,
+        models: &[Model<VertexData, I>],
+    ) -> Result<()> {
+        let mut vertices = vec![];
+        self.ranges.clear();
+        for trail in &mut self.trails {
+            let followed = trail.target.and_then(|(model, handle)| {
+                models
+                    .get(model)
+                    .and_then(|model| model.instances.get(handle))
+            });
+            if let Some(instance) = followed {
+                trail.record(instance.base().position().into());
+            }
+            let strip = trail.strip(camera_position);
+            self.ranges
+                .push((vertices.len() as u32, strip.len() as u32));
+            vertices.extend(strip);
+        }
+        if vertices.is_empty() {
+            return Ok(());
+        }
+        match &mut self.vertex_buffer {
+            Some(buffer) => buffer.fill(logical_device, &vertices, memory_properties)?,
+            None => {
+                let mut buffer = Buffer::init(
+                    std::mem::size_of_val(&vertices[..]),
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    memory_properties,
+                    logical_device,
+                )?;
+                buffer.fill(logical_device, &vertices, memory_properties)?;
+                self.vertex_buffer = Some(buffer);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn draw(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        let Some(vertex_buffer) = &self.vertex_buffer else {
+            return;
+        };
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[vertex_buffer.buffer],
+                &[0],
+            );
+            for &(first_vertex, vertex_count) in &self.ranges {
+                if vertex_count > 0 {
+                    logical_device.cmd_draw(command_buffer, vertex_count, 1, first_vertex, 0);
+                }
+            }
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            if let Some(buffer) = &self.vertex_buffer {
+                buffer.cleanup(logical_device);
+            }
+            logical_device.destroy_pipeline(self.pipeline, None);
+            logical_device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
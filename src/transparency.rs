@@ -0,0 +1,206 @@
+use anyhow::{Ok, Result};
+use ash::vk;
+use nalgebra::Vector3;
+
+use crate::model::{Instance, Model, VertexData};
+use crate::pipeline::{Pipeline, Topology};
+
+/// How translucent instances (opacity below 1) are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransparencyMode {
+    /// Back to front through [`TransparentPass`]; exact, but needs a sort every frame.
+    #[default]
+    Sorted,
+    /// Through [`crate::oit::WeightedBlendedOit`]; approximate, but needs no sorting.
+    WeightedBlended,
+}
+
+/// Draws translucent instances (opacity below 1) after the opaque geometry, one at a
+/// time and back to front, testing against but not writing depth.
+pub struct TransparentPass {
+    pub pipeline: vk::Pipeline,
+    /// (index into the models, instance index) pairs, furthest from the camera first.
+    pub draw_order: Vec<(usize, usize)>,
+}
+
+impl TransparentPass {
+    /// Shares the main pipeline's layout, so the same descriptor sets bind to it.
+    pub fn init(
+        logical_device: &ash::Device,
+        renderpass: &vk::RenderPass,
+        pipeline: &Pipeline,
+    ) -> Result<Self> {
+        /* Shaders */
+        let vertex_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.vert", kind: vert));
+        let vertex_module = unsafe { logical_device.create_shader_module(&vertex_info, None) }?;
+
+        let fragment_info = vk::ShaderModuleCreateInfo::builder()
+            .code(vk_shader_macros::include_glsl!("shaders/shader.frag", kind: frag));
+        let fragment_module = unsafe { logical_device.create_shader_module(&fragment_info, None) }?;
+
+        let main_function_name = std::ffi::CString::new("main").unwrap();
+        let transparent_pass = vk::TRUE;
+        let specialization_entries = [vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<vk::Bool32>(),
+        }];
+        let specialization_data = transparent_pass.to_ne_bytes();
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&specialization_entries)
+            .data(&specialization_data);
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(&main_function_name)
+                .specialization_info(&specialization_info)
+                .build(),
+            vk::PipelineShaderStageCreateInfo::builder()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment_module)
+                .name(&main_function_name)
+                .build(),
+        ];
+
+        /* Fixed Functions */
+        let vertex_input_info = pipeline.vertex_input.state_info();
+        let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
+            .viewport_count(1)
+            .scissor_count(1);
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+        let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
+            .line_width(1.0)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .cull_mode(vk::CullModeFlags::BACK)
+            .polygon_mode(vk::PolygonMode::FILL);
+        let multisampler_info = pipeline.multisampling.state_info();
+
+        let colourblend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+            .blend_enable(true)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .color_write_mask(
+                vk::ColorComponentFlags::R
+                    | vk::ColorComponentFlags::G
+                    | vk::ColorComponentFlags::B
+                    | vk::ColorComponentFlags::A,
+            )
+            .build()];
+        let colourblend_info =
+            vk::PipelineColorBlendStateCreateInfo::builder().attachments(&colourblend_attachments);
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        /* Pipeline */
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_info)
+            .input_assembly_state(&input_assembly_info)
+            .viewport_state(&viewport_info)
+            .rasterization_state(&rasterizer_info)
+            .multisample_state(&multisampler_info)
+            .depth_stencil_state(&depth_stencil_info)
+            .color_blend_state(&colourblend_info)
+            .dynamic_state(&dynamic_state_info)
+            .layout(pipeline.layout)
+            .render_pass(*renderpass)
+            .subpass(0);
+        let transparent_pipeline = unsafe {
+            logical_device
+                .create_graphics_pipelines(
+                    vk::PipelineCache::null(),
+                    &[pipeline_info.build()],
+                    None,
+                )
+                .map_err(|(_, e)| e)?
+        }[0];
+
+        unsafe {
+            logical_device.destroy_shader_module(fragment_module, None);
+            logical_device.destroy_shader_module(vertex_module, None)
+        }
+
+        Ok(TransparentPass {
+            pipeline: transparent_pipeline,
+            draw_order: vec![],
+        })
+    }
+
+    /// Collects the visible translucent instances of `models` and orders them back to front.
+    pub fn sort<I: Instance>(
+        &mut self,
+        models: &[Model<VertexData, I>],
+        camera_position: &Vector3<f32>,
+    ) {
+        let mut draws: Vec<(f32, usize, usize)> = models
+            .iter()
+            .enumerate()
+            .filter(|(_, model)| model.mesh.topology == Topology::TriangleList)
+            .flat_map(|(model_index, model)| {
+                model
+                    .instances
+                    .visible()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, instance)| instance.base().is_translucent())
+                    .map(move |(instance_index, instance)| {
+                        let distance = (Vector3::from(instance.base().position())
+                            - camera_position)
+                            .norm_squared();
+                        (distance, model_index, instance_index)
+                    })
+            })
+            .collect();
+        draws.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        self.draw_order.clear();
+        self.draw_order.extend(
+            draws
+                .into_iter()
+                .map(|(_, model, instance)| (model, instance)),
+        );
+    }
+
+    /// Expects the main pipeline's descriptor sets to be bound already.
+    pub fn draw<I: Instance>(
+        &self,
+        logical_device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        models: &[Model<VertexData, I>],
+    ) {
+        if self.draw_order.is_empty() {
+            return;
+        }
+        unsafe {
+            logical_device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+        }
+        for (model, instance) in &self.draw_order {
+            if let Some(model) = models.get(*model) {
+                model.draw_instance_at(logical_device, command_buffer, *instance);
+            }
+        }
+    }
+
+    pub fn cleanup(&self, logical_device: &ash::Device) {
+        unsafe {
+            logical_device.destroy_pipeline(self.pipeline, None);
+        }
+    }
+}
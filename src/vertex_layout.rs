@@ -0,0 +1,254 @@
+use anyhow::{bail, Ok, Result};
+use ash::vk;
+
+/// A field type the vertex shader can read, with the format of each location it takes.
+pub trait VertexFormat {
+    const FORMAT: vk::Format;
+    /// Matrices take a location per column.
+    const LOCATIONS: u32 = 1;
+}
+
+impl VertexFormat for f32 {
+    const FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+}
+
+impl VertexFormat for [f32; 2] {
+    const FORMAT: vk::Format = vk::Format::R32G32_SFLOAT;
+}
+
+impl VertexFormat for [f32; 3] {
+    const FORMAT: vk::Format = vk::Format::R32G32B32_SFLOAT;
+}
+
+impl VertexFormat for [f32; 4] {
+    const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+}
+
+impl VertexFormat for [[f32; 4]; 4] {
+    const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+    const LOCATIONS: u32 = 4;
+}
+
+impl VertexFormat for u32 {
+    const FORMAT: vk::Format = vk::Format::R32_UINT;
+}
+
+impl VertexFormat for [u32; 2] {
+    const FORMAT: vk::Format = vk::Format::R32G32_UINT;
+}
+
+impl VertexFormat for [u32; 3] {
+    const FORMAT: vk::Format = vk::Format::R32G32B32_UINT;
+}
+
+impl VertexFormat for [u32; 4] {
+    const FORMAT: vk::Format = vk::Format::R32G32B32A32_UINT;
+}
+
+/// One field of a [`VertexLayout`] type.
+#[derive(Clone, Copy, Debug)]
+pub struct VertexAttribute {
+    pub offset: u32,
+    pub format: vk::Format,
+    pub locations: u32,
+}
+
+impl VertexAttribute {
+    /// The attribute for the field `field` points at; see [`vertex_layout!`].
+    pub fn of_field<S, T: VertexFormat>(offset: usize, _field: fn(&S) -> &T) -> Self {
+        VertexAttribute {
+            offset: offset as u32,
+            format: T::FORMAT,
+            locations: T::LOCATIONS,
+        }
+    }
+
+    /// The attributes of an embedded [`VertexLayout`] struct at `offset`.
+    pub fn of_embedded<S, T: VertexLayout>(offset: usize, _field: fn(&S) -> &T) -> Vec<Self> {
+        T::attributes()
+            .into_iter()
+            .map(|attribute| VertexAttribute {
+                offset: attribute.offset + offset as u32,
+                ..attribute
+            })
+            .collect()
+    }
+}
+
+/// A `#[repr(C)]` vertex or instance struct the shaders read field by field, in
+/// consecutive locations. Implement it with [`vertex_layout!`] so offsets follow the
+/// struct instead of being written out by hand.
+pub trait VertexLayout: Copy {
+    /// The fields in shader location order.
+    fn attributes() -> Vec<VertexAttribute>;
+
+    fn stride() -> u32 {
+        std::mem::size_of::<Self>() as u32
+    }
+
+    fn locations() -> u32 {
+        Self::attributes().iter().map(|a| a.locations).sum()
+    }
+}
+
+/// Implements [`VertexLayout`] for a struct from the fields the shaders read, in location
+/// order. A leading `..field` embeds another `VertexLayout` struct's attributes:
+///
+/// ```ignore
+/// krakatoa::vertex_layout!(VertexData { position, normal });
+/// krakatoa::vertex_layout!(Sprite { ..base, uv_offset });
+/// ```
+#[macro_export]
+macro_rules! vertex_layout {
+    ($ty:ty { ..$base:ident $(, $field:ident)* $(,)? }) => {
+        impl $crate::vertex_layout::VertexLayout for $ty {
+            fn attributes() -> Vec<$crate::vertex_layout::VertexAttribute> {
+                let mut attributes = $crate::vertex_layout::VertexAttribute::of_embedded(
+                    std::mem::offset_of!($ty, $base),
+                    |v: &$ty| &v.$base,
+                );
+                attributes.extend([$(
+                    $crate::vertex_layout::VertexAttribute::of_field(
+                        std::mem::offset_of!($ty, $field),
+                        |v: &$ty| &v.$field,
+                    ),
+                )*]);
+                attributes
+            }
+        }
+    };
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::vertex_layout::VertexLayout for $ty {
+            fn attributes() -> Vec<$crate::vertex_layout::VertexAttribute> {
+                vec![$(
+                    $crate::vertex_layout::VertexAttribute::of_field(
+                        std::mem::offset_of!($ty, $field),
+                        |v: &$ty| &v.$field,
+                    ),
+                )*]
+            }
+        }
+    };
+}
+
+/// Vertex input state for drawing `Model<V, I>`: vertices in binding 0 and instances in
+/// binding 1, their locations numbered on from the vertex's.
+#[derive(Clone, Debug)]
+pub struct VertexInput {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexInput {
+    pub fn of<V: VertexLayout, I: VertexLayout>() -> Self {
+        let mut attributes = vec![];
+        let mut location = 0;
+        for (binding, fields) in [V::attributes(), I::attributes()].into_iter().enumerate() {
+            for field in fields {
+                for column in 0..field.locations {
+                    attributes.push(vk::VertexInputAttributeDescription {
+                        binding: binding as u32,
+                        location,
+                        // Matrix columns are 16 bytes apart.
+                        offset: field.offset + 16 * column,
+                        format: field.format,
+                    });
+                    location += 1;
+                }
+            }
+        }
+        VertexInput {
+            bindings: vec![
+                vk::VertexInputBindingDescription {
+                    binding: 0,
+                    stride: V::stride(),
+                    input_rate: vk::VertexInputRate::VERTEX,
+                },
+                vk::VertexInputBindingDescription {
+                    binding: 1,
+                    stride: I::stride(),
+                    input_rate: vk::VertexInputRate::INSTANCE,
+                },
+            ],
+            attributes,
+        }
+    }
+
+    /// Checks that every attribute is 4-byte aligned, ends within its binding's stride and
+    /// does not overlap another, so a [`VertexLayout`] written by hand cannot silently
+    /// corrupt rendering.
+    pub fn validate(&self) -> Result<()> {
+        for binding in &self.bindings {
+            if binding.stride % 4 != 0 {
+                bail!(
+                    "Binding {} has a stride of {} bytes, which is not a multiple of 4.",
+                    binding.binding,
+                    binding.stride
+                );
+            }
+            let mut ranges: Vec<(u32, u32, u32)> = vec![];
+            for attribute in self
+                .attributes
+                .iter()
+                .filter(|a| a.binding == binding.binding)
+            {
+                let size = format_size(attribute.format);
+                if size == 0 {
+                    bail!(
+                        "Location {} uses {:?}, which has no known size.",
+                        attribute.location,
+                        attribute.format
+                    );
+                }
+                let end = attribute.offset + size;
+                if attribute.offset % 4 != 0 {
+                    bail!(
+                        "Location {} starts at offset {}, which is not 4-byte aligned.",
+                        attribute.location,
+                        attribute.offset
+                    );
+                }
+                if end > binding.stride {
+                    bail!(
+                        "Location {} spans bytes {}..{}, past the {}-byte stride of binding {}.",
+                        attribute.location,
+                        attribute.offset,
+                        end,
+                        binding.stride,
+                        binding.binding
+                    );
+                }
+                if let Some((location, _, _)) = ranges
+                    .iter()
+                    .find(|(_, start, other_end)| attribute.offset < *other_end && *start < end)
+                {
+                    bail!(
+                        "Locations {} and {} overlap in binding {}.",
+                        location,
+                        attribute.location,
+                        binding.binding
+                    );
+                }
+                ranges.push((attribute.location, attribute.offset, end));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn state_info(&self) -> vk::PipelineVertexInputStateCreateInfoBuilder<'_> {
+        vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_attribute_descriptions(&self.attributes)
+            .vertex_binding_descriptions(&self.bindings)
+    }
+}
+
+/// Bytes taken by one attribute of `format`, or 0 for formats [`VertexFormat`] never uses.
+pub fn format_size(format: vk::Format) -> u32 {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32_UINT => 4,
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_UINT => 8,
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT => 12,
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT => 16,
+        _ => 0,
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::{bail, Context, Result};
+use ash::vk;
+
+use crate::pipeline::PipelineShaders;
+
+/// SPIR-V for the WGSL entry point `entry_point` of `source`, for `stage` (vertex,
+/// fragment or compute), translated with naga. Clip space is left as the shader writes
+/// it: the engine's matrices already target Vulkan's, so positions are not flipped the
+/// way wgpu does for its own.
+pub fn compile_wgsl(
+    source: &str,
+    stage: vk::ShaderStageFlags,
+    entry_point: &str,
+) -> Result<Vec<u32>> {
+    let shader_stage = match stage {
+        vk::ShaderStageFlags::VERTEX => naga::ShaderStage::Vertex,
+        vk::ShaderStageFlags::FRAGMENT => naga::ShaderStage::Fragment,
+        vk::ShaderStageFlags::COMPUTE => naga::ShaderStage::Compute,
+        _ => bail!("WGSL has no {:?} shaders.", stage),
+    };
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|error| anyhow::anyhow!(error.emit_to_string(source)))?;
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|error| anyhow::anyhow!(error.emit_to_string(source)))?;
+
+    let mut options = naga::back::spv::Options::default();
+    options
+        .flags
+        .remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+    let pipeline_options = naga::back::spv::PipelineOptions {
+        shader_stage,
+        entry_point: entry_point.to_owned(),
+    };
+    naga::back::spv::write_vec(&module, &info, &options, Some(&pipeline_options))
+        .with_context(|| format!("Writing SPIR-V for {} failed.", entry_point))
+}
+
+/// Reads and translates the WGSL file at `path`.
+pub fn compile_wgsl_file(
+    path: impl AsRef<std::path::Path>,
+    stage: vk::ShaderStageFlags,
+    entry_point: &str,
+) -> Result<Vec<u32>> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading {} failed.", path.display()))?;
+    compile_wgsl(&source, stage, entry_point)
+}
+
+/// Shaders for [`crate::krakatoa_builder::KrakatoaBuilder::shaders`] from a WGSL module
+/// holding both the vertex and the fragment entry point, as wgpu render pipelines
+/// usually do. Vertex inputs use `@location`s 0 (position), 1 (normal), 2 to 5 (model
+/// matrix columns), 6 to 9 (inverse model matrix columns), 10 (colour), 11 (opacity),
+/// 12 (alpha cutoff) and 13 (cutout layer), and the frame uniforms `@group(0) @binding(0)`.
+pub fn pipeline_shaders(
+    source: &str,
+    vertex_entry_point: &str,
+    fragment_entry_point: &str,
+) -> Result<PipelineShaders> {
+    Ok(PipelineShaders {
+        vertex: compile_wgsl(source, vk::ShaderStageFlags::VERTEX, vertex_entry_point)?,
+        vertex_entry_point: vertex_entry_point.to_owned(),
+        fragment: compile_wgsl(source, vk::ShaderStageFlags::FRAGMENT, fragment_entry_point)?,
+        fragment_entry_point: fragment_entry_point.to_owned(),
+    })
+}
@@ -0,0 +1,35 @@
+use winit::monitor::VideoMode;
+use winit::window::{Fullscreen, Window};
+
+/// How the window occupies its monitor.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// A borderless window covering the current monitor, without a video mode change.
+    Borderless,
+    /// Exclusive fullscreen in the current monitor's largest, fastest video mode.
+    Fullscreen,
+}
+
+impl WindowMode {
+    /// The winit fullscreen setting for this mode; `None` when windowed or when
+    /// the window is not on any monitor.
+    pub fn fullscreen(&self, window: &Window) -> Option<Fullscreen> {
+        match self {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(window.current_monitor())),
+            WindowMode::Fullscreen => window
+                .current_monitor()?
+                .video_modes()
+                .max_by_key(|mode: &VideoMode| {
+                    (
+                        mode.size().width * mode.size().height,
+                        mode.refresh_rate_millihertz(),
+                        mode.bit_depth(),
+                    )
+                })
+                .map(Fullscreen::Exclusive),
+        }
+    }
+}
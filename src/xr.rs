@@ -0,0 +1,431 @@
+use std::f32::consts::PI;
+
+use anyhow::{anyhow, bail, Ok, Result};
+use ash::vk::{self, Handle};
+use nalgebra::{Isometry3, Matrix4, Quaternion, Translation3, UnitQuaternion, Vector3};
+use openxr as xr;
+use raw_window_handle::HasRawDisplayHandle;
+
+use crate::camera::Camera;
+use crate::capabilities::DeviceCapabilities;
+use crate::instance_api_version;
+use crate::krakatoa::{BlitTarget, Krakatoa};
+use crate::krakatoa_builder::KrakatoaBuilder;
+use crate::queue::{QueueFamilies, Queues};
+use crate::raw_context::RawContext;
+use crate::surface::Surface;
+
+const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+
+/// One swapchain per eye, owned by the runtime.
+pub struct EyeSwapchain {
+    pub swapchain: xr::Swapchain<xr::Vulkan>,
+    pub images: Vec<vk::Image>,
+    pub extent: vk::Extent2D,
+}
+
+/// Renders the scene to a headset through OpenXR, with the window mirroring the last eye.
+/// Each eye is drawn as a regular window frame and scaled into the eye's swapchain image,
+/// so the window must stay visible while the headset is in use.
+pub struct XrSession {
+    // Declared before `krakatoa`: the runtime's objects go before the device they use.
+    pub eyes: Vec<EyeSwapchain>,
+    pub space: xr::Space,
+    pub frame_stream: xr::FrameStream<xr::Vulkan>,
+    pub frame_waiter: xr::FrameWaiter,
+    pub session: xr::Session<xr::Vulkan>,
+    pub blend_mode: xr::EnvironmentBlendMode,
+    pub system: xr::SystemId,
+    pub instance: xr::Instance,
+    /// Between the runtime's `READY` and `STOPPING` states; frames go to the window only
+    /// while this is false.
+    pub running: bool,
+    /// Where the runtime's reference space sits in the world. The default turns its
+    /// y-up, -z-forward axes into the engine's y-down ones.
+    pub origin: Isometry3<f32>,
+    eye_camera: Camera,
+    /// Owns the Vulkan device and instance the runtime asked for.
+    pub krakatoa: Krakatoa,
+}
+
+impl XrSession {
+    /// Creates the Vulkan instance and device through the OpenXR runtime, which picks the
+    /// GPU driving the headset, and the engine on top of them. The builder's adapter,
+    /// extra extensions and host memory tracking are ignored.
+    pub fn init(window: winit::window::Window, builder: KrakatoaBuilder) -> Result<Self> {
+        /* OpenXR Instance */
+        let xr_entry = unsafe { xr::Entry::load() }
+            .map_err(|error| anyhow!("no OpenXR loader: {:?}", error))?;
+        if !xr_entry.enumerate_extensions()?.khr_vulkan_enable2 {
+            bail!("the OpenXR runtime does not support XR_KHR_vulkan_enable2");
+        }
+        let mut extensions = xr::ExtensionSet::default();
+        extensions.khr_vulkan_enable2 = true;
+        let instance = xr_entry.create_instance(
+            &xr::ApplicationInfo {
+                application_name: "Learn Vulkan",
+                application_version: 0,
+                engine_name: "UnknownGameEngine",
+                engine_version: 0,
+            },
+            &extensions,
+            &[],
+        )?;
+        let system = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)?;
+        let blend_mode = instance.enumerate_environment_blend_modes(system, VIEW_TYPE)?[0];
+
+        /* Vulkan Instance */
+        let entry = ash::Entry::linked();
+        let api_version = instance_api_version(&entry)?;
+        let requirements = instance.graphics_requirements::<xr::Vulkan>(system)?;
+        let xr_version = xr::Version::new(
+            vk::api_version_major(api_version) as u16,
+            vk::api_version_minor(api_version) as u16,
+            0,
+        );
+        if xr_version < requirements.min_api_version_supported {
+            bail!(
+                "the OpenXR runtime needs Vulkan {}, the loader has {}",
+                requirements.min_api_version_supported,
+                xr_version
+            );
+        }
+        let get_instance_proc_addr: xr::sys::platform::VkGetInstanceProcAddr =
+            unsafe { std::mem::transmute(entry.static_fn().get_instance_proc_addr) };
+        let engine_name = std::ffi::CString::new("UnknownGameEngine").unwrap();
+        let app_name = std::ffi::CString::new("Learn Vulkan").unwrap();
+        let app_info = vk::ApplicationInfo::builder()
+            .application_name(&app_name)
+            .engine_name(&engine_name)
+            .api_version(api_version);
+        let extension_names =
+            ash_window::enumerate_required_extensions(window.raw_display_handle())?;
+        let instance_create_info = vk::InstanceCreateInfo::builder()
+            .application_info(&app_info)
+            .enabled_extension_names(extension_names);
+        let vk_instance = unsafe {
+            let raw = instance
+                .create_vulkan_instance(
+                    system,
+                    get_instance_proc_addr,
+                    &*instance_create_info as *const _ as *const _,
+                )?
+                .map_err(vk::Result::from_raw)?;
+            ash::Instance::load(entry.static_fn(), vk::Instance::from_raw(raw as _))
+        };
+
+        /* Physical Device & Queues */
+        let physical_device = vk::PhysicalDevice::from_raw(unsafe {
+            instance.vulkan_graphics_device(system, vk_instance.handle().as_raw() as _)?
+        } as _);
+        let graphics_q_index = {
+            let surface = Surface::init(&window, &entry, &vk_instance)?;
+            QueueFamilies::init(&vk_instance, physical_device, &surface)?.graphics_q_index
+        };
+        let Some(graphics_q_index) = graphics_q_index else {
+            bail!("the headset's GPU cannot present to the window");
+        };
+        // One queue does both; uploads are rare next to the per-eye frames.
+        let queue_families = QueueFamilies {
+            graphics_q_index: Some(graphics_q_index),
+            transfer_q_index: Some(graphics_q_index),
+        };
+
+        /* Logical Device */
+        let features = unsafe { vk_instance.get_physical_device_features(physical_device) };
+        let priorities = [1.0f32];
+        let queue_infos = [vk::DeviceQueueCreateInfo::builder()
+            .queue_family_index(graphics_q_index)
+            .queue_priorities(&priorities)
+            .build()];
+        let device_extension_names = [ash::extensions::khr::Swapchain::name().as_ptr()];
+        let device_create_info = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(&queue_infos)
+            .enabled_extension_names(&device_extension_names)
+            .enabled_features(&features);
+        let device = unsafe {
+            let raw = instance
+                .create_vulkan_device(
+                    system,
+                    get_instance_proc_addr,
+                    physical_device.as_raw() as _,
+                    &*device_create_info as *const _ as *const _,
+                )?
+                .map_err(vk::Result::from_raw)?;
+            ash::Device::load(vk_instance.fp_v1_0(), vk::Device::from_raw(raw as _))
+        };
+        let queue = unsafe { device.get_device_queue(graphics_q_index, 0) };
+        let properties = unsafe { vk_instance.get_physical_device_properties(physical_device) };
+
+        /* Engine */
+        let mut krakatoa = Krakatoa::from_raw(
+            window,
+            RawContext {
+                entry,
+                instance: vk_instance,
+                physical_device,
+                device,
+                queue_families,
+                queues: Queues {
+                    graphics_queue: queue,
+                    transfer_queue: queue,
+                },
+                features,
+                capabilities: DeviceCapabilities {
+                    api_version: api_version.min(properties.api_version),
+                    ..Default::default()
+                },
+            },
+            builder,
+        )?;
+        // Nothing else holds on to the device and instance.
+        krakatoa.owns_context = true;
+        if krakatoa.output_colour_space().is_hdr() {
+            bail!("OpenXR output needs an SDR window");
+        }
+        if !krakatoa
+            .swapchain
+            .image_usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            bail!("the window's swapchain images cannot be copied to the headset");
+        }
+
+        /* Session */
+        let (session, frame_waiter, frame_stream) = unsafe {
+            instance.create_session::<xr::Vulkan>(
+                system,
+                &xr::vulkan::SessionCreateInfo {
+                    instance: krakatoa.instance.handle().as_raw() as _,
+                    physical_device: physical_device.as_raw() as _,
+                    device: krakatoa.logical_device.handle().as_raw() as _,
+                    queue_family_index: graphics_q_index,
+                    queue_index: 0,
+                },
+            )
+        }?;
+        let space =
+            session.create_reference_space(xr::ReferenceSpaceType::LOCAL, xr::Posef::IDENTITY)?;
+
+        /* Eye Swapchains */
+        let window_format = krakatoa.swapchain.surface_format.format;
+        let offered: Vec<vk::Format> = session
+            .enumerate_swapchain_formats()?
+            .into_iter()
+            .map(|format| vk::Format::from_raw(format as _))
+            .collect();
+        let Some(format) = [
+            window_format,
+            vk::Format::R8G8B8A8_SRGB,
+            vk::Format::B8G8R8A8_SRGB,
+        ]
+        .into_iter()
+        .find(|format| offered.contains(format)) else {
+            bail!("the OpenXR runtime offers no usable swapchain format");
+        };
+        let mut eyes = vec![];
+        for view in instance.enumerate_view_configuration_views(system, VIEW_TYPE)? {
+            let extent = vk::Extent2D {
+                width: view.recommended_image_rect_width,
+                height: view.recommended_image_rect_height,
+            };
+            let swapchain = session.create_swapchain(&xr::SwapchainCreateInfo {
+                create_flags: xr::SwapchainCreateFlags::EMPTY,
+                usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
+                    | xr::SwapchainUsageFlags::TRANSFER_DST,
+                format: format.as_raw() as _,
+                sample_count: 1,
+                width: extent.width,
+                height: extent.height,
+                face_count: 1,
+                array_size: 1,
+                mip_count: 1,
+            })?;
+            let images = swapchain
+                .enumerate_images()?
+                .into_iter()
+                .map(vk::Image::from_raw)
+                .collect();
+            eyes.push(EyeSwapchain {
+                swapchain,
+                images,
+                extent,
+            });
+        }
+
+        Ok(Self {
+            eyes,
+            space,
+            frame_stream,
+            frame_waiter,
+            session,
+            blend_mode,
+            system,
+            instance,
+            running: false,
+            origin: Isometry3::rotation(Vector3::x() * PI),
+            eye_camera: Camera::builder().build(),
+            krakatoa,
+        })
+    }
+
+    /// Follows the runtime's session state; call once per frame. Returns false once the
+    /// runtime wants the application to quit.
+    pub fn poll_events(&mut self) -> Result<bool> {
+        let mut buffer = xr::EventDataBuffer::new();
+        while let Some(event) = self.instance.poll_event(&mut buffer)? {
+            match event {
+                xr::Event::SessionStateChanged(change) => match change.state() {
+                    xr::SessionState::READY => {
+                        self.session.begin(VIEW_TYPE)?;
+                        self.running = true;
+                    }
+                    xr::SessionState::STOPPING => {
+                        self.session.end()?;
+                        self.running = false;
+                    }
+                    xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => {
+                        return Ok(false);
+                    }
+                    _ => {}
+                },
+                xr::Event::InstanceLossPending(_) => return Ok(false),
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Renders both eyes from the headset's predicted pose, keeping `camera`'s near and
+    /// far planes, or just the window from `camera` while the session is not running.
+    /// Returns whether the window's swapchain was recreated, like [`Krakatoa::render_frame`].
+    pub fn render_frame(&mut self, camera: &Camera) -> Result<bool> {
+        if !self.running {
+            return self.krakatoa.render_frame(camera);
+        }
+        let frame_state = self.frame_waiter.wait()?;
+        self.frame_stream.begin()?;
+        if !frame_state.should_render || self.krakatoa.is_paused() {
+            self.frame_stream
+                .end(frame_state.predicted_display_time, self.blend_mode, &[])?;
+            return Ok(false);
+        }
+        let (_, views) = self.session.locate_views(
+            VIEW_TYPE,
+            frame_state.predicted_display_time,
+            &self.space,
+        )?;
+
+        let mut recreated = false;
+        for (eye, view) in self.eyes.iter_mut().zip(&views) {
+            let image_index = eye.swapchain.acquire_image()?;
+            eye.swapchain.wait_image(xr::Duration::INFINITE)?;
+            self.eye_camera.view_matrix = eye_view_matrix(&self.origin, &view.pose);
+            self.eye_camera.projection_matrix = eye_projection_matrix(&view.fov, camera);
+            self.krakatoa.blit_target = Some(BlitTarget {
+                image: eye.images[image_index as usize],
+                extent: eye.extent,
+                final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            });
+            // A swapchain recreated before drawing leaves the eye image unwritten.
+            if self.krakatoa.render_frame(&self.eye_camera)? {
+                recreated = true;
+                self.krakatoa.render_frame(&self.eye_camera)?;
+            }
+            self.krakatoa.blit_target = None;
+            eye.swapchain.release_image()?;
+        }
+
+        let projection_views: Vec<_> = self
+            .eyes
+            .iter()
+            .zip(&views)
+            .map(|(eye, view)| {
+                xr::CompositionLayerProjectionView::new()
+                    .pose(view.pose)
+                    .fov(view.fov)
+                    .sub_image(
+                        xr::SwapchainSubImage::new()
+                            .swapchain(&eye.swapchain)
+                            .image_array_index(0)
+                            .image_rect(xr::Rect2Di {
+                                offset: xr::Offset2Di { x: 0, y: 0 },
+                                extent: xr::Extent2Di {
+                                    width: eye.extent.width as i32,
+                                    height: eye.extent.height as i32,
+                                },
+                            }),
+                    )
+            })
+            .collect();
+        self.frame_stream.end(
+            frame_state.predicted_display_time,
+            self.blend_mode,
+            &[&xr::CompositionLayerProjection::new()
+                .space(&self.space)
+                .views(&projection_views)],
+        )?;
+
+        Ok(recreated)
+    }
+}
+
+impl Drop for XrSession {
+    fn drop(&mut self) {
+        unsafe {
+            self.krakatoa
+                .logical_device
+                .device_wait_idle()
+                .expect("Something wrong while waiting.");
+        }
+    }
+}
+
+/// World-to-view matrix for an eye posed in the reference space placed at `origin`.
+fn eye_view_matrix(origin: &Isometry3<f32>, pose: &xr::Posef) -> Matrix4<f32> {
+    let orientation = UnitQuaternion::from_quaternion(Quaternion::new(
+        pose.orientation.w,
+        pose.orientation.x,
+        pose.orientation.y,
+        pose.orientation.z,
+    ));
+    let eye = Isometry3::from_parts(
+        Translation3::new(pose.position.x, pose.position.y, pose.position.z),
+        orientation,
+    );
+    // OpenXR views look down -z with y up, the engine's down +z with y down.
+    Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, -1.0, -1.0))
+        * (origin * eye).inverse().to_homogeneous()
+}
+
+/// Off-centre version of [`Camera::update_projection_matrix`] for the eye's field of view.
+fn eye_projection_matrix(fov: &xr::Fovf, camera: &Camera) -> Matrix4<f32> {
+    let (left, right) = (fov.angle_left.tan(), fov.angle_right.tan());
+    let (up, down) = (fov.angle_up.tan(), fov.angle_down.tan());
+    let (depth_scale, depth_offset) = if camera.far.is_infinite() {
+        (1.0, -camera.near)
+    } else {
+        (
+            camera.far / (camera.far - camera.near),
+            -camera.near * camera.far / (camera.far - camera.near),
+        )
+    };
+    Matrix4::new(
+        2.0 / (right - left),
+        0.0,
+        -(right + left) / (right - left),
+        0.0,
+        0.0,
+        2.0 / (up - down),
+        (up + down) / (up - down),
+        0.0,
+        0.0,
+        0.0,
+        depth_scale,
+        depth_offset,
+        0.0,
+        0.0,
+        1.0,
+        0.0,
+    )
+}